@@ -0,0 +1,373 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+
+use crate::error::Result;
+use crate::ops::{IntArg, Opcode, OpcodeInfo};
+use crate::{Error, ErrorCode};
+
+/// Assembles a pickle from a listing in the style of `pickletools.dis`, one
+/// opcode per line:
+///
+/// ```text
+/// PROTO      4
+/// FRAME
+/// EMPTY_DICT
+/// MEMOIZE
+/// SHORT_BINUNICODE 'a'
+/// BININT1    1
+/// SETITEM
+/// STOP
+/// ```
+///
+/// Arguments are written as Python literals, as `dis` shows them, and
+/// length prefixes are computed.  A `FRAME` without an argument spans the
+/// bytes up to the next `FRAME` or the end.  The output of `dis` is
+/// accepted as is: offsets, codes, annotations such as `(as 0)` or
+/// `(MARK at 5)`, blank lines, `#` comments and the closing "highest
+/// protocol" line are skipped.
+///
+/// Every opcode must exist in the protocol given by `PROTO`, or in
+/// protocol 1 before any `PROTO`.
+pub fn assemble(listing: &str) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut proto = 1;
+    // The starts of all frames, and the positions of the lengths of the
+    // frames that are to be computed.
+    let mut frames = Vec::new();
+    let mut open_frames = Vec::new();
+    for (i, line) in listing.lines().enumerate() {
+        let lineno = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("highest protocol") {
+            continue;
+        }
+        let (op, auto_frame) = parse_line(line).map_err(|msg| syntax(lineno, msg))?;
+        if let Opcode::Proto(p) = op {
+            proto = p;
+        }
+        if op.proto() > proto {
+            let msg = format!(
+                "{} needs protocol {}, but the pickle is protocol {}",
+                op.name(),
+                op.proto(),
+                proto
+            );
+            return Err(syntax(lineno, msg));
+        }
+        if let Opcode::Frame(_) = op {
+            frames.push(out.len());
+            if auto_frame {
+                open_frames.push(out.len() + 1);
+            }
+        }
+        op.encode(&mut out).map_err(|err| match err {
+            Error::Syntax(code) => syntax(lineno, code.to_string()),
+            err => err,
+        })?;
+    }
+    for len_pos in open_frames {
+        let start = len_pos + 8;
+        let end = frames
+            .iter()
+            .copied()
+            .find(|&frame| frame > len_pos)
+            .unwrap_or(out.len());
+        let len = (end - start) as u64;
+        out[len_pos..start].copy_from_slice(&len.to_le_bytes());
+    }
+    Ok(out)
+}
+
+fn syntax(lineno: usize, msg: String) -> Error {
+    Error::Syntax(ErrorCode::InvalidValue(format!("line {}: {}", lineno, msg)))
+}
+
+// Parse one line into an opcode, and whether it is a `FRAME` whose length
+// is to be computed.
+fn parse_line(line: &str) -> std::result::Result<(Opcode<'static>, bool), String> {
+    let mut line = line;
+    // Skip the offset and the code of a `dis` line.
+    if let Some((offset, rest)) = line.split_once(':') {
+        if !offset.is_empty() && offset.trim().bytes().all(|b| b.is_ascii_digit()) {
+            let rest = rest.trim_start();
+            line = rest.split_once(char::is_whitespace).map_or("", |(_, r)| r);
+        }
+    }
+    let line = line.trim_start();
+    let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let info = OpcodeInfo::from_name(name).ok_or_else(|| format!("unknown opcode {}", name))?;
+    let mut args = Args(rest.trim_start());
+    let mut auto_frame = false;
+    let op = match info.name {
+        "MARK" => Opcode::Mark,
+        "STOP" => Opcode::Stop,
+        "POP" => Opcode::Pop,
+        "POP_MARK" => Opcode::PopMark,
+        "DUP" => Opcode::Dup,
+        "FLOAT" => Opcode::Float(args.float()?),
+        "INT" => Opcode::Int(match args.word()? {
+            "True" => IntArg::Bool(true),
+            "False" => IntArg::Bool(false),
+            word => IntArg::Int(parse_number(word)?),
+        }),
+        "BININT" => Opcode::BinInt(args.number()?),
+        "BININT1" => Opcode::BinInt1(args.number()?),
+        "LONG" => Opcode::Long(args.big_int()?),
+        "BININT2" => Opcode::BinInt2(args.number()?),
+        "NONE" => Opcode::None,
+        "PERSID" => Opcode::PersId(args.latin1()?),
+        "BINPERSID" => Opcode::BinPersId,
+        "REDUCE" => Opcode::Reduce,
+        "STRING" => Opcode::String(args.latin1()?),
+        "BINSTRING" => Opcode::BinString(args.latin1()?),
+        "SHORT_BINSTRING" => Opcode::ShortBinString(args.latin1()?),
+        "UNICODE" => Opcode::Unicode(args.string()?.into()),
+        "BINUNICODE" => Opcode::BinUnicode(args.string()?.into()),
+        "APPEND" => Opcode::Append,
+        "BUILD" => Opcode::Build,
+        "GLOBAL" | "INST" => {
+            let arg = args.string()?;
+            let (module, name) = arg
+                .split_once(' ')
+                .ok_or_else(|| format!("expected 'module name', got {:?}", arg))?;
+            let (module, name) = (module.to_string().into(), name.to_string().into());
+            match info.name {
+                "GLOBAL" => Opcode::Global { module, name },
+                _ => Opcode::Inst { module, name },
+            }
+        }
+        "DICT" => Opcode::Dict,
+        "EMPTY_DICT" => Opcode::EmptyDict,
+        "APPENDS" => Opcode::Appends,
+        "GET" => Opcode::Get(args.number()?),
+        "BINGET" => Opcode::BinGet(args.number()?),
+        "LONG_BINGET" => Opcode::LongBinGet(args.number()?),
+        "LIST" => Opcode::List,
+        "EMPTY_LIST" => Opcode::EmptyList,
+        "OBJ" => Opcode::Obj,
+        "PUT" => Opcode::Put(args.number()?),
+        "BINPUT" => Opcode::BinPut(args.number()?),
+        "LONG_BINPUT" => Opcode::LongBinPut(args.number()?),
+        "SETITEM" => Opcode::SetItem,
+        "TUPLE" => Opcode::Tuple,
+        "EMPTY_TUPLE" => Opcode::EmptyTuple,
+        "SETITEMS" => Opcode::SetItems,
+        "BINFLOAT" => Opcode::BinFloat(args.float()?),
+        "PROTO" => Opcode::Proto(args.number()?),
+        "NEWOBJ" => Opcode::NewObj,
+        "EXT1" => Opcode::Ext1(args.number()?),
+        "EXT2" => Opcode::Ext2(args.number()?),
+        "EXT4" => Opcode::Ext4(args.number()?),
+        "TUPLE1" => Opcode::Tuple1,
+        "TUPLE2" => Opcode::Tuple2,
+        "TUPLE3" => Opcode::Tuple3,
+        "NEWTRUE" => Opcode::NewTrue,
+        "NEWFALSE" => Opcode::NewFalse,
+        "LONG1" => Opcode::Long1(args.big_int()?),
+        "LONG4" => Opcode::Long4(args.big_int()?),
+        "BINBYTES" => Opcode::BinBytes(args.bytes()?.into()),
+        "SHORT_BINBYTES" => Opcode::ShortBinBytes(args.bytes()?.into()),
+        "SHORT_BINUNICODE" => Opcode::ShortBinUnicode(args.string()?.into()),
+        "BINUNICODE8" => Opcode::BinUnicode8(args.string()?.into()),
+        "BINBYTES8" => Opcode::BinBytes8(args.bytes()?.into()),
+        "EMPTY_SET" => Opcode::EmptySet,
+        "ADDITEMS" => Opcode::AddItems,
+        "FROZENSET" => Opcode::FrozenSet,
+        "NEWOBJ_EX" => Opcode::NewObjEx,
+        "STACK_GLOBAL" => Opcode::StackGlobal,
+        "MEMOIZE" => Opcode::Memoize,
+        "FRAME" => {
+            if args.is_done() {
+                auto_frame = true;
+                Opcode::Frame(0)
+            } else {
+                Opcode::Frame(args.number()?)
+            }
+        }
+        "BYTEARRAY8" => {
+            let bytes = match args.0.strip_prefix("bytearray(") {
+                Some(rest) => {
+                    args.0 = rest;
+                    let bytes = args.bytes()?;
+                    args.0 = args.0.strip_prefix(')').ok_or("expected )")?;
+                    bytes
+                }
+                None => args.bytes()?,
+            };
+            Opcode::ByteArray8(bytes.into())
+        }
+        "NEXT_BUFFER" => Opcode::NextBuffer,
+        "READONLY_BUFFER" => Opcode::ReadonlyBuffer,
+        name => return Err(format!("unknown opcode {}", name)),
+    };
+    args.finish()?;
+    Ok((op, auto_frame))
+}
+
+fn parse_number<T: FromStr>(word: &str) -> std::result::Result<T, String> {
+    word.parse().map_err(|_| format!("invalid number {}", word))
+}
+
+// The rest of a line after the opcode name.
+struct Args<'a>(&'a str);
+
+impl<'a> Args<'a> {
+    fn is_done(&self) -> bool {
+        self.0.is_empty() || self.is_annotation()
+    }
+
+    // Annotations that `dis` appends after the argument.
+    fn is_annotation(&self) -> bool {
+        ["(", "[", "<==", "no MARK"]
+            .iter()
+            .any(|prefix| self.0.starts_with(prefix))
+    }
+
+    fn finish(&self) -> std::result::Result<(), String> {
+        if self.is_done() {
+            Ok(())
+        } else {
+            Err(format!("unexpected {:?}", self.0))
+        }
+    }
+
+    fn word(&mut self) -> std::result::Result<&'a str, String> {
+        if self.is_done() {
+            return Err("missing argument".into());
+        }
+        let (word, rest) = self
+            .0
+            .split_once(char::is_whitespace)
+            .unwrap_or((self.0, ""));
+        self.0 = rest.trim_start();
+        Ok(word)
+    }
+
+    fn number<T: FromStr>(&mut self) -> std::result::Result<T, String> {
+        parse_number(self.word()?)
+    }
+
+    fn big_int(&mut self) -> std::result::Result<BigInt, String> {
+        let word = self.word()?;
+        parse_number(word.strip_suffix('L').unwrap_or(word))
+    }
+
+    fn float(&mut self) -> std::result::Result<f64, String> {
+        self.number()
+    }
+
+    // A quoted literal, with its escapes resolved into code points.
+    fn quoted(&mut self, bytes: bool) -> std::result::Result<Vec<u32>, String> {
+        let mut chars = self.0.chars();
+        let quote = match chars.next() {
+            Some(quote @ ('\'' | '"')) => quote,
+            _ => return Err(format!("expected a quoted literal, got {:?}", self.0)),
+        };
+        let mut out = Vec::new();
+        loop {
+            let ch = chars.next().ok_or("unterminated literal")?;
+            if ch == quote {
+                break;
+            }
+            if ch != '\\' {
+                if bytes && !ch.is_ascii() {
+                    return Err("bytes literals may only contain ASCII".into());
+                }
+                out.push(ch as u32);
+                continue;
+            }
+            let escape = chars.next().ok_or("unterminated literal")?;
+            let code = match escape {
+                '\\' | '\'' | '"' => escape as u32,
+                'n' => 0x0a,
+                'r' => 0x0d,
+                't' => 0x09,
+                'a' => 0x07,
+                'b' => 0x08,
+                'f' => 0x0c,
+                'v' => 0x0b,
+                '0'..='7' => {
+                    let mut code = escape.to_digit(8).unwrap_or(0);
+                    for _ in 0..2 {
+                        match chars.clone().next().and_then(|ch| ch.to_digit(8)) {
+                            Some(digit) => {
+                                code = code * 8 + digit;
+                                chars.next();
+                            }
+                            None => break,
+                        }
+                    }
+                    code
+                }
+                'x' => hex(&mut chars, 2)?,
+                'u' if !bytes => hex(&mut chars, 4)?,
+                'U' if !bytes => hex(&mut chars, 8)?,
+                other => return Err(format!("unknown escape \\{}", other)),
+            };
+            out.push(code);
+        }
+        self.0 = chars.as_str().trim_start();
+        Ok(out)
+    }
+
+    fn string(&mut self) -> std::result::Result<String, String> {
+        self.quoted(false)?
+            .into_iter()
+            .map(|code| char::from_u32(code).ok_or_else(|| format!("invalid code point {}", code)))
+            .collect()
+    }
+
+    // A str literal whose code points are all below 256, as `dis` shows
+    // the arguments of `STRING` and friends.
+    fn latin1(&mut self) -> std::result::Result<Cow<'static, [u8]>, String> {
+        let codes = self.quoted(false)?;
+        let bytes = codes
+            .into_iter()
+            .map(|code| u8::try_from(code).map_err(|_| "code point above 255".to_string()))
+            .collect::<std::result::Result<Vec<u8>, String>>()?;
+        Ok(bytes.into())
+    }
+
+    fn bytes(&mut self) -> std::result::Result<Vec<u8>, String> {
+        self.0 = self.0.strip_prefix('b').ok_or("expected a bytes literal")?;
+        Ok(self
+            .quoted(true)?
+            .into_iter()
+            .map(|code| code as u8)
+            .collect())
+    }
+}
+
+fn hex(chars: &mut std::str::Chars, digits: usize) -> std::result::Result<u32, String> {
+    let mut code = 0;
+    for _ in 0..digits {
+        let digit = chars
+            .next()
+            .and_then(|ch| ch.to_digit(16))
+            .ok_or("invalid hex escape")?;
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
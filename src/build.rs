@@ -0,0 +1,210 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::iter;
+
+use crate::{HashMapWrapper, HashSetWrapper, Value};
+
+/// Builds a value from Python-like syntax, e.g.
+///
+/// ```text
+/// py!({"a": [1, 2.5, None], b"k": (True, frozenset({3})), "t": (x,), "s": set()})
+/// ```
+///
+/// `None`, `True` and `False`, lists, tuples, dicts, sets, `set()` and
+/// `frozenset({...})` nest as in Python; every other item is a Rust
+/// expression, converted with `Value::from`.
+#[macro_export]
+macro_rules! py {
+    // The items of a list, tuple or set, split at the commas at the top.
+    (@seq [$($items:expr,)*] []) => { ::std::vec![$($items,)*] };
+    (@seq [$($items:expr,)*] [] $item:tt , $($rest:tt)*) => {
+        $crate::py!(@seq [$($items,)* $crate::py!($item),] [] $($rest)*)
+    };
+    (@seq [$($items:expr,)*] [$($item:tt)+]) => {
+        ::std::vec![$($items,)* $crate::py!($($item)+),]
+    };
+    (@seq [$($items:expr,)*] [$($item:tt)+] , $($rest:tt)*) => {
+        $crate::py!(@seq [$($items,)* $crate::py!($($item)+),] [] $($rest)*)
+    };
+    (@seq [$($items:expr,)*] [$($item:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::py!(@seq [$($items,)*] [$($item)* $next] $($rest)*)
+    };
+
+    // The items of a dict: the tokens of a key up to its colon, then those
+    // of its value up to the next comma.
+    (@dict [$($items:expr,)*] []) => { ::std::vec![$($items,)*] };
+    (@dict [$($items:expr,)*] [] $key:tt : $value:tt $(, $($rest:tt)*)?) => {
+        $crate::py!(@dict [$($items,)* ($crate::py!($key), $crate::py!($value)),] [] $($($rest)*)?)
+    };
+    (@dict [$($items:expr,)*] [$($key:tt)+] : $($rest:tt)*) => {
+        $crate::py!(@value [$($items,)*] [$($key)+] [] $($rest)*)
+    };
+    (@dict [$($items:expr,)*] [$($key:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::py!(@dict [$($items,)*] [$($key)* $next] $($rest)*)
+    };
+    (@value [$($items:expr,)*] [$($key:tt)+] [$($value:tt)+] $(, $($rest:tt)*)?) => {
+        $crate::py!(
+            @dict [$($items,)* ($crate::py!($($key)+), $crate::py!($($value)+)),] []
+            $($($rest)*)?
+        )
+    };
+    (@value [$($items:expr,)*] [$($key:tt)+] [$($value:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::py!(@value [$($items,)*] [$($key)+] [$($value)* $next] $($rest)*)
+    };
+
+    // Parentheses make a tuple if there is a comma at the top.
+    (@paren [$($all:tt)+] , $($rest:tt)*) => {
+        $crate::Value::Tuple($crate::py!(@seq [] [] $($all)+))
+    };
+    (@paren [$($all:tt)+] $next:tt $($rest:tt)*) => { $crate::py!(@paren [$($all)+] $($rest)*) };
+    (@paren [$($all:tt)+]) => { $crate::py!($($all)+) };
+
+    // Braces make a dict if there is a colon at the top, else a set.
+    (@braces [$($all:tt)+] : $($rest:tt)*) => {
+        $crate::Value::Dict($crate::py!(@dict [] [] $($all)+).into_iter().collect())
+    };
+    (@braces [$($all:tt)+] $next:tt $($rest:tt)*) => { $crate::py!(@braces [$($all)+] $($rest)*) };
+    (@braces [$($all:tt)+]) => {
+        $crate::Value::Set($crate::py!(@seq [] [] $($all)+).into_iter().collect())
+    };
+
+    (None) => { $crate::Value::None };
+    (True) => { $crate::Value::Bool(true) };
+    (False) => { $crate::Value::Bool(false) };
+    // A list of plain literals is built in one step, so long ones stay within
+    // the recursion limit.  Other items can't take this path: once matched
+    // as fragments they no longer match `None`, `[...]` or `{...}` above.
+    ([$($items:literal),* $(,)?]) => {
+        $crate::Value::List(::std::vec![$($crate::Value::from($items)),*])
+    };
+    ([$($items:tt)*]) => { $crate::Value::List($crate::py!(@seq [] [] $($items)*)) };
+    (()) => { $crate::Value::Tuple(::std::vec::Vec::new()) };
+    (($($all:tt)+)) => { $crate::py!(@paren [$($all)+] $($all)+) };
+    ({}) => { $crate::Value::Dict($crate::HashMapWrapper::new()) };
+    ({$key:tt : $($rest:tt)*}) => {
+        $crate::Value::Dict($crate::py!(@dict [] [] $key : $($rest)*).into_iter().collect())
+    };
+    ({$($all:tt)+}) => { $crate::py!(@braces [$($all)+] $($all)+) };
+    (set()) => { $crate::Value::Set($crate::HashSetWrapper::new()) };
+    (frozenset()) => { $crate::Value::FrozenSet($crate::HashSetWrapper::new()) };
+    (frozenset({$($items:tt)*})) => {
+        $crate::Value::FrozenSet($crate::py!(@seq [] [] $($items)*).into_iter().collect())
+    };
+    ($value:expr) => { $crate::Value::from($value) };
+}
+
+/// Builds a `Value::Dict` one item at a time.  Keys and values are anything
+/// that converts into a `Value`, including other builders; a key that is
+/// already there keeps its place and gets the new value, as in Python.
+#[derive(Default)]
+pub struct DictBuilder<'a>(HashMapWrapper<Value<'a>, Value<'a>>);
+
+impl<'a> DictBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn item(mut self, key: impl Into<Value<'a>>, value: impl Into<Value<'a>>) -> Self {
+        self.0.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn items<K, V, I>(mut self, items: I) -> Self
+    where
+        K: Into<Value<'a>>,
+        V: Into<Value<'a>>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.0
+            .extend(items.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    pub fn build(self) -> Value<'a> {
+        Value::Dict(self.0)
+    }
+}
+
+impl<'a> From<DictBuilder<'a>> for Value<'a> {
+    fn from(builder: DictBuilder<'a>) -> Self {
+        builder.build()
+    }
+}
+
+macro_rules! seq_builder {
+    ($(#[$doc:meta])* $name:ident, $variant:ident, $items:ty) => {
+        $(#[$doc])*
+        #[derive(Default)]
+        pub struct $name<'a>($items);
+
+        impl<'a> $name<'a> {
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            pub fn item(mut self, item: impl Into<Value<'a>>) -> Self {
+                self.0.extend(iter::once(item.into()));
+                self
+            }
+
+            pub fn items<T: Into<Value<'a>>>(mut self, items: impl IntoIterator<Item = T>) -> Self {
+                self.0.extend(items.into_iter().map(Into::into));
+                self
+            }
+
+            pub fn build(self) -> Value<'a> {
+                Value::$variant(self.0)
+            }
+        }
+
+        impl<'a> From<$name<'a>> for Value<'a> {
+            fn from(builder: $name<'a>) -> Self {
+                builder.build()
+            }
+        }
+    };
+}
+
+seq_builder!(
+    /// Builds a `Value::List` one item at a time.
+    ListBuilder,
+    List,
+    Vec<Value<'a>>
+);
+seq_builder!(
+    /// Builds a `Value::Tuple` one item at a time.
+    TupleBuilder,
+    Tuple,
+    Vec<Value<'a>>
+);
+seq_builder!(
+    /// Builds a `Value::Set` one member at a time.  Members that Python
+    /// takes as equal to one already there are left out.
+    SetBuilder,
+    Set,
+    HashSetWrapper<Value<'a>>
+);
+seq_builder!(
+    /// Builds a `Value::FrozenSet` one member at a time, like `SetBuilder`.
+    FrozenSetBuilder,
+    FrozenSet,
+    HashSetWrapper<Value<'a>>
+);
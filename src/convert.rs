@@ -0,0 +1,370 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::error::Result;
+use crate::path::{Path, PathElem};
+use crate::{Error, ErrorCode, F64Wrapper, HashMapWrapper, HashSetWrapper, Value};
+
+/// Rust types that a `Value` converts into, as by `TryFrom<Value>`.  Items
+/// of containers are converted with the path to them, so that an error
+/// says where the value of the wrong type is.
+pub trait FromValue<'a>: Sized {
+    /// Converts `value`, found at `path`.
+    fn from_value(value: Value<'a>, path: &mut Path<'static>) -> Result<Self>;
+}
+
+fn invalid_type<T>(path: &Path<'static>, expected: &'static str, value: &Value) -> Result<T> {
+    let code = ErrorCode::InvalidType(path.clone(), expected, value.type_name());
+    Err(Error::Syntax(code))
+}
+
+// Converts an item of a container, with `elem` added to the path.
+fn item<'a, T: FromValue<'a>>(
+    value: Value<'a>,
+    path: &mut Path<'static>,
+    elem: PathElem<'static>,
+) -> Result<T> {
+    path.push(elem);
+    let result = T::from_value(value, path);
+    path.0.pop();
+    result
+}
+
+impl<'a> FromValue<'a> for bool {
+    fn from_value(value: Value<'a>, path: &mut Path<'static>) -> Result<Self> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            other => invalid_type(path, "bool", &other),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for i64 {
+    fn from_value(value: Value<'a>, path: &mut Path<'static>) -> Result<Self> {
+        match value.as_i64() {
+            Some(i) => Ok(i),
+            None => invalid_type(path, "int in the range of i64", &value),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for f64 {
+    fn from_value(value: Value<'a>, path: &mut Path<'static>) -> Result<Self> {
+        match value.as_f64() {
+            Some(f) => Ok(f),
+            None => invalid_type(path, "float", &value),
+        }
+    }
+}
+
+impl<'a> FromValue<'a> for String {
+    fn from_value(value: Value<'a>, path: &mut Path<'static>) -> Result<Self> {
+        match value {
+            Value::String(s) => Ok(s.into_owned()),
+            other => invalid_type(path, "str", &other),
+        }
+    }
+}
+
+impl<'a, T: FromValue<'a>> FromValue<'a> for Option<T> {
+    fn from_value(value: Value<'a>, path: &mut Path<'static>) -> Result<Self> {
+        match value {
+            Value::None => Ok(None),
+            other => T::from_value(other, path).map(Some),
+        }
+    }
+}
+
+impl<'a, T: FromValue<'a>> FromValue<'a> for Vec<T> {
+    fn from_value(value: Value<'a>, path: &mut Path<'static>) -> Result<Self> {
+        match value {
+            Value::List(items) | Value::Tuple(items) => items
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| item(value, path, PathElem::Index(i)))
+                .collect(),
+            other => invalid_type(path, "list", &other),
+        }
+    }
+}
+
+impl<'a, K, V> FromValue<'a> for HashMap<K, V>
+where
+    K: FromValue<'a> + Eq + Hash,
+    V: FromValue<'a>,
+{
+    fn from_value(value: Value<'a>, path: &mut Path<'static>) -> Result<Self> {
+        match value {
+            Value::Dict(dict) => dict
+                .0
+                .into_iter()
+                .map(|(key, value)| {
+                    let elem = PathElem::Key(key.clone().into_owned());
+                    let key = K::from_value(key, path)?;
+                    Ok((key, item(value, path, elem)?))
+                })
+                .collect(),
+            other => invalid_type(path, "dict", &other),
+        }
+    }
+}
+
+// Tuples convert from Python tuples, or lists, of the same length.
+macro_rules! tuple_from_value {
+    ($len:expr, $name:expr, $($T:ident),+) => {
+        impl<'a, $($T: FromValue<'a>),+> FromValue<'a> for ($($T,)+) {
+            fn from_value(value: Value<'a>, path: &mut Path<'static>) -> Result<Self> {
+                match value {
+                    Value::List(items) | Value::Tuple(items) if items.len() == $len => {
+                        let mut items = items.into_iter().enumerate();
+                        Ok(($({
+                            let (i, value) = items.next().unwrap();
+                            item::<$T>(value, path, PathElem::Index(i))?
+                        },)+))
+                    }
+                    other => invalid_type(path, $name, &other),
+                }
+            }
+        }
+
+        impl<'a, $($T: FromValue<'a>),+> TryFrom<Value<'a>> for ($($T,)+) {
+            type Error = Error;
+
+            fn try_from(value: Value<'a>) -> Result<Self> {
+                Self::from_value(value, &mut Path::new())
+            }
+        }
+    };
+}
+
+tuple_from_value!(1, "tuple of 1 item", A);
+tuple_from_value!(2, "tuple of 2 items", A, B);
+tuple_from_value!(3, "tuple of 3 items", A, B, C);
+tuple_from_value!(4, "tuple of 4 items", A, B, C, D);
+
+macro_rules! try_from_value {
+    ($($T:ty),+) => {
+        $(
+            impl<'a> TryFrom<Value<'a>> for $T {
+                type Error = Error;
+
+                fn try_from(value: Value<'a>) -> Result<Self> {
+                    Self::from_value(value, &mut Path::new())
+                }
+            }
+        )+
+    };
+}
+
+try_from_value!(bool, i64, f64, String);
+
+impl<'a, T: FromValue<'a>> TryFrom<Value<'a>> for Option<T> {
+    type Error = Error;
+
+    fn try_from(value: Value<'a>) -> Result<Self> {
+        Self::from_value(value, &mut Path::new())
+    }
+}
+
+impl<'a, T: FromValue<'a>> TryFrom<Value<'a>> for Vec<T> {
+    type Error = Error;
+
+    fn try_from(value: Value<'a>) -> Result<Self> {
+        Self::from_value(value, &mut Path::new())
+    }
+}
+
+impl<'a, K, V> TryFrom<Value<'a>> for HashMap<K, V>
+where
+    K: FromValue<'a> + Eq + Hash,
+    V: FromValue<'a>,
+{
+    type Error = Error;
+
+    fn try_from(value: Value<'a>) -> Result<Self> {
+        Self::from_value(value, &mut Path::new())
+    }
+}
+
+// Conversions the other way, from Rust values into `Value`s.  Ints are
+// `Value::I64` where they fit, as the unpickler returns them, and bytes
+// come from byte slices: a `Vec<u8>` is a list of ints like any `Vec`.
+
+macro_rules! value_from_int {
+    ($($T:ty),+) => {
+        $(
+            impl<'a> From<$T> for Value<'a> {
+                fn from(i: $T) -> Self {
+                    match i64::try_from(i) {
+                        Ok(i) => Value::I64(i),
+                        Err(_) => Value::Int(BigInt::from(i)),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+value_from_int!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl<'a> From<BigInt> for Value<'a> {
+    fn from(i: BigInt) -> Self {
+        match i.to_i64() {
+            Some(i) => Value::I64(i),
+            None => Value::Int(i),
+        }
+    }
+}
+
+impl<'a> From<bool> for Value<'a> {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl<'a> From<f64> for Value<'a> {
+    fn from(f: f64) -> Self {
+        Value::F64(F64Wrapper(f))
+    }
+}
+
+impl<'a> From<f32> for Value<'a> {
+    fn from(f: f32) -> Self {
+        Value::F64(F64Wrapper(f.into()))
+    }
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(s: &'a str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl<'a> From<String> for Value<'a> {
+    fn from(s: String) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl<'a> From<Cow<'a, str>> for Value<'a> {
+    fn from(s: Cow<'a, str>) -> Self {
+        Value::String(s)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Value<'a> {
+    fn from(b: &'a [u8]) -> Self {
+        Value::Bytes(b.into())
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u8; N]> for Value<'a> {
+    fn from(b: &'a [u8; N]) -> Self {
+        Value::Bytes(b[..].into())
+    }
+}
+
+impl<'a> From<Cow<'a, [u8]>> for Value<'a> {
+    fn from(b: Cow<'a, [u8]>) -> Self {
+        Value::Bytes(b)
+    }
+}
+
+impl<'a, T: Into<Value<'a>>> From<Option<T>> for Value<'a> {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::None, Into::into)
+    }
+}
+
+impl<'a, T: Into<Value<'a>>> From<Vec<T>> for Value<'a> {
+    fn from(items: Vec<T>) -> Self {
+        Value::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<'a, T: Into<Value<'a>>> From<HashSet<T>> for Value<'a> {
+    fn from(items: HashSet<T>) -> Self {
+        Value::Set(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<'a, T: Into<Value<'a>>> From<BTreeSet<T>> for Value<'a> {
+    fn from(items: BTreeSet<T>) -> Self {
+        Value::Set(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<'a, K: Into<Value<'a>>, V: Into<Value<'a>>> From<HashMap<K, V>> for Value<'a> {
+    fn from(items: HashMap<K, V>) -> Self {
+        Value::Dict(
+            items
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+impl<'a, K: Into<Value<'a>>, V: Into<Value<'a>>> From<BTreeMap<K, V>> for Value<'a> {
+    fn from(items: BTreeMap<K, V>) -> Self {
+        Value::Dict(
+            items
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+impl<'a> From<HashMapWrapper<Value<'a>, Value<'a>>> for Value<'a> {
+    fn from(dict: HashMapWrapper<Value<'a>, Value<'a>>) -> Self {
+        Value::Dict(dict)
+    }
+}
+
+impl<'a> From<HashSetWrapper<Value<'a>>> for Value<'a> {
+    fn from(set: HashSetWrapper<Value<'a>>) -> Self {
+        Value::Set(set)
+    }
+}
+
+// Rust tuples become Python tuples.
+macro_rules! value_from_tuple {
+    ($($T:ident),+) => {
+        impl<'a, $($T: Into<Value<'a>>),+> From<($($T,)+)> for Value<'a> {
+            #[allow(non_snake_case)]
+            fn from(($($T,)+): ($($T,)+)) -> Self {
+                Value::Tuple(vec![$($T.into()),+])
+            }
+        }
+    };
+}
+
+value_from_tuple!(A);
+value_from_tuple!(A, B);
+value_from_tuple!(A, B, C);
+value_from_tuple!(A, B, C, D);
@@ -0,0 +1,568 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+use crate::error::Result;
+use crate::ops::{ops, IntArg, Opcode};
+use crate::{Error, ErrorCode, F64Wrapper, Value};
+
+// An entry of the symbolic stack: the Python expression that builds a
+// value.  Containers stay literals while they are filled.
+#[derive(Clone)]
+enum Expr {
+    Mark,
+    /// An immutable scalar or a global, which is repeated instead of bound
+    /// to a variable.
+    Literal(String),
+    /// A str, which `STACK_GLOBAL` takes as is.
+    Str(String),
+    /// A variable.
+    Var(String),
+    List(Vec<Expr>),
+    Tuple(Vec<Expr>),
+    Dict(Vec<(Expr, Expr)>),
+    Set(Vec<Expr>),
+    /// A call, which is kept as a statement if its value is dropped.
+    Call(String),
+    /// Any other expression.
+    Code(String),
+}
+
+impl Expr {
+    fn render(&self) -> String {
+        match self {
+            Expr::Mark => "<mark>".into(),
+            Expr::Literal(code) | Expr::Var(code) | Expr::Call(code) | Expr::Code(code) => {
+                code.clone()
+            }
+            Expr::Str(s) => Value::String(s.as_str().into()).to_string(),
+            Expr::List(items) => format!("[{}]", render_all(items)),
+            Expr::Tuple(items) if items.len() == 1 => format!("({},)", items[0].render()),
+            Expr::Tuple(items) => format!("({})", render_all(items)),
+            Expr::Dict(items) => {
+                let items = items
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k.render(), v.render()))
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", items.join(", "))
+            }
+            Expr::Set(items) if items.is_empty() => "set()".into(),
+            Expr::Set(items) => format!("{{{}}}", render_all(items)),
+        }
+    }
+
+    // The arguments of a call with this tuple.
+    fn args(&self) -> String {
+        match self {
+            Expr::Tuple(items) => render_all(items),
+            args => format!("*{}", args.render()),
+        }
+    }
+}
+
+fn render_all(items: &[Expr]) -> String {
+    items
+        .iter()
+        .map(Expr::render)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+struct Decompiler {
+    imports: Vec<String>,
+    // The module of each name that is imported.
+    names: HashMap<String, String>,
+    lines: Vec<String>,
+    vars: usize,
+    stack: Vec<Expr>,
+    memo: HashMap<u32, Option<Expr>>,
+    // The memo ids that are fetched, whose values must be variables.
+    fetched: HashSet<u32>,
+    pos: usize,
+}
+
+/// Decompiles a pickle into Python source that builds the same object and
+/// assigns it to `result`, working from the opcodes without executing
+/// anything:
+///
+/// ```python
+/// from collections import OrderedDict
+/// _0 = OrderedDict()
+/// _0['a'] = [1, 2]
+/// result = _0
+/// ```
+///
+/// Globals become imports, memo entries that are fetched again become
+/// variables `_0`, `_1`, ..., `REDUCE` becomes a call, `NEWOBJ` a call of
+/// `__new__`, and `BUILD` an update of `__dict__` when the state is a dict
+/// literal, else a call of `__setstate__`.  Persistent ids are loaded with
+/// `persistent_load`, and out-of-band buffers are taken from `buffers`.
+pub fn decompile<R: Read>(reader: R) -> Result<String> {
+    let mut all_ops = Vec::new();
+    let mut fetched = HashSet::new();
+    for op in ops(reader) {
+        let (pos, op) = op?;
+        match op {
+            Opcode::Get(id) | Opcode::LongBinGet(id) => {
+                fetched.insert(id);
+            }
+            Opcode::BinGet(id) => {
+                fetched.insert(id.into());
+            }
+            _ => {}
+        }
+        all_ops.push((pos, op));
+    }
+
+    let mut decompiler = Decompiler {
+        imports: Vec::new(),
+        names: HashMap::new(),
+        lines: Vec::new(),
+        vars: 0,
+        stack: Vec::new(),
+        memo: HashMap::new(),
+        fetched,
+        pos: 0,
+    };
+    for (pos, op) in all_ops {
+        decompiler.pos = pos;
+        decompiler.op(op)?;
+    }
+    let mut source = String::new();
+    for line in decompiler.imports.iter().chain(&decompiler.lines) {
+        source += line;
+        source.push('\n');
+    }
+    Ok(source)
+}
+
+impl Decompiler {
+    fn error<T>(&self, code: ErrorCode) -> Result<T> {
+        Err(Error::eval(code, self.pos))
+    }
+
+    fn pop(&mut self) -> Result<Expr> {
+        match self.stack.pop() {
+            Some(Expr::Mark) | None => self.error(ErrorCode::StackUnderflow),
+            Some(expr) => Ok(expr),
+        }
+    }
+
+    fn pop_mark(&mut self) -> Result<Vec<Expr>> {
+        match self
+            .stack
+            .iter()
+            .rposition(|expr| matches!(expr, Expr::Mark))
+        {
+            Some(mark) => {
+                let items = self.stack.split_off(mark + 1);
+                self.stack.pop();
+                Ok(items)
+            }
+            None => self.error(ErrorCode::StackUnderflow),
+        }
+    }
+
+    fn top(&mut self) -> Result<&mut Expr> {
+        match self.stack.last_mut() {
+            Some(Expr::Mark) | None => Err(Error::eval(ErrorCode::StackUnderflow, self.pos)),
+            Some(expr) => Ok(expr),
+        }
+    }
+
+    // Assigns an expression to a new variable, unless it is one.
+    fn bind(&mut self, expr: Expr) -> String {
+        if let Expr::Var(name) = expr {
+            return name;
+        }
+        let name = format!("_{}", self.vars);
+        self.vars += 1;
+        self.lines.push(format!("{} = {}", name, expr.render()));
+        name
+    }
+
+    // Binds the top of the stack to a variable, to mutate it.
+    fn bind_top(&mut self) -> Result<String> {
+        let top = self.top()?.clone();
+        let name = self.bind(top);
+        *self.top()? = Expr::Var(name.clone());
+        Ok(name)
+    }
+
+    fn drop_expr(&mut self, expr: Expr) {
+        if let Expr::Call(code) = expr {
+            self.lines.push(code);
+        }
+    }
+
+    // The expression for a global, with the import that it needs.
+    fn global(&mut self, module: &str, name: &str) -> String {
+        let is_dotted_name = |s: &str| {
+            s.split('.').all(|part| {
+                let mut chars = part.chars();
+                chars
+                    .next()
+                    .is_some_and(|ch| ch.is_alphabetic() || ch == '_')
+                    && chars.all(|ch| ch.is_alphanumeric() || ch == '_')
+            })
+        };
+        if !is_dotted_name(module) || !is_dotted_name(name) {
+            self.import("import importlib".into());
+            return format!(
+                "getattr(importlib.import_module({}), {})",
+                Expr::Str(module.into()).render(),
+                Expr::Str(name.into()).render()
+            );
+        }
+        let (first, rest) = match name.split_once('.') {
+            Some((first, rest)) => (first, format!(".{}", rest)),
+            None => (name, String::new()),
+        };
+        // The Python 2 modules that `pickle` maps.
+        let module = match module {
+            "__builtin__" => "builtins",
+            "copy_reg" => "copyreg",
+            module => module,
+        };
+        let mut local = first.to_string();
+        let mut n = 1;
+        loop {
+            match self.names.get(&local) {
+                Some(other) if other != module => {
+                    local = format!("{}_{}", first, n);
+                    n += 1;
+                }
+                Some(_) => return format!("{}{}", local, rest),
+                None => break,
+            }
+        }
+        // Builtins take their name too, so that an import can't shadow one;
+        // they need an import only to be renamed.
+        self.names.insert(local.clone(), module.into());
+        if local != first {
+            self.import(format!("from {} import {} as {}", module, first, local));
+        } else if module != "builtins" {
+            self.import(format!("from {} import {}", module, first));
+        }
+        format!("{}{}", local, rest)
+    }
+
+    fn import(&mut self, import: String) {
+        if !self.imports.contains(&import) {
+            self.imports.push(import);
+        }
+    }
+
+    fn op(&mut self, op: Opcode) -> Result<()> {
+        let expr = match op {
+            Opcode::Proto(_) | Opcode::Frame(_) => return Ok(()),
+            Opcode::Mark => Expr::Mark,
+            Opcode::Stop => {
+                let result = self.pop()?.render();
+                self.lines.push(format!("result = {}", result));
+                return Ok(());
+            }
+            Opcode::Pop => {
+                if let Some(Expr::Mark) = self.stack.last() {
+                    self.stack.pop();
+                } else {
+                    let expr = self.pop()?;
+                    self.drop_expr(expr);
+                }
+                return Ok(());
+            }
+            Opcode::PopMark => {
+                for expr in self.pop_mark()? {
+                    self.drop_expr(expr);
+                }
+                return Ok(());
+            }
+            Opcode::Dup => Expr::Var(self.bind_top()?),
+
+            Opcode::None => Expr::Literal("None".into()),
+            Opcode::NewTrue | Opcode::Int(IntArg::Bool(true)) => Expr::Literal("True".into()),
+            Opcode::NewFalse | Opcode::Int(IntArg::Bool(false)) => Expr::Literal("False".into()),
+            Opcode::Int(IntArg::Int(i)) => Expr::Literal(i.to_string()),
+            Opcode::BinInt(i) => Expr::Literal(i.to_string()),
+            Opcode::BinInt1(i) => Expr::Literal(i.to_string()),
+            Opcode::BinInt2(i) => Expr::Literal(i.to_string()),
+            Opcode::Long(i) | Opcode::Long1(i) | Opcode::Long4(i) => Expr::Literal(i.to_string()),
+            Opcode::Float(f) | Opcode::BinFloat(f) => {
+                Expr::Literal(Value::F64(F64Wrapper(f)).to_string())
+            }
+            Opcode::String(b) | Opcode::BinString(b) | Opcode::ShortBinString(b) => {
+                Expr::Str(b.iter().map(|&b| b as char).collect())
+            }
+            Opcode::Unicode(s)
+            | Opcode::BinUnicode(s)
+            | Opcode::ShortBinUnicode(s)
+            | Opcode::BinUnicode8(s) => Expr::Str(s.into_owned()),
+            Opcode::BinBytes(b) | Opcode::ShortBinBytes(b) | Opcode::BinBytes8(b) => {
+                Expr::Literal(Value::Bytes(b).to_string())
+            }
+            Opcode::ByteArray8(b) => Expr::Code(format!("bytearray({})", Value::Bytes(b))),
+            Opcode::NextBuffer => Expr::Call("next(buffers)".into()),
+            Opcode::ReadonlyBuffer => {
+                let buffer = self.pop()?.render();
+                Expr::Call(format!("memoryview({}).toreadonly()", buffer))
+            }
+
+            Opcode::EmptyList => Expr::List(Vec::new()),
+            Opcode::EmptyTuple => Expr::Tuple(Vec::new()),
+            Opcode::EmptyDict => Expr::Dict(Vec::new()),
+            Opcode::EmptySet => Expr::Set(Vec::new()),
+            Opcode::List => Expr::List(self.pop_mark()?),
+            Opcode::Tuple => Expr::Tuple(self.pop_mark()?),
+            Opcode::Tuple1 | Opcode::Tuple2 | Opcode::Tuple3 => {
+                let n = match op {
+                    Opcode::Tuple1 => 1,
+                    Opcode::Tuple2 => 2,
+                    _ => 3,
+                };
+                let mut items = (0..n).map(|_| self.pop()).collect::<Result<Vec<_>>>()?;
+                items.reverse();
+                Expr::Tuple(items)
+            }
+            Opcode::Dict => {
+                let items = self.pop_mark()?;
+                Expr::Dict(pairs(items))
+            }
+            Opcode::FrozenSet => {
+                let items = self.pop_mark()?;
+                match items.is_empty() {
+                    true => Expr::Code("frozenset()".into()),
+                    false => Expr::Code(format!("frozenset({})", Expr::Set(items).render())),
+                }
+            }
+
+            Opcode::Append => {
+                let item = self.pop()?;
+                return self.append(vec![item], false);
+            }
+            Opcode::Appends => {
+                let items = self.pop_mark()?;
+                return self.append(items, true);
+            }
+            Opcode::SetItem => {
+                let value = self.pop()?;
+                let key = self.pop()?;
+                return self.set_items(vec![(key, value)]);
+            }
+            Opcode::SetItems => {
+                let items = self.pop_mark()?;
+                return self.set_items(pairs(items));
+            }
+            Opcode::AddItems => {
+                let items = self.pop_mark()?;
+                if let Expr::Set(set) = self.top()? {
+                    set.extend(items);
+                } else {
+                    let name = self.bind_top()?;
+                    for item in items {
+                        self.lines.push(format!("{}.add({})", name, item.render()));
+                    }
+                }
+                return Ok(());
+            }
+            Opcode::Build => {
+                let state = self.pop()?;
+                let name = self.bind_top()?;
+                return self.build(name, state);
+            }
+
+            Opcode::Global { module, name } => Expr::Literal(self.global(&module, &name)),
+            Opcode::StackGlobal => {
+                let name = self.pop()?;
+                let module = self.pop()?;
+                match (module, name) {
+                    (Expr::Str(module), Expr::Str(name)) => {
+                        Expr::Literal(self.global(&module, &name))
+                    }
+                    (_, other) => {
+                        let code = ErrorCode::InvalidStackTop("string", other.render());
+                        return self.error(code);
+                    }
+                }
+            }
+            Opcode::Ext1(_) | Opcode::Ext2(_) | Opcode::Ext4(_) => {
+                let code = match op {
+                    Opcode::Ext1(code) => code.to_string(),
+                    Opcode::Ext2(code) => code.to_string(),
+                    Opcode::Ext4(code) => code.to_string(),
+                    _ => unreachable!(),
+                };
+                self.import("import copyreg".into());
+                self.import("import importlib".into());
+                Expr::Call(format!(
+                    "getattr(importlib.import_module(copyreg._inverted_registry[{0}][0]), \
+                     copyreg._inverted_registry[{0}][1])",
+                    code
+                ))
+            }
+            Opcode::Reduce => {
+                let args = self.pop()?;
+                let callable = self.pop()?.render();
+                Expr::Call(format!("{}({})", callable, args.args()))
+            }
+            Opcode::NewObj => {
+                let args = self.pop()?;
+                let cls = self.pop()?.render();
+                let args = match args.args() {
+                    args if args.is_empty() => args,
+                    args => format!(", {}", args),
+                };
+                Expr::Call(format!("{0}.__new__({0}{1})", cls, args))
+            }
+            Opcode::NewObjEx => {
+                let kwargs = self.pop()?.render();
+                let args = self.pop()?;
+                let cls = self.pop()?.render();
+                let args = match args.args() {
+                    args if args.is_empty() => args,
+                    args => format!(", {}", args),
+                };
+                Expr::Call(format!("{0}.__new__({0}{1}, **{2})", cls, args, kwargs))
+            }
+            Opcode::Obj => {
+                let mut items = self.pop_mark()?;
+                if items.is_empty() {
+                    return self.error(ErrorCode::StackUnderflow);
+                }
+                let cls = items.remove(0).render();
+                Expr::Call(format!("{}({})", cls, render_all(&items)))
+            }
+            Opcode::Inst { module, name } => {
+                let items = self.pop_mark()?;
+                let cls = self.global(&module, &name);
+                Expr::Call(format!("{}({})", cls, render_all(&items)))
+            }
+            Opcode::PersId(id) => {
+                let id = Expr::Str(id.iter().map(|&b| b as char).collect()).render();
+                Expr::Call(format!("persistent_load({})", id))
+            }
+            Opcode::BinPersId => {
+                let id = self.pop()?.render();
+                Expr::Call(format!("persistent_load({})", id))
+            }
+
+            Opcode::Put(id) | Opcode::LongBinPut(id) => return self.memoize(id),
+            Opcode::BinPut(id) => return self.memoize(id.into()),
+            Opcode::Memoize => return self.memoize(self.memo.len() as u32),
+            Opcode::Get(id) | Opcode::LongBinGet(id) => self.fetch(id)?,
+            Opcode::BinGet(id) => self.fetch(id.into())?,
+        };
+        self.stack.push(expr);
+        Ok(())
+    }
+
+    fn memoize(&mut self, id: u32) -> Result<()> {
+        let top = self.top()?.clone();
+        let expr = match top {
+            Expr::Literal(_) | Expr::Str(_) | Expr::Var(_) => Some(top),
+            _ if self.fetched.contains(&id) => Some(Expr::Var(self.bind_top()?)),
+            // The entry still counts for `MEMOIZE`.
+            _ => None,
+        };
+        self.memo.insert(id, expr);
+        Ok(())
+    }
+
+    fn fetch(&self, id: u32) -> Result<Expr> {
+        match self.memo.get(&id) {
+            Some(Some(expr)) => Ok(expr.clone()),
+            _ => self.error(ErrorCode::MissingMemo(id)),
+        }
+    }
+
+    fn append(&mut self, items: Vec<Expr>, extend: bool) -> Result<()> {
+        if let Expr::List(list) = self.top()? {
+            list.extend(items);
+            return Ok(());
+        }
+        let name = self.bind_top()?;
+        let line = match extend {
+            true => format!("{}.extend([{}])", name, render_all(&items)),
+            false => format!("{}.append({})", name, items[0].render()),
+        };
+        self.lines.push(line);
+        Ok(())
+    }
+
+    fn set_items(&mut self, items: Vec<(Expr, Expr)>) -> Result<()> {
+        if let Expr::Dict(dict) = self.top()? {
+            dict.extend(items);
+            return Ok(());
+        }
+        let name = self.bind_top()?;
+        for (key, value) in items {
+            let line = format!("{}[{}] = {}", name, key.render(), value.render());
+            self.lines.push(line);
+        }
+        Ok(())
+    }
+
+    // A dict state updates `__dict__`, and a `(dict or None, dict)` pair
+    // also sets the slots, as `pickle` does without `__setstate__`.
+    fn build(&mut self, name: String, state: Expr) -> Result<()> {
+        match state {
+            Expr::Dict(_) => {
+                let line = format!("{}.__dict__.update({})", name, state.render());
+                self.lines.push(line);
+            }
+            Expr::Tuple(items) if is_slot_state(&items) => {
+                if let Expr::Dict(_) = items[0] {
+                    let line = format!("{}.__dict__.update({})", name, items[0].render());
+                    self.lines.push(line);
+                }
+                if let Expr::Dict(slots) = &items[1] {
+                    for (key, value) in slots {
+                        let line =
+                            format!("setattr({}, {}, {})", name, key.render(), value.render());
+                        self.lines.push(line);
+                    }
+                }
+            }
+            state => {
+                let line = format!("{}.__setstate__({})", name, state.render());
+                self.lines.push(line);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_slot_state(items: &[Expr]) -> bool {
+    match items {
+        [Expr::Dict(_), Expr::Dict(_)] => true,
+        [Expr::Literal(none), Expr::Dict(_)] => none == "None",
+        _ => false,
+    }
+}
+
+fn pairs(items: Vec<Expr>) -> Vec<(Expr, Expr)> {
+    let mut iter = items.into_iter();
+    let mut pairs = Vec::new();
+    while let (Some(key), Some(value)) = (iter.next(), iter.next()) {
+        pairs.push((key, value));
+    }
+    pairs
+}
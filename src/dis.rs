@@ -0,0 +1,309 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+use crate::error::Result;
+use crate::ops::{ops, IntArg, Opcode};
+use crate::value::float_repr;
+use crate::{Error, ErrorCode, Value};
+
+pub struct DisOptions {
+    indent: usize,
+    stack_depth: bool,
+    error_offset: Option<usize>,
+}
+
+impl DisOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of blanks by which to indent each MARK level (default 4).
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    /// Append the depth of the stack after each opcode, counting marks.
+    pub fn stack_depth(mut self, stack_depth: bool) -> Self {
+        self.stack_depth = stack_depth;
+        self
+    }
+
+    /// Flag the opcode that was being executed when the unpickler failed
+    /// with an `Error::Eval` at `offset`, i.e. the one whose bytes end at or
+    /// span it.
+    pub fn error_offset(mut self, offset: usize) -> Self {
+        self.error_offset = Some(offset);
+        self
+    }
+}
+
+impl Default for DisOptions {
+    fn default() -> Self {
+        Self {
+            indent: 4,
+            stack_depth: false,
+            error_offset: None,
+        }
+    }
+}
+
+// An entry of the emulated stack.  Renaming globals tracks strings by the
+// index of the opcode that pushed them; everything else is an item.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Slot {
+    Mark,
+    Item,
+    String(usize),
+}
+
+// What an opcode did to the emulated stack besides its items.
+#[derive(Default)]
+pub(crate) struct Effect {
+    // It popped the stack down to the topmost mark.
+    pub(crate) popped_mark: bool,
+    // It takes a mark, or more items, than the stack holds.
+    pub(crate) missing_mark: bool,
+    pub(crate) missing_items: bool,
+}
+
+// Applies the stack effect of `op` to `stack`.  The opcodes that take a
+// mark, and `POP` of a mark, pop down to the topmost mark first.  Whatever
+// is missing is reported and popped as if it were there.
+pub(crate) fn emulate(stack: &mut Vec<Slot>, op: &Opcode) -> Effect {
+    let (uses_mark, mut pops, pushes) = stack_effect(op);
+    let mut effect = Effect::default();
+    if uses_mark || (*op == Opcode::Pop && stack.last() == Some(&Slot::Mark)) {
+        match stack.iter().rposition(|&slot| slot == Slot::Mark) {
+            Some(mark) => {
+                stack.truncate(mark);
+                effect.popped_mark = true;
+            }
+            None => {
+                stack.clear();
+                effect.missing_mark = true;
+            }
+        }
+        if !uses_mark {
+            pops = 0;
+        }
+    }
+    effect.missing_items = stack.len() < pops;
+    stack.truncate(stack.len().saturating_sub(pops));
+    if *op == Opcode::Mark {
+        stack.push(Slot::Mark);
+    }
+    stack.extend(std::iter::repeat_n(Slot::Item, pushes));
+    effect
+}
+
+/// Writes a symbolic disassembly of the pickle in `reader` to `out`, in the
+/// layout of Python's `pickletools.dis`: one line per opcode with its
+/// offset, code, name and argument, indented by MARK nesting, followed by
+/// the highest protocol among the opcodes.
+///
+/// Like `pickletools.dis`, the stack and memo are emulated to check the
+/// pickle.  On the first inconsistency the offending line is still written,
+/// and an `Error::Eval` at its offset is returned.
+pub fn dis<R: Read, W: Write>(reader: R, mut out: W, options: DisOptions) -> Result<()> {
+    let indent = " ".repeat(options.indent);
+    let mut stack = Vec::new();
+    let mut memo = HashSet::new();
+    let mut marks = Vec::new();
+    let mut max_proto = 0;
+    let mut ops = ops(reader);
+    while let Some(op) = ops.next() {
+        let (pos, op) = op?;
+        let end = ops.position();
+        let mut line = format!(
+            "{:5}: {:<4} {}{}",
+            pos,
+            code_repr(op.code()),
+            indent.repeat(marks.len()),
+            op.name()
+        );
+        max_proto = max_proto.max(op.proto());
+
+        // Emulate the stack effects.
+        let mut error = None;
+        let mut mark_msg = None;
+        let effect = emulate(&mut stack, &op);
+        if effect.popped_mark {
+            mark_msg = marks
+                .pop()
+                .map(|mark_pos| format!("(MARK at {})", mark_pos));
+        }
+        if effect.missing_mark {
+            mark_msg = Some("no MARK exists on stack".into());
+        }
+        if effect.missing_mark || effect.missing_items {
+            error = Some(ErrorCode::StackUnderflow);
+        }
+        if op == Opcode::Mark {
+            marks.push(pos);
+        }
+
+        let memo_id = match op {
+            Opcode::Put(id) | Opcode::LongBinPut(id) => Some(id),
+            Opcode::BinPut(id) => Some(id.into()),
+            Opcode::Memoize => {
+                mark_msg = Some(format!("(as {})", memo.len()));
+                Some(memo.len() as u32)
+            }
+            _ => None,
+        };
+        if let Some(id) = memo_id {
+            if memo.contains(&id) {
+                error = Some(ErrorCode::InvalidValue(format!(
+                    "memo key {} already defined",
+                    id
+                )));
+            } else if stack.last() != Some(&Slot::Item) {
+                error = Some(ErrorCode::InvalidValue(
+                    "can't store markobject or empty stack in the memo".into(),
+                ));
+            } else {
+                memo.insert(id);
+            }
+        }
+        if let Opcode::Get(id) | Opcode::LongBinGet(id) = op {
+            if !memo.contains(&id) {
+                error = Some(ErrorCode::MissingMemo(id));
+            }
+        }
+        if let Opcode::BinGet(id) = op {
+            if !memo.contains(&id.into()) {
+                error = Some(ErrorCode::MissingMemo(id.into()));
+            }
+        }
+
+        let arg = arg_repr(&op);
+        if arg.is_some() || mark_msg.is_some() {
+            line += &" ".repeat(10usize.saturating_sub(op.name().len()));
+            for part in [arg, mark_msg].into_iter().flatten() {
+                line.push(' ');
+                line += &part;
+            }
+        }
+
+        if op == Opcode::Stop && error.is_none() && !stack.is_empty() {
+            error = Some(ErrorCode::InvalidValue("stack not empty after STOP".into()));
+        }
+
+        if options.stack_depth {
+            line += &format!("  [depth {}]", stack.len());
+        }
+        if let Some(offset) = options.error_offset {
+            if pos < offset && offset <= end {
+                line += "  <== error";
+            }
+        }
+        writeln!(out, "{}", line)?;
+        if let Some(code) = error {
+            return Err(Error::eval(code, pos));
+        }
+    }
+    writeln!(out, "highest protocol among opcodes = {}", max_proto)?;
+    Ok(())
+}
+
+// Python's repr of the code as a one-character string, without quotes.
+fn code_repr(code: u8) -> String {
+    match code {
+        0x20..=0x7e => (code as char).to_string(),
+        code => format!("\\x{:02x}", code),
+    }
+}
+
+// Whether an opcode takes the items down to the topmost MARK, and how many
+// items it pops (below the MARK if it takes one) and pushes.
+pub(crate) fn stack_effect(op: &Opcode) -> (bool, usize, usize) {
+    match op {
+        Opcode::Mark | Opcode::Proto(_) | Opcode::Frame(_) => (false, 0, 0),
+        Opcode::Put(_) | Opcode::BinPut(_) | Opcode::LongBinPut(_) | Opcode::Memoize => {
+            (false, 0, 0)
+        }
+        Opcode::Stop | Opcode::Pop => (false, 1, 0),
+        Opcode::PopMark => (true, 0, 0),
+        Opcode::Dup => (false, 1, 2),
+        Opcode::Appends | Opcode::SetItems | Opcode::AddItems => (true, 1, 1),
+        Opcode::List
+        | Opcode::Tuple
+        | Opcode::Dict
+        | Opcode::FrozenSet
+        | Opcode::Inst { .. }
+        | Opcode::Obj => (true, 0, 1),
+        Opcode::BinPersId | Opcode::Tuple1 | Opcode::ReadonlyBuffer => (false, 1, 1),
+        Opcode::Append
+        | Opcode::Reduce
+        | Opcode::Build
+        | Opcode::StackGlobal
+        | Opcode::NewObj
+        | Opcode::Tuple2 => (false, 2, 1),
+        Opcode::SetItem | Opcode::NewObjEx | Opcode::Tuple3 => (false, 3, 1),
+        // Everything else pushes one value.
+        _ => (false, 0, 1),
+    }
+}
+
+// The argument as `pickletools.dis` shows it, i.e. as Python's repr of the
+// value that `genops` decodes.
+fn arg_repr(op: &Opcode) -> Option<String> {
+    let latin1 = |bytes: &[u8]| -> String {
+        let string = bytes.iter().map(|&b| b as char).collect::<String>();
+        Value::String(string.into()).to_string()
+    };
+    let arg = match op {
+        Opcode::Float(f) | Opcode::BinFloat(f) => float_repr(*f),
+        Opcode::Int(IntArg::Int(i)) => i.to_string(),
+        Opcode::Int(IntArg::Bool(b)) => Value::Bool(*b).to_string(),
+        Opcode::BinInt(i) | Opcode::Ext4(i) => i.to_string(),
+        Opcode::BinInt1(i) | Opcode::BinGet(i) | Opcode::BinPut(i) | Opcode::Ext1(i) => {
+            i.to_string()
+        }
+        Opcode::BinInt2(i) | Opcode::Ext2(i) => i.to_string(),
+        Opcode::Proto(p) => p.to_string(),
+        Opcode::Get(i) | Opcode::LongBinGet(i) | Opcode::Put(i) | Opcode::LongBinPut(i) => {
+            i.to_string()
+        }
+        Opcode::Frame(n) => n.to_string(),
+        Opcode::Long(i) | Opcode::Long1(i) | Opcode::Long4(i) => i.to_string(),
+        Opcode::PersId(b)
+        | Opcode::String(b)
+        | Opcode::BinString(b)
+        | Opcode::ShortBinString(b) => latin1(b),
+        Opcode::Unicode(s)
+        | Opcode::BinUnicode(s)
+        | Opcode::ShortBinUnicode(s)
+        | Opcode::BinUnicode8(s) => Value::String(s.as_ref().into()).to_string(),
+        Opcode::BinBytes(b) | Opcode::ShortBinBytes(b) | Opcode::BinBytes8(b) => {
+            Value::Bytes(b.as_ref().into()).to_string()
+        }
+        Opcode::ByteArray8(b) => format!("bytearray({})", Value::Bytes(b.as_ref().into())),
+        Opcode::Global { module, name } | Opcode::Inst { module, name } => {
+            Value::String(format!("{} {}", module, name).into()).to_string()
+        }
+        _ => return None,
+    };
+    Some(arg)
+}
@@ -25,6 +25,8 @@ use std::num::ParseIntError;
 use std::result;
 use std::string::FromUtf8Error;
 
+use crate::Path;
+
 #[derive(Clone, PartialEq, Debug)]
 pub enum ErrorCode {
     /// Unsupported opcode
@@ -59,6 +61,9 @@ pub enum ErrorCode {
     InvalidValue(String),
     /// Structure deserialization error (e.g., unknown variant)
     Structure(String),
+    /// Value of the wrong type for a Rust type, at the path, expected the
+    /// first type and got the second
+    InvalidType(Path<'static>, &'static str, &'static str),
 }
 
 impl fmt::Display for ErrorCode {
@@ -89,22 +94,201 @@ impl fmt::Display for ErrorCode {
             ErrorCode::TrailingBytes => write!(fmt, "trailing bytes found"),
             ErrorCode::InvalidValue(ref s) => write!(fmt, "invalid value: {}", s),
             ErrorCode::Structure(ref s) => fmt.write_str(s),
+            ErrorCode::InvalidType(ref path, expected, got) if path.is_empty() => {
+                write!(fmt, "invalid type, expected {}, got {}", expected, got)
+            }
+            ErrorCode::InvalidType(ref path, expected, got) => {
+                write!(
+                    fmt,
+                    "invalid type at {}, expected {}, got {}",
+                    path, expected, got
+                )
+            }
         }
     }
 }
 
+/// The state of the unpickler when it failed, see `Error::Eval`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EvalContext {
+    /// The offset of the opcode that failed.
+    pub start: usize,
+    /// The byte of the opcode, unless the input ended before it.
+    pub code: Option<u8>,
+    /// The name of the opcode, unless it is unknown.
+    pub name: Option<&'static str>,
+    /// The number of open MARKs.
+    pub mark_depth: usize,
+    /// The number of stack entries above the topmost MARK.
+    pub stack_len: usize,
+    /// Python reprs of the topmost of these entries, shortened, with the
+    /// topmost last.
+    pub stack: Vec<String>,
+    /// The memo id that the opcode stores or fetches.
+    pub memo_id: Option<u32>,
+    /// The module and name of the global that the opcode pushes or, for an
+    /// opcode that calls or builds an object, of the last global pushed.
+    pub global: Option<(String, String)>,
+}
+
 /// This type represents all possible errors that can occur when serializing or
 /// deserializing a value.
 #[derive(Debug)]
 pub enum Error {
     /// Some IO error occurred when serializing or deserializing a value.
     Io(io::Error),
-    /// The pickle had some error while interpreting.
-    Eval(ErrorCode, usize),
+    /// The pickle had some error while interpreting, at the given offset.
+    /// Errors of the `Unpickler` come with the state it was in.
+    Eval {
+        code: ErrorCode,
+        pos: usize,
+        context: Option<Box<EvalContext>>,
+    },
     /// Syntax error while transforming into Rust values.
     Syntax(ErrorCode),
 }
 
+impl Error {
+    /// An `Error::Eval` without context.
+    pub fn eval(code: ErrorCode, pos: usize) -> Self {
+        Error::Eval {
+            code,
+            pos,
+            context: None,
+        }
+    }
+
+    /// The state of the unpickler when it failed, if known.
+    pub fn context(&self) -> Option<&EvalContext> {
+        match self {
+            Error::Eval { context, .. } => context.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Renders the error for a log: the message, the state of the unpickler,
+    /// and a hex dump of the bytes of `data` around the offset, with the
+    /// failing opcode marked.
+    pub fn report(&self, data: &[u8]) -> String {
+        let mut out = format!("error: {}\n", self);
+        let (pos, context) = match self {
+            Error::Eval { pos, context, .. } => (*pos, context.as_deref()),
+            _ => return out,
+        };
+        let mut marked = pos..pos + 1;
+        if let Some(context) = context {
+            let opcode = match (context.name, context.code) {
+                (Some(name), Some(code)) => format!("{} (0x{:02x})", name, code),
+                (None, Some(code)) => format!("unknown (0x{:02x})", code),
+                _ => "none, the input ended".into(),
+            };
+            out += &format!("  opcode: {} at offset {}\n", opcode, context.start);
+            out += &format!("  mark depth: {}\n", context.mark_depth);
+            let entries = match context.stack_len {
+                1 => "1 entry".to_string(),
+                n => format!("{} entries", n),
+            };
+            let above = if context.mark_depth > 0 {
+                " above the mark"
+            } else {
+                ""
+            };
+            out += &format!("  stack: {}{}", entries, above);
+            if !context.stack.is_empty() {
+                out += &format!(", topmost last: {}", context.stack.join(", "));
+            }
+            out.push('\n');
+            if let Some(id) = context.memo_id {
+                out += &format!("  memo id: {}\n", id);
+            }
+            if let Some((module, name)) = &context.global {
+                out += &format!("  global: {}.{}\n", module, name);
+            }
+            marked = context.start..pos.max(context.start + 1);
+        }
+        out.push('\n');
+        hex_excerpt(&mut out, data, marked);
+        out
+    }
+}
+
+// Bytes per line of a hex excerpt, and lines shown before and after the
+// marked ones.
+const HEX_WIDTH: usize = 16;
+const HEX_CONTEXT: usize = 1;
+
+// Writes the lines of a hex dump of `data` around `marked`, in the layout of
+// `hexdump -C`, each followed by a line that marks the bytes in `marked`.
+fn hex_excerpt(out: &mut String, data: &[u8], marked: std::ops::Range<usize>) {
+    let first = (marked.start / HEX_WIDTH).saturating_sub(HEX_CONTEXT);
+    let last = (marked.end.saturating_sub(1) / HEX_WIDTH) + HEX_CONTEXT;
+    for line in first..=last {
+        let offset = line * HEX_WIDTH;
+        if offset >= data.len() && offset > marked.start {
+            break;
+        }
+        let bytes = data.get(offset..).unwrap_or_default();
+        let bytes = &bytes[..bytes.len().min(HEX_WIDTH)];
+        let mut hex = String::new();
+        let mut marks = String::new();
+        for i in 0..HEX_WIDTH {
+            let sep = if i == HEX_WIDTH / 2 { "  " } else { " " };
+            hex += sep;
+            marks += sep;
+            match bytes.get(i) {
+                Some(byte) => hex += &format!("{:02x}", byte),
+                None => hex += "  ",
+            }
+            marks += if marked.contains(&(offset + i)) {
+                "^^"
+            } else {
+                "  "
+            };
+        }
+        let ascii = bytes
+            .iter()
+            .map(|&b| {
+                if (0x20..0x7f).contains(&b) {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        *out += &format!("  {:08x} {}  |{}|\n", offset, hex, ascii);
+        if marks.contains('^') {
+            *out += &format!("           {}\n", marks.trim_end());
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(fmt, "I/O error: {}", err),
+            Error::Eval { code, pos, context } => {
+                write!(fmt, "{} at offset {}", code, pos)?;
+                match context.as_deref() {
+                    Some(EvalContext {
+                        name: Some(name), ..
+                    }) => write!(fmt, " in {}", name),
+                    _ => Ok(()),
+                }
+            }
+            Error::Syntax(code) => write!(fmt, "{}", code),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(error: io::Error) -> Error {
         Error::Io(error)
@@ -0,0 +1,611 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::error::Result;
+use crate::ops::Opcode;
+use crate::pickle::Step;
+use crate::read::Source;
+use crate::{Error, ErrorCode, MemoId, Unpickler, Value};
+
+/// Identifies a container within one event stream.
+pub type ContainerId = usize;
+
+/// Receives the events produced by `Unpickler::stream_events`.
+///
+/// Pickles build containers bottom-up, so a container is started when it is
+/// created and its contents are reported as they are added to it.  Each
+/// `item` and `dict_key` call announces where the following value(s) go:
+/// one value for `item`, a key and then a value for `dict_key`.  A value is
+/// reported as `scalar`, `global`, or `reference` to an already started
+/// container.  The value following `result` is the unpickled object.
+///
+/// A container ends once it is taken off the stack.  Containers shared
+/// through the memo are started once and referenced by id thereafter.
+/// Adding to a container that has ended, as recursive pickles do, is an
+/// error.
+///
+/// Objects built by `REDUCE`, `NEWOBJ`, `NEWOBJ_EX`, `OBJ` and `INST` are
+/// reported as object containers whose items are the callable or class, the
+/// argument tuple (and keyword arguments for `NEWOBJ_EX`), and the state
+/// passed to `BUILD`, if any.  Only `_codecs.encode` and `bytearray` calls
+/// with scalar arguments are reported as the bytes they produce.
+#[allow(unused_variables)]
+pub trait EventHandler<'de> {
+    fn start_list(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_list(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    fn start_tuple(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_tuple(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    fn start_dict(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_dict(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    fn start_set(&mut self, id: ContainerId, frozen: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_set(&mut self, id: ContainerId, frozen: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn start_object(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_object(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    fn start_persistent_id(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_persistent_id(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    /// The next value is an item of the list, tuple, set or object `id`.
+    fn item(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    /// The next two values are a key and its value in the dict `id`.
+    fn dict_key(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    fn scalar(&mut self, value: Value<'de>) -> Result<()> {
+        Ok(())
+    }
+
+    fn global(&mut self, module: &str, name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn reference(&mut self, id: ContainerId) -> Result<()> {
+        Ok(())
+    }
+
+    /// The next value is the unpickled object.
+    fn result(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    List,
+    Tuple,
+    Dict,
+    Set,
+    FrozenSet,
+    Object,
+    PersistentId,
+}
+
+// A stack entry.  Containers only live in the handler; the stack just holds
+// their ids.  Tuples are immutable and often only feed into a REDUCE, so
+// they are reported lazily, once they are put somewhere.
+#[derive(Clone, Debug)]
+enum Node<'de> {
+    Scalar(Value<'de>),
+    Global(String, String),
+    Container(ContainerId, Kind),
+    Tuple(Rc<RefCell<PendingTuple<'de>>>),
+}
+
+#[derive(Debug)]
+struct PendingTuple<'de> {
+    id: ContainerId,
+    // `None` once the tuple has been reported.
+    items: Option<Vec<Node<'de>>>,
+}
+
+struct Events<'h, 'de, H: EventHandler<'de>> {
+    handler: &'h mut H,
+    // Set once the handler returned an error, which is passed on as is.
+    handler_failed: bool,
+    metastack: Vec<Vec<Node<'de>>>,
+    stack: Vec<Node<'de>>,
+    memo: HashMap<MemoId, Node<'de>>,
+    open: HashMap<ContainerId, Kind>,
+    next_id: ContainerId,
+}
+
+impl<'de, S: Source<'de>> Unpickler<'de, S> {
+    /// Decodes a value as a stream of events sent to `handler`, without
+    /// building the value in memory.  Errors returned by `handler` stop
+    /// decoding and are returned unchanged.
+    ///
+    /// Memoized containers are kept as ids only, but memoized scalars have
+    /// to be kept until the end of the input, since they may be fetched
+    /// from the memo again; a pickle with many distinct memoized strings
+    /// holds all of them.
+    pub fn stream_events<H: EventHandler<'de>>(mut self, handler: &mut H) -> Result<()> {
+        let mut events = Events {
+            handler,
+            handler_failed: false,
+            metastack: Vec::new(),
+            stack: Vec::new(),
+            memo: HashMap::new(),
+            open: HashMap::new(),
+            next_id: 0,
+        };
+        loop {
+            let op = self.read_op()?;
+            match events.exec(op) {
+                Ok(true) => break,
+                Ok(false) => {}
+                Err(Error::Syntax(code)) if !events.handler_failed => return self.error(code),
+                Err(err) => return Err(err),
+            }
+        }
+        self.end()
+    }
+}
+
+impl<'h, 'de, H: EventHandler<'de>> Events<'h, 'de, H> {
+    // Execute one opcode; returns true on STOP.  Errors are returned as
+    // `Error::Syntax` and get the position attached by the caller, unless
+    // they come from the handler.
+    fn exec(&mut self, step: Step<'de>) -> Result<bool> {
+        let op = match step {
+            Step::Push(value) => {
+                self.stack.push(Node::Scalar(value));
+                return Ok(false);
+            }
+            Step::Op(op) => op,
+        };
+        match op {
+            Opcode::Stop => {
+                let node = self.pop()?;
+                self.finish(&node)?;
+                let result = self.handler.result();
+                self.handled(result)?;
+                self.report(node)?;
+                return Ok(true);
+            }
+            Opcode::Mark => {
+                let stack = std::mem::take(&mut self.stack);
+                self.metastack.push(stack);
+            }
+            Opcode::Pop => {
+                if self.stack.is_empty() {
+                    self.pop_mark()?;
+                } else {
+                    let node = self.pop()?;
+                    self.finish(&node)?;
+                }
+            }
+            Opcode::PopMark => {
+                for node in self.pop_mark()? {
+                    self.finish(&node)?;
+                }
+            }
+            Opcode::Dup => {
+                let top = self.top()?.clone();
+                self.stack.push(top);
+            }
+
+            Opcode::Put(memo_id) | Opcode::LongBinPut(memo_id) => self.put(memo_id)?,
+            Opcode::BinPut(memo_id) => self.put(memo_id.into())?,
+            Opcode::Memoize => self.put(self.memo.len() as MemoId)?,
+            Opcode::Get(memo_id) | Opcode::LongBinGet(memo_id) => self.get(memo_id)?,
+            Opcode::BinGet(memo_id) => self.get(memo_id.into())?,
+
+            Opcode::EmptyTuple => self.push_tuple(Vec::new()),
+            Opcode::Tuple1 | Opcode::Tuple2 | Opcode::Tuple3 => {
+                let n = match op {
+                    Opcode::Tuple1 => 1,
+                    Opcode::Tuple2 => 2,
+                    _ => 3,
+                };
+                if self.stack.len() < n {
+                    return Err(Error::Syntax(ErrorCode::StackUnderflow));
+                }
+                let items = self.stack.split_off(self.stack.len() - n);
+                self.push_tuple(items);
+            }
+            Opcode::Tuple => {
+                let items = self.pop_mark()?;
+                self.push_tuple(items);
+            }
+
+            Opcode::EmptyList => self.push_container(Kind::List, Vec::new())?,
+            Opcode::List => {
+                let items = self.pop_mark()?;
+                self.push_container(Kind::List, items)?;
+            }
+            Opcode::Append => {
+                let item = self.pop()?;
+                let id = self.top_container("list", &[Kind::List])?;
+                self.add_items(id, vec![item])?;
+            }
+            Opcode::Appends => {
+                let items = self.pop_mark()?;
+                let id = self.top_container("list", &[Kind::List])?;
+                self.add_items(id, items)?;
+            }
+
+            Opcode::EmptyDict => self.push_container(Kind::Dict, Vec::new())?,
+            Opcode::Dict => {
+                let items = self.pop_mark()?;
+                self.push_container(Kind::Dict, items)?;
+            }
+            Opcode::SetItem => {
+                let value = self.pop()?;
+                let key = self.pop()?;
+                let id = self.top_container("dict", &[Kind::Dict])?;
+                self.add_pairs(id, vec![key, value])?;
+            }
+            Opcode::SetItems => {
+                let items = self.pop_mark()?;
+                let id = self.top_container("dict", &[Kind::Dict])?;
+                self.add_pairs(id, items)?;
+            }
+
+            Opcode::EmptySet => self.push_container(Kind::Set, Vec::new())?,
+            Opcode::FrozenSet => {
+                let items = self.pop_mark()?;
+                self.push_container(Kind::FrozenSet, items)?;
+            }
+            Opcode::AddItems => {
+                let items = self.pop_mark()?;
+                let id = self.top_container("set", &[Kind::Set])?;
+                self.add_items(id, items)?;
+            }
+
+            Opcode::Global { module, name } => {
+                let global = Node::Global(module.into_owned(), name.into_owned());
+                self.stack.push(global);
+            }
+            Opcode::StackGlobal => {
+                let globname = self.pop_string()?;
+                let modname = self.pop_string()?;
+                self.stack.push(Node::Global(modname, globname));
+            }
+            Opcode::Reduce => {
+                let args = self.pop()?;
+                let callable = self.pop()?;
+                match Self::reduce_to_bytes(&callable, &args) {
+                    Some(bytes) => self.stack.push(Node::Scalar(Value::Bytes(bytes))),
+                    None => self.push_container(Kind::Object, vec![callable, args])?,
+                }
+            }
+            Opcode::Inst { module, name } => {
+                let args = self.pop_mark()?;
+                let class = Node::Global(module.into_owned(), name.into_owned());
+                let args = self.new_tuple(args);
+                self.push_container(Kind::Object, vec![class, args])?;
+            }
+            Opcode::Obj => {
+                let mut args = self.pop_mark()?;
+                if args.is_empty() {
+                    return Err(Error::Syntax(ErrorCode::StackUnderflow));
+                }
+                let class = args.remove(0);
+                let args = self.new_tuple(args);
+                self.push_container(Kind::Object, vec![class, args])?;
+            }
+            Opcode::NewObj => {
+                let args = self.pop()?;
+                let class = self.pop()?;
+                self.push_container(Kind::Object, vec![class, args])?;
+            }
+            Opcode::NewObjEx => {
+                let kwargs = self.pop()?;
+                let args = self.pop()?;
+                let class = self.pop()?;
+                self.push_container(Kind::Object, vec![class, args, kwargs])?;
+            }
+            Opcode::Build => {
+                let state = self.pop()?;
+                let id = self.top_container("object", &[Kind::Object])?;
+                self.add_items(id, vec![state])?;
+            }
+
+            Opcode::PersId(line) => {
+                let pid = Value::BinPersId(Box::new(Value::Bytes(line)));
+                self.stack.push(Node::Scalar(pid));
+            }
+            Opcode::BinPersId => match self.pop()? {
+                Node::Scalar(value) => {
+                    let pid = Value::BinPersId(Box::new(value));
+                    self.stack.push(Node::Scalar(pid));
+                }
+                other => self.push_container(Kind::PersistentId, vec![other])?,
+            },
+
+            Opcode::ReadonlyBuffer => {
+                self.top()?;
+            }
+
+            // Extensions and out-of-band buffers; scalars are pushed above.
+            op => return Err(Error::Syntax(ErrorCode::Unsupported(op.code() as char))),
+        }
+        Ok(false)
+    }
+
+    fn put(&mut self, memo_id: MemoId) -> Result<()> {
+        let top = self.top()?.clone();
+        self.memo.insert(memo_id, top);
+        Ok(())
+    }
+
+    fn get(&mut self, memo_id: MemoId) -> Result<()> {
+        match self.memo.get(&memo_id) {
+            Some(node) => {
+                self.stack.push(node.clone());
+                Ok(())
+            }
+            None => Err(Error::Syntax(ErrorCode::MissingMemo(memo_id))),
+        }
+    }
+
+    fn pop(&mut self) -> Result<Node<'de>> {
+        match self.stack.pop() {
+            Some(node) => Ok(node),
+            None => Err(Error::Syntax(ErrorCode::StackUnderflow)),
+        }
+    }
+
+    fn pop_mark(&mut self) -> Result<Vec<Node<'de>>> {
+        match self.metastack.pop() {
+            Some(new) => Ok(std::mem::replace(&mut self.stack, new)),
+            None => Err(Error::Syntax(ErrorCode::StackUnderflow)),
+        }
+    }
+
+    fn top(&mut self) -> Result<&Node<'de>> {
+        match self.stack.last() {
+            Some(node) => Ok(node),
+            None => Err(Error::Syntax(ErrorCode::StackUnderflow)),
+        }
+    }
+
+    fn pop_string(&mut self) -> Result<String> {
+        match self.pop()? {
+            Node::Scalar(Value::String(s)) => Ok(s.into_owned()),
+            other => Self::stack_error("string", &other),
+        }
+    }
+
+    // The id of the stack-top container, which must be of one of `kinds`
+    // and not have ended yet.
+    fn top_container(&mut self, what: &'static str, kinds: &[Kind]) -> Result<ContainerId> {
+        let id = match self.top()? {
+            Node::Container(id, kind) if kinds.contains(kind) => *id,
+            other => return Self::stack_error(what, other),
+        };
+        match self.open.contains_key(&id) {
+            true => Ok(id),
+            false => Err(Error::Syntax(ErrorCode::Recursive)),
+        }
+    }
+
+    fn new_tuple(&mut self, items: Vec<Node<'de>>) -> Node<'de> {
+        let id = self.next_id;
+        self.next_id += 1;
+        Node::Tuple(Rc::new(RefCell::new(PendingTuple {
+            id,
+            items: Some(items),
+        })))
+    }
+
+    fn push_tuple(&mut self, items: Vec<Node<'de>>) {
+        let tuple = self.new_tuple(items);
+        self.stack.push(tuple);
+    }
+
+    // Start a new container with the given initial contents, and push it.
+    fn push_container(&mut self, kind: Kind, items: Vec<Node<'de>>) -> Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.start(id, kind)?;
+        self.open.insert(id, kind);
+        if kind == Kind::Dict {
+            self.add_pairs(id, items)?;
+        } else {
+            self.add_items(id, items)?;
+        }
+        self.stack.push(Node::Container(id, kind));
+        Ok(())
+    }
+
+    fn add_items(&mut self, id: ContainerId, items: Vec<Node<'de>>) -> Result<()> {
+        for item in items {
+            Self::check_not_within(id, &item)?;
+            self.finish(&item)?;
+            let result = self.handler.item(id);
+            self.handled(result)?;
+            self.report(item)?;
+        }
+        Ok(())
+    }
+
+    fn add_pairs(&mut self, id: ContainerId, items: Vec<Node<'de>>) -> Result<()> {
+        let mut items = items.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            Self::check_not_within(id, &key)?;
+            Self::check_not_within(id, &value)?;
+            self.finish(&key)?;
+            self.finish(&value)?;
+            let result = self.handler.dict_key(id);
+            self.handled(result)?;
+            self.report(key)?;
+            self.report(value)?;
+        }
+        Ok(())
+    }
+
+    // Called when a node is taken off the stack: ends an open container, and
+    // reports a tuple that hasn't been reported yet.
+    fn finish(&mut self, node: &Node<'de>) -> Result<()> {
+        match node {
+            Node::Container(id, _) => {
+                if let Some(kind) = self.open.remove(id) {
+                    self.end(*id, kind)?;
+                }
+            }
+            Node::Tuple(tuple) => {
+                let (id, items) = {
+                    let mut tuple = tuple.borrow_mut();
+                    (tuple.id, tuple.items.take())
+                };
+                if let Some(items) = items {
+                    self.start(id, Kind::Tuple)?;
+                    self.add_items(id, items)?;
+                    self.end(id, Kind::Tuple)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // A container can't be put into itself.
+    fn check_not_within(id: ContainerId, node: &Node<'de>) -> Result<()> {
+        match node {
+            Node::Container(item_id, _) if *item_id == id => {
+                Err(Error::Syntax(ErrorCode::Recursive))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Passes on the result of a handler call, noting if it failed.
+    fn handled(&mut self, result: Result<()>) -> Result<()> {
+        if result.is_err() {
+            self.handler_failed = true;
+        }
+        result
+    }
+
+    fn report(&mut self, node: Node<'de>) -> Result<()> {
+        let result = match node {
+            Node::Scalar(value) => self.handler.scalar(value),
+            Node::Global(modname, globname) => self.handler.global(&modname, &globname),
+            Node::Container(id, _) => self.handler.reference(id),
+            Node::Tuple(tuple) => {
+                let id = tuple.borrow().id;
+                self.handler.reference(id)
+            }
+        };
+        self.handled(result)
+    }
+
+    fn start(&mut self, id: ContainerId, kind: Kind) -> Result<()> {
+        let result = match kind {
+            Kind::List => self.handler.start_list(id),
+            Kind::Tuple => self.handler.start_tuple(id),
+            Kind::Dict => self.handler.start_dict(id),
+            Kind::Set => self.handler.start_set(id, false),
+            Kind::FrozenSet => self.handler.start_set(id, true),
+            Kind::Object => self.handler.start_object(id),
+            Kind::PersistentId => self.handler.start_persistent_id(id),
+        };
+        self.handled(result)
+    }
+
+    fn end(&mut self, id: ContainerId, kind: Kind) -> Result<()> {
+        let result = match kind {
+            Kind::List => self.handler.end_list(id),
+            Kind::Tuple => self.handler.end_tuple(id),
+            Kind::Dict => self.handler.end_dict(id),
+            Kind::Set => self.handler.end_set(id, false),
+            Kind::FrozenSet => self.handler.end_set(id, true),
+            Kind::Object => self.handler.end_object(id),
+            Kind::PersistentId => self.handler.end_persistent_id(id),
+        };
+        self.handled(result)
+    }
+
+    // Bytes are pickled as `_codecs.encode(str, 'latin1')` before protocol
+    // 3, and bytearrays as `bytearray(...)` before protocol 5.
+    fn reduce_to_bytes(callable: &Node<'de>, args: &Node<'de>) -> Option<Cow<'de, [u8]>> {
+        let (modname, globname) = match callable {
+            Node::Global(modname, globname) => (modname.as_str(), globname.as_str()),
+            _ => return None,
+        };
+        let tuple = match args {
+            Node::Tuple(tuple) => tuple.borrow(),
+            _ => return None,
+        };
+        let first = match tuple.items.as_ref().and_then(|items| items.first()) {
+            Some(Node::Scalar(value)) => value,
+            _ => return None,
+        };
+        match (modname, globname, first) {
+            ("_codecs", "encode", Value::String(s)) => {
+                Some(Cow::Owned(s.chars().map(|ch| ch as u8).collect()))
+            }
+            ("__builtin__" | "builtins", "bytearray", Value::Bytes(bytes)) => Some(bytes.clone()),
+            ("__builtin__" | "builtins", "bytearray", Value::String(s)) => {
+                Some(Cow::Owned(s.chars().map(|ch| ch as u32 as u8).collect()))
+            }
+            _ => None,
+        }
+    }
+
+    fn stack_error<T>(what: &'static str, node: &Node<'de>) -> Result<T> {
+        let it = format!("{:?}", node);
+        Err(Error::Syntax(ErrorCode::InvalidStackTop(what, it)))
+    }
+}
@@ -18,12 +18,52 @@
 // specific language governing permissions and limitations
 // under the License.
 
+pub mod asm;
+pub mod build;
+pub mod convert;
+pub mod decompile;
+pub mod dis;
 pub mod error;
+pub mod events;
+pub mod lint;
+pub mod literal;
+pub mod lossless;
+pub mod ops;
+pub mod optimize;
+pub mod path;
 pub mod pickle;
+pub mod pickler;
+pub mod pprint;
+pub mod query;
+pub mod read;
+pub mod recover;
+pub mod rename;
+pub mod transcode;
 pub mod value;
+pub mod visit;
 pub mod wrappers;
 
+pub use asm::*;
+pub use build::*;
+pub use convert::*;
+pub use decompile::*;
+pub use dis::*;
 pub use error::*;
+pub use events::*;
+pub use lint::*;
+pub use literal::*;
+pub use lossless::*;
+pub use ops::*;
+pub use optimize::*;
+pub use path::*;
 pub use pickle::*;
+pub use pickler::*;
+pub use pprint::*;
+pub use query::*;
+pub use read::*;
+pub use recover::*;
+pub use rename::*;
+pub use transcode::*;
 pub use value::*;
+pub use visit::*;
 pub use wrappers::*;
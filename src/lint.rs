@@ -0,0 +1,324 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::dis::{emulate, Slot};
+use crate::ops::{encode_long, ops_from_slice, IntArg, Opcode};
+use crate::{Error, ErrorCode};
+
+/// The highest protocol that `pickle` knows.
+pub(crate) const HIGHEST_PROTOCOL: u8 = 5;
+
+/// A problem that `lint` finds in a pickle.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lint {
+    /// An opcode that was introduced after the declared protocol.
+    NewerOpcode {
+        name: &'static str,
+        proto: u8,
+        declared: u8,
+    },
+    /// A `PROTO` other than at the start of the pickle.
+    MisplacedProto,
+    /// A protocol that is higher than any known one.
+    UnknownProtocol(u8),
+    /// A `FRAME` whose length doesn't end at an opcode boundary, or that
+    /// reaches past `STOP` or the next `FRAME`.  `actual` is the length up
+    /// to the end of the opcode that crosses the declared end, or up to
+    /// `STOP` or the next `FRAME`.
+    FrameLength { declared: u64, actual: u64 },
+    /// A `MEMOIZE` whose id, the number of values in the memo, doesn't
+    /// follow the highest id in the memo, because other stores skipped
+    /// ids.
+    MemoOutOfOrder { id: u32, expected: u32 },
+    /// A memo store that overwrites a value.
+    MemoRedefined(u32),
+    /// A memo fetch of an id that was not stored.
+    MissingMemo(u32),
+    /// An integer that `pickle` would write with another opcode, or, if
+    /// `canonical` is `name`, with a shorter argument.
+    NonCanonicalInt {
+        name: &'static str,
+        canonical: &'static str,
+    },
+    /// An opcode that needs a MARK when there is none.
+    MissingMark,
+    /// An opcode that pops more items than there are.
+    StackUnderflow,
+    /// MARKs that are still open at `STOP`.
+    UnclosedMarks(usize),
+    /// Items that are left below the result at `STOP`.
+    StackNotEmpty(usize),
+    /// Bytes after `STOP`.
+    TrailingBytes,
+    /// The pickle can't be read on from here.
+    Invalid(ErrorCode),
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Lint::NewerOpcode {
+                name,
+                proto,
+                declared,
+            } => write!(
+                fmt,
+                "{} needs protocol {}, but the pickle declares protocol {}",
+                name, proto, declared
+            ),
+            Lint::MisplacedProto => write!(fmt, "PROTO not at the start of the pickle"),
+            Lint::UnknownProtocol(proto) => write!(fmt, "unknown protocol {}", proto),
+            Lint::FrameLength { declared, actual } => write!(
+                fmt,
+                "frame declares {} bytes, but its opcodes take {}",
+                declared, actual
+            ),
+            Lint::MemoOutOfOrder { id, expected } => {
+                write!(fmt, "MEMOIZE stores id {}, expected {}", id, expected)
+            }
+            Lint::MemoRedefined(id) => write!(fmt, "memo id {} already defined", id),
+            Lint::MissingMemo(id) => write!(fmt, "missing memo with id {}", id),
+            Lint::NonCanonicalInt { name, canonical } if name == canonical => {
+                write!(fmt, "{} argument not written in the shortest form", name)
+            }
+            Lint::NonCanonicalInt { name, canonical } => {
+                write!(fmt, "integer written with {}, expected {}", name, canonical)
+            }
+            Lint::MissingMark => write!(fmt, "no MARK exists on stack"),
+            Lint::StackUnderflow => write!(fmt, "pickle stack underflow"),
+            Lint::UnclosedMarks(n) => write!(fmt, "{} MARKs left on stack at STOP", n),
+            Lint::StackNotEmpty(n) => write!(fmt, "{} items left on stack at STOP", n),
+            Lint::TrailingBytes => write!(fmt, "trailing bytes found"),
+            Lint::Invalid(code) => write!(fmt, "{}", code),
+        }
+    }
+}
+
+/// A problem and the offset of the opcode where it was found.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+    pub pos: usize,
+    pub lint: Lint,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {}", self.pos, self.lint)
+    }
+}
+
+/// Checks a pickle against the protocol it declares with `PROTO`, and
+/// returns all findings in order.  A pickle without `PROTO` may be of
+/// protocol 0 or 1: it may use the opcodes of either, and write integers as
+/// either does.
+///
+/// Besides opcodes from later protocols, it checks that frames end where
+/// their opcodes do, that the memo is filled in order, that integers are
+/// written as `pickle` writes them, and that the marks and the stack
+/// balance at `STOP`.  An unknown opcode or a truncated pickle ends the
+/// check with a `Lint::Invalid` finding.
+pub fn lint(data: &[u8]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut declared = None;
+    let mut stack = Vec::new();
+    let mut memo = HashSet::new();
+    // The id after the highest one in the memo.
+    let mut memo_next = 0;
+    // The offset of the current frame, where its opcodes start, and its
+    // declared length.
+    let mut frame: Option<(usize, usize, u64)> = None;
+    let mut ops = ops_from_slice(data);
+    loop {
+        let offset = ops.position();
+        let (pos, op) = match ops.next() {
+            Some(Ok(op)) => op,
+            Some(Err(Error::Eval { code, .. })) => {
+                findings.push(Finding {
+                    pos: offset,
+                    lint: Lint::Invalid(code),
+                });
+                break;
+            }
+            // Reading a slice can't fail otherwise.
+            Some(Err(_)) | None => break,
+        };
+        let end = ops.position();
+        if let Some((frame_pos, start, len)) = frame {
+            // A length past the end of memory just runs to the STOP.
+            let frame_end = start.saturating_add(usize::try_from(len).unwrap_or(usize::MAX));
+            let actual = match op {
+                _ if end > frame_end => Some(end),
+                Opcode::Frame(_) if pos < frame_end => Some(pos),
+                Opcode::Stop if end < frame_end => Some(end),
+                _ => None,
+            };
+            if let Some(actual) = actual {
+                findings.push(Finding {
+                    pos: frame_pos,
+                    lint: Lint::FrameLength {
+                        declared: len,
+                        actual: (actual - start) as u64,
+                    },
+                });
+            }
+            if actual.is_some() || end >= frame_end {
+                frame = None;
+            }
+        }
+
+        let mut lints = Vec::new();
+        match op {
+            Opcode::Proto(proto) => {
+                if pos == 0 {
+                    declared = Some(proto);
+                } else {
+                    lints.push(Lint::MisplacedProto);
+                }
+                if proto > HIGHEST_PROTOCOL {
+                    lints.push(Lint::UnknownProtocol(proto));
+                }
+            }
+            _ if op.proto() > declared.unwrap_or(1) => lints.push(Lint::NewerOpcode {
+                name: op.name(),
+                proto: op.proto(),
+                declared: declared.unwrap_or(1),
+            }),
+            _ => {}
+        }
+        if let Opcode::Frame(len) = op {
+            frame = Some((pos, end, len));
+        }
+        if let Some(canonical) = canonical_int(&op, &data[pos..end], declared) {
+            lints.push(Lint::NonCanonicalInt {
+                name: op.name(),
+                canonical,
+            });
+        }
+
+        let memo_id = match op {
+            Opcode::Put(id) | Opcode::LongBinPut(id) => Some(id),
+            Opcode::BinPut(id) => Some(id.into()),
+            Opcode::Memoize => Some(memo.len() as u32),
+            _ => None,
+        };
+        if let Some(id) = memo_id {
+            if memo.contains(&id) {
+                lints.push(Lint::MemoRedefined(id));
+            } else if op == Opcode::Memoize && memo_next != id {
+                lints.push(Lint::MemoOutOfOrder {
+                    id,
+                    expected: memo_next,
+                });
+            }
+            memo.insert(id);
+            memo_next = memo_next.max(id + 1);
+        }
+        let fetched = match op {
+            Opcode::Get(id) | Opcode::LongBinGet(id) => Some(id),
+            Opcode::BinGet(id) => Some(id.into()),
+            _ => None,
+        };
+        if let Some(id) = fetched {
+            if !memo.contains(&id) {
+                lints.push(Lint::MissingMemo(id));
+            }
+        }
+
+        // Emulate the stack effects, going on after an underflow as if the
+        // missing items were there.
+        let effect = emulate(&mut stack, &op);
+        if effect.missing_mark {
+            lints.push(Lint::MissingMark);
+        }
+        if effect.missing_items {
+            lints.push(Lint::StackUnderflow);
+        }
+
+        if op == Opcode::Stop {
+            let marks = stack.iter().filter(|&&slot| slot == Slot::Mark).count();
+            if marks > 0 {
+                lints.push(Lint::UnclosedMarks(marks));
+            }
+            if stack.len() > marks {
+                lints.push(Lint::StackNotEmpty(stack.len() - marks));
+            }
+        }
+        findings.extend(lints.into_iter().map(|lint| Finding { pos, lint }));
+        if op == Opcode::Stop && end < data.len() {
+            findings.push(Finding {
+                pos: end,
+                lint: Lint::TrailingBytes,
+            });
+        }
+    }
+    findings
+}
+
+// The opcode that `pickle` would write for an integer, if it isn't `op`
+// itself, or the name of `op` if its argument isn't written in the
+// shortest way.  `raw` is the opcode with its argument.  Without a declared
+// protocol, the text opcodes of protocol 0 are taken as canonical.
+fn canonical_int(op: &Opcode, raw: &[u8], declared: Option<u8>) -> Option<&'static str> {
+    // The text of `INT` and `LONG`, between the code and the newline.
+    let text = || &raw[1..raw.len() - 1];
+    let canonical = match op {
+        Opcode::BinInt(i) => smallest_int(&(*i).into()),
+        Opcode::BinInt2(i) => smallest_int(&(*i).into()),
+        Opcode::Long1(i) | Opcode::Long4(i) => {
+            // The bytes follow the code and a 1 or 4 byte length.
+            let len = raw.len() - if let Opcode::Long1(_) = op { 2 } else { 5 };
+            match smallest_int(i) {
+                name if name == op.name() && len != encode_long(i).len() => return Some(name),
+                name => name,
+            }
+        }
+        Opcode::Int(IntArg::Int(i)) if declared >= Some(1) && i32::try_from(*i).is_ok() => {
+            smallest_int(&(*i).into())
+        }
+        Opcode::Int(IntArg::Int(i)) if text() != i.to_string().as_bytes() => op.name(),
+        // `pickle` only writes `LONG` below protocol 2, for what doesn't fit
+        // in 32 bits.
+        Opcode::Long(i) if declared >= Some(2) => smallest_int(i),
+        Opcode::Long(i) if i.to_i32().is_some() => match declared {
+            Some(1) => smallest_int(i),
+            _ => "INT",
+        },
+        Opcode::Long(i) if text() != format!("{}L", i).as_bytes() => op.name(),
+        _ => return None,
+    };
+    (canonical != op.name() || matches!(op, Opcode::Int(_) | Opcode::Long(_))).then_some(canonical)
+}
+
+// The smallest binary opcode for an integer, as `pickle` chooses it.
+fn smallest_int(i: &BigInt) -> &'static str {
+    match i.to_i64() {
+        Some(0..=0xff) => "BININT1",
+        Some(0x100..=0xffff) => "BININT2",
+        Some(i) if i32::try_from(i).is_ok() => "BININT",
+        _ if encode_long(i).len() < 256 => "LONG1",
+        _ => "LONG4",
+    }
+}
@@ -0,0 +1,718 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+use num_traits::{ToPrimitive, Zero};
+
+use crate::error::Result;
+use crate::{Error, ErrorCode, F64Wrapper, HashMapWrapper, HashSetWrapper, Value};
+
+// As deep as CPython's parser lets brackets nest.
+const MAX_DEPTH: usize = 200;
+
+/// Parses a Python literal, as `ast.literal_eval` does:
+///
+/// ```text
+/// {'a': [1, 2.5, None], b'k': (True, frozenset({3}))}
+/// ```
+///
+/// Strings and bytes (with their prefixes, escapes and implicit
+/// concatenation), ints of any size, floats, complex numbers, tuples,
+/// lists, dicts, sets, `None`, `True` and `False` are accepted, as are
+/// `set()`, `frozenset(...)`, `float('nan')` and `complex(re, im)`, so
+/// that whatever `Value` displays reads back.  Blanks and `#` comments may
+/// appear between tokens, also across lines.  Unlike in Python, `...`
+/// (Ellipsis), which no `Value` stands for, and `\N{...}` escapes, which
+/// would need the Unicode name database, are rejected.
+///
+/// Ints that fit are `Value::I64`, as the unpickler returns them.  Errors
+/// give the line and column (in characters, from 1) where they were found.
+pub fn literal_eval(source: &str) -> Result<Value<'static>> {
+    let mut parser = Parser {
+        src: source,
+        pos: 0,
+        depth: 0,
+    };
+    parser.skip_blanks();
+    let value = parser.expr_list()?;
+    parser.skip_blanks();
+    if parser.pos < source.len() {
+        return Err(parser.unexpected());
+    }
+    Ok(value)
+}
+
+// Parses the literal that starts at `pos`, as part of some other syntax.
+// Returns it with the position after it.
+pub(crate) fn parse_literal_at(source: &str, pos: usize) -> Result<(Value<'static>, usize)> {
+    let mut parser = Parser {
+        src: source,
+        pos,
+        depth: 0,
+    };
+    let value = parser.expr()?;
+    Ok((value, parser.pos))
+}
+
+// An error at byte `pos` of `source`, with the line and column of it.
+pub(crate) fn syntax_error(source: &str, pos: usize, msg: impl Into<String>) -> Error {
+    let before = &source[..pos];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    let msg = format!("line {}, column {}: {}", line, column, msg.into());
+    Error::Syntax(ErrorCode::InvalidValue(msg))
+}
+
+impl FromStr for Value<'static> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        literal_eval(s)
+    }
+}
+
+struct Parser<'s> {
+    src: &'s str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn rest(&self) -> &'s str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += ch.len_utf8();
+        Some(ch)
+    }
+
+    fn error(&self, pos: usize, msg: impl Into<String>) -> Error {
+        syntax_error(self.src, pos, msg)
+    }
+
+    fn unexpected(&self) -> Error {
+        match self.peek() {
+            Some(ch) => self.error(self.pos, format!("unexpected {:?}", ch)),
+            None => self.error(self.pos, "unexpected end of input"),
+        }
+    }
+
+    // Skips blanks, line breaks, backslash continuations and comments.
+    fn skip_blanks(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with('#') {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("\\\n") {
+                self.pos += 2;
+            } else if trimmed.starts_with("\\\r\n") {
+                self.pos += 3;
+            } else {
+                break;
+            }
+        }
+    }
+
+    // Consumes `ch` after any blanks, if it is next.
+    fn eat(&mut self, ch: char) -> bool {
+        self.skip_blanks();
+        if self.peek() == Some(ch) {
+            self.pos += ch.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, ch: char) -> Result<()> {
+        if self.eat(ch) {
+            Ok(())
+        } else {
+            match self.peek() {
+                Some(got) => Err(self.error(self.pos, format!("expected {:?}, got {:?}", ch, got))),
+                None => Err(self.error(self.pos, format!("expected {:?}", ch))),
+            }
+        }
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error(self.pos, "too deeply nested"));
+        }
+        Ok(())
+    }
+
+    // Items separated by commas up to `close`, which may follow a comma.
+    // Returns the position of each item too.
+    fn items(&mut self, close: char) -> Result<Vec<(usize, Value<'static>)>> {
+        let mut items = Vec::new();
+        while !self.eat(close) {
+            self.skip_blanks();
+            let pos = self.pos;
+            items.push((pos, self.expr()?));
+            if !self.eat(',') {
+                self.expect(close)?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    // An expression, or several separated by commas, which make a tuple.
+    fn expr_list(&mut self) -> Result<Value<'static>> {
+        let first = self.expr()?;
+        if !self.eat(',') {
+            return Ok(first);
+        }
+        let mut items = vec![first];
+        loop {
+            self.skip_blanks();
+            match self.peek() {
+                None | Some(')') => return Ok(Value::Tuple(items)),
+                _ => items.push(self.expr()?),
+            }
+            if !self.eat(',') {
+                return Ok(Value::Tuple(items));
+            }
+        }
+    }
+
+    // A value, or a real number plus or minus an imaginary one.
+    fn expr(&mut self) -> Result<Value<'static>> {
+        let left = self.unary()?;
+        self.skip_blanks();
+        let op = match self.peek() {
+            Some(op @ ('+' | '-')) => op,
+            _ => return Ok(left),
+        };
+        let op_pos = self.pos;
+        self.pos += 1;
+        self.skip_blanks();
+        let right_pos = self.pos;
+        let right = self.atom()?;
+        let re = match left {
+            Value::I64(i) => i as f64,
+            Value::Int(i) => i.to_f64().unwrap_or(f64::NAN),
+            Value::F64(f) => f.0,
+            _ => return Err(self.error(op_pos, format!("unsupported operand {:?}", op))),
+        };
+        match right {
+            Value::Complex(F64Wrapper(r), F64Wrapper(i)) => Ok(match op {
+                '+' => Value::Complex(F64Wrapper(re + r), F64Wrapper(0.0 + i)),
+                _ => Value::Complex(F64Wrapper(re - r), F64Wrapper(0.0 - i)),
+            }),
+            _ => Err(self.error(right_pos, "expected an imaginary number")),
+        }
+    }
+
+    // A value, or a number with a sign.  As in `ast.literal_eval`, the sign
+    // takes a single number literal, which may be in parentheses.
+    fn unary(&mut self) -> Result<Value<'static>> {
+        self.skip_blanks();
+        let sign = match self.peek() {
+            Some(sign @ ('+' | '-')) => sign,
+            _ => return self.atom(),
+        };
+        self.pos += 1;
+        self.skip_blanks();
+        let pos = self.pos;
+        let value = self.number_literal()?;
+        if sign == '+' {
+            return Ok(value);
+        }
+        match value {
+            Value::I64(i) => Ok(i
+                .checked_neg()
+                .map_or_else(|| int(-BigInt::from(i)), Value::I64)),
+            Value::Int(i) => Ok(int(-i)),
+            Value::F64(f) => Ok(Value::F64(F64Wrapper(-f.0))),
+            Value::Complex(re, im) => Ok(Value::Complex(F64Wrapper(-re.0), F64Wrapper(-im.0))),
+            _ => Err(self.error(pos, "expected a number")),
+        }
+    }
+
+    fn number_literal(&mut self) -> Result<Value<'static>> {
+        self.skip_blanks();
+        match self.peek() {
+            Some('(') => {
+                self.enter()?;
+                self.pos += 1;
+                let value = self.number_literal()?;
+                self.expect(')')?;
+                self.depth -= 1;
+                Ok(value)
+            }
+            Some('0'..='9') => self.number(),
+            Some('.') if self.rest()[1..].starts_with(|ch: char| ch.is_ascii_digit()) => {
+                self.number()
+            }
+            _ => Err(self.error(self.pos, "expected a number")),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Value<'static>> {
+        self.skip_blanks();
+        let start = self.pos;
+        match self.peek() {
+            Some('(') => {
+                self.enter()?;
+                self.pos += 1;
+                let value = match self.eat(')') {
+                    true => Value::Tuple(Vec::new()),
+                    false => {
+                        self.skip_blanks();
+                        let value = self.expr_list()?;
+                        self.expect(')')?;
+                        value
+                    }
+                };
+                self.depth -= 1;
+                Ok(value)
+            }
+            Some('[') => {
+                self.enter()?;
+                self.pos += 1;
+                let items = self.items(']')?;
+                self.depth -= 1;
+                Ok(Value::List(items.into_iter().map(|(_, v)| v).collect()))
+            }
+            Some('{') => {
+                self.enter()?;
+                self.pos += 1;
+                let value = self.braces()?;
+                self.depth -= 1;
+                Ok(value)
+            }
+            Some('0'..='9') => self.number(),
+            Some('.') if self.rest()[1..].starts_with(|ch: char| ch.is_ascii_digit()) => {
+                self.number()
+            }
+            Some('\'' | '"') => self.strings(),
+            Some(ch) if ch.is_alphabetic() || ch == '_' => {
+                if self.string_prefix().is_some() {
+                    return self.strings();
+                }
+                let name = self.name();
+                match name {
+                    "None" => Ok(Value::None),
+                    "True" => Ok(Value::Bool(true)),
+                    "False" => Ok(Value::Bool(false)),
+                    "set" | "frozenset" | "float" | "complex" => {
+                        self.enter()?;
+                        let value = self.call(name, start)?;
+                        self.depth -= 1;
+                        Ok(value)
+                    }
+                    _ => Err(self.error(start, format!("unknown name {}", name))),
+                }
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn name(&mut self) -> &'s str {
+        let rest = self.rest();
+        let len = rest
+            .find(|ch: char| !ch.is_alphanumeric() && ch != '_')
+            .unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    // A dict or a set, after the opening brace.
+    fn braces(&mut self) -> Result<Value<'static>> {
+        if self.eat('}') {
+            return Ok(Value::Dict(HashMapWrapper::new()));
+        }
+        self.skip_blanks();
+        let pos = self.pos;
+        let first = self.expr()?;
+        if !self.eat(':') {
+            let mut set = HashSetWrapper::new();
+            self.check_hashable(pos, &first)?;
+            set.insert(first);
+            if self.eat(',') {
+                for (pos, item) in self.items('}')? {
+                    self.check_hashable(pos, &item)?;
+                    set.insert(item);
+                }
+            } else {
+                self.expect('}')?;
+            }
+            return Ok(Value::Set(set));
+        }
+        let mut dict = HashMapWrapper::new();
+        let (mut pos, mut key) = (pos, first);
+        loop {
+            self.check_hashable(pos, &key)?;
+            dict.insert(key, self.expr()?);
+            if !self.eat(',') {
+                self.expect('}')?;
+                break;
+            }
+            if self.eat('}') {
+                break;
+            }
+            self.skip_blanks();
+            pos = self.pos;
+            key = self.expr()?;
+            self.expect(':')?;
+        }
+        Ok(Value::Dict(dict))
+    }
+
+    fn check_hashable(&self, pos: usize, value: &Value) -> Result<()> {
+        match unhashable(value) {
+            Some(type_name) => Err(self.error(pos, format!("unhashable type: '{}'", type_name))),
+            None => Ok(()),
+        }
+    }
+
+    // The calls that stand for values without a literal of their own.
+    fn call(&mut self, name: &str, start: usize) -> Result<Value<'static>> {
+        self.expect('(')?;
+        let args = self.items(')')?;
+        let bad_args = || self.error(start, format!("invalid arguments to {}()", name));
+        match (name, args.as_slice()) {
+            ("set", []) => Ok(Value::Set(HashSetWrapper::new())),
+            ("frozenset", []) => Ok(Value::FrozenSet(HashSetWrapper::new())),
+            ("set" | "frozenset", [(pos, arg)]) => {
+                let items = match arg {
+                    Value::List(items) | Value::Tuple(items) => items.iter().collect::<Vec<_>>(),
+                    Value::Set(set) | Value::FrozenSet(set) => set.0.iter().collect(),
+                    Value::Dict(dict) => dict.0.keys().collect(),
+                    _ => return Err(self.error(*pos, format!("{}() needs an iterable", name))),
+                };
+                let mut set = HashSetWrapper::new();
+                for item in items {
+                    self.check_hashable(*pos, item)?;
+                    set.insert(item.clone());
+                }
+                Ok(match name {
+                    "set" => Value::Set(set),
+                    _ => Value::FrozenSet(set),
+                })
+            }
+            ("float", [(pos, Value::String(s))]) => match parse_float(s) {
+                Some(f) => Ok(Value::F64(F64Wrapper(f))),
+                None => {
+                    Err(self.error(*pos, format!("could not convert string to float: {:?}", s)))
+                }
+            },
+            ("float", [(pos, arg)]) => match real(arg) {
+                Some(f) => Ok(Value::F64(F64Wrapper(f))),
+                None => Err(self.error(*pos, "float() needs a number or a string")),
+            },
+            ("complex", [(_, re)]) => match (re, real(re)) {
+                (Value::Complex(..), _) => Ok(re.clone()),
+                (_, Some(re)) => Ok(Value::Complex(F64Wrapper(re), F64Wrapper(0.0))),
+                _ => Err(bad_args()),
+            },
+            ("complex", [(_, re), (_, im)]) => match (real(re), real(im)) {
+                (Some(re), Some(im)) => Ok(Value::Complex(F64Wrapper(re), F64Wrapper(im))),
+                _ => Err(bad_args()),
+            },
+            _ => Err(bad_args()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value<'static>> {
+        let start = self.pos;
+        let rest = self.rest().as_bytes();
+        let radix = rest.len() > 1 && rest[0] == b'0' && b"xXoObB".contains(&rest[1]);
+        let mut len = 0;
+        while len < rest.len() {
+            let b = rest[len];
+            let exponent_sign =
+                (b == b'+' || b == b'-') && !radix && matches!(rest[len - 1], b'e' | b'E');
+            if b.is_ascii_alphanumeric() || b == b'_' || b == b'.' || exponent_sign {
+                len += 1;
+            } else {
+                break;
+            }
+        }
+        self.pos += len;
+        let token = &self.src[start..self.pos];
+        parse_number(token).map_err(|msg| self.error(start, msg))
+    }
+
+    // The length of the prefix of a string literal that starts here.
+    fn string_prefix(&self) -> Option<usize> {
+        let rest = self.rest();
+        let len = rest.bytes().take(3).position(|b| b == b'\'' || b == b'"')?;
+        match rest[..len].to_ascii_lowercase().as_str() {
+            "" | "r" | "u" | "b" | "br" | "rb" => Some(len),
+            _ => None,
+        }
+    }
+
+    // String or bytes literals, joined if several follow each other.
+    fn strings(&mut self) -> Result<Value<'static>> {
+        let mut text = String::new();
+        let mut bytes = Vec::new();
+        let mut kind = None;
+        while let Some(prefix_len) = self.string_prefix() {
+            let pos = self.pos;
+            let prefix = self.rest()[..prefix_len].to_ascii_lowercase();
+            let is_bytes = prefix.contains('b');
+            if *kind.get_or_insert(is_bytes) != is_bytes {
+                return Err(self.error(pos, "cannot mix bytes and nonbytes literals"));
+            }
+            self.pos += prefix_len;
+            let code_points = self.string_body(is_bytes, prefix.contains('r'))?;
+            if is_bytes {
+                bytes.extend(code_points.iter().map(|&code| code as u8));
+            } else {
+                for code in code_points {
+                    let ch = char::from_u32(code)
+                        .ok_or_else(|| self.error(pos, "surrogates are not supported"))?;
+                    text.push(ch);
+                }
+            }
+            self.skip_blanks();
+        }
+        Ok(match kind {
+            Some(true) => Value::Bytes(bytes.into()),
+            _ => Value::String(text.into()),
+        })
+    }
+
+    // A quoted literal after its prefix, with its escapes resolved into code
+    // points.  Those of bytes are all below 256.
+    fn string_body(&mut self, bytes: bool, raw: bool) -> Result<Vec<u32>> {
+        let start = self.pos;
+        let quote = match self.bump() {
+            Some(quote @ ('\'' | '"')) => quote,
+            _ => return Err(self.error(start, "expected a quote")),
+        };
+        let triple = quote.to_string().repeat(3);
+        let long = self.src[start..].starts_with(&triple);
+        if long {
+            self.pos += 2;
+        }
+        let unterminated = |p: &Self| p.error(start, "unterminated string literal");
+        let mut out = Vec::new();
+        loop {
+            if long && self.rest().starts_with(&triple) {
+                self.pos += 3;
+                return Ok(out);
+            }
+            let pos = self.pos;
+            let ch = self.bump().ok_or_else(|| unterminated(self))?;
+            match ch {
+                _ if ch == quote && !long => return Ok(out),
+                '\n' if !long => return Err(unterminated(self)),
+                '\\' if raw => {
+                    let next = self.bump().ok_or_else(|| unterminated(self))?;
+                    out.extend(['\\' as u32, next as u32]);
+                }
+                '\\' => self.escape(bytes, pos, &mut out)?,
+                _ if bytes && !ch.is_ascii() => {
+                    let msg = "bytes can only contain ASCII literal characters";
+                    return Err(self.error(pos, msg));
+                }
+                _ => out.push(ch as u32),
+            }
+        }
+    }
+
+    // An escape after its backslash at `pos`.
+    fn escape(&mut self, bytes: bool, pos: usize, out: &mut Vec<u32>) -> Result<()> {
+        let escape = self
+            .bump()
+            .ok_or_else(|| self.error(pos, "unterminated string literal"))?;
+        let code = match escape {
+            '\n' => return Ok(()),
+            '\\' | '\'' | '"' => escape as u32,
+            'n' => 0x0a,
+            'r' => 0x0d,
+            't' => 0x09,
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            '0'..='7' => {
+                let mut code = escape as u32 - '0' as u32;
+                for _ in 0..2 {
+                    match self.peek().and_then(|ch| ch.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            self.pos += 1;
+                        }
+                        None => break,
+                    }
+                }
+                if bytes && code > 0xff {
+                    return Err(self.error(pos, "octal escape out of range"));
+                }
+                code
+            }
+            'x' => self.hex(2, pos)?,
+            'u' if !bytes => self.hex(4, pos)?,
+            'U' if !bytes => self.hex(8, pos)?,
+            'N' if !bytes => return Err(self.error(pos, "\\N{...} escapes are not supported")),
+            // Python keeps the backslash of an unknown escape.
+            _ => {
+                out.push('\\' as u32);
+                escape as u32
+            }
+        };
+        if code > 0x10ffff {
+            return Err(self.error(pos, "illegal Unicode character"));
+        }
+        out.push(code);
+        Ok(())
+    }
+
+    fn hex(&mut self, digits: usize, pos: usize) -> Result<u32> {
+        let rest = self.rest();
+        let hex = rest
+            .get(..digits)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()));
+        match hex {
+            Some(hex) => {
+                self.pos += digits;
+                Ok(u32::from_str_radix(hex, 16).unwrap_or(u32::MAX))
+            }
+            None => {
+                let name = match digits {
+                    2 => "\\xXX",
+                    4 => "\\uXXXX",
+                    _ => "\\UXXXXXXXX",
+                };
+                Err(self.error(pos, format!("truncated {} escape", name)))
+            }
+        }
+    }
+}
+
+// An int as the unpickler returns it: `Value::I64` if it fits.
+fn int(i: BigInt) -> Value<'static> {
+    match i.to_i64() {
+        Some(i) => Value::I64(i),
+        None => Value::Int(i),
+    }
+}
+
+// The value of a real number, as Python's `float()` gives it.
+fn real(value: &Value) -> Option<f64> {
+    match value {
+        Value::I64(i) => Some(*i as f64),
+        Value::Int(i) => i.to_f64(),
+        Value::F64(f) => Some(f.0),
+        _ => None,
+    }
+}
+
+// The name of the type of a value that can't be a dict key or set member.
+fn unhashable(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::List(_) | Value::Dict(_) | Value::Set(_) => Some(value.type_name()),
+        Value::Tuple(items) => items.iter().find_map(unhashable),
+        _ => None,
+    }
+}
+
+// A string as `float()` takes it, e.g. "nan", "-inf" or " 1_000.5 ".
+fn parse_float(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let digits = s.trim_start_matches(['+', '-']);
+    match digits.to_ascii_lowercase().as_str() {
+        "nan" | "inf" | "infinity" => s.to_ascii_lowercase().parse().ok(),
+        _ if valid_underscores(digits) => s.replace('_', "").parse().ok(),
+        _ => None,
+    }
+}
+
+// Whether every underscore in a number stands between two digits.
+fn valid_underscores(s: &str) -> bool {
+    let b = s.as_bytes();
+    (0..b.len()).all(|i| {
+        b[i] != b'_'
+            || (i > 0
+                && i + 1 < b.len()
+                && b[i - 1].is_ascii_alphanumeric()
+                && b[i + 1].is_ascii_alphanumeric())
+    })
+}
+
+fn parse_number(token: &str) -> std::result::Result<Value<'static>, String> {
+    let lower = token.to_ascii_lowercase();
+    let radix = match lower.get(..2) {
+        Some("0x") => Some((16, "hexadecimal")),
+        Some("0o") => Some((8, "octal")),
+        Some("0b") => Some((2, "binary")),
+        _ => None,
+    };
+    if let Some((radix, kind)) = radix {
+        // An underscore may also follow the prefix.
+        let digits = lower[2..].strip_prefix('_').unwrap_or(&lower[2..]);
+        let invalid = || format!("invalid {} literal {}", kind, token);
+        if digits.is_empty() || !valid_underscores(digits) {
+            return Err(invalid());
+        }
+        return BigInt::parse_bytes(digits.replace('_', "").as_bytes(), radix)
+            .map(int)
+            .ok_or_else(invalid);
+    }
+    let (body, imaginary) = match lower.strip_suffix('j') {
+        Some(body) => (body, true),
+        None => (lower.as_str(), false),
+    };
+    let invalid = || format!("invalid decimal literal {}", token);
+    let well_formed = !body.is_empty()
+        && valid_underscores(body)
+        && body
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b"_.e+-".contains(&b));
+    if !well_formed {
+        return Err(invalid());
+    }
+    let clean = body.replace('_', "");
+    if imaginary || clean.contains(['.', 'e']) {
+        let f = clean.parse::<f64>().map_err(|_| invalid())?;
+        return Ok(match imaginary {
+            true => Value::Complex(F64Wrapper(0.0), F64Wrapper(f)),
+            false => Value::F64(F64Wrapper(f)),
+        });
+    }
+    if clean.len() > 1 && clean.starts_with('0') && clean.bytes().any(|b| b != b'0') {
+        return Err(format!(
+            "leading zeros in decimal integer literals are not permitted: {}",
+            token
+        ));
+    }
+    match clean.parse::<BigInt>() {
+        Ok(i) if i.is_zero() => Ok(Value::I64(0)),
+        Ok(i) => Ok(int(i)),
+        Err(_) => Err(invalid()),
+    }
+}
@@ -0,0 +1,808 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::rc::Rc;
+
+use indexmap::IndexMap;
+use num_traits::ToPrimitive;
+
+use crate::error::Result;
+use crate::ops::{ops_from_slice, Opcode};
+use crate::pickle::Step;
+use crate::{
+    Error, ErrorCode, MemoId, Path, PathElem, Pickler, PicklerOptions, PyKey, Unpickler,
+    UnpicklerOptions, Value,
+};
+
+/// Where a value of a loaded pickle came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotation {
+    /// The offset of the first opcode that went into the value, e.g. the
+    /// MARK of a tuple or the GLOBAL of an object.
+    pub start: usize,
+    /// The end of the last opcode that built the value or stored it in the
+    /// memo, e.g. its final APPENDS, SETITEMS or BUILD.
+    pub end: usize,
+    /// The code of the opcode that pushed the value, e.g. `BININT1` rather
+    /// than `BININT`, `REDUCE` for the result of a call, or `BINGET` if the
+    /// value was fetched from the memo.
+    pub opcode: u8,
+    /// The memo ids under which the value was stored here.
+    pub memo: Vec<MemoId>,
+    /// The memo id from which the value was fetched, if it was.
+    pub fetched: Option<MemoId>,
+}
+
+impl Annotation {
+    /// The bytes of the opcodes that built the value.
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.end
+    }
+}
+
+/// The annotations of the values of a pickle, by the paths at which
+/// `Unpickler` puts them.  Items of sets, and values below dict keys that
+/// aren't scalars or tuples, have no paths.
+///
+/// The annotations map each value to the bytes that built it and back, e.g.
+/// to highlight a value in a hex view, or to find what takes up the space
+/// of a large pickle.
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    proto: u8,
+    annotations: HashMap<Path<'static>, Annotation>,
+    // The paths of values that are pushed again by DUP.
+    duplicated: HashSet<Path<'static>>,
+    // The spans of the values that lie within the spans of the values that
+    // hold them, by start and then by descending end, so that they nest.
+    spans: Vec<(Range<usize>, Path<'static>)>,
+}
+
+impl SourceMap {
+    /// Annotates the values of the pickle in `data`.
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        let mut proto = None;
+        let mut highest = 0;
+        for op in ops_from_slice(data) {
+            match op?.1 {
+                Opcode::Proto(p) if proto.is_none() => proto = Some(p),
+                op => highest = highest.max(op.proto()),
+            }
+        }
+        let mut sources = SourceMap {
+            proto: proto.unwrap_or(highest),
+            ..Default::default()
+        };
+        let root = Annotator::run(data)?;
+        sources.walk(&root, &mut Path::new(), &mut Vec::new());
+        // Values below one fetched from the memo were built elsewhere.
+        for (path, annotation) in &sources.annotations {
+            let span = annotation.span();
+            let nested = (0..path.len()).all(|len| {
+                let outer = &sources.annotations[&Path(path[..len].to_vec())];
+                outer.start <= span.start && span.end <= outer.end
+            });
+            if nested {
+                sources.spans.push((span, path.clone()));
+            }
+        }
+        sources
+            .spans
+            .sort_by_key(|(span, path)| (span.start, Reverse(span.end), path.len()));
+        Ok(sources)
+    }
+
+    /// The protocol of the pickle: the one of its PROTO opcode, or else the
+    /// highest among its opcodes.
+    pub fn proto(&self) -> u8 {
+        self.proto
+    }
+
+    /// The annotation of the value at `path`.
+    pub fn get(&self, path: &[PathElem]) -> Option<&Annotation> {
+        let path = path.iter().cloned().map(PathElem::into_owned).collect();
+        self.annotations.get(&path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Path<'static>, &Annotation)> {
+        self.annotations.iter()
+    }
+
+    /// The bytes of the opcodes that built the value at `path`, from the
+    /// first one to the last APPENDS, SETITEMS, BUILD or memo store.
+    pub fn span(&self, path: &[PathElem]) -> Option<Range<usize>> {
+        self.get(path).map(Annotation::span)
+    }
+
+    /// The path of the innermost value whose opcodes span `offset`.  A
+    /// value that is fetched from the memo spans only the fetch, so the
+    /// bytes of a shared value map to the place where it was built.
+    pub fn path_at(&self, offset: usize) -> Option<&Path<'static>> {
+        let before = self.spans.partition_point(|(span, _)| span.start <= offset);
+        // The spans nest, so the last one that contains the offset is the
+        // innermost.
+        self.spans[..before]
+            .iter()
+            .rev()
+            .find(|(span, _)| span.contains(&offset))
+            .map(|(_, path)| path)
+    }
+
+    fn walk(&mut self, place: &Place, path: &mut Path<'static>, open: &mut Vec<*const Entry>) {
+        let entry = place.entry.borrow();
+        let annotation = match &place.site {
+            Some(site) => site.clone(),
+            None => {
+                if entry.duplicated {
+                    self.duplicated.insert(path.clone());
+                }
+                Annotation {
+                    start: entry.start,
+                    end: entry.end,
+                    opcode: entry.opcode,
+                    memo: entry.memo.clone(),
+                    fetched: None,
+                }
+            }
+        };
+        self.annotations.insert(path.clone(), annotation);
+        drop(entry);
+        self.walk_items(place, path, open);
+    }
+
+    fn walk_items(
+        &mut self,
+        place: &Place,
+        path: &mut Path<'static>,
+        open: &mut Vec<*const Entry>,
+    ) {
+        // Recursive values can't be unpickled, so they have no paths.
+        if open.contains(&place.entry.as_ptr().cast_const()) {
+            return;
+        }
+        open.push(place.entry.as_ptr());
+        let entry = place.entry.borrow();
+        match &entry.kind {
+            Kind::List(items) | Kind::Tuple(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i);
+                    self.walk(item, path, open);
+                    path.0.pop();
+                }
+            }
+            Kind::Dict(items) => {
+                // Keys merge as `HashMapWrapper::insert` merges them: the
+                // first key stays, with the last value.  Keys fetched from
+                // the same memo id merge too, as they do in `Unpickler`.
+                let mut merged: IndexMap<Value<'static>, &Place> = IndexMap::new();
+                let mut fetched = HashMap::new();
+                for (key, value) in items {
+                    let Some(key_value) = key.key() else {
+                        continue;
+                    };
+                    let memo_id = key.site.as_ref().and_then(|site| site.fetched);
+                    let index = memo_id
+                        .and_then(|id| fetched.get(&id).copied())
+                        .or_else(|| merged.get_index_of(&PyKey(&key_value)));
+                    let index = match index {
+                        Some(index) => {
+                            merged[index] = value;
+                            index
+                        }
+                        None => merged.insert_full(key_value, value).0,
+                    };
+                    if let Some(id) = memo_id {
+                        fetched.insert(id, index);
+                    }
+                }
+                for (key, value) in merged {
+                    path.push(PathElem::Key(key));
+                    self.walk(value, path, open);
+                    path.0.pop();
+                }
+            }
+            // An object unpickles to its state.
+            Kind::Object(Some(state)) => self.walk_items(state, path, open),
+            _ => {}
+        }
+        open.pop();
+    }
+}
+
+// A value on the stack of the annotator, shared through the memo.
+struct Entry {
+    start: usize,
+    end: usize,
+    opcode: u8,
+    memo: Vec<MemoId>,
+    duplicated: bool,
+    kind: Kind,
+}
+
+enum Kind {
+    /// A value without paths below it, with its value if it can be a dict
+    /// key.
+    Leaf(Option<Value<'static>>),
+    Global(String, String),
+    List(Vec<Place>),
+    Tuple(Vec<Place>),
+    Dict(Vec<(Place, Place)>),
+    /// An object, with the state that it unpickles to, if any.
+    Object(Option<Place>),
+}
+
+// A value where it is used: where it is built, or, with a `site`, where
+// it is fetched from the memo or duplicated.
+#[derive(Clone)]
+struct Place {
+    entry: Rc<RefCell<Entry>>,
+    site: Option<Annotation>,
+}
+
+impl Place {
+    fn new(start: usize, end: usize, opcode: u8, kind: Kind) -> Self {
+        let entry = Entry {
+            start,
+            end,
+            opcode,
+            memo: Vec::new(),
+            duplicated: false,
+            kind,
+        };
+        Place {
+            entry: Rc::new(RefCell::new(entry)),
+            site: None,
+        }
+    }
+
+    fn start(&self) -> usize {
+        match &self.site {
+            Some(site) => site.start,
+            None => self.entry.borrow().start,
+        }
+    }
+
+    // Extends the place to the end of an opcode that modified the value.
+    fn extend(&mut self, end: usize) {
+        match &mut self.site {
+            Some(site) => site.end = end,
+            None => self.entry.borrow_mut().end = end,
+        }
+    }
+
+    // The value as a dict key, as `Unpickler` converts it.
+    fn key(&self) -> Option<Value<'static>> {
+        match &self.entry.borrow().kind {
+            Kind::Leaf(value) => value.clone(),
+            Kind::Tuple(items) => items
+                .iter()
+                .map(Place::key)
+                .collect::<Option<_>>()
+                .map(Value::Tuple),
+            _ => None,
+        }
+    }
+}
+
+// Emulates the unpickler on the operations that `Unpickler` reads, and
+// keeps track of the places of the values.
+struct Annotator {
+    stack: Vec<Place>,
+    metastack: Vec<(usize, Vec<Place>)>,
+    memo: HashMap<MemoId, Rc<RefCell<Entry>>>,
+    pos: usize,
+}
+
+impl Annotator {
+    fn run(data: &[u8]) -> Result<Place> {
+        let mut annotator = Annotator {
+            stack: Vec::new(),
+            metastack: Vec::new(),
+            memo: HashMap::new(),
+            pos: 0,
+        };
+        let mut unpickler = Unpickler::from_slice(data, UnpicklerOptions::new());
+        loop {
+            let op = unpickler.read_op()?;
+            let (start, end) = (unpickler.op_start, unpickler.pos);
+            annotator.pos = start;
+            if let Some(root) = annotator.exec(op, start, end, data[start])? {
+                return Ok(root);
+            }
+        }
+    }
+
+    fn exec(&mut self, step: Step, start: usize, end: usize, code: u8) -> Result<Option<Place>> {
+        let new = |kind| Place::new(start, end, code, kind);
+        let op = match step {
+            Step::Push(value) => {
+                let value = match value {
+                    Value::Int(i) => match i.to_i64() {
+                        Some(i) => Value::I64(i),
+                        None => Value::Int(i),
+                    },
+                    value => value.into_owned(),
+                };
+                self.stack.push(new(Kind::Leaf(Some(value))));
+                return Ok(None);
+            }
+            Step::Op(op) => op,
+        };
+        match op {
+            Opcode::Stop => return self.pop().map(Some),
+            Opcode::Mark => {
+                let stack = std::mem::take(&mut self.stack);
+                self.metastack.push((start, stack));
+            }
+            Opcode::Pop => {
+                if self.stack.is_empty() {
+                    self.pop_mark()?;
+                } else {
+                    self.pop()?;
+                }
+            }
+            Opcode::PopMark => {
+                self.pop_mark()?;
+            }
+            Opcode::Dup => {
+                let top = self.top()?;
+                top.entry.borrow_mut().duplicated = true;
+                let entry = top.entry.clone();
+                self.push_site(entry, start, end, code, None);
+            }
+
+            Opcode::Put(id) | Opcode::LongBinPut(id) => self.memoize(id, end)?,
+            Opcode::BinPut(id) => self.memoize(id.into(), end)?,
+            Opcode::Memoize => self.memoize(self.memo.len() as MemoId, end)?,
+            Opcode::Get(id) | Opcode::LongBinGet(id) => self.get(id, start, end, code)?,
+            Opcode::BinGet(id) => self.get(id.into(), start, end, code)?,
+
+            Opcode::EmptyTuple => self.stack.push(new(Kind::Tuple(Vec::new()))),
+            Opcode::Tuple1 | Opcode::Tuple2 | Opcode::Tuple3 => {
+                let n = match op {
+                    Opcode::Tuple1 => 1,
+                    Opcode::Tuple2 => 2,
+                    _ => 3,
+                };
+                let items = self.pop_n(n)?;
+                self.push_built(&items, start, end, code, Kind::Tuple(items.clone()));
+            }
+            Opcode::Tuple => {
+                let (mark, items) = self.pop_mark()?;
+                self.stack
+                    .push(Place::new(mark, end, code, Kind::Tuple(items)));
+            }
+
+            Opcode::EmptyList => self.stack.push(new(Kind::List(Vec::new()))),
+            Opcode::List => {
+                let (mark, items) = self.pop_mark()?;
+                self.stack
+                    .push(Place::new(mark, end, code, Kind::List(items)));
+            }
+            Opcode::Append => {
+                let item = self.pop()?;
+                self.add_items(vec![item], end)?;
+            }
+            Opcode::Appends => {
+                let (_, items) = self.pop_mark()?;
+                self.add_items(items, end)?;
+            }
+
+            Opcode::EmptyDict => self.stack.push(new(Kind::Dict(Vec::new()))),
+            Opcode::Dict => {
+                let (mark, items) = self.pop_mark()?;
+                let place = Place::new(mark, end, code, Kind::Dict(Vec::new()));
+                self.stack.push(place);
+                self.add_items(items, end)?;
+            }
+            Opcode::SetItem => {
+                let items = self.pop_n(2)?;
+                self.add_items(items, end)?;
+            }
+            Opcode::SetItems => {
+                let (_, items) = self.pop_mark()?;
+                self.add_items(items, end)?;
+            }
+
+            Opcode::EmptySet => self.stack.push(new(Kind::Leaf(None))),
+            Opcode::FrozenSet => {
+                let (mark, _) = self.pop_mark()?;
+                self.stack
+                    .push(Place::new(mark, end, code, Kind::Leaf(None)));
+            }
+            Opcode::AddItems => {
+                self.pop_mark()?;
+                self.top_mut()?.extend(end);
+            }
+
+            Opcode::Global { module, name } => self
+                .stack
+                .push(new(Kind::Global(module.into_owned(), name.into_owned()))),
+            Opcode::StackGlobal => {
+                let operands = self.pop_n(2)?;
+                let kind = match (operands[0].key(), operands[1].key()) {
+                    (Some(Value::String(module)), Some(Value::String(name))) => {
+                        Kind::Global(module.into_owned(), name.into_owned())
+                    }
+                    _ => Kind::Leaf(None),
+                };
+                self.push_built(&operands, start, end, code, kind);
+            }
+            Opcode::Reduce => {
+                let operands = self.pop_n(2)?;
+                let kind = reduce(&operands[0], &operands[1]);
+                self.push_built(&operands, start, end, code, kind);
+            }
+            Opcode::Inst { .. } | Opcode::Obj => {
+                let (mark, _) = self.pop_mark()?;
+                self.stack
+                    .push(Place::new(mark, end, code, Kind::Object(None)));
+            }
+            Opcode::NewObj => {
+                let operands = self.pop_n(2)?;
+                self.push_built(&operands, start, end, code, Kind::Object(None));
+            }
+            Opcode::NewObjEx => {
+                let operands = self.pop_n(3)?;
+                self.push_built(&operands, start, end, code, Kind::Object(None));
+            }
+            Opcode::Build => {
+                let state = self.pop()?;
+                let top = self.top_mut()?;
+                top.extend(end);
+                top.entry.borrow_mut().kind = Kind::Object(Some(state));
+            }
+
+            Opcode::PersId(_) => self.stack.push(new(Kind::Leaf(None))),
+            Opcode::BinPersId => {
+                let operands = self.pop_n(1)?;
+                self.push_built(&operands, start, end, code, Kind::Leaf(None));
+            }
+            Opcode::ReadonlyBuffer => self.top_mut()?.extend(end),
+
+            // Extensions and out-of-band buffers; scalars are pushed above.
+            op => return Err(self.error(ErrorCode::Unsupported(op.code() as char))),
+        }
+        Ok(None)
+    }
+
+    fn get(&mut self, id: MemoId, start: usize, end: usize, code: u8) -> Result<()> {
+        match self.memo.get(&id) {
+            Some(entry) => {
+                self.push_site(entry.clone(), start, end, code, Some(id));
+                Ok(())
+            }
+            None => Err(self.error(ErrorCode::MissingMemo(id))),
+        }
+    }
+
+    fn error(&self, code: ErrorCode) -> Error {
+        Error::eval(code, self.pos)
+    }
+
+    fn pop(&mut self) -> Result<Place> {
+        self.stack
+            .pop()
+            .ok_or_else(|| self.error(ErrorCode::StackUnderflow))
+    }
+
+    fn pop_n(&mut self, n: usize) -> Result<Vec<Place>> {
+        match self.stack.len().checked_sub(n) {
+            Some(len) => Ok(self.stack.split_off(len)),
+            None => Err(self.error(ErrorCode::StackUnderflow)),
+        }
+    }
+
+    // Pops the items down to the topmost MARK, and returns the offset of
+    // the MARK with them.
+    fn pop_mark(&mut self) -> Result<(usize, Vec<Place>)> {
+        match self.metastack.pop() {
+            Some((mark, stack)) => Ok((mark, std::mem::replace(&mut self.stack, stack))),
+            None => Err(self.error(ErrorCode::StackUnderflow)),
+        }
+    }
+
+    fn top(&self) -> Result<&Place> {
+        self.stack
+            .last()
+            .ok_or_else(|| self.error(ErrorCode::StackUnderflow))
+    }
+
+    fn top_mut(&mut self) -> Result<&mut Place> {
+        let pos = self.pos;
+        self.stack
+            .last_mut()
+            .ok_or(Error::eval(ErrorCode::StackUnderflow, pos))
+    }
+
+    fn push_site(
+        &mut self,
+        entry: Rc<RefCell<Entry>>,
+        start: usize,
+        end: usize,
+        opcode: u8,
+        fetched: Option<MemoId>,
+    ) {
+        let site = Annotation {
+            start,
+            end,
+            opcode,
+            memo: Vec::new(),
+            fetched,
+        };
+        self.stack.push(Place {
+            entry,
+            site: Some(site),
+        });
+    }
+
+    // Pushes a value built from `operands`, which starts where the first of
+    // them does.
+    fn push_built(&mut self, operands: &[Place], start: usize, end: usize, code: u8, kind: Kind) {
+        let start = operands.first().map_or(start, Place::start);
+        self.stack.push(Place::new(start, end, code, kind));
+    }
+
+    fn memoize(&mut self, id: MemoId, end: usize) -> Result<()> {
+        let top = self.top_mut()?;
+        match &mut top.site {
+            Some(site) => {
+                site.memo.push(id);
+                site.end = end;
+            }
+            None => {
+                let mut entry = top.entry.borrow_mut();
+                entry.memo.push(id);
+                entry.end = end;
+            }
+        }
+        let entry = top.entry.clone();
+        self.memo.insert(id, entry);
+        Ok(())
+    }
+
+    // Adds items to the list or dict on top of the stack.
+    fn add_items(&mut self, items: Vec<Place>, end: usize) -> Result<()> {
+        let top = self.top_mut()?;
+        top.extend(end);
+        let mut entry = top.entry.borrow_mut();
+        match &mut entry.kind {
+            Kind::List(list) => list.extend(items),
+            Kind::Dict(dict) => {
+                let mut items = items.into_iter();
+                while let (Some(key), Some(value)) = (items.next(), items.next()) {
+                    dict.push((key, value));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+// What the few calls that `Unpickler` evaluates give, or else an object.
+fn reduce(callable: &Place, args: &Place) -> Kind {
+    let entry = callable.entry.borrow();
+    let (module, name) = match &entry.kind {
+        Kind::Global(module, name) => (module.as_str(), name.as_str()),
+        _ => return Kind::Object(None),
+    };
+    let args = args.entry.borrow();
+    let first = match &args.kind {
+        Kind::Tuple(items) => items.first(),
+        _ => None,
+    };
+    let latin1 = |s: &str| Value::Bytes(s.chars().map(|ch| ch as u8).collect::<Vec<_>>().into());
+    match (module, name) {
+        ("_codecs", "encode") | ("__builtin__" | "builtins", "bytearray") => {
+            match first.and_then(Place::key) {
+                Some(Value::String(s)) => Kind::Leaf(Some(latin1(&s))),
+                Some(bytes @ Value::Bytes(_)) => Kind::Leaf(Some(bytes)),
+                _ => Kind::Leaf(None),
+            }
+        }
+        ("__builtin__" | "builtins", "list") => match first.map(|first| first.entry.borrow()) {
+            Some(list) => match &list.kind {
+                Kind::List(items) => Kind::List(items.clone()),
+                _ => Kind::Leaf(None),
+            },
+            None => Kind::Leaf(None),
+        },
+        ("__builtin__" | "builtins", "int") => Kind::Leaf(first.and_then(Place::key)),
+        ("__builtin__" | "builtins", "set" | "frozenset" | "complex") => Kind::Leaf(None),
+        _ => Kind::Object(None),
+    }
+}
+
+/// A pickle that is edited in place.  Replacing a value rewrites only the
+/// opcodes that built it, so everything else stays byte-identical: the
+/// opcode forms, the memo ids and the layout of the frames.
+pub struct Document {
+    data: Vec<u8>,
+    value: Value<'static>,
+    sources: SourceMap,
+}
+
+impl Document {
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        let value = Unpickler::value_from_slice(data, UnpicklerOptions::new())?.into_owned();
+        let sources = SourceMap::from_slice(data)?;
+        Ok(Document {
+            data: data.to_vec(),
+            value,
+            sources,
+        })
+    }
+
+    /// The unpickled value.
+    pub fn value(&self) -> &Value<'static> {
+        &self.value
+    }
+
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Replaces the value at `path` by `value`, written with the `Pickler`
+    /// in the protocol of the pickle and in the forms of the values it
+    /// replaces (see `Pickler::sources`).  The frame around the value, if
+    /// any, is resized.
+    ///
+    /// Replacing a value by an equal one leaves the pickle as it is.
+    ///
+    /// A value that is fetched from the memo elsewhere can't be replaced
+    /// without changing the other places too, so this fails, as it does for
+    /// a value that holds such a value.  Replacing the value at a place
+    /// where it is fetched only changes that place.
+    pub fn replace(&mut self, path: &[PathElem], value: &Value) -> Result<()> {
+        let mut path = path
+            .iter()
+            .cloned()
+            .map(PathElem::into_owned)
+            .collect::<Path>();
+        let annotation = match self.sources.annotations.get(&path) {
+            Some(annotation) => annotation.clone(),
+            None => return Err(invalid(format!("no value at {}", path))),
+        };
+        if lookup(&self.value, &path) == Some(value) {
+            return Ok(());
+        }
+        let (start, end) = (annotation.start, annotation.end);
+
+        // The memo stores within the value, the memo fetches, and the
+        // frames.
+        let mut stores = Vec::new();
+        let mut stored = HashSet::new();
+        let mut fetches = Vec::new();
+        let mut frames = Vec::new();
+        for op in ops_from_slice(&self.data) {
+            let (pos, op) = op?;
+            let id = match op {
+                Opcode::Put(id) | Opcode::LongBinPut(id) => Some(id),
+                Opcode::BinPut(id) => Some(id.into()),
+                Opcode::Memoize => Some(stored.len() as MemoId),
+                Opcode::Get(id) | Opcode::LongBinGet(id) => {
+                    fetches.push((pos, id));
+                    None
+                }
+                Opcode::BinGet(id) => {
+                    fetches.push((pos, id.into()));
+                    None
+                }
+                Opcode::Frame(len) => {
+                    let body = pos + 9;
+                    frames.push(body..body.saturating_add(len as usize));
+                    None
+                }
+                _ => None,
+            };
+            if let Some(id) = id {
+                stored.insert(id);
+                if (start..end).contains(&pos) {
+                    stores.push((id, op.into_owned()));
+                }
+            }
+        }
+        // The value is shared if it holds a value that is fetched after
+        // it, or if it is within a value that is fetched or duplicated.
+        let fetched_after = |id: &MemoId| fetches.iter().any(|&(pos, f)| pos >= end && f == *id);
+        let mut shared = stores.iter().any(|(id, _)| fetched_after(id));
+        for len in 0..=path.len() {
+            let outer = Path(path[..len].to_vec());
+            let within = len < path.len();
+            shared |= self.sources.duplicated.contains(&outer);
+            if let Some(outer) = self.sources.annotations.get(&outer) {
+                shared |= within && outer.fetched.is_some();
+                shared |= outer
+                    .memo
+                    .iter()
+                    .any(|id| fetches.iter().any(|&(_, f)| f == *id));
+            }
+        }
+        if shared {
+            return Err(invalid(format!(
+                "the value at {} is shared through the memo",
+                path
+            )));
+        }
+
+        // The new value is followed by its own memo stores, and by a
+        // MEMOIZE for each one within the old value, so that the ids of
+        // later MEMOIZEs stay the same.
+        let pickler = PicklerOptions::new().proto(self.sources.proto);
+        let pickler = Pickler::new(pickler).sources(&self.sources);
+        let mut ops = Vec::new();
+        pickler.write(value, &mut path, &mut ops)?;
+        for (id, op) in stores {
+            if op == Opcode::Memoize || annotation.memo.contains(&id) {
+                ops.push(op);
+            }
+        }
+        let mut replacement = Vec::new();
+        for op in ops {
+            op.encode(&mut replacement)?;
+        }
+
+        let mut data = Vec::with_capacity(self.data.len() - (end - start) + replacement.len());
+        data.extend_from_slice(&self.data[..start]);
+        data.extend_from_slice(&replacement);
+        data.extend_from_slice(&self.data[end..]);
+        // The frame that holds the start of the value now runs to the end of
+        // the frame that held its end, if any.
+        let containing = |pos: usize| frames.iter().find(|frame| frame.contains(&pos));
+        if let Some(first) = containing(start) {
+            let rest = containing(end - 1).map_or(0, |last| last.end.saturating_sub(end));
+            let len = (start - first.start + replacement.len() + rest) as u64;
+            data[first.start - 8..first.start].copy_from_slice(&len.to_le_bytes());
+        }
+        *self = Document::from_slice(&data)?;
+        Ok(())
+    }
+}
+
+// The value at `path` within `value`.
+fn lookup<'v>(value: &'v Value<'static>, path: &[PathElem<'static>]) -> Option<&'v Value<'static>> {
+    let (elem, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Some(value),
+    };
+    let item = match (value, elem) {
+        (Value::List(items) | Value::Tuple(items), PathElem::Index(i)) => items.get(*i)?,
+        (Value::Dict(dict), PathElem::Key(key)) => dict.get(key)?,
+        _ => return None,
+    };
+    lookup(item, rest)
+}
+
+fn invalid(msg: String) -> Error {
+    Error::Syntax(ErrorCode::InvalidValue(msg))
+}
@@ -0,0 +1,768 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::borrow::Cow;
+use std::io::Read;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use num_bigint::BigInt;
+use num_traits::Zero;
+
+use crate::error::Result;
+use crate::pickle::*;
+use crate::read::{IoSource, SliceSource, Source};
+use crate::value::float_repr;
+use crate::{Error, ErrorCode, Unpickler, UnpicklerOptions};
+
+/// The argument of `INT`, which protocol 1 also uses for booleans.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntArg {
+    Int(i64),
+    Bool(bool),
+}
+
+/// A pickle opcode with its argument decoded, as yielded by `ops`.
+///
+/// Strings and bytes borrow from the input when it is read from a slice.
+/// The quoted strings of `STRING` are unescaped, but left undecoded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Opcode<'a> {
+    // Protocol 0 and 1
+    Mark,
+    Stop,
+    Pop,
+    PopMark,
+    Dup,
+    Float(f64),
+    Int(IntArg),
+    BinInt(i32),
+    BinInt1(u8),
+    Long(BigInt),
+    BinInt2(u16),
+    None,
+    PersId(Cow<'a, [u8]>),
+    BinPersId,
+    Reduce,
+    String(Cow<'a, [u8]>),
+    BinString(Cow<'a, [u8]>),
+    ShortBinString(Cow<'a, [u8]>),
+    Unicode(Cow<'a, str>),
+    BinUnicode(Cow<'a, str>),
+    Append,
+    Build,
+    Global {
+        module: Cow<'a, str>,
+        name: Cow<'a, str>,
+    },
+    Dict,
+    EmptyDict,
+    Appends,
+    Get(u32),
+    BinGet(u8),
+    Inst {
+        module: Cow<'a, str>,
+        name: Cow<'a, str>,
+    },
+    LongBinGet(u32),
+    List,
+    EmptyList,
+    Obj,
+    Put(u32),
+    BinPut(u8),
+    LongBinPut(u32),
+    SetItem,
+    Tuple,
+    EmptyTuple,
+    SetItems,
+    BinFloat(f64),
+
+    // Protocol 2
+    Proto(u8),
+    NewObj,
+    Ext1(u8),
+    Ext2(u16),
+    Ext4(i32),
+    Tuple1,
+    Tuple2,
+    Tuple3,
+    NewTrue,
+    NewFalse,
+    Long1(BigInt),
+    Long4(BigInt),
+
+    // Protocol 3
+    BinBytes(Cow<'a, [u8]>),
+    ShortBinBytes(Cow<'a, [u8]>),
+
+    // Protocol 4
+    ShortBinUnicode(Cow<'a, str>),
+    BinUnicode8(Cow<'a, str>),
+    BinBytes8(Cow<'a, [u8]>),
+    EmptySet,
+    AddItems,
+    FrozenSet,
+    NewObjEx,
+    StackGlobal,
+    Memoize,
+    Frame(u64),
+
+    // Protocol 5
+    ByteArray8(Cow<'a, [u8]>),
+    NextBuffer,
+    ReadonlyBuffer,
+}
+
+/// The name, code and protocol of an opcode, as in `pickletools.opcodes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OpcodeInfo {
+    pub name: &'static str,
+    pub code: u8,
+    /// The first protocol that has the opcode.
+    pub proto: u8,
+}
+
+const fn info(name: &'static str, code: u8, proto: u8) -> OpcodeInfo {
+    OpcodeInfo { name, code, proto }
+}
+
+/// All opcodes, in the order of `pickletools.opcodes`.
+pub const OPCODES: &[OpcodeInfo] = &[
+    info("INT", INT, 0),
+    info("BININT", BININT, 1),
+    info("BININT1", BININT1, 1),
+    info("BININT2", BININT2, 1),
+    info("LONG", LONG, 0),
+    info("LONG1", LONG1, 2),
+    info("LONG4", LONG4, 2),
+    info("STRING", STRING, 0),
+    info("BINSTRING", BINSTRING, 1),
+    info("SHORT_BINSTRING", SHORT_BINSTRING, 1),
+    info("BINBYTES", BINBYTES, 3),
+    info("SHORT_BINBYTES", SHORT_BINBYTES, 3),
+    info("BINBYTES8", BINBYTES8, 4),
+    info("BYTEARRAY8", BYTEARRAY8, 5),
+    info("NEXT_BUFFER", NEXT_BUFFER, 5),
+    info("READONLY_BUFFER", READONLY_BUFFER, 5),
+    info("NONE", NONE, 0),
+    info("NEWTRUE", NEWTRUE, 2),
+    info("NEWFALSE", NEWFALSE, 2),
+    info("UNICODE", UNICODE, 0),
+    info("SHORT_BINUNICODE", SHORT_BINUNICODE, 4),
+    info("BINUNICODE", BINUNICODE, 1),
+    info("BINUNICODE8", BINUNICODE8, 4),
+    info("FLOAT", FLOAT, 0),
+    info("BINFLOAT", BINFLOAT, 1),
+    info("EMPTY_LIST", EMPTY_LIST, 1),
+    info("APPEND", APPEND, 0),
+    info("APPENDS", APPENDS, 1),
+    info("LIST", LIST, 0),
+    info("EMPTY_TUPLE", EMPTY_TUPLE, 1),
+    info("TUPLE", TUPLE, 0),
+    info("TUPLE1", TUPLE1, 2),
+    info("TUPLE2", TUPLE2, 2),
+    info("TUPLE3", TUPLE3, 2),
+    info("EMPTY_DICT", EMPTY_DICT, 1),
+    info("DICT", DICT, 0),
+    info("SETITEM", SETITEM, 0),
+    info("SETITEMS", SETITEMS, 1),
+    info("EMPTY_SET", EMPTY_SET, 4),
+    info("ADDITEMS", ADDITEMS, 4),
+    info("FROZENSET", FROZENSET, 4),
+    info("POP", POP, 0),
+    info("DUP", DUP, 0),
+    info("MARK", MARK, 0),
+    info("POP_MARK", POP_MARK, 1),
+    info("GET", GET, 0),
+    info("BINGET", BINGET, 1),
+    info("LONG_BINGET", LONG_BINGET, 1),
+    info("PUT", PUT, 0),
+    info("BINPUT", BINPUT, 1),
+    info("LONG_BINPUT", LONG_BINPUT, 1),
+    info("MEMOIZE", MEMOIZE, 4),
+    info("EXT1", EXT1, 2),
+    info("EXT2", EXT2, 2),
+    info("EXT4", EXT4, 2),
+    info("GLOBAL", GLOBAL, 0),
+    info("STACK_GLOBAL", STACK_GLOBAL, 4),
+    info("REDUCE", REDUCE, 0),
+    info("BUILD", BUILD, 0),
+    info("INST", INST, 0),
+    info("OBJ", OBJ, 1),
+    info("NEWOBJ", NEWOBJ, 2),
+    info("NEWOBJ_EX", NEWOBJ_EX, 4),
+    info("PROTO", PROTO, 2),
+    info("STOP", STOP, 0),
+    info("FRAME", FRAME, 4),
+    info("PERSID", PERSID, 0),
+    info("BINPERSID", BINPERSID, 1),
+];
+
+impl OpcodeInfo {
+    /// Looks up an opcode by its code byte.
+    pub fn from_code(code: u8) -> Option<&'static OpcodeInfo> {
+        OPCODES.iter().find(|info| info.code == code)
+    }
+
+    /// Looks up an opcode by its name, e.g. `"SHORT_BINUNICODE"`.
+    pub fn from_name(name: &str) -> Option<&'static OpcodeInfo> {
+        OPCODES.iter().find(|info| info.name == name)
+    }
+}
+
+impl<'a> Opcode<'a> {
+    /// The byte that encodes the opcode.
+    pub fn code(&self) -> u8 {
+        match self {
+            Opcode::Mark => MARK,
+            Opcode::Stop => STOP,
+            Opcode::Pop => POP,
+            Opcode::PopMark => POP_MARK,
+            Opcode::Dup => DUP,
+            Opcode::Float(_) => FLOAT,
+            Opcode::Int(_) => INT,
+            Opcode::BinInt(_) => BININT,
+            Opcode::BinInt1(_) => BININT1,
+            Opcode::Long(_) => LONG,
+            Opcode::BinInt2(_) => BININT2,
+            Opcode::None => NONE,
+            Opcode::PersId(_) => PERSID,
+            Opcode::BinPersId => BINPERSID,
+            Opcode::Reduce => REDUCE,
+            Opcode::String(_) => STRING,
+            Opcode::BinString(_) => BINSTRING,
+            Opcode::ShortBinString(_) => SHORT_BINSTRING,
+            Opcode::Unicode(_) => UNICODE,
+            Opcode::BinUnicode(_) => BINUNICODE,
+            Opcode::Append => APPEND,
+            Opcode::Build => BUILD,
+            Opcode::Global { .. } => GLOBAL,
+            Opcode::Dict => DICT,
+            Opcode::EmptyDict => EMPTY_DICT,
+            Opcode::Appends => APPENDS,
+            Opcode::Get(_) => GET,
+            Opcode::BinGet(_) => BINGET,
+            Opcode::Inst { .. } => INST,
+            Opcode::LongBinGet(_) => LONG_BINGET,
+            Opcode::List => LIST,
+            Opcode::EmptyList => EMPTY_LIST,
+            Opcode::Obj => OBJ,
+            Opcode::Put(_) => PUT,
+            Opcode::BinPut(_) => BINPUT,
+            Opcode::LongBinPut(_) => LONG_BINPUT,
+            Opcode::SetItem => SETITEM,
+            Opcode::Tuple => TUPLE,
+            Opcode::EmptyTuple => EMPTY_TUPLE,
+            Opcode::SetItems => SETITEMS,
+            Opcode::BinFloat(_) => BINFLOAT,
+            Opcode::Proto(_) => PROTO,
+            Opcode::NewObj => NEWOBJ,
+            Opcode::Ext1(_) => EXT1,
+            Opcode::Ext2(_) => EXT2,
+            Opcode::Ext4(_) => EXT4,
+            Opcode::Tuple1 => TUPLE1,
+            Opcode::Tuple2 => TUPLE2,
+            Opcode::Tuple3 => TUPLE3,
+            Opcode::NewTrue => NEWTRUE,
+            Opcode::NewFalse => NEWFALSE,
+            Opcode::Long1(_) => LONG1,
+            Opcode::Long4(_) => LONG4,
+            Opcode::BinBytes(_) => BINBYTES,
+            Opcode::ShortBinBytes(_) => SHORT_BINBYTES,
+            Opcode::ShortBinUnicode(_) => SHORT_BINUNICODE,
+            Opcode::BinUnicode8(_) => BINUNICODE8,
+            Opcode::BinBytes8(_) => BINBYTES8,
+            Opcode::EmptySet => EMPTY_SET,
+            Opcode::AddItems => ADDITEMS,
+            Opcode::FrozenSet => FROZENSET,
+            Opcode::NewObjEx => NEWOBJ_EX,
+            Opcode::StackGlobal => STACK_GLOBAL,
+            Opcode::Memoize => MEMOIZE,
+            Opcode::Frame(_) => FRAME,
+            Opcode::ByteArray8(_) => BYTEARRAY8,
+            Opcode::NextBuffer => NEXT_BUFFER,
+            Opcode::ReadonlyBuffer => READONLY_BUFFER,
+        }
+    }
+
+    pub fn info(&self) -> &'static OpcodeInfo {
+        // Every opcode has an entry in the table.
+        OpcodeInfo::from_code(self.code()).unwrap()
+    }
+
+    /// The name of the opcode, e.g. `"SHORT_BINUNICODE"`.
+    pub fn name(&self) -> &'static str {
+        self.info().name
+    }
+
+    /// The first protocol that has the opcode.
+    pub fn proto(&self) -> u8 {
+        self.info().proto
+    }
+
+    /// Appends the encoding of the opcode and its argument to `out`.  Fails
+    /// if the argument does not fit the length prefix of the opcode, or if
+    /// a line argument contains a newline.
+    pub fn encode(&self, out: &mut Vec<u8>) -> Result<()> {
+        out.push(self.code());
+        match self {
+            Opcode::Float(f) => write_line(out, float_repr(*f).as_bytes())?,
+            Opcode::Int(IntArg::Bool(b)) => {
+                write_line(out, if *b { TRUE } else { FALSE }.as_bytes())?
+            }
+            Opcode::Int(IntArg::Int(i)) => write_line(out, i.to_string().as_bytes())?,
+            Opcode::Long(i) => write_line(out, format!("{}L", i).as_bytes())?,
+            Opcode::Get(id) | Opcode::Put(id) => write_line(out, id.to_string().as_bytes())?,
+            Opcode::PersId(id) => write_line(out, id)?,
+            Opcode::String(b) => write_line(out, &escape_string(b))?,
+            Opcode::Unicode(s) => write_line(out, &escape_unicode(s))?,
+            Opcode::Global { module, name } | Opcode::Inst { module, name } => {
+                write_line(out, module.as_bytes())?;
+                write_line(out, name.as_bytes())?;
+            }
+            Opcode::BinInt(i) | Opcode::Ext4(i) => out.write_i32::<LittleEndian>(*i)?,
+            Opcode::BinInt1(i) | Opcode::BinGet(i) | Opcode::BinPut(i) | Opcode::Ext1(i) => {
+                out.push(*i)
+            }
+            Opcode::Proto(p) => out.push(*p),
+            Opcode::BinInt2(i) | Opcode::Ext2(i) => out.write_u16::<LittleEndian>(*i)?,
+            Opcode::LongBinGet(id) | Opcode::LongBinPut(id) => {
+                out.write_u32::<LittleEndian>(*id)?
+            }
+            Opcode::Frame(len) => out.write_u64::<LittleEndian>(*len)?,
+            Opcode::BinFloat(f) => out.write_f64::<BigEndian>(*f)?,
+            Opcode::Long1(i) => write_short(out, &encode_long(i), self)?,
+            Opcode::Long4(i) => write_long(out, &encode_long(i), self)?,
+            Opcode::BinString(b) => write_long(out, b, self)?,
+            Opcode::ShortBinString(b) | Opcode::ShortBinBytes(b) => write_short(out, b, self)?,
+            Opcode::BinUnicode(s) => write_u32(out, s.as_bytes(), self)?,
+            Opcode::ShortBinUnicode(s) => write_short(out, s.as_bytes(), self)?,
+            Opcode::BinUnicode8(s) => write_u64(out, s.as_bytes()),
+            Opcode::BinBytes(b) => write_u32(out, b, self)?,
+            Opcode::BinBytes8(b) | Opcode::ByteArray8(b) => write_u64(out, b),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn into_owned(self) -> Opcode<'static> {
+        fn bytes(b: Cow<[u8]>) -> Cow<'static, [u8]> {
+            Cow::Owned(b.into_owned())
+        }
+        fn string(s: Cow<str>) -> Cow<'static, str> {
+            Cow::Owned(s.into_owned())
+        }
+        match self {
+            Opcode::PersId(b) => Opcode::PersId(bytes(b)),
+            Opcode::String(b) => Opcode::String(bytes(b)),
+            Opcode::BinString(b) => Opcode::BinString(bytes(b)),
+            Opcode::ShortBinString(b) => Opcode::ShortBinString(bytes(b)),
+            Opcode::BinBytes(b) => Opcode::BinBytes(bytes(b)),
+            Opcode::ShortBinBytes(b) => Opcode::ShortBinBytes(bytes(b)),
+            Opcode::BinBytes8(b) => Opcode::BinBytes8(bytes(b)),
+            Opcode::ByteArray8(b) => Opcode::ByteArray8(bytes(b)),
+            Opcode::Unicode(s) => Opcode::Unicode(string(s)),
+            Opcode::BinUnicode(s) => Opcode::BinUnicode(string(s)),
+            Opcode::ShortBinUnicode(s) => Opcode::ShortBinUnicode(string(s)),
+            Opcode::BinUnicode8(s) => Opcode::BinUnicode8(string(s)),
+            Opcode::Global { module, name } => Opcode::Global {
+                module: string(module),
+                name: string(name),
+            },
+            Opcode::Inst { module, name } => Opcode::Inst {
+                module: string(module),
+                name: string(name),
+            },
+            Opcode::Mark => Opcode::Mark,
+            Opcode::Stop => Opcode::Stop,
+            Opcode::Pop => Opcode::Pop,
+            Opcode::PopMark => Opcode::PopMark,
+            Opcode::Dup => Opcode::Dup,
+            Opcode::Float(f) => Opcode::Float(f),
+            Opcode::Int(i) => Opcode::Int(i),
+            Opcode::BinInt(i) => Opcode::BinInt(i),
+            Opcode::BinInt1(i) => Opcode::BinInt1(i),
+            Opcode::Long(i) => Opcode::Long(i),
+            Opcode::BinInt2(i) => Opcode::BinInt2(i),
+            Opcode::None => Opcode::None,
+            Opcode::BinPersId => Opcode::BinPersId,
+            Opcode::Reduce => Opcode::Reduce,
+            Opcode::Append => Opcode::Append,
+            Opcode::Build => Opcode::Build,
+            Opcode::Dict => Opcode::Dict,
+            Opcode::EmptyDict => Opcode::EmptyDict,
+            Opcode::Appends => Opcode::Appends,
+            Opcode::Get(i) => Opcode::Get(i),
+            Opcode::BinGet(i) => Opcode::BinGet(i),
+            Opcode::LongBinGet(i) => Opcode::LongBinGet(i),
+            Opcode::List => Opcode::List,
+            Opcode::EmptyList => Opcode::EmptyList,
+            Opcode::Obj => Opcode::Obj,
+            Opcode::Put(i) => Opcode::Put(i),
+            Opcode::BinPut(i) => Opcode::BinPut(i),
+            Opcode::LongBinPut(i) => Opcode::LongBinPut(i),
+            Opcode::SetItem => Opcode::SetItem,
+            Opcode::Tuple => Opcode::Tuple,
+            Opcode::EmptyTuple => Opcode::EmptyTuple,
+            Opcode::SetItems => Opcode::SetItems,
+            Opcode::BinFloat(f) => Opcode::BinFloat(f),
+            Opcode::Proto(p) => Opcode::Proto(p),
+            Opcode::NewObj => Opcode::NewObj,
+            Opcode::Ext1(i) => Opcode::Ext1(i),
+            Opcode::Ext2(i) => Opcode::Ext2(i),
+            Opcode::Ext4(i) => Opcode::Ext4(i),
+            Opcode::Tuple1 => Opcode::Tuple1,
+            Opcode::Tuple2 => Opcode::Tuple2,
+            Opcode::Tuple3 => Opcode::Tuple3,
+            Opcode::NewTrue => Opcode::NewTrue,
+            Opcode::NewFalse => Opcode::NewFalse,
+            Opcode::Long1(i) => Opcode::Long1(i),
+            Opcode::Long4(i) => Opcode::Long4(i),
+            Opcode::EmptySet => Opcode::EmptySet,
+            Opcode::AddItems => Opcode::AddItems,
+            Opcode::FrozenSet => Opcode::FrozenSet,
+            Opcode::NewObjEx => Opcode::NewObjEx,
+            Opcode::StackGlobal => Opcode::StackGlobal,
+            Opcode::Memoize => Opcode::Memoize,
+            Opcode::Frame(n) => Opcode::Frame(n),
+            Opcode::NextBuffer => Opcode::NextBuffer,
+            Opcode::ReadonlyBuffer => Opcode::ReadonlyBuffer,
+        }
+    }
+}
+
+fn too_long<T>(op: &Opcode) -> Result<T> {
+    let reason = format!("argument of {} is too long", op.name());
+    Err(Error::Syntax(ErrorCode::InvalidValue(reason)))
+}
+
+fn write_line(out: &mut Vec<u8>, line: &[u8]) -> Result<()> {
+    if line.contains(&b'\n') {
+        let reason = "line argument contains a newline".into();
+        return Err(Error::Syntax(ErrorCode::InvalidValue(reason)));
+    }
+    out.extend_from_slice(line);
+    out.push(b'\n');
+    Ok(())
+}
+
+fn write_short(out: &mut Vec<u8>, data: &[u8], op: &Opcode) -> Result<()> {
+    match u8::try_from(data.len()) {
+        Ok(len) => out.push(len),
+        Err(_) => return too_long(op),
+    }
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+// With a signed 4-byte length, as `BINSTRING` and `LONG4` have.
+fn write_long(out: &mut Vec<u8>, data: &[u8], op: &Opcode) -> Result<()> {
+    match i32::try_from(data.len()) {
+        Ok(len) => out.write_i32::<LittleEndian>(len)?,
+        Err(_) => return too_long(op),
+    }
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+fn write_u32(out: &mut Vec<u8>, data: &[u8], op: &Opcode) -> Result<()> {
+    match u32::try_from(data.len()) {
+        Ok(len) => out.write_u32::<LittleEndian>(len)?,
+        Err(_) => return too_long(op),
+    }
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+fn write_u64(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+// The shortest little-endian two's complement encoding, which is empty for
+// zero, as in Python's `pickle.encode_long`.
+pub(crate) fn encode_long(i: &BigInt) -> Vec<u8> {
+    if i.is_zero() {
+        Vec::new()
+    } else {
+        i.to_signed_bytes_le()
+    }
+}
+
+// Quote bytes the way Python 2 writes a `STRING` argument.
+fn escape_string(bytes: &[u8]) -> Vec<u8> {
+    let mut out = vec![b'\''];
+    for &b in bytes {
+        match b {
+            b'\\' => out.extend_from_slice(b"\\\\"),
+            b'\'' => out.extend_from_slice(b"\\'"),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\r' => out.extend_from_slice(b"\\r"),
+            b'\t' => out.extend_from_slice(b"\\t"),
+            0x20..=0x7e => out.push(b),
+            b => out.extend_from_slice(format!("\\x{:02x}", b).as_bytes()),
+        }
+    }
+    out.push(b'\'');
+    out
+}
+
+// Encode a string as "raw-unicode-escape" for `UNICODE`, escaping the
+// characters that Python's pickler escapes as well.
+fn escape_unicode(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '\\' | '\0' | '\n' | '\r' | '\x1a' => {
+                out.extend_from_slice(format!("\\u{:04x}", ch as u32).as_bytes())
+            }
+            ch if (ch as u32) < 0x100 => out.push(ch as u8),
+            ch if (ch as u32) < 0x10000 => {
+                out.extend_from_slice(format!("\\u{:04x}", ch as u32).as_bytes())
+            }
+            ch => out.extend_from_slice(format!("\\U{:08x}", ch as u32).as_bytes()),
+        }
+    }
+    out
+}
+
+/// An iterator over the opcodes of a pickle and their offsets, see `ops`.
+///
+/// The iterator ends after `STOP`, or after the first error.
+pub struct Ops<'de, S: Source<'de>> {
+    unpickler: Unpickler<'de, S>,
+    done: bool,
+}
+
+/// Iterates over the opcodes of the pickle in `reader`, with the offset of
+/// each from the start of the pickle.  This is the counterpart of Python's
+/// `pickletools.genops`: no values are built, and nothing is checked beyond
+/// the encoding of each opcode.
+pub fn ops<R: Read>(reader: R) -> Ops<'static, IoSource<R>> {
+    Ops::with_source(IoSource::new(reader))
+}
+
+/// Like `ops`, for a pickle in a byte slice.  Arguments borrow from `slice`.
+pub fn ops_from_slice(slice: &[u8]) -> Ops<'_, SliceSource<'_>> {
+    Ops::with_source(SliceSource::new(slice))
+}
+
+impl<'de, S: Source<'de>> Ops<'de, S> {
+    pub fn with_source(source: S) -> Self {
+        Self {
+            unpickler: Unpickler::with_source(source, UnpicklerOptions::new()),
+            done: false,
+        }
+    }
+
+    /// The offset of the next opcode.
+    pub fn position(&self) -> usize {
+        self.unpickler.pos
+    }
+}
+
+impl<'de, S: Source<'de>> Iterator for Ops<'de, S> {
+    type Item = Result<(usize, Opcode<'de>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let offset = self.unpickler.pos;
+        let result = self.unpickler.read_opcode();
+        self.done = !matches!(result, Ok(ref op) if *op != Opcode::Stop);
+        Some(result.map(|op| (offset, op)))
+    }
+}
+
+impl<'de, S: Source<'de>> Unpickler<'de, S> {
+    // Read the next opcode and decode its argument, without acting on it.
+    pub(crate) fn read_opcode(&mut self) -> Result<Opcode<'de>> {
+        let code = self.read_byte()?;
+        self.read_opcode_arg(code)
+    }
+
+    // Decode the argument of the opcode `code`, which has just been read.
+    pub(crate) fn read_opcode_arg(&mut self, code: u8) -> Result<Opcode<'de>> {
+        let op = match code {
+            MARK => Opcode::Mark,
+            STOP => Opcode::Stop,
+            POP => Opcode::Pop,
+            POP_MARK => Opcode::PopMark,
+            DUP => Opcode::Dup,
+            FLOAT => {
+                let line = self.read_line()?;
+                Opcode::Float(self.parse_ascii(line)?)
+            }
+            INT => {
+                let line = self.read_line()?;
+                Opcode::Int(match &*line {
+                    b if b == FALSE.as_bytes() => IntArg::Bool(false),
+                    b if b == TRUE.as_bytes() => IntArg::Bool(true),
+                    _ => IntArg::Int(self.parse_ascii(line)?),
+                })
+            }
+            BININT => Opcode::BinInt(LittleEndian::read_i32(&self.read_fixed_4_bytes()?)),
+            BININT1 => Opcode::BinInt1(self.read_byte()?),
+            LONG => {
+                let line = self.read_line()?;
+                Opcode::Long(self.decode_text_long(line)?)
+            }
+            BININT2 => Opcode::BinInt2(LittleEndian::read_u16(&self.read_fixed_2_bytes()?)),
+            NONE => Opcode::None,
+            PERSID => Opcode::PersId(self.read_line()?),
+            BINPERSID => Opcode::BinPersId,
+            REDUCE => Opcode::Reduce,
+            STRING => {
+                let line = self.read_line()?;
+                Opcode::String(Cow::Owned(self.unescape_string(&line)?))
+            }
+            BINSTRING => {
+                let len = self.read_i32_length()?;
+                Opcode::BinString(self.read_sized(len)?)
+            }
+            SHORT_BINSTRING => {
+                let len = self.read_u8_length()?;
+                Opcode::ShortBinString(self.read_sized(len)?)
+            }
+            UNICODE => {
+                let line = self.read_line()?;
+                Opcode::Unicode(Cow::Owned(self.decode_escaped_unicode(&line)?))
+            }
+            BINUNICODE => {
+                let len = self.read_u32_length()?;
+                Opcode::BinUnicode(self.read_utf8(len)?)
+            }
+            APPEND => Opcode::Append,
+            BUILD => Opcode::Build,
+            GLOBAL => {
+                let (module, name) = self.read_global_names()?;
+                Opcode::Global { module, name }
+            }
+            DICT => Opcode::Dict,
+            EMPTY_DICT => Opcode::EmptyDict,
+            APPENDS => Opcode::Appends,
+            GET => {
+                let line = self.read_line()?;
+                Opcode::Get(self.parse_ascii(line)?)
+            }
+            BINGET => Opcode::BinGet(self.read_byte()?),
+            INST => {
+                let (module, name) = self.read_global_names()?;
+                Opcode::Inst { module, name }
+            }
+            LONG_BINGET => Opcode::LongBinGet(LittleEndian::read_u32(&self.read_fixed_4_bytes()?)),
+            LIST => Opcode::List,
+            EMPTY_LIST => Opcode::EmptyList,
+            OBJ => Opcode::Obj,
+            PUT => {
+                let line = self.read_line()?;
+                Opcode::Put(self.parse_ascii(line)?)
+            }
+            BINPUT => Opcode::BinPut(self.read_byte()?),
+            LONG_BINPUT => Opcode::LongBinPut(LittleEndian::read_u32(&self.read_fixed_4_bytes()?)),
+            SETITEM => Opcode::SetItem,
+            TUPLE => Opcode::Tuple,
+            EMPTY_TUPLE => Opcode::EmptyTuple,
+            SETITEMS => Opcode::SetItems,
+            BINFLOAT => Opcode::BinFloat(BigEndian::read_f64(&self.read_fixed_8_bytes()?)),
+
+            PROTO => Opcode::Proto(self.read_byte()?),
+            NEWOBJ => Opcode::NewObj,
+            EXT1 => Opcode::Ext1(self.read_byte()?),
+            EXT2 => Opcode::Ext2(LittleEndian::read_u16(&self.read_fixed_2_bytes()?)),
+            EXT4 => Opcode::Ext4(LittleEndian::read_i32(&self.read_fixed_4_bytes()?)),
+            TUPLE1 => Opcode::Tuple1,
+            TUPLE2 => Opcode::Tuple2,
+            TUPLE3 => Opcode::Tuple3,
+            NEWTRUE => Opcode::NewTrue,
+            NEWFALSE => Opcode::NewFalse,
+            LONG1 => {
+                let len = self.read_u8_length()?;
+                let bytes = self.read_sized(len)?;
+                Opcode::Long1(self.decode_binary_long(bytes))
+            }
+            LONG4 => {
+                let len = self.read_i32_length()?;
+                let bytes = self.read_sized(len)?;
+                Opcode::Long4(self.decode_binary_long(bytes))
+            }
+
+            BINBYTES => {
+                let len = self.read_u32_length()?;
+                Opcode::BinBytes(self.read_sized(len)?)
+            }
+            SHORT_BINBYTES => {
+                let len = self.read_u8_length()?;
+                Opcode::ShortBinBytes(self.read_sized(len)?)
+            }
+
+            SHORT_BINUNICODE => {
+                let len = self.read_u8_length()?;
+                Opcode::ShortBinUnicode(self.read_utf8(len)?)
+            }
+            BINUNICODE8 => {
+                let len = self.read_u64_length()?;
+                Opcode::BinUnicode8(self.read_utf8(len)?)
+            }
+            BINBYTES8 => {
+                let len = self.read_u64_length()?;
+                Opcode::BinBytes8(self.read_sized(len)?)
+            }
+            EMPTY_SET => Opcode::EmptySet,
+            ADDITEMS => Opcode::AddItems,
+            FROZENSET => Opcode::FrozenSet,
+            NEWOBJ_EX => Opcode::NewObjEx,
+            STACK_GLOBAL => Opcode::StackGlobal,
+            MEMOIZE => Opcode::Memoize,
+            FRAME => Opcode::Frame(self.read_u64_length()?),
+
+            BYTEARRAY8 => {
+                let len = self.read_u64_length()?;
+                Opcode::ByteArray8(self.read_sized(len)?)
+            }
+            NEXT_BUFFER => Opcode::NextBuffer,
+            READONLY_BUFFER => Opcode::ReadonlyBuffer,
+
+            code => return self.error(ErrorCode::Unsupported(code as char)),
+        };
+        Ok(op)
+    }
+
+    fn read_utf8(&mut self, len: u64) -> Result<Cow<'de, str>> {
+        let bytes = self.read_sized(len)?;
+        self.decode_utf8(bytes)
+    }
+
+    // Read a payload of the length its opcode declares, which is checked
+    // to fit a `usize` as the unpickler does.
+    fn read_sized(&mut self, len: u64) -> Result<Cow<'de, [u8]>> {
+        let len = self.payload_len(len)?;
+        self.read_bytes(len)
+    }
+
+    // Read the module and name lines of `GLOBAL` and `INST`.
+    fn read_global_names(&mut self) -> Result<(Cow<'de, str>, Cow<'de, str>)> {
+        let module = self.read_line()?;
+        let module = self.decode_utf8(module)?;
+        let name = self.read_line()?;
+        let name = self.decode_utf8(name)?;
+        Ok((module, name))
+    }
+}
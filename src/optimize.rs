@@ -0,0 +1,194 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::error::Result;
+use crate::ops::{ops_from_slice, Opcode};
+use crate::{Error, ErrorCode};
+
+// The size from which `pickle` ends a frame, and below which a frame is
+// not worth its header.
+pub(crate) const FRAME_SIZE_TARGET: usize = 64 * 1024;
+const FRAME_SIZE_MIN: usize = 4;
+
+/// Optimizes a pickle like Python's `pickletools.optimize`, working on the
+/// opcodes without unpickling:
+///
+/// * memo stores (`PUT`, `BINPUT`, `LONG_BINPUT` and `MEMOIZE`) whose value
+///   is never fetched are dropped,
+/// * the remaining stores are renumbered densely in order, and written as
+///   `MEMOIZE` from protocol 4, else in the smallest form, as are the
+///   fetches,
+/// * protocol 4 and later pickles are re-framed into frames of about 64
+///   KiB, with large opcodes outside any frame.
+///
+/// The output is the same as `pickletools.optimize`'s, and unpickles to
+/// the same value as `data`.
+pub fn optimize(data: &[u8]) -> Result<Vec<u8>> {
+    enum Item {
+        Put(u32),
+        Get(u32, usize),
+        Raw(usize, usize),
+    }
+
+    let mut items = Vec::new();
+    // The memo ids that are fetched, mapped to their new ids once stored.
+    let mut used = HashMap::new();
+    // The distinct memo ids stored, whose count `MEMOIZE` stores at.
+    let mut stored = HashSet::new();
+    let mut proto = 0;
+    let mut header = &data[..0];
+    let mut ops = ops_from_slice(data);
+    while let Some(op) = ops.next() {
+        let (pos, op) = op?;
+        let end = ops.position();
+        match op {
+            Opcode::Put(id) | Opcode::LongBinPut(id) => {
+                stored.insert(id);
+                items.push(Item::Put(id));
+            }
+            Opcode::BinPut(id) => {
+                stored.insert(id.into());
+                items.push(Item::Put(id.into()));
+            }
+            Opcode::Memoize => {
+                let id = stored.len() as u32;
+                stored.insert(id);
+                items.push(Item::Put(id));
+            }
+            Opcode::Frame(_) => {}
+            Opcode::Get(id) | Opcode::LongBinGet(id) => {
+                proto = proto.max(op.proto());
+                used.insert(id, None);
+                items.push(Item::Get(id, pos));
+            }
+            Opcode::BinGet(id) => {
+                proto = proto.max(op.proto());
+                used.insert(id.into(), None);
+                items.push(Item::Get(id.into(), pos));
+            }
+            Opcode::Proto(p) => {
+                proto = proto.max(p);
+                if pos == 0 {
+                    header = &data[pos..end];
+                } else {
+                    items.push(Item::Raw(pos, end));
+                }
+            }
+            op => {
+                proto = proto.max(op.proto());
+                items.push(Item::Raw(pos, end));
+            }
+        }
+    }
+
+    let mut out = header.to_vec();
+    let mut framer = Framer::new(proto >= 4);
+    let mut next_id = 0;
+    for item in items {
+        let mut op = Vec::new();
+        let large = match item {
+            Item::Put(id) => {
+                let Some(new_id) = used.get_mut(&id) else {
+                    continue;
+                };
+                *new_id = Some(next_id);
+                put(next_id, proto).encode(&mut op)?;
+                next_id += 1;
+                false
+            }
+            Item::Get(id, pos) => match used.get(&id) {
+                Some(Some(new_id)) => {
+                    get(*new_id, proto).encode(&mut op)?;
+                    false
+                }
+                _ => return Err(Error::eval(ErrorCode::MissingMemo(id), pos)),
+            },
+            Item::Raw(pos, end) => {
+                op.extend_from_slice(&data[pos..end]);
+                op.len() > FRAME_SIZE_TARGET
+            }
+        };
+        framer.write(&mut out, &op, large);
+    }
+    framer.finish(&mut out);
+    Ok(out)
+}
+
+// The memo store and fetch of `id` that `pickle` writes in `proto`.
+pub(crate) fn put(id: u32, proto: u8) -> Opcode<'static> {
+    match (proto, u8::try_from(id)) {
+        (4.., _) => Opcode::Memoize,
+        (1.., Ok(id)) => Opcode::BinPut(id),
+        (1.., Err(_)) => Opcode::LongBinPut(id),
+        _ => Opcode::Put(id),
+    }
+}
+
+pub(crate) fn get(id: u32, proto: u8) -> Opcode<'static> {
+    match (proto, u8::try_from(id)) {
+        (1.., Ok(id)) => Opcode::BinGet(id),
+        (1.., Err(_)) => Opcode::LongBinGet(id),
+        _ => Opcode::Get(id),
+    }
+}
+
+/// Splits the opcodes of a protocol 4 or later pickle into frames as
+/// `pickle` does: a frame is ended once it reaches 64 KiB, or before an
+/// opcode that is too large to be framed.
+pub(crate) struct Framer {
+    frame: Option<Vec<u8>>,
+}
+
+impl Framer {
+    pub(crate) fn new(framing: bool) -> Self {
+        Self {
+            frame: framing.then(Vec::new),
+        }
+    }
+
+    /// Writes the bytes of one opcode, outside of any frame if `large`.
+    pub(crate) fn write(&mut self, out: &mut Vec<u8>, op: &[u8], large: bool) {
+        self.commit(out, large);
+        match &mut self.frame {
+            Some(frame) if !large => frame.extend_from_slice(op),
+            _ => out.extend_from_slice(op),
+        }
+    }
+
+    pub(crate) fn finish(&mut self, out: &mut Vec<u8>) {
+        self.commit(out, true);
+        self.frame = None;
+    }
+
+    fn commit(&mut self, out: &mut Vec<u8>, force: bool) {
+        let Some(frame) = &mut self.frame else {
+            return;
+        };
+        if frame.len() >= FRAME_SIZE_TARGET || force {
+            if frame.len() >= FRAME_SIZE_MIN {
+                // The length of a frame always fits.
+                let _ = Opcode::Frame(frame.len() as u64).encode(out);
+            }
+            out.append(frame);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::fmt;
+use std::ops::Deref;
+
+use crate::Value;
+
+/// One step from a container to one of its items.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathElem<'a> {
+    /// The value of a dict key.
+    Key(Value<'a>),
+    /// An item of a list or tuple.
+    Index(usize),
+}
+
+impl<'a> PathElem<'a> {
+    pub fn into_owned(self) -> PathElem<'static> {
+        match self {
+            PathElem::Key(key) => PathElem::Key(key.into_owned()),
+            PathElem::Index(i) => PathElem::Index(i),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for PathElem<'a> {
+    fn from(key: &'a str) -> Self {
+        PathElem::Key(Value::String(key.into()))
+    }
+}
+
+impl<'a> From<usize> for PathElem<'a> {
+    fn from(index: usize) -> Self {
+        PathElem::Index(index)
+    }
+}
+
+impl<'a> fmt::Display for PathElem<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathElem::Key(Value::String(s)) => write!(fmt, "[{:?}]", s),
+            PathElem::Key(Value::Bytes(b)) => {
+                write!(fmt, "[b{:?}]", String::from_utf8_lossy(b))
+            }
+            PathElem::Key(Value::I64(i)) => write!(fmt, "[{}]", i),
+            PathElem::Key(Value::Int(i)) => write!(fmt, "[{}]", i),
+            PathElem::Key(Value::Bool(b)) => write!(fmt, "[{}]", b),
+            PathElem::Key(Value::None) => write!(fmt, "[None]"),
+            PathElem::Key(other) => write!(fmt, "[{}]", other),
+            PathElem::Index(i) => write!(fmt, "[{}]", i),
+        }
+    }
+}
+
+/// The location of a value within the unpickled object, e.g.
+/// `["optimizer"]["param_groups"][0]`.  The empty path is the object itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Path<'a>(pub Vec<PathElem<'a>>);
+
+impl<'a> Path<'a> {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn push<E: Into<PathElem<'a>>>(&mut self, elem: E) {
+        self.0.push(elem.into());
+    }
+
+    /// Returns the path extended by one step.
+    pub fn join<E: Into<PathElem<'a>>>(&self, elem: E) -> Self {
+        let mut path = self.clone();
+        path.push(elem);
+        path
+    }
+
+    pub fn into_owned(self) -> Path<'static> {
+        Path(self.0.into_iter().map(PathElem::into_owned).collect())
+    }
+}
+
+impl<'a> Deref for Path<'a> {
+    type Target = [PathElem<'a>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<'a> From<Vec<PathElem<'a>>> for Path<'a> {
+    fn from(elems: Vec<PathElem<'a>>) -> Self {
+        Self(elems)
+    }
+}
+
+impl<'a> FromIterator<PathElem<'a>> for Path<'a> {
+    fn from_iter<I: IntoIterator<Item = PathElem<'a>>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl<'a> fmt::Display for Path<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for elem in &self.0 {
+            write!(fmt, "{}", elem)?;
+        }
+        Ok(())
+    }
+}
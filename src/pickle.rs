@@ -18,499 +18,1391 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
-use std::io::{BufRead, BufReader, Read};
+use std::fmt::{self, Write};
+use std::io::{Read, Seek};
 use std::str::{self, FromStr};
 
-use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use byteorder::{ByteOrder, LittleEndian};
 use num_bigint::{BigInt, Sign};
 use num_traits::ToPrimitive;
 
-use crate::error::Result;
-use crate::value::{Global, Value};
+use crate::error::{EvalContext, Result};
+use crate::ops::{IntArg, Opcode, OpcodeInfo};
+use crate::path::{Path, PathElem};
+use crate::query::Query;
+use crate::read::{IoSource, SeekSource, SliceSource, Source};
+use crate::recover::{Diagnostic, Partial, Recovery};
+use crate::value::{Global, LazyBytes, Skipped, Truncated, Value};
 use crate::{Error, ErrorCode, F64Wrapper, HashMapWrapper, HashSetWrapper, MemoId};
 
-const MARK: u8 = b'('; // push special markobject on stack
-const STOP: u8 = b'.'; // every pickle ends with STOP
-const POP: u8 = b'0'; // discard topmost stack item
-const POP_MARK: u8 = b'1'; // discard stack top through topmost markobject
-const DUP: u8 = b'2'; // duplicate top stack item
-const FLOAT: u8 = b'F'; // push float object; decimal string argument
-const INT: u8 = b'I'; // push integer or bool; decimal string argument
-const BININT: u8 = b'J'; // push four-byte signed int
-const BININT1: u8 = b'K'; // push 1-byte unsigned int
-const LONG: u8 = b'L'; // push long; decimal string argument
-const BININT2: u8 = b'M'; // push 2-byte unsigned int
-const NONE: u8 = b'N'; // push None
-const PERSID: u8 = b'P'; // push persistent object; id is taken from string arg
-const BINPERSID: u8 = b'Q'; // " " " ;  "  "   "    "  stack
-const REDUCE: u8 = b'R'; // apply callable to argtuple, both on stack
-const STRING: u8 = b'S'; // push string; NL-terminated string argument
-const BINSTRING: u8 = b'T'; // push string; counted binary string argument
-const SHORT_BINSTRING: u8 = b'U'; // " " " ;    "      "       "      " < 256 bytes
-const UNICODE: u8 = b'V'; // push Unicode string; raw-unicode-escaped'd argument
-const BINUNICODE: u8 = b'X'; // " " " ; counted UTF-8 string argument
-const APPEND: u8 = b'a'; // append stack top to list below it
-const BUILD: u8 = b'b'; // call __setstate__ or __dict__.update()
-const GLOBAL: u8 = b'c'; // push self.find_class(modname, name); 2 string args
-const DICT: u8 = b'd'; // build a dict from stack items
-const EMPTY_DICT: u8 = b'}'; // push empty dict
-const APPENDS: u8 = b'e'; // extend list on stack by topmost stack slice
-const GET: u8 = b'g'; // push item from memo on stack; index is string arg
-const BINGET: u8 = b'h'; // " " " " " ;   "    " 1-byte arg
-const INST: u8 = b'i'; // build & push class instance
-const LONG_BINGET: u8 = b'j'; // push item from memo on stack; index is 4-byte arg
-const LIST: u8 = b'l'; // build list from topmost stack items
-const EMPTY_LIST: u8 = b']'; // push empty list
-const OBJ: u8 = b'o'; // build & push class instance
-const PUT: u8 = b'p'; // store stack top in memo; index is string arg
-const BINPUT: u8 = b'q'; // " " " " " " ;   "    " 1-byte arg
-const LONG_BINPUT: u8 = b'r'; // " " " " " " ;   "    " 4-byte arg
-const SETITEM: u8 = b's'; // add key+value pair to dict
-const TUPLE: u8 = b't'; // build tuple from topmost stack items
-const EMPTY_TUPLE: u8 = b')'; // push empty tuple
-const SETITEMS: u8 = b'u'; // modify dict by adding topmost key+value pairs
-const BINFLOAT: u8 = b'G'; // push float; arg is 8-byte float encoding
+pub(crate) const MARK: u8 = b'('; // push special markobject on stack
+pub(crate) const STOP: u8 = b'.'; // every pickle ends with STOP
+pub(crate) const POP: u8 = b'0'; // discard topmost stack item
+pub(crate) const POP_MARK: u8 = b'1'; // discard stack top through topmost markobject
+pub(crate) const DUP: u8 = b'2'; // duplicate top stack item
+pub(crate) const FLOAT: u8 = b'F'; // push float object; decimal string argument
+pub(crate) const INT: u8 = b'I'; // push integer or bool; decimal string argument
+pub(crate) const BININT: u8 = b'J'; // push four-byte signed int
+pub(crate) const BININT1: u8 = b'K'; // push 1-byte unsigned int
+pub(crate) const LONG: u8 = b'L'; // push long; decimal string argument
+pub(crate) const BININT2: u8 = b'M'; // push 2-byte unsigned int
+pub(crate) const NONE: u8 = b'N'; // push None
+pub(crate) const PERSID: u8 = b'P'; // push persistent object; id is taken from string arg
+pub(crate) const BINPERSID: u8 = b'Q'; // " " " ;  "  "   "    "  stack
+pub(crate) const REDUCE: u8 = b'R'; // apply callable to argtuple, both on stack
+pub(crate) const STRING: u8 = b'S'; // push string; NL-terminated string argument
+pub(crate) const BINSTRING: u8 = b'T'; // push string; counted binary string argument
+pub(crate) const SHORT_BINSTRING: u8 = b'U'; // " " " ;    "      "       "      " < 256 bytes
+pub(crate) const UNICODE: u8 = b'V'; // push Unicode string; raw-unicode-escaped'd argument
+pub(crate) const BINUNICODE: u8 = b'X'; // " " " ; counted UTF-8 string argument
+pub(crate) const APPEND: u8 = b'a'; // append stack top to list below it
+pub(crate) const BUILD: u8 = b'b'; // call __setstate__ or __dict__.update()
+pub(crate) const GLOBAL: u8 = b'c'; // push self.find_class(modname, name); 2 string args
+pub(crate) const DICT: u8 = b'd'; // build a dict from stack items
+pub(crate) const EMPTY_DICT: u8 = b'}'; // push empty dict
+pub(crate) const APPENDS: u8 = b'e'; // extend list on stack by topmost stack slice
+pub(crate) const GET: u8 = b'g'; // push item from memo on stack; index is string arg
+pub(crate) const BINGET: u8 = b'h'; // " " " " " ;   "    " 1-byte arg
+pub(crate) const INST: u8 = b'i'; // build & push class instance
+pub(crate) const LONG_BINGET: u8 = b'j'; // push item from memo on stack; index is 4-byte arg
+pub(crate) const LIST: u8 = b'l'; // build list from topmost stack items
+pub(crate) const EMPTY_LIST: u8 = b']'; // push empty list
+pub(crate) const OBJ: u8 = b'o'; // build & push class instance
+pub(crate) const PUT: u8 = b'p'; // store stack top in memo; index is string arg
+pub(crate) const BINPUT: u8 = b'q'; // " " " " " " ;   "    " 1-byte arg
+pub(crate) const LONG_BINPUT: u8 = b'r'; // " " " " " " ;   "    " 4-byte arg
+pub(crate) const SETITEM: u8 = b's'; // add key+value pair to dict
+pub(crate) const TUPLE: u8 = b't'; // build tuple from topmost stack items
+pub(crate) const EMPTY_TUPLE: u8 = b')'; // push empty tuple
+pub(crate) const SETITEMS: u8 = b'u'; // modify dict by adding topmost key+value pairs
+pub(crate) const BINFLOAT: u8 = b'G'; // push float; arg is 8-byte float encoding
 
 // # Protocol 2
-const PROTO: u8 = b'\x80'; // identify pickle protocol
-const NEWOBJ: u8 = b'\x81'; // build object by applying cls.__new__ to argtuple
-const EXT1: u8 = b'\x82'; // push object from extension registry; 1-byte index
-const EXT2: u8 = b'\x83'; // ditto, but 2-byte index
-const EXT4: u8 = b'\x84'; // ditto, but 4-byte index
-const TUPLE1: u8 = b'\x85'; // build 1-tuple from stack top
-const TUPLE2: u8 = b'\x86'; // build 2-tuple from two topmost stack items
-const TUPLE3: u8 = b'\x87'; // build 3-tuple from three topmost stack items
-const NEWTRUE: u8 = b'\x88'; // push True
-const NEWFALSE: u8 = b'\x89'; // push False
-const LONG1: u8 = b'\x8a'; // push long from < 256 bytes
-const LONG4: u8 = b'\x8b'; // push really big long
+pub(crate) const PROTO: u8 = b'\x80'; // identify pickle protocol
+pub(crate) const NEWOBJ: u8 = b'\x81'; // build object by applying cls.__new__ to argtuple
+pub(crate) const EXT1: u8 = b'\x82'; // push object from extension registry; 1-byte index
+pub(crate) const EXT2: u8 = b'\x83'; // ditto, but 2-byte index
+pub(crate) const EXT4: u8 = b'\x84'; // ditto, but 4-byte index
+pub(crate) const TUPLE1: u8 = b'\x85'; // build 1-tuple from stack top
+pub(crate) const TUPLE2: u8 = b'\x86'; // build 2-tuple from two topmost stack items
+pub(crate) const TUPLE3: u8 = b'\x87'; // build 3-tuple from three topmost stack items
+pub(crate) const NEWTRUE: u8 = b'\x88'; // push True
+pub(crate) const NEWFALSE: u8 = b'\x89'; // push False
+pub(crate) const LONG1: u8 = b'\x8a'; // push long from < 256 bytes
+pub(crate) const LONG4: u8 = b'\x8b'; // push really big long
 
 // # Protocol 3 (Python 3.x)
 
-const BINBYTES: u8 = b'B'; // push bytes; counted binary string argument
-const SHORT_BINBYTES: u8 = b'C'; // < 256 bytes
+pub(crate) const BINBYTES: u8 = b'B'; // push bytes; counted binary string argument
+pub(crate) const SHORT_BINBYTES: u8 = b'C'; // < 256 bytes
 
 // # Protocol 4
 
-const SHORT_BINUNICODE: u8 = b'\x8c'; // push short string; UTF-8 length < 256 bytes
-const BINUNICODE8: u8 = b'\x8d'; // push very long string
-const BINBYTES8: u8 = b'\x8e'; // push very long bytes string
-const EMPTY_SET: u8 = b'\x8f'; // push empty set on the stack
-const ADDITEMS: u8 = b'\x90'; // modify set by adding topmost stack items
-const FROZENSET: u8 = b'\x91'; // build frozenset from topmost stack items
-const NEWOBJ_EX: u8 = b'\x92'; // like NEWOBJ but work with keyword only arguments
-const STACK_GLOBAL: u8 = b'\x93'; // same as GLOBAL but using names on the stacks
-const MEMOIZE: u8 = b'\x94'; // store top of the stack in memo
-const FRAME: u8 = b'\x95'; // indicate the beginning of a new frame
+pub(crate) const SHORT_BINUNICODE: u8 = b'\x8c'; // push short string; UTF-8 length < 256 bytes
+pub(crate) const BINUNICODE8: u8 = b'\x8d'; // push very long string
+pub(crate) const BINBYTES8: u8 = b'\x8e'; // push very long bytes string
+pub(crate) const EMPTY_SET: u8 = b'\x8f'; // push empty set on the stack
+pub(crate) const ADDITEMS: u8 = b'\x90'; // modify set by adding topmost stack items
+pub(crate) const FROZENSET: u8 = b'\x91'; // build frozenset from topmost stack items
+pub(crate) const NEWOBJ_EX: u8 = b'\x92'; // like NEWOBJ but work with keyword only arguments
+pub(crate) const STACK_GLOBAL: u8 = b'\x93'; // same as GLOBAL but using names on the stacks
+pub(crate) const MEMOIZE: u8 = b'\x94'; // store top of the stack in memo
+pub(crate) const FRAME: u8 = b'\x95'; // indicate the beginning of a new frame
 
 // # Protocol 5
 
-const BYTEARRAY8: u8 = b'\x96'; // push bytearray
-const NEXT_BUFFER: u8 = b'\x97'; // push next out-of-band buffer
-const READONLY_BUFFER: u8 = b'\x98'; // make top of stack readonly
+pub(crate) const BYTEARRAY8: u8 = b'\x96'; // push bytearray
+pub(crate) const NEXT_BUFFER: u8 = b'\x97'; // push next out-of-band buffer
+pub(crate) const READONLY_BUFFER: u8 = b'\x98'; // make top of stack readonly
 
-const TRUE: &str = "01"; // not an opcode; see INT docs in pickletools.py
-const FALSE: &str = "00"; // not an opcode; see INT docs in pickletools.py
+pub(crate) const TRUE: &str = "01"; // not an opcode; see INT docs in pickletools.py
+pub(crate) const FALSE: &str = "00"; // not an opcode; see INT docs in pickletools.py
+
+// What the unpickler reads in one step: a scalar decoded right into a
+// `Value`, or another opcode as `ops` yields it.
+pub(crate) enum Step<'de> {
+    Push(Value<'de>),
+    Op(Opcode<'de>),
+}
+
+// Maximum number of stack slots looked at to find the paths of a value.
+const SELECT_BUDGET: usize = 256;
+// Maximum number of selected paths that a value may end up at, above which
+// it is built without looking further.
+const SELECT_PATHS: usize = 64;
+// Size up to which payloads are read even if they are not selected.
+const SMALL_PAYLOAD: u64 = 256;
+// Size of the pieces in which the rest of a truncated string is read.
+const CHUNK_SIZE: u64 = 64 * 1024;
+// Number of stack entries, and characters of each, shown in the context of
+// an error.
+const CONTEXT_ENTRIES: usize = 3;
+const CONTEXT_REPR_LEN: usize = 60;
 
 pub struct UnpicklerOptions {
-    fix_imports: bool,
-    encoding: String,
-    strict: bool,
     decode_strings: bool,
+    lazy_threshold: Option<u64>,
+    select: Option<Selector>,
+    preview: Option<PreviewLimits>,
+}
+
+/// How much of each value is built in preview mode, see
+/// `UnpicklerOptions::preview`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PreviewLimits {
+    /// Items kept of each list, tuple, set and frozenset.
+    pub items: usize,
+    /// Keys kept of each dict.
+    pub keys: usize,
+    /// Characters kept of each string, and bytes of each bytes value.
+    pub len: usize,
+}
+
+impl Default for PreviewLimits {
+    fn default() -> Self {
+        Self {
+            items: 20,
+            keys: 50,
+            len: 200,
+        }
+    }
+}
+
+type Selector = Box<dyn Fn(&[PathElem<'_>]) -> bool>;
+
+impl UnpicklerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode Python 2 strings (`STRING`, `BINSTRING` and `SHORT_BINSTRING`)
+    /// as UTF-8 into `Value::String`, instead of keeping them as `Value::Bytes`.
+    pub fn decode_strings(mut self, decode_strings: bool) -> Self {
+        self.decode_strings = decode_strings;
+        self
+    }
+
+    /// Leave bytes payloads (`BINBYTES`, `BINBYTES8` and `BYTEARRAY8`) of at
+    /// least `threshold` bytes in the input, and decode them as
+    /// `Value::LazyBytes` handles instead.  Use a `SeekSource` to skip over
+    /// them without reading.
+    pub fn lazy_bytes(mut self, threshold: u64) -> Self {
+        self.lazy_threshold = Some(threshold);
+        self
+    }
+
+    /// Build only the values whose path is accepted by `select`, and replace
+    /// everything else by `Value::Skipped` placeholders.  Payloads of skipped
+    /// strings and bytes are skipped over in the input.
+    ///
+    /// The predicate must accept all prefixes of a path that it accepts,
+    /// since containers on the way to a selected value have to be built too.
+    /// Keys of selected dicts and members of selected sets are always built.
+    ///
+    /// The path of a value is inferred from the stack before the value is
+    /// complete, following the layout of Python's pickler.  Where a value
+    /// may end up in several places, it is built if any of them is selected,
+    /// so more than the selection may be built.  Values skipped where they
+    /// first appear are built where the memo or a tuple later puts them at
+    /// a selected path, except for lists, dicts, sets and objects that took
+    /// items or state, and for large strings and bytes: these are built as
+    /// for the first place they appear at.  Paths through tuple keys are
+    /// not supported.
+    pub fn select<F>(mut self, select: F) -> Self
+    where
+        F: Fn(&[PathElem<'_>]) -> bool + 'static,
+    {
+        self.select = Some(Box::new(select));
+        self
+    }
+
+    /// Build only the value at `path`, the containers leading to it and
+    /// their keys.  See `select`.
+    pub fn select_path(self, path: Path<'static>) -> Self {
+        self.select(move |p: &[PathElem<'_>]| p.starts_with(&path) || path.starts_with(p))
+    }
+
+    /// Build only what `query` may pick, the containers leading to it and
+    /// their keys.  Everything below `..` and filters is built.  See
+    /// `select` and `Query::selects`.
+    pub fn select_query(self, query: &Query) -> Self {
+        let query = query.clone();
+        self.select(move |p: &[PathElem<'_>]| query.selects(p))
+    }
+
+    /// Build only the first items of each container and the start of each
+    /// string and bytes, as given by `limits`.  Values that were cut short
+    /// are wrapped in `Value::Truncated` with their true length, and the
+    /// rest of their payload is skipped over in the input.
+    ///
+    /// Payloads that protocol 0 writes as lines of text are read in full
+    /// before they are cut.
+    pub fn preview(mut self, limits: PreviewLimits) -> Self {
+        self.preview = Some(limits);
+        self
+    }
 }
 
 impl Default for UnpicklerOptions {
     fn default() -> Self {
         Self {
-            fix_imports: true,
-            encoding: "ASCII".to_string(),
-            strict: true,
             decode_strings: true,
+            lazy_threshold: None,
+            select: None,
+            preview: None,
         }
     }
 }
 
-pub struct Unpickler<R: Read> {
+pub struct Unpickler<'de, S: Source<'de>> {
     options: UnpicklerOptions,
-    reader: BufReader<R>,
-    metastack: Vec<Vec<Value>>,
-    stack: Vec<Value>,
-    memo: HashMap<MemoId, (Value, i32)>,
-    pos: usize,
+    source: S,
+    metastack: Vec<Vec<Value<'de>>>,
+    stack: Vec<Value<'de>>,
+    memo: HashMap<MemoId, (Value<'de>, i32)>,
+    pub(crate) pos: usize,
+    // State for selective decoding: the positions of open MARKs and of the
+    // most recently closed one, the position of the current opcode, whether
+    // payloads are skipped, the complete values that placeholders replaced,
+    // by the start and end of the placeholders, and the starts of
+    // placeholders standing in for objects that may still be built upon.
+    mark_pos: Vec<usize>,
+    last_mark: usize,
+    pub(crate) op_start: usize,
+    selecting: bool,
+    skipped_values: HashMap<(u64, u64), Value<'de>>,
+    objects: HashSet<u64>,
+    // State for the context of errors: the byte of the current opcode, the
+    // memo id that it refers to, and the last global pushed.
+    op_code: Option<u8>,
+    op_memo: Option<MemoId>,
+    last_global: Option<(String, String)>,
+}
+
+// Lookups of the paths of stack slots for one value.
+struct Lookup<'de> {
+    budget: usize,
+    cache: HashMap<SlotKey, Option<Vec<Path<'de>>>>,
 }
 
-impl<R: Read> Unpickler<R> {
+impl<'de> Lookup<'de> {
+    fn new() -> Self {
+        Lookup {
+            budget: SELECT_BUDGET,
+            cache: HashMap::new(),
+        }
+    }
+}
+
+// A stack level and position, and the `key_ok` and `direct` flags of
+// `Unpickler::slot_paths`.
+type SlotKey = (usize, usize, bool, bool);
+
+impl<R: Read> Unpickler<'static, IoSource<R>> {
     pub fn new(reader: R, options: UnpicklerOptions) -> Self {
+        Unpickler::with_source(IoSource::new(reader), options)
+    }
+
+    /// Decodes a value from a `std::io::Read`.
+    pub fn value_from_reader(rdr: R, options: UnpicklerOptions) -> Result<Value<'static>> {
+        Unpickler::new(rdr, options).load()
+    }
+}
+
+impl<R: Read + Seek> Unpickler<'static, SeekSource<R>> {
+    /// Decodes a value from a `std::io::Read + std::io::Seek`, seeking over
+    /// payloads that are left in the input.
+    pub fn value_from_seekable(rdr: R, options: UnpicklerOptions) -> Result<Value<'static>> {
+        Unpickler::with_source(SeekSource::new(rdr)?, options).load()
+    }
+}
+
+impl<'de> Unpickler<'de, SliceSource<'de>> {
+    pub fn from_slice(slice: &'de [u8], options: UnpicklerOptions) -> Self {
+        Unpickler::with_source(SliceSource::new(slice), options)
+    }
+
+    /// Decodes a value from a byte slice.  Bytes and strings in the result
+    /// borrow from `slice` wherever possible, instead of being copied.
+    pub fn value_from_slice(slice: &'de [u8], options: UnpicklerOptions) -> Result<Value<'de>> {
+        Unpickler::from_slice(slice, options).load()
+    }
+}
+
+impl<'de, S: Source<'de>> Unpickler<'de, S> {
+    pub fn with_source(source: S, options: UnpicklerOptions) -> Self {
         Self {
             options,
-            reader: BufReader::new(reader),
+            source,
             metastack: Vec::new(),
             stack: Vec::new(),
             memo: HashMap::new(),
             pos: 0,
+            mark_pos: Vec::new(),
+            last_mark: 0,
+            op_start: 0,
+            selecting: false,
+            skipped_values: HashMap::new(),
+            objects: HashSet::new(),
+            op_code: None,
+            op_memo: None,
+            last_global: None,
         }
     }
 
-    /// Decodes a value from a `std::io::Read`.
-    pub fn value_from_reader(rdr: R, options: UnpicklerOptions) -> Result<Value> {
-        let mut unpickler = Unpickler::new(rdr, options);
-        let value = unpickler.deserialize_value()?;
-        unpickler.end()?;
+    /// Decodes a single value and asserts that the input ends after it.
+    pub fn load(mut self) -> Result<Value<'de>> {
+        let value = self.deserialize_value()?;
+        self.end()?;
         Ok(value)
     }
 
-    fn deserialize_value(&mut self) -> Result<Value> {
-        let internal_value = self.parse_value()?;
-        self.convert_value(internal_value)
+    /// Decodes a value like `load`, but recovers from a pickle that ends
+    /// early or holds an unknown opcode: the MARKs that are still open are
+    /// closed, and the value at the bottom of the stack is returned, with
+    /// the containers built so far.  The diagnostics say where decoding
+    /// stopped and what was made up or dropped.  Other errors, and pickles
+    /// that end before any value, still fail.
+    pub fn load_partial(mut self) -> Result<Partial<'de>> {
+        let mut diagnostics = Vec::new();
+        let value = match self.parse_value() {
+            Ok(value) => {
+                if let Err(Error::Eval {
+                    code: ErrorCode::TrailingBytes,
+                    pos,
+                    ..
+                }) = self.end()
+                {
+                    let recovery = Recovery::TrailingBytes;
+                    diagnostics.push(Diagnostic { pos, recovery });
+                }
+                value
+            }
+            Err(Error::Eval {
+                code: code @ (ErrorCode::EOFWhileParsing | ErrorCode::Unsupported(_)),
+                context: None,
+                ..
+            }) if !(self.stack.is_empty() && self.metastack.is_empty()) => {
+                let pos = self.op_start;
+                diagnostics.push(Diagnostic {
+                    pos,
+                    recovery: Recovery::Stopped(code),
+                });
+                self.salvage(&mut diagnostics)?
+            }
+            Err(err) => return Err(self.with_context(err)),
+        };
+        let value = self.finish(value)?;
+        Ok(Partial { value, diagnostics })
+    }
+
+    // Closes the open MARKs, and returns the value at the bottom of the
+    // stack.
+    fn salvage(&mut self, diagnostics: &mut Vec<Diagnostic>) -> Result<Value<'de>> {
+        let stopped = self.op_start;
+        while !self.metastack.is_empty() {
+            let mut items = self.pop_mark()?;
+            let pos = self.last_mark;
+            let below = self.stack.last().map(|top| self.peek(top));
+            let recovery = match below {
+                Some(Value::List(_)) => {
+                    let n = items.len();
+                    self.modify_list(|list| list.extend(items))?;
+                    Recovery::AddedItems(n)
+                }
+                Some(Value::Dict(_)) => {
+                    if items.len() % 2 == 1 {
+                        items.pop();
+                        let recovery = Recovery::DroppedKey;
+                        diagnostics.push(Diagnostic { pos, recovery });
+                    }
+                    let n = items.len() / 2;
+                    self.modify_dict(|dict| {
+                        for chunk in items.chunks_exact(2) {
+                            dict.insert(chunk[0].clone(), chunk[1].clone());
+                        }
+                    })?;
+                    Recovery::AddedItems(n)
+                }
+                Some(Value::Set(_)) => {
+                    let n = items.len();
+                    self.modify_set(|set| set.extend(items))?;
+                    Recovery::AddedItems(n)
+                }
+                _ => {
+                    let n = items.len();
+                    self.stack.push(Value::List(items));
+                    Recovery::MadeList(n)
+                }
+            };
+            diagnostics.push(Diagnostic { pos, recovery });
+        }
+        // Values above a list or dict are taken to be waiting for APPEND or
+        // SETITEM, as in protocol 0.
+        let mut added = 0;
+        loop {
+            let len = self.stack.len();
+            if len >= 3 && matches!(self.peek(&self.stack[len - 3]), Value::Dict(_)) {
+                let value = self.pop()?;
+                let key = self.pop()?;
+                self.modify_dict(|dict| {
+                    dict.insert(key, value);
+                })?;
+            } else if len >= 2 && matches!(self.peek(&self.stack[len - 2]), Value::List(_)) {
+                let value = self.pop()?;
+                self.modify_list(|list| list.push(value))?;
+            } else {
+                break;
+            }
+            added += 1;
+        }
+        if added > 0 {
+            let recovery = Recovery::AddedValues(added);
+            diagnostics.push(Diagnostic {
+                pos: stopped,
+                recovery,
+            });
+        }
+        if self.stack.len() > 1 {
+            let recovery = Recovery::DroppedValues(self.stack.len() - 1);
+            diagnostics.push(Diagnostic {
+                pos: stopped,
+                recovery,
+            });
+        }
+        self.stack.truncate(1);
+        self.pop()
+    }
+
+    fn deserialize_value(&mut self) -> Result<Value<'de>> {
+        let internal_value = self.parse_value().map_err(|err| self.with_context(err))?;
+        self.finish(internal_value)
+    }
+
+    // Resolve the memo references in a decoded value, and put back the
+    // values skipped on selected paths.
+    fn finish(&mut self, value: Value<'de>) -> Result<Value<'de>> {
+        let mut value = self.convert_value(value)?;
+        if !self.skipped_values.is_empty() {
+            self.fill(&mut value, &mut Path::new());
+        }
+        Ok(value)
     }
 
-    fn parse_value(&mut self) -> Result<Value> {
+    fn parse_value(&mut self) -> Result<Value<'de>> {
+        self.selecting = self.options.select.is_some();
         loop {
-            let byte = self.read_byte()?;
-            match byte {
-                // Specials
-                PROTO => {
-                    // Ignore this, as it is only important for instances (read
-                    // the version byte).
-                    self.read_byte()?;
-                }
-                FRAME => {
-                    // We'll ignore framing. But we still have to gobble up the length.
-                    self.read_fixed_8_bytes()?;
-                }
-                STOP => return self.pop(),
-                MARK => {
+            let step = self.read_op()?;
+            let start = self.op_start;
+            self.op_memo = match step {
+                Step::Op(Opcode::Put(memo_id) | Opcode::LongBinPut(memo_id))
+                | Step::Op(Opcode::Get(memo_id) | Opcode::LongBinGet(memo_id)) => Some(memo_id),
+                Step::Op(Opcode::BinPut(memo_id) | Opcode::BinGet(memo_id)) => Some(memo_id.into()),
+                Step::Op(Opcode::Memoize) => Some(self.memo.len() as MemoId),
+                _ => None,
+            };
+            let op = match step {
+                // Numbers, strings and other scalars
+                Step::Push(value) => {
+                    self.stack.push(value);
+                    self.settle(start);
+                    continue;
+                }
+                Step::Op(op) => op,
+            };
+            match op {
+                Opcode::Stop => return self.pop(),
+                Opcode::Mark => {
                     let stack = std::mem::replace(&mut self.stack, Vec::with_capacity(128));
                     self.metastack.push(stack);
+                    self.mark_pos.push(start);
                 }
-                POP => {
+                Opcode::Pop => {
                     if self.stack.is_empty() {
                         self.pop_mark()?;
                     } else {
                         self.pop()?;
                     }
                 }
-                POP_MARK => {
+                Opcode::PopMark => {
                     self.pop_mark()?;
                 }
-                DUP => {
+                Opcode::Dup => {
                     let top = self.top()?.clone();
                     self.stack.push(top);
                 }
 
-                // Memo saving ops
-                PUT => {
-                    let bytes = self.read_line()?;
-                    let memo_id = self.parse_ascii(bytes)?;
-                    self.memoize(memo_id)?;
-                }
-                BINPUT => {
-                    let memo_id = self.read_byte()?;
-                    self.memoize(memo_id.into())?;
-                }
-                LONG_BINPUT => {
-                    let bytes = self.read_fixed_4_bytes()?;
-                    let memo_id = LittleEndian::read_u32(&bytes);
-                    self.memoize(memo_id)?;
-                }
-                MEMOIZE => {
+                // Memo ops
+                Opcode::Put(memo_id) | Opcode::LongBinPut(memo_id) => self.memoize(memo_id)?,
+                Opcode::BinPut(memo_id) => self.memoize(memo_id.into())?,
+                Opcode::Memoize => {
                     let memo_id = self.memo.len();
                     self.memoize(memo_id as MemoId)?;
                 }
-
-                // Memo getting ops
-                GET => {
-                    let bytes = self.read_line()?;
-                    let memo_id = self.parse_ascii(bytes)?;
-                    self.push_memo_ref(memo_id)?;
-                }
-                BINGET => {
-                    let memo_id = self.read_byte()?;
-                    self.push_memo_ref(memo_id.into())?;
-                }
-                LONG_BINGET => {
-                    let bytes = self.read_fixed_4_bytes()?;
-                    let memo_id = LittleEndian::read_u32(&bytes);
-                    self.push_memo_ref(memo_id)?;
-                }
-
-                // Singletons
-                NONE => self.stack.push(Value::None),
-                NEWFALSE => self.stack.push(Value::Bool(false)),
-                NEWTRUE => self.stack.push(Value::Bool(true)),
-
-                // ASCII-formatted numbers
-                INT => {
-                    let line = self.read_line()?;
-                    let val = self.decode_text_int(line)?;
-                    self.stack.push(val);
-                }
-                LONG => {
-                    let line = self.read_line()?;
-                    let long = self.decode_text_long(line)?;
-                    self.stack.push(long);
-                }
-                FLOAT => {
-                    let line = self.read_line()?;
-                    let f = F64Wrapper(self.parse_ascii(line)?);
-                    self.stack.push(Value::F64(f));
-                }
-
-                // ASCII-formatted strings
-                STRING => {
-                    let line = self.read_line()?;
-                    let string = self.decode_escaped_string(&line)?;
-                    self.stack.push(string);
-                }
-                UNICODE => {
-                    let line = self.read_line()?;
-                    let string = self.decode_escaped_unicode(&line)?;
-                    self.stack.push(string);
-                }
-
-                // Binary-coded numbers
-                BINFLOAT => {
-                    let bytes = self.read_fixed_8_bytes()?;
-                    self.stack
-                        .push(Value::F64(F64Wrapper(BigEndian::read_f64(&bytes))));
-                }
-                BININT => {
-                    let bytes = self.read_fixed_4_bytes()?;
-                    self.stack
-                        .push(Value::I64(LittleEndian::read_i32(&bytes).into()));
-                }
-                BININT1 => {
-                    let byte = self.read_byte()?;
-                    self.stack.push(Value::I64(byte.into()));
-                }
-                BININT2 => {
-                    let bytes = self.read_fixed_2_bytes()?;
-                    self.stack
-                        .push(Value::I64(LittleEndian::read_u16(&bytes).into()));
-                }
-                LONG1 => {
-                    let bytes = self.read_u8_prefixed_bytes()?;
-                    let long = self.decode_binary_long(bytes);
-                    self.stack.push(long);
-                }
-                LONG4 => {
-                    let bytes = self.read_i32_prefixed_bytes()?;
-                    let long = self.decode_binary_long(bytes);
-                    self.stack.push(long);
-                }
-
-                // Length-prefixed (byte)strings
-                SHORT_BINBYTES => {
-                    let string = self.read_u8_prefixed_bytes()?;
-                    self.stack.push(Value::Bytes(string));
-                }
-                BINBYTES => {
-                    let string = self.read_u32_prefixed_bytes()?;
-                    self.stack.push(Value::Bytes(string));
-                }
-                BINBYTES8 => {
-                    let string = self.read_u64_prefixed_bytes()?;
-                    self.stack.push(Value::Bytes(string));
-                }
-                SHORT_BINSTRING => {
-                    let string = self.read_u8_prefixed_bytes()?;
-                    let decoded = self.decode_string(string)?;
-                    self.stack.push(decoded);
-                }
-                BINSTRING => {
-                    let string = self.read_i32_prefixed_bytes()?;
-                    let decoded = self.decode_string(string)?;
-                    self.stack.push(decoded);
-                }
-                SHORT_BINUNICODE => {
-                    let string = self.read_u8_prefixed_bytes()?;
-                    let decoded = self.decode_unicode(string)?;
-                    self.stack.push(decoded);
-                }
-                BINUNICODE => {
-                    println!("BINUNICODE");
-                    let string = self.read_u32_prefixed_bytes()?;
-                    let decoded = self.decode_unicode(string)?;
-                    println!("BINUNICODE - decoded: {:?}", decoded);
-                    self.stack.push(decoded);
-                }
-                BINUNICODE8 => {
-                    let string = self.read_u64_prefixed_bytes()?;
-                    let decoded = self.decode_unicode(string)?;
-                    self.stack.push(decoded);
-                }
-                BYTEARRAY8 => {
-                    let string = self.read_u64_prefixed_bytes()?;
-                    self.stack.push(Value::Bytes(string));
+                Opcode::Get(memo_id) | Opcode::LongBinGet(memo_id) => {
+                    self.push_memo_ref(memo_id)?
                 }
+                Opcode::BinGet(memo_id) => self.push_memo_ref(memo_id.into())?,
 
                 // Tuples
-                EMPTY_TUPLE => self.stack.push(Value::Tuple(Vec::new())),
-                TUPLE1 => {
-                    let item = self.pop()?;
-                    self.stack.push(Value::Tuple(vec![item]));
-                }
-                TUPLE2 => {
-                    let item2 = self.pop()?;
-                    let item1 = self.pop()?;
-                    self.stack.push(Value::Tuple(vec![item1, item2]));
-                }
-                TUPLE3 => {
-                    let item3 = self.pop()?;
-                    let item2 = self.pop()?;
-                    let item1 = self.pop()?;
-                    self.stack.push(Value::Tuple(vec![item1, item2, item3]));
-                }
-                TUPLE => {
+                Opcode::EmptyTuple => {
+                    self.stack.push(Value::Tuple(Vec::new()));
+                    self.settle(start);
+                }
+                Opcode::Tuple1 | Opcode::Tuple2 | Opcode::Tuple3 => {
+                    let n = match op {
+                        Opcode::Tuple1 => 1,
+                        Opcode::Tuple2 => 2,
+                        _ => 3,
+                    };
+                    let mut items = Vec::with_capacity(n);
+                    for _ in 0..n {
+                        items.push(self.pop()?);
+                    }
+                    items.reverse();
+                    let start = self.first_start(&items, start);
+                    let tuple = Self::cap(self.item_limit(), 1, items, Value::Tuple);
+                    self.stack.push(tuple);
+                    self.settle(start);
+                }
+                Opcode::Tuple => {
                     let items = self.pop_mark()?;
-                    self.stack.push(Value::Tuple(items));
+                    let tuple = Self::cap(self.item_limit(), 1, items, Value::Tuple);
+                    self.stack.push(tuple);
+                    self.settle(self.last_mark);
                 }
 
                 // Lists
-                EMPTY_LIST => self.stack.push(Value::List(Vec::new())),
-                LIST => {
+                Opcode::EmptyList => {
+                    self.stack.push(Value::List(Vec::new()));
+                    self.settle(start);
+                }
+                Opcode::List => {
                     let items = self.pop_mark()?;
-                    self.stack.push(Value::List(items));
+                    let list = Self::cap(self.item_limit(), 1, items, Value::List);
+                    self.stack.push(list);
+                    self.settle(self.last_mark);
                 }
-                APPEND => {
+                Opcode::Append => {
                     let value = self.pop()?;
-                    self.modify_list(|list| list.push(value))?;
+                    self.append(vec![value])?;
                 }
-                APPENDS => {
+                Opcode::Appends => {
                     let items = self.pop_mark()?;
-                    self.modify_list(|list| list.extend(items))?;
+                    self.append(items)?;
                 }
 
                 // Dicts
-                EMPTY_DICT => self.stack.push(Value::Dict(HashMapWrapper(HashMap::new()))),
-                DICT => {
-                    let items = self.pop_mark()?;
-                    let mut dict = HashMap::with_capacity(items.len() / 2);
-                    for chunk in items.chunks_exact(2) {
-                        dict.insert(chunk[0].clone(), chunk[1].clone());
-                    }
-                    self.stack.push(Value::Dict(HashMapWrapper(dict)));
+                Opcode::EmptyDict => {
+                    self.stack.push(Value::Dict(HashMapWrapper::new()));
+                    self.settle(start);
                 }
-                SETITEM => {
+                Opcode::Dict => {
+                    let items = self.pop_mark()?;
+                    let dict = Self::cap(self.key_limit(), 2, items, |items| {
+                        let mut dict = Vec::with_capacity(items.len() / 2);
+                        Self::extend_dict(&mut dict, items);
+                        Value::Dict(dict.into_iter().collect())
+                    });
+                    self.stack.push(dict);
+                    self.settle(self.last_mark);
+                }
+                Opcode::SetItem => {
                     let value = self.pop()?;
                     let key = self.pop()?;
-                    self.modify_dict(|dict| {
-                        dict.insert(key, value);
-                    })?;
+                    self.set_items(vec![key, value])?;
                 }
-                SETITEMS => {
+                Opcode::SetItems => {
                     let items = self.pop_mark()?;
-                    self.modify_dict(|dict| {
-                        for chunk in items.chunks_exact(2) {
-                            dict.insert(chunk[0].clone(), chunk[1].clone());
-                        }
-                    })?;
+                    self.set_items(items)?;
                 }
 
                 // Sets and frozensets
-                EMPTY_SET => self.stack.push(Value::Set(HashSetWrapper(HashSet::new()))),
-                FROZENSET => {
-                    let items = self.pop_mark()?;
-                    self.stack.push(Value::FrozenSet(HashSetWrapper(
-                        items.into_iter().collect(),
-                    )));
+                Opcode::EmptySet => {
+                    self.stack.push(Value::Set(HashSetWrapper::new()));
+                    self.settle(start);
                 }
-                ADDITEMS => {
+                Opcode::FrozenSet => {
                     let items = self.pop_mark()?;
-                    self.modify_set(|set| set.extend(items))?;
+                    let items = self.realize_all(items, None);
+                    let set = Self::cap(self.item_limit(), 1, items, |items| {
+                        Value::FrozenSet(items.into_iter().collect())
+                    });
+                    self.stack.push(set);
+                    self.settle(self.last_mark);
+                }
+                Opcode::AddItems => {
+                    let mut items = self.pop_mark()?;
+                    if self.extend_skipped(items.len())? {
+                        items.iter().for_each(|item| self.forget(item));
+                    } else {
+                        let room = self.preview_room(self.item_limit(), items.len())?;
+                        items.truncate(room);
+                        let items = self.realize_all(items, None);
+                        self.modify_set(|set| set.extend(items))?;
+                    }
                 }
 
                 // Arbitrary module globals, used here for unpickling set and frozenset
                 // from protocols < 4
-                GLOBAL => {
-                    let modname = self.read_line()?;
-                    let globname = self.read_line()?;
-                    let value = self.decode_global(modname, globname)?;
+                Opcode::Global { module, name } => {
+                    self.set_last_global(module.as_bytes(), name.as_bytes());
+                    // Globals are small and often shared through the memo,
+                    // so they are always built.
+                    let value = self.decode_global(module.as_bytes(), name.as_bytes())?;
                     self.stack.push(value);
                 }
-                STACK_GLOBAL => {
-                    let globname = match self.pop_resolve()? {
-                        Value::String(string) => string.into_bytes(),
-                        other => return Self::stack_error("string", &other, self.pos),
-                    };
-                    let modname = match self.pop_resolve()? {
-                        Value::String(string) => string.into_bytes(),
-                        other => return Self::stack_error("string", &other, self.pos),
+                Opcode::StackGlobal => {
+                    let globname = self.pop_resolve()?;
+                    let modname = self.pop_resolve()?;
+                    let mut operands = self.realize_all([modname, globname], None).into_iter();
+                    let value = match (operands.next().unwrap(), operands.next().unwrap()) {
+                        (Value::String(modname), Value::String(globname)) => {
+                            self.set_last_global(modname.as_bytes(), globname.as_bytes());
+                            self.decode_global(modname.as_bytes(), globname.as_bytes())?
+                        }
+                        (modname @ Value::Skipped(_), globname)
+                        | (modname, globname @ Value::Skipped(_)) => {
+                            let start = self.first_start([&modname, &globname], start);
+                            self.push_object_placeholder(start);
+                            continue;
+                        }
+                        (modname, Value::String(_)) => {
+                            return Self::stack_error("string", &modname, self.pos)
+                        }
+                        (_, globname) => return Self::stack_error("string", &globname, self.pos),
                     };
-                    let value = self.decode_global(modname, globname)?;
                     self.stack.push(value);
                 }
-                REDUCE => {
-                    let argtuple = match self.pop_resolve()? {
-                        Value::Tuple(args) => args,
+                Opcode::Reduce => {
+                    let argtuple = self.pop_resolve()?;
+                    let global = self.pop_resolve()?;
+                    let start = self.first_start([&global, &argtuple], start);
+                    let mut operands = self.realize_all([global, argtuple], None).into_iter();
+                    let (global, argtuple) = (operands.next().unwrap(), operands.next().unwrap());
+                    let skipped =
+                        |value: &Value<'de>| matches!(self.peek(value), Value::Skipped(_));
+                    let argtuple = match argtuple {
+                        Value::Tuple(args) if !skipped(&global) && !args.iter().any(skipped) => {
+                            args
+                        }
+                        Value::Tuple(_) | Value::Skipped(_) => {
+                            self.push_object_placeholder(start);
+                            continue;
+                        }
                         other => return Self::stack_error("tuple", &other, self.pos),
                     };
-                    let global = self.pop_resolve()?;
                     self.reduce_global(global, argtuple)?;
+                    self.settle_object(start);
                 }
 
                 // Arbitrary classes - make a best effort attempt to recover some data
-                INST => {
-                    // pop module name and class name
-                    for _ in 0..2 {
-                        self.read_line()?;
-                    }
+                Opcode::Inst { module, name } => {
+                    self.set_last_global(module.as_bytes(), name.as_bytes());
                     // pop arguments to init
                     self.pop_mark()?;
                     // push empty dictionary instead of the class instance
-                    self.stack.push(Value::Dict(HashMapWrapper(HashMap::new())));
+                    self.stack.push(Value::Dict(HashMapWrapper::new()));
+                    self.settle_object(self.last_mark);
                 }
-                OBJ => {
+                Opcode::Obj => {
                     // pop arguments to init
                     self.pop_mark()?;
                     // pop class object
                     self.pop()?;
-                    self.stack.push(Value::Dict(HashMapWrapper(HashMap::new())));
+                    self.stack.push(Value::Dict(HashMapWrapper::new()));
+                    self.settle_object(self.last_mark);
                 }
-                NEWOBJ => {
+                Opcode::NewObj => {
                     // pop arguments and class object
+                    let mut operands = Vec::with_capacity(2);
                     for _ in 0..2 {
-                        self.pop()?;
+                        operands.push(self.pop()?);
                     }
-                    self.stack.push(Value::Dict(HashMapWrapper(HashMap::new())));
+                    let start = self.first_start(&operands, start);
+                    self.stack.push(Value::Dict(HashMapWrapper::new()));
+                    self.settle_object(start);
                 }
-                NEWOBJ_EX => {
+                Opcode::NewObjEx => {
                     // pop keyword args, arguments and class object
+                    let mut operands = Vec::with_capacity(3);
                     for _ in 0..3 {
-                        self.pop()?;
+                        operands.push(self.pop()?);
                     }
-                    self.stack.push(Value::Dict(HashMapWrapper(HashMap::new())));
+                    let start = self.first_start(&operands, start);
+                    self.stack.push(Value::Dict(HashMapWrapper::new()));
+                    self.settle_object(start);
                 }
-                BUILD => {
+                Opcode::Build => {
                     // The top-of-stack for BUILD is used either as the instance __dict__,
                     // or an argument for __setstate__, in which case it can be *any* type
                     // of object.  In both cases, we just replace the standin.
                     let state = self.pop()?;
-                    self.pop()?; // remove the object standin
+                    let standin = self.pop()?; // remove the object standin
+                    let start = self.first_start([&standin, &state], start);
                     self.stack.push(state);
+                    self.settle(start);
                 }
 
-                PERSID => {
-                    let line = self.read_line()?;
-                    println!("PERSID: {:?}", line);
+                Opcode::PersId(line) => {
                     let bytes = Value::Bytes(line);
                     self.stack.push(Value::BinPersId(Box::new(bytes)));
+                    self.settle(start);
                 }
-
-                BINPERSID => {
+                Opcode::BinPersId => {
                     let binpers_id = self.pop()?;
+                    let start = self.first_start([&binpers_id], start);
                     self.stack.push(Value::BinPersId(Box::new(binpers_id)));
+                    self.settle(start);
+                }
+
+                // Out-of-band buffers are never backed by anything we could
+                // modify, so there is nothing to do to make one read-only.
+                Opcode::ReadonlyBuffer => {
+                    self.top()?;
+                }
+
+                // Extensions and out-of-band buffers; scalars are pushed
+                // above.
+                op => return self.error(ErrorCode::Unsupported(op.code() as char)),
+            }
+        }
+    }
+
+    fn item_limit(&self) -> Option<usize> {
+        self.options.preview.map(|limits| limits.items)
+    }
+
+    fn key_limit(&self) -> Option<usize> {
+        self.options.preview.map(|limits| limits.keys)
+    }
+
+    // Build a container from at most `limit` of the items, which come in
+    // groups of `per` values, and wrap it if any were left out.
+    fn cap<F>(limit: Option<usize>, per: usize, mut items: Vec<Value<'de>>, build: F) -> Value<'de>
+    where
+        F: FnOnce(Vec<Value<'de>>) -> Value<'de>,
+    {
+        let len = items.len() / per;
+        match limit {
+            Some(limit) if len > limit => {
+                items.truncate(limit * per);
+                Value::Truncated(Box::new(Truncated {
+                    value: build(items),
+                    len: len as u64,
+                }))
+            }
+            _ => build(items),
+        }
+    }
+
+    // The number of `n` items to be added to the stack-top container that
+    // fit within `limit`.  The container is wrapped as soon as items are
+    // left out, and counts all further items from then on.
+    fn preview_room(&mut self, limit: Option<usize>, n: usize) -> Result<usize> {
+        let limit = match limit {
+            Some(limit) => limit,
+            None => return Ok(n),
+        };
+        let top = self.top()?;
+        let len = match top {
+            Value::Truncated(truncated) => {
+                truncated.len += n as u64;
+                return Ok(0);
+            }
+            Value::List(items) => items.len(),
+            Value::Set(set) => set.0.len(),
+            Value::Dict(dict) => dict.0.len(),
+            // Not a container; the caller reports the error.
+            _ => return Ok(n),
+        };
+        let room = limit.saturating_sub(len);
+        if n > room {
+            let value = std::mem::replace(top, Value::None);
+            *top = Value::Truncated(Box::new(Truncated {
+                value,
+                len: (len + n) as u64,
+            }));
+        }
+        Ok(room.min(n))
+    }
+
+    // Replace the value just pushed by a placeholder, unless it may end up
+    // on a selected path.  The value is kept aside, in case its place turns
+    // out to be selected after all: through the memo, or as item of a tuple
+    // built around it.  A placeholder that gets items or state no longer
+    // stands for the value kept aside, since it ends further on.
+    fn settle(&mut self, start: usize) {
+        if !self.selecting {
+            return;
+        }
+        let j = self.stack.len() - 1;
+        let key_ok = match self.stack[j] {
+            // Already built or already skipped.
+            Value::MemoRef(_) | Value::Skipped(_) => return,
+            ref value => Self::hashable(value),
+        };
+        if !self.slot_selected(self.metastack.len(), j, key_ok) {
+            let len = Self::value_len(&self.stack[j]);
+            let placeholder = self.placeholder(start, len);
+            let value = std::mem::replace(&mut self.stack[j], placeholder);
+            self.skipped_values
+                .insert((start as u64, self.pos as u64), value);
+        }
+    }
+
+    // Like `settle`, for a global or an object that may still take
+    // arguments or state.
+    fn settle_object(&mut self, start: usize) {
+        self.settle(start);
+        if let Some(Value::Skipped(skipped)) = self.stack.last() {
+            self.objects.insert(skipped.start);
+        }
+    }
+
+    // Let go of the values skipped within `value`.
+    fn forget(&mut self, value: &Value<'de>) {
+        match value {
+            Value::Skipped(skipped) => {
+                if let Some(value) = self.skipped_values.remove(&(skipped.start, skipped.end)) {
+                    self.forget(&value);
                 }
+            }
+            Value::Tuple(items) => items.iter().for_each(|item| self.forget(item)),
+            Value::FrozenSet(set) => set.0.iter().for_each(|item| self.forget(item)),
+            Value::Truncated(truncated) => self.forget(&truncated.value),
+            _ => {}
+        }
+    }
+
+    // Put back all values skipped within `value`.  Keys and members of
+    // sets, and operands of globals and objects, are always built.
+    fn realize(&self, value: Value<'de>) -> Value<'de> {
+        self.realize_in(value, None)
+    }
+
+    // Like `realize`, also resolving memo references if `open` is given,
+    // which holds the memo ids being resolved.  A memo reference within
+    // its own value is left alone.
+    fn realize_in(&self, value: Value<'de>, mut open: Option<&mut Vec<MemoId>>) -> Value<'de> {
+        match value {
+            Value::Skipped(skipped) => match self.skipped_values.get(&(skipped.start, skipped.end))
+            {
+                Some(value) => self.realize_in(value.clone(), open),
+                None => Value::Skipped(skipped),
+            },
+            Value::MemoRef(id) => match (self.memo.get(&id), open) {
+                (Some((value, _)), Some(open)) if !open.contains(&id) => {
+                    open.push(id);
+                    let value = self.realize_in(value.clone(), Some(&mut *open));
+                    open.pop();
+                    value
+                }
+                (Some((value, _)), None) if self.holds_skipped(value) => {
+                    self.realize_in(value.clone(), None)
+                }
+                _ => Value::MemoRef(id),
+            },
+            Value::List(items) => Value::List(self.realize_all(items, open)),
+            Value::Tuple(items) => Value::Tuple(self.realize_all(items, open)),
+            Value::Set(set) => Value::Set(self.realize_all(set.0, open).into_iter().collect()),
+            Value::FrozenSet(set) => {
+                Value::FrozenSet(self.realize_all(set.0, open).into_iter().collect())
+            }
+            Value::Dict(dict) => {
+                let mut realized = HashMapWrapper::new();
+                for (key, value) in dict.0 {
+                    let key = self.realize_in(key, open.as_deref_mut());
+                    let value = self.realize_in(value, open.as_deref_mut());
+                    realized.0.insert(key, value);
+                }
+                Value::Dict(realized)
+            }
+            Value::Truncated(mut truncated) => {
+                truncated.value = self.realize_in(truncated.value, open);
+                Value::Truncated(truncated)
+            }
+            other => other,
+        }
+    }
+
+    fn realize_all<I>(&self, items: I, mut open: Option<&mut Vec<MemoId>>) -> Vec<Value<'de>>
+    where
+        I: IntoIterator<Item = Value<'de>>,
+    {
+        items
+            .into_iter()
+            .map(|item| self.realize_in(item, open.as_deref_mut()))
+            .collect()
+    }
+
+    // Whether a key or member of a set holds values that were skipped.
+    fn holds_skipped(&self, value: &Value<'de>) -> bool {
+        match value {
+            Value::Skipped(skipped) => self
+                .skipped_values
+                .contains_key(&(skipped.start, skipped.end)),
+            Value::MemoRef(id) => self
+                .memo
+                .get(id)
+                .is_some_and(|(value, _)| self.holds_skipped(value)),
+            Value::Tuple(items) => items.iter().any(|item| self.holds_skipped(item)),
+            Value::FrozenSet(set) => set.0.iter().any(|item| self.holds_skipped(item)),
+            _ => false,
+        }
+    }
+
+    // Put back the values skipped within `value`, which is at `path`, that
+    // turned out to be on a selected path.
+    fn fill(&self, value: &mut Value<'de>, path: &mut Path<'de>) {
+        match &self.options.select {
+            Some(select) if select(path) => {}
+            _ => return,
+        }
+        match value {
+            Value::Skipped(skipped) => {
+                if let Some(real) = self.skipped_values.get(&(skipped.start, skipped.end)) {
+                    *value = self.realize_in(real.clone(), Some(&mut Vec::new()));
+                }
+            }
+            Value::List(items) | Value::Tuple(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    path.push(i);
+                    self.fill(item, path);
+                    path.0.pop();
+                }
+            }
+            Value::Dict(dict) => {
+                if dict.0.keys().any(|key| self.holds_skipped(key)) {
+                    let items = std::mem::take(&mut dict.0);
+                    dict.0 = items
+                        .into_iter()
+                        .map(|(key, value)| (self.realize_in(key, Some(&mut Vec::new())), value))
+                        .collect();
+                }
+                for (key, value) in dict.0.iter_mut() {
+                    path.push(self.path_key(key));
+                    self.fill(value, path);
+                    path.0.pop();
+                }
+            }
+            Value::Set(set) | Value::FrozenSet(set)
+                if set.0.iter().any(|item| self.holds_skipped(item)) =>
+            {
+                let items = std::mem::take(&mut set.0);
+                set.0 = self
+                    .realize_all(items, Some(&mut Vec::new()))
+                    .into_iter()
+                    .collect();
+            }
+            Value::Truncated(truncated) => self.fill(&mut truncated.value, path),
+            _ => {}
+        }
+    }
+
+    // Add `items` to the list on the stack top, or count them if it is
+    // skipped.
+    fn append(&mut self, mut items: Vec<Value<'de>>) -> Result<()> {
+        if self.extend_skipped(items.len())? {
+            items.iter().for_each(|item| self.forget(item));
+            return Ok(());
+        }
+        let room = self.preview_room(self.item_limit(), items.len())?;
+        items.truncate(room);
+        self.modify_list(|list| list.extend(items))
+    }
+
+    // Add the keys and values in `items` to the dict on the stack top, or
+    // count them if it is skipped.
+    fn set_items(&mut self, mut items: Vec<Value<'de>>) -> Result<()> {
+        if self.extend_skipped(items.len() / 2)? {
+            items.iter().for_each(|item| self.forget(item));
+            return Ok(());
+        }
+        let room = self.preview_room(self.key_limit(), items.len() / 2)?;
+        items.truncate(room * 2);
+        let mut items = items.into_iter();
+        let mut pairs = Vec::with_capacity(items.len() / 2);
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            pairs.push((self.realize(key), value));
+        }
+        self.modify_dict(|dict| dict.extend(pairs))
+    }
+
+    fn push_object_placeholder(&mut self, start: usize) {
+        self.stack.push(self.placeholder(start, 0));
+        self.objects.insert(start as u64);
+    }
+
+    fn placeholder(&self, start: usize, len: usize) -> Value<'de> {
+        Value::Skipped(Skipped {
+            start: start as u64,
+            end: self.pos as u64,
+            len: len as u64,
+        })
+    }
 
-                // Unsupported opcodes
-                code => return self.error(ErrorCode::Unsupported(code as char)),
+    // Count items added to a placeholder on the stack top.  Returns false if
+    // the stack top is not a placeholder.
+    fn extend_skipped(&mut self, n: usize) -> Result<bool> {
+        let end = self.pos as u64;
+        match self.top()? {
+            Value::Skipped(skipped) => {
+                skipped.len += n as u64;
+                skipped.end = end;
+                Ok(true)
             }
+            _ => Ok(false),
+        }
+    }
+
+    // The earliest start of the placeholders among the operands of an
+    // opcode, or `start` if there is none before it.
+    fn first_start<'v, I>(&self, operands: I, start: usize) -> usize
+    where
+        I: IntoIterator<Item = &'v Value<'de>>,
+        'de: 'v,
+    {
+        operands
+            .into_iter()
+            .filter_map(|value| match self.peek(value) {
+                Value::Skipped(skipped) => Some(skipped.start as usize),
+                _ => None,
+            })
+            .fold(start, usize::min)
+    }
+
+    fn value_len(value: &Value<'de>) -> usize {
+        match value {
+            Value::List(items) | Value::Tuple(items) => items.len(),
+            Value::Set(set) | Value::FrozenSet(set) => set.0.len(),
+            Value::Dict(dict) => dict.0.len(),
+            Value::Bytes(bytes) => bytes.len(),
+            Value::LazyBytes(lazy) => usize::try_from(lazy.len).unwrap_or(usize::MAX),
+            Value::String(string) => string.len(),
+            Value::Truncated(truncated) => usize::try_from(truncated.len).unwrap_or(usize::MAX),
+            _ => 0,
+        }
+    }
+
+    fn hashable(value: &Value<'de>) -> bool {
+        match value {
+            Value::List(_) | Value::Dict(_) | Value::Set(_) => false,
+            Value::Truncated(truncated) => Self::hashable(&truncated.value),
+            _ => true,
+        }
+    }
+
+    // Globals and placeholders of objects can still take arguments or state,
+    // so the values above them may belong to them.
+    fn is_pending(&self, value: &Value<'de>) -> bool {
+        match self.peek(value) {
+            Value::Global(_) => true,
+            Value::Skipped(skipped) => self.objects.contains(&skipped.start),
+            _ => false,
+        }
+    }
+
+    // Look through a memo reference without counting it as a use, and
+    // through a truncated container.
+    fn peek<'v>(&'v self, value: &'v Value<'de>) -> &'v Value<'de> {
+        let value = match value {
+            Value::MemoRef(id) => self.memo.get(id).map_or(value, |(v, _)| v),
+            other => other,
+        };
+        match value {
+            Value::Truncated(truncated) => &truncated.value,
+            other => other,
+        }
+    }
+
+    // The dict key `value` as a path element.
+    fn path_key(&self, value: &Value<'de>) -> PathElem<'de> {
+        match self.realize(self.peek(value).clone()) {
+            Value::Int(i) => match i.to_i64() {
+                Some(i) => PathElem::Key(Value::I64(i)),
+                None => PathElem::Key(Value::Int(i)),
+            },
+            other => PathElem::Key(other),
+        }
+    }
+
+    // The stack at the given level, counting the metastack from the bottom.
+    fn level(&self, level: usize) -> &[Value<'de>] {
+        match self.metastack.get(level) {
+            Some(stack) => stack,
+            None => &self.stack,
+        }
+    }
+
+    // Whether the value at position `j` of the stack at `level` may end up
+    // on a selected path.
+    fn slot_selected(&self, level: usize, j: usize, key_ok: bool) -> bool {
+        let mut lookup = Lookup::new();
+        match self.slot_paths(level, j, key_ok, true, &mut lookup) {
+            Some(paths) => !paths.is_empty(),
+            None => true,
+        }
+    }
+
+    // The selected paths that the value at position `j` of the stack at
+    // `level` may end up at.  Returns `None` if the value has to be built in
+    // full: if it may be a key or member of a selected dict or set (and
+    // `key_ok`), or if the lookup ran out of budget or found too many paths.
+    //
+    // A value above a list or a dict and key (or the MARK of one) goes into
+    // it.  Other values are taken as items of the value that a MARK below
+    // them starts, as arguments or state of an object below them, as second
+    // or third item of a tuple starting below them, and as first item of
+    // tuples, however deeply nested.  The `direct` lookup
+    // is for the value itself; keys of selected dicts are always built then,
+    // even above an object.
+    fn slot_paths(
+        &self,
+        level: usize,
+        j: usize,
+        key_ok: bool,
+        direct: bool,
+        lookup: &mut Lookup<'de>,
+    ) -> Option<Vec<Path<'de>>> {
+        let cache_key = (level, j, key_ok, direct);
+        if let Some(paths) = lookup.cache.get(&cache_key) {
+            return paths.clone();
+        }
+        if lookup.budget == 0 {
+            return None;
+        }
+        lookup.budget -= 1;
+        let paths = self.find_slot_paths(level, j, key_ok, direct, lookup);
+        lookup.cache.insert(cache_key, paths.clone());
+        paths
+    }
+
+    fn find_slot_paths(
+        &self,
+        level: usize,
+        j: usize,
+        key_ok: bool,
+        direct: bool,
+        lookup: &mut Lookup<'de>,
+    ) -> Option<Vec<Path<'de>>> {
+        let items = self.level(level);
+        let below = j.checked_sub(1).map(|i| self.peek(&items[i]));
+        let force_key = key_ok && (direct || !below.is_some_and(|v| self.is_pending(v)));
+        let mut out = Vec::new();
+
+        // The unpickled object itself
+        if level == 0 && j == 0 {
+            self.push_selected(&mut out, Path::new());
+        }
+
+        // APPEND and SETITEM
+        match below {
+            Some(Value::List(list)) => {
+                let elem = PathElem::Index(list.len());
+                self.child_paths(level, j - 1, elem, &mut out, lookup)?;
+            }
+            Some(Value::Dict(_)) if key_ok && self.container_selected(level, j - 1, lookup)? => {
+                return None;
+            }
+            _ => {}
+        }
+        if j >= 2 {
+            if let Value::Dict(_) = self.peek(&items[j - 2]) {
+                let elem = self.path_key(&items[j - 1]);
+                self.child_paths(level, j - 2, elem, &mut out, lookup)?;
+            }
+        }
+
+        // APPENDS, SETITEMS and ADDITEMS, or the items of a TUPLE, LIST,
+        // DICT, FROZENSET, OBJ or INST
+        if level > 0 {
+            let lower = self.level(level - 1);
+            let parent = lower.len().checked_sub(1);
+            match lower.last().map(|v| self.peek(v)) {
+                Some(Value::List(list)) => {
+                    let elem = PathElem::Index(list.len() + j);
+                    self.child_paths(level - 1, parent?, elem, &mut out, lookup)?;
+                }
+                Some(Value::Dict(_)) if j % 2 == 1 => {
+                    let elem = self.path_key(&items[j - 1]);
+                    self.child_paths(level - 1, parent?, elem, &mut out, lookup)?;
+                }
+                Some(Value::Dict(_) | Value::Set(_))
+                    if force_key && self.container_selected(level - 1, parent?, lookup)? =>
+                {
+                    return None;
+                }
+                _ => {}
+            }
+            // A MARK above a list, dict or set may also start a value of its
+            // own.  Keys are built when they are added, so a value that may
+            // be one is not forced here.
+            let parents = self.slot_paths(level - 1, lower.len(), false, false, lookup)?;
+            if force_key && !parents.is_empty() {
+                return None;
+            }
+            for path in parents {
+                self.push_selected(&mut out, path.join(j));
+                if j % 2 == 1 {
+                    self.push_selected(&mut out, path.join(self.path_key(&items[j - 1])));
+                }
+                self.push_selected(&mut out, path);
+            }
+        }
+
+        // Arguments and state of objects
+        if below.is_some_and(|v| self.is_pending(v)) {
+            for path in self.slot_paths(level, j - 1, false, false, lookup)? {
+                self.push_selected(&mut out, path);
+            }
+        } else if j >= 2 && self.is_pending(&items[j - 2]) {
+            for path in self.slot_paths(level, j - 2, false, false, lookup)? {
+                self.push_selected(&mut out, path);
+            }
+        }
+
+        // TUPLE2 and TUPLE3
+        for k in 1..=2.min(j) {
+            for path in self.slot_paths(level, j - k, false, false, lookup)? {
+                self.push_selected(&mut out, path.join(k));
+            }
+        }
+
+        // The first item of a tuple sits where the tuple ends up.  Paths
+        // only get longer here if they are selected, since selections are
+        // prefix-closed, so this ends unless everything below is selected.
+        let mut first = 0;
+        while first < out.len() {
+            if out.len() > SELECT_PATHS {
+                return None;
+            }
+            let path = out[first].join(0);
+            self.push_selected(&mut out, path);
+            first += 1;
+        }
+
+        Some(out)
+    }
+
+    // Add the paths of the container at position `i` of the stack at
+    // `level`, extended by `elem`, if they are selected.
+    fn child_paths(
+        &self,
+        level: usize,
+        i: usize,
+        elem: PathElem<'de>,
+        out: &mut Vec<Path<'de>>,
+        lookup: &mut Lookup<'de>,
+    ) -> Option<()> {
+        for path in self.slot_paths(level, i, false, false, lookup)? {
+            self.push_selected(out, path.join(elem.clone()));
+        }
+        Some(())
+    }
+
+    fn container_selected(&self, level: usize, i: usize, lookup: &mut Lookup<'de>) -> Option<bool> {
+        self.slot_paths(level, i, false, false, lookup)
+            .map(|paths| !paths.is_empty())
+    }
+
+    fn push_selected(&self, out: &mut Vec<Path<'de>>, path: Path<'de>) {
+        if let Some(select) = &self.options.select {
+            if select(&path) && !out.contains(&path) {
+                out.push(path);
+            }
+        }
+    }
+
+    // Whether a payload of `len` bytes about to be read has to be read.
+    // Small payloads are always read, since the memo may share them with
+    // keys of selected dicts.
+    fn payload_selected(&self, len: u64) -> bool {
+        !self.selecting
+            || len <= SMALL_PAYLOAD
+            || self.slot_selected(self.metastack.len(), self.stack.len(), true)
+    }
+
+    // Read the next opcode and decode its argument.  Opcodes that push a
+    // single scalar onto the stack are decoded right into a `Value`, and
+    // payloads are read, cut or skipped as the options ask.
+    pub(crate) fn read_op(&mut self) -> Result<Step<'de>> {
+        loop {
+            self.op_start = self.pos;
+            self.op_code = None;
+            let code = self.read_byte()?;
+            self.op_code = Some(code);
+            let value = match code {
+                LONG1 => {
+                    let len = self.read_u8_length()?;
+                    self.read_payload(len, |slf, bytes| {
+                        Ok(Value::Int(slf.decode_binary_long(bytes)))
+                    })?
+                }
+                LONG4 => {
+                    let len = self.read_i32_length()?;
+                    self.read_payload(len, |slf, bytes| {
+                        Ok(Value::Int(slf.decode_binary_long(bytes)))
+                    })?
+                }
+                SHORT_BINBYTES => {
+                    let len = self.read_u8_length()?;
+                    self.read_text(len, |_, bytes| Ok(Value::Bytes(bytes)))?
+                }
+                BINBYTES => {
+                    let len = self.read_u32_length()?;
+                    self.read_bytes_value(len)?
+                }
+                BINBYTES8 | BYTEARRAY8 => {
+                    let len = self.read_u64_length()?;
+                    self.read_bytes_value(len)?
+                }
+                SHORT_BINSTRING => {
+                    let len = self.read_u8_length()?;
+                    self.read_text(len, Self::decode_string)?
+                }
+                BINSTRING => {
+                    let len = self.read_i32_length()?;
+                    self.read_text(len, Self::decode_string)?
+                }
+                SHORT_BINUNICODE => {
+                    let len = self.read_u8_length()?;
+                    self.read_text(len, Self::decode_unicode)?
+                }
+                BINUNICODE => {
+                    let len = self.read_u32_length()?;
+                    self.read_text(len, Self::decode_unicode)?
+                }
+                BINUNICODE8 => {
+                    let len = self.read_u64_length()?;
+                    self.read_text(len, Self::decode_unicode)?
+                }
+                code => match self.read_opcode_arg(code)? {
+                    // PROTO only matters for instances, and framing is
+                    // ignored.
+                    Opcode::Proto(_) | Opcode::Frame(_) => continue,
+                    Opcode::None => Value::None,
+                    Opcode::NewFalse | Opcode::Int(IntArg::Bool(false)) => Value::Bool(false),
+                    Opcode::NewTrue | Opcode::Int(IntArg::Bool(true)) => Value::Bool(true),
+                    Opcode::Int(IntArg::Int(i)) => Value::I64(i),
+                    Opcode::BinInt(i) => Value::I64(i.into()),
+                    Opcode::BinInt1(i) => Value::I64(i.into()),
+                    Opcode::BinInt2(i) => Value::I64(i.into()),
+                    Opcode::Long(i) => Value::Int(i),
+                    Opcode::Float(f) | Opcode::BinFloat(f) => Value::F64(F64Wrapper(f)),
+                    Opcode::String(bytes) => self.cut_text(self.decode_string(bytes)?),
+                    Opcode::Unicode(string) => self.cut_text(Value::String(string)),
+                    op => return Ok(Step::Op(op)),
+                },
+            };
+            return Ok(Step::Push(value));
         }
     }
 
     // Pop the stack top item.
-    fn pop(&mut self) -> Result<Value> {
+    fn pop(&mut self) -> Result<Value<'de>> {
         match self.stack.pop() {
             Some(v) => Ok(v),
             None => self.error(ErrorCode::StackUnderflow),
@@ -518,7 +1410,7 @@ impl<R: Read> Unpickler<R> {
     }
 
     // Pop the stack top item, and resolve it if it is a memo reference.
-    fn pop_resolve(&mut self) -> Result<Value> {
+    fn pop_resolve(&mut self) -> Result<Value<'de>> {
         let top = self.stack.pop();
         match self.resolve(top) {
             Some(v) => Ok(v),
@@ -527,15 +1419,18 @@ impl<R: Read> Unpickler<R> {
     }
 
     // Pop all topmost stack items until the next MARK.
-    fn pop_mark(&mut self) -> Result<Vec<Value>> {
+    fn pop_mark(&mut self) -> Result<Vec<Value<'de>>> {
         match self.metastack.pop() {
-            Some(new) => Ok(std::mem::replace(&mut self.stack, new)),
+            Some(new) => {
+                self.last_mark = self.mark_pos.pop().unwrap_or(0);
+                Ok(std::mem::replace(&mut self.stack, new))
+            }
             None => self.error(ErrorCode::StackUnderflow),
         }
     }
 
     // Mutably view the stack top item.
-    fn top(&mut self) -> Result<&mut Value> {
+    fn top(&mut self) -> Result<&mut Value<'de>> {
         match self.stack.last_mut() {
             // Since some operations like APPEND do things to the stack top, we
             // need to provide the reference to the "real" object here, not the
@@ -544,9 +1439,17 @@ impl<R: Read> Unpickler<R> {
                 .memo
                 .get_mut(&n)
                 .map(|&mut (ref mut v, _)| v)
-                .ok_or_else(|| Error::Syntax(ErrorCode::MissingMemo(n))),
+                .ok_or(Error::Syntax(ErrorCode::MissingMemo(n))),
             Some(other_value) => Ok(other_value),
-            None => Err(Error::Eval(ErrorCode::StackUnderflow, self.pos)),
+            None => Err(Error::eval(ErrorCode::StackUnderflow, self.pos)),
+        }
+    }
+
+    // Like `top`, looking through a container truncated in preview mode.
+    fn top_container(&mut self) -> Result<&mut Value<'de>> {
+        match self.top()? {
+            Value::Truncated(truncated) => Ok(&mut truncated.value),
+            other => Ok(other),
         }
     }
 
@@ -558,7 +1461,7 @@ impl<R: Read> Unpickler<R> {
                 *count += 1;
                 Ok(())
             }
-            None => Err(Error::Eval(ErrorCode::MissingMemo(memo_id), self.pos)),
+            None => Err(Error::eval(ErrorCode::MissingMemo(memo_id), self.pos)),
         }
     }
 
@@ -570,7 +1473,7 @@ impl<R: Read> Unpickler<R> {
             // TODO: is this even possible?
             item = match self.memo.get(&id) {
                 Some((v, _)) => v.clone(),
-                None => return Err(Error::Eval(ErrorCode::MissingMemo(id), self.pos)),
+                None => return Err(Error::eval(ErrorCode::MissingMemo(id), self.pos)),
             };
         }
         self.memo.insert(memo_id, (item, 1));
@@ -579,7 +1482,7 @@ impl<R: Read> Unpickler<R> {
     }
 
     // Resolve memo reference during stream decoding.
-    fn resolve(&mut self, maybe_memo: Option<Value>) -> Option<Value> {
+    fn resolve(&mut self, maybe_memo: Option<Value<'de>>) -> Option<Value<'de>> {
         match maybe_memo {
             Some(Value::MemoRef(id)) => {
                 self.memo.get_mut(&id).map(|&mut (ref val, ref mut count)| {
@@ -595,11 +1498,8 @@ impl<R: Read> Unpickler<R> {
     }
 
     // Resolve memo reference during Value deserializing.
-    fn resolve_recursive<T, U, F>(&mut self, id: MemoId, u: U, f: F) -> Result<T>
-    where
-        F: FnOnce(&mut Self, U, Value) -> Result<T>,
-    {
-        // Take the value from the memo while visiting it.  This prevents us
+    fn resolve_recursive(&mut self, id: MemoId) -> Result<Value<'de>> {
+        // Take the value from the memo while converting it.  This prevents us
         // from trying to depickle recursive structures, which we can't do
         // because our Values aren't references.
         let (value, mut count) = match self.memo.remove(&id) {
@@ -607,183 +1507,280 @@ impl<R: Read> Unpickler<R> {
             None => return Err(Error::Syntax(ErrorCode::Recursive)),
         };
         count -= 1;
-        if count <= 0 {
-            f(self, u, value)
-            // No need to put it back.
-        } else {
-            let result = f(self, u, value.clone());
-            self.memo.insert(id, (value, count));
-            result
+        let value = self.convert_value(value)?;
+        if count > 0 {
+            // Put back the converted value, since the memo references within
+            // it have been used up.
+            self.memo.insert(id, (value.clone(), count));
         }
+        Ok(value)
     }
 
     /// Assert that we reached the end of the stream.
-    fn end(&mut self) -> Result<()> {
-        let mut buf = [0];
-        match self.reader.read(&mut buf) {
+    pub(crate) fn end(&mut self) -> Result<()> {
+        match self.source.read_byte() {
             Err(err) => Err(Error::Io(err)),
-            Ok(1) => self.error(ErrorCode::TrailingBytes),
-            _ => Ok(()),
+            Ok(Some(_)) => self.error(ErrorCode::TrailingBytes),
+            Ok(None) => Ok(()),
         }
     }
 
-    fn read_line(&mut self) -> Result<Vec<u8>> {
-        let mut buf = Vec::with_capacity(16);
-        match self.reader.read_until(b'\n', &mut buf) {
-            Ok(_) => {
-                self.pos += buf.len();
-                buf.pop(); // remove newline
-                if buf.last() == Some(&b'\r') {
-                    buf.pop();
-                }
-                Ok(buf)
+    pub(crate) fn read_line(&mut self) -> Result<Cow<'de, [u8]>> {
+        match self.source.read_line() {
+            Ok(line) => {
+                self.pos += line.len();
+                // Like CPython, take a line that the input cuts short as
+                // truncated data.
+                if line.last() != Some(&b'\n') {
+                    return self.error(ErrorCode::EOFWhileParsing);
+                }
+                let mut len = line.len() - 1; // remove newline
+                if len > 0 && line[len - 1] == b'\r' {
+                    len -= 1;
+                }
+                Ok(match line {
+                    Cow::Borrowed(slice) => Cow::Borrowed(&slice[..len]),
+                    Cow::Owned(mut buf) => {
+                        buf.truncate(len);
+                        Cow::Owned(buf)
+                    }
+                })
             }
             Err(err) => Err(Error::Io(err)),
         }
     }
 
     #[inline]
-    fn read_byte(&mut self) -> Result<u8> {
-        let mut buf = [0];
-        match self.reader.read(&mut buf) {
-            Ok(1) => {
+    pub(crate) fn read_byte(&mut self) -> Result<u8> {
+        match self.source.read_byte() {
+            Ok(Some(byte)) => {
                 self.pos += 1;
-                Ok(buf[0])
+                Ok(byte)
             }
-            Ok(_) => self.error(ErrorCode::EOFWhileParsing),
+            Ok(None) => self.error(ErrorCode::EOFWhileParsing),
             Err(err) => Err(Error::Io(err)),
         }
     }
 
     #[inline]
-    fn read_bytes(&mut self, n: usize) -> Result<Vec<u8>> {
-        let mut buf = Vec::new();
-        match self.reader.by_ref().take(n as u64).read_to_end(&mut buf) {
-            Ok(m) if n == m => {
+    pub(crate) fn read_bytes(&mut self, n: usize) -> Result<Cow<'de, [u8]>> {
+        match self.source.read_bytes(n) {
+            Ok(Some(buf)) => {
                 self.pos += n;
                 Ok(buf)
             }
-            Ok(_) => self.error(ErrorCode::EOFWhileParsing),
+            Ok(None) => self.error(ErrorCode::EOFWhileParsing),
             Err(err) => Err(Error::Io(err)),
         }
     }
 
     #[inline]
-    fn read_fixed_2_bytes(&mut self) -> Result<[u8; 2]> {
+    pub(crate) fn read_fixed_2_bytes(&mut self) -> Result<[u8; 2]> {
         let mut buf = [0; 2];
-        match self.reader.by_ref().take(2).read_exact(&mut buf) {
-            Ok(()) => {
-                self.pos += 2;
-                Ok(buf)
-            }
-            Err(err) => {
-                if err.kind() == std::io::ErrorKind::UnexpectedEof {
-                    self.error(ErrorCode::EOFWhileParsing)
-                } else {
-                    Err(Error::Io(err))
-                }
-            }
-        }
+        buf.copy_from_slice(&self.read_bytes(2)?);
+        Ok(buf)
     }
 
     #[inline]
-    fn read_fixed_4_bytes(&mut self) -> Result<[u8; 4]> {
+    pub(crate) fn read_fixed_4_bytes(&mut self) -> Result<[u8; 4]> {
         let mut buf = [0; 4];
-        match self.reader.by_ref().take(4).read_exact(&mut buf) {
-            Ok(()) => {
-                self.pos += 4;
-                Ok(buf)
-            }
-            Err(err) => {
-                if err.kind() == std::io::ErrorKind::UnexpectedEof {
-                    self.error(ErrorCode::EOFWhileParsing)
-                } else {
-                    Err(Error::Io(err))
-                }
-            }
-        }
+        buf.copy_from_slice(&self.read_bytes(4)?);
+        Ok(buf)
     }
 
     #[inline]
-    fn read_fixed_8_bytes(&mut self) -> Result<[u8; 8]> {
+    pub(crate) fn read_fixed_8_bytes(&mut self) -> Result<[u8; 8]> {
         let mut buf = [0; 8];
-        match self.reader.by_ref().take(8).read_exact(&mut buf) {
-            Ok(()) => {
-                self.pos += 8;
-                Ok(buf)
-            }
-            Err(err) => {
-                if err.kind() == std::io::ErrorKind::UnexpectedEof {
-                    self.error(ErrorCode::EOFWhileParsing)
-                } else {
-                    Err(Error::Io(err))
-                }
-            }
-        }
+        buf.copy_from_slice(&self.read_bytes(8)?);
+        Ok(buf)
+    }
+
+    pub(crate) fn read_u8_length(&mut self) -> Result<u64> {
+        Ok(self.read_byte()?.into())
     }
 
-    fn read_i32_prefixed_bytes(&mut self) -> Result<Vec<u8>> {
+    pub(crate) fn read_i32_length(&mut self) -> Result<u64> {
         let lenbytes = self.read_fixed_4_bytes()?;
         match LittleEndian::read_i32(&lenbytes) {
-            0 => Ok(vec![]),
             l if l < 0 => self.error(ErrorCode::NegativeLength),
-            l => self.read_bytes(l as usize),
+            l => Ok(l as u64),
         }
     }
 
-    fn read_u64_prefixed_bytes(&mut self) -> Result<Vec<u8>> {
+    pub(crate) fn read_u32_length(&mut self) -> Result<u64> {
+        let lenbytes = self.read_fixed_4_bytes()?;
+        Ok(LittleEndian::read_u32(&lenbytes).into())
+    }
+
+    pub(crate) fn read_u64_length(&mut self) -> Result<u64> {
         let lenbytes = self.read_fixed_8_bytes()?;
-        self.read_bytes(LittleEndian::read_u64(&lenbytes) as usize)
+        Ok(LittleEndian::read_u64(&lenbytes))
     }
 
-    fn read_u32_prefixed_bytes(&mut self) -> Result<Vec<u8>> {
-        let lenbytes = self.read_fixed_4_bytes()?;
-        println!("read_u32_prefixed_bytes - lenbytes: {:?}", lenbytes);
-        self.read_bytes(LittleEndian::read_u32(&lenbytes) as usize)
+    // Read a payload of the given length and decode it, or skip over it and
+    // return a placeholder if it is not selected.
+    fn read_payload<F>(&mut self, len: u64, decode: F) -> Result<Value<'de>>
+    where
+        F: FnOnce(&Self, Cow<'de, [u8]>) -> Result<Value<'de>>,
+    {
+        if !self.payload_selected(len) {
+            self.skip_bytes(len)?;
+            return Ok(self.placeholder(self.op_start, len as usize));
+        }
+        let bytes = self.read_bytes(self.payload_len(len)?)?;
+        decode(self, bytes)
     }
 
-    fn read_u8_prefixed_bytes(&mut self) -> Result<Vec<u8>> {
-        let lenbyte = self.read_byte()?;
-        println!("read_u8_prefixed_bytes - lenbyte: {}", lenbyte);
-        self.read_bytes(lenbyte as usize)
+    // Like `read_payload`, for a string or bytes payload, which is cut
+    // short in preview mode.
+    fn read_text<F>(&mut self, len: u64, decode: F) -> Result<Value<'de>>
+    where
+        F: FnOnce(&Self, Cow<'de, [u8]>) -> Result<Value<'de>>,
+    {
+        match self.options.preview {
+            Some(limits) if len > limits.len as u64 && self.payload_selected(len) => {
+                self.read_truncated(len, limits.len, decode)
+            }
+            _ => self.read_payload(len, decode),
+        }
+    }
+
+    // Read the start of a payload of more than `limit` bytes, and skip over
+    // the rest.  Strings are cut to `limit` characters, which may take up
+    // to four bytes each, and the characters of the rest are counted.
+    fn read_truncated<F>(&mut self, len: u64, limit: usize, decode: F) -> Result<Value<'de>>
+    where
+        F: FnOnce(&Self, Cow<'de, [u8]>) -> Result<Value<'de>>,
+    {
+        let take = len.min(limit as u64 * 4);
+        let head = self.read_bytes(take as usize)?;
+        let head_chars = count_chars(&head);
+        // Do not cut a character in two.
+        let boundary = match str::from_utf8(&head) {
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            _ => head.len(),
+        };
+        let (value, len) = match decode(self, cut_bytes(head, boundary))? {
+            Value::String(string) => {
+                let chars = head_chars + self.skip_chars(len - take)?;
+                if chars <= limit as u64 {
+                    return Ok(Value::String(string));
+                }
+                let end = string
+                    .char_indices()
+                    .nth(limit)
+                    .map_or(string.len(), |(i, _)| i);
+                (Value::String(cut_str(string, end)), chars)
+            }
+            Value::Bytes(bytes) => {
+                self.skip_bytes(len - take)?;
+                let end = bytes.len().min(limit);
+                (Value::Bytes(cut_bytes(bytes, end)), len)
+            }
+            other => {
+                self.skip_bytes(len - take)?;
+                return Ok(other);
+            }
+        };
+        Ok(Value::Truncated(Box::new(Truncated { value, len })))
+    }
+
+    // Cut a string or bytes decoded from a line of text in preview mode.
+    fn cut_text(&self, value: Value<'de>) -> Value<'de> {
+        let limit = match self.options.preview {
+            Some(limits) => limits.len,
+            None => return value,
+        };
+        let (value, len) = match value {
+            Value::String(string) => match string.char_indices().nth(limit) {
+                Some((end, _)) => {
+                    let len = string.chars().count() as u64;
+                    (Value::String(cut_str(string, end)), len)
+                }
+                None => return Value::String(string),
+            },
+            Value::Bytes(bytes) if bytes.len() > limit => {
+                let len = bytes.len() as u64;
+                (Value::Bytes(cut_bytes(bytes, limit)), len)
+            }
+            other => return other,
+        };
+        Value::Truncated(Box::new(Truncated { value, len }))
+    }
+
+    // Read over `len` bytes of UTF-8 in chunks, and count the characters.
+    fn skip_chars(&mut self, mut len: u64) -> Result<u64> {
+        let mut chars = 0;
+        while len > 0 {
+            let n = len.min(CHUNK_SIZE);
+            chars += count_chars(&self.read_bytes(n as usize)?);
+            len -= n;
+        }
+        Ok(chars)
+    }
+
+    // Read a bytes payload of the given length, or skip over it and return
+    // a handle if it is above the lazy threshold, or a placeholder if it is
+    // not selected.
+    fn read_bytes_value(&mut self, len: u64) -> Result<Value<'de>> {
+        if !self.payload_selected(len) {
+            self.skip_bytes(len)?;
+            return Ok(self.placeholder(self.op_start, len as usize));
+        }
+        match (self.options.lazy_threshold, self.options.preview) {
+            (Some(threshold), _) if len >= threshold => {
+                let offset = self.source.start() + self.pos as u64;
+                self.skip_bytes(len)?;
+                Ok(Value::LazyBytes(LazyBytes { offset, len }))
+            }
+            (_, Some(limits)) if len > limits.len as u64 => {
+                self.read_truncated(len, limits.len, |_, bytes| Ok(Value::Bytes(bytes)))
+            }
+            _ => Ok(Value::Bytes(self.read_bytes(self.payload_len(len)?)?)),
+        }
+    }
+
+    // A payload length as a `usize`, which it may not fit on 32-bit targets.
+    pub(crate) fn payload_len(&self, len: u64) -> Result<usize> {
+        match usize::try_from(len) {
+            Ok(n) => Ok(n),
+            Err(_) => self.error(ErrorCode::InvalidValue(format!("payload of {} bytes", len))),
+        }
+    }
+
+    fn skip_bytes(&mut self, len: u64) -> Result<()> {
+        let n = self.payload_len(len)?;
+        match self.source.skip(len) {
+            Ok(true) => {
+                self.pos += n;
+                Ok(())
+            }
+            Ok(false) => self.error(ErrorCode::EOFWhileParsing),
+            Err(err) => Err(Error::Io(err)),
+        }
     }
 
     // Parse an expected ASCII literal from the stream or raise an error.
-    fn parse_ascii<T: FromStr>(&self, bytes: Vec<u8>) -> Result<T> {
+    pub(crate) fn parse_ascii<T: FromStr>(&self, bytes: Cow<'de, [u8]>) -> Result<T> {
         match str::from_utf8(&bytes).unwrap_or("").parse() {
             Ok(v) => Ok(v),
-            Err(_) => self.error(ErrorCode::InvalidLiteral(bytes)),
+            Err(_) => self.error(ErrorCode::InvalidLiteral(bytes.into_owned())),
         }
     }
 
-    // Decode a text-encoded integer.
-    fn decode_text_int(&self, line: Vec<u8>) -> Result<Value> {
-        // Handle protocol 1 way of spelling true/false
-        Ok(if line == b"00" {
-            Value::Bool(false)
-        } else if line == b"01" {
-            Value::Bool(true)
-        } else {
-            let i = self.parse_ascii(line)?;
-            Value::I64(i)
-        })
-    }
-
     // Decode a text-encoded long integer.
-    fn decode_text_long(&self, mut line: Vec<u8>) -> Result<Value> {
+    pub(crate) fn decode_text_long(&self, line: Cow<'de, [u8]>) -> Result<BigInt> {
         // Remove "L" suffix.
-        if line.last() == Some(&b'L') {
-            line.pop();
-        }
-        match BigInt::parse_bytes(&line, 10) {
-            Some(i) => Ok(Value::Int(i)),
-            None => self.error(ErrorCode::InvalidLiteral(line)),
+        let digits = line.strip_suffix(b"L").unwrap_or(&line);
+        match BigInt::parse_bytes(digits, 10) {
+            Some(i) => Ok(i),
+            None => self.error(ErrorCode::InvalidLiteral(digits.to_vec())),
         }
     }
 
-    // Decode an escaped string.  These are encoded with "normal" Python string
-    // escape rules.
-    fn decode_escaped_string(&self, slice: &[u8]) -> Result<Value> {
+    // Unescape the argument of STRING.  These are encoded with "normal"
+    // Python string escape rules.
+    pub(crate) fn unescape_string(&self, slice: &[u8]) -> Result<Vec<u8>> {
         // Remove quotes if they appear.
         let slice = if (slice.len() >= 2)
             && (slice[0] == slice[slice.len() - 1])
@@ -824,13 +1821,13 @@ impl<R: Read> Unpickler<R> {
                 _ => result.push(b),
             }
         }
-        self.decode_string(result)
+        Ok(result)
     }
 
     // Decode escaped Unicode strings. These are encoded with "raw-unicode-escape",
     // which only knows the \uXXXX and \UYYYYYYYY escapes. The backslash is escaped
     // in this way, too.
-    fn decode_escaped_unicode(&self, s: &[u8]) -> Result<Value> {
+    pub(crate) fn decode_escaped_unicode(&self, s: &[u8]) -> Result<String> {
         let mut result = String::with_capacity(s.len());
         let mut iter = s.iter();
         while let Some(&b) = iter.next() {
@@ -857,11 +1854,11 @@ impl<R: Read> Unpickler<R> {
                 _ => result.push(b as char),
             }
         }
-        Ok(Value::String(result))
+        Ok(result)
     }
 
     // Decode a string - either as Unicode or as bytes.
-    fn decode_string(&self, string: Vec<u8>) -> Result<Value> {
+    fn decode_string(&self, string: Cow<'de, [u8]>) -> Result<Value<'de>> {
         if self.options.decode_strings {
             self.decode_unicode(string)
         } else {
@@ -870,15 +1867,23 @@ impl<R: Read> Unpickler<R> {
     }
 
     // Decode a Unicode string from UTF-8.
-    fn decode_unicode(&self, string: Vec<u8>) -> Result<Value> {
-        match String::from_utf8(string) {
-            Ok(v) => Ok(Value::String(v)),
-            Err(_) => self.error(ErrorCode::StringNotUTF8),
+    fn decode_unicode(&self, string: Cow<'de, [u8]>) -> Result<Value<'de>> {
+        Ok(Value::String(self.decode_utf8(string)?))
+    }
+
+    pub(crate) fn decode_utf8(&self, string: Cow<'de, [u8]>) -> Result<Cow<'de, str>> {
+        let decoded = match string {
+            Cow::Borrowed(slice) => str::from_utf8(slice).map(Cow::Borrowed).ok(),
+            Cow::Owned(buf) => String::from_utf8(buf).map(Cow::Owned).ok(),
+        };
+        match decoded {
+            Some(v) => Ok(v),
+            None => self.error(ErrorCode::StringNotUTF8),
         }
     }
 
     // Decode a binary-encoded long integer.
-    fn decode_binary_long(&self, bytes: Vec<u8>) -> Value {
+    pub(crate) fn decode_binary_long(&self, bytes: Cow<'de, [u8]>) -> BigInt {
         // BigInt::from_bytes_le doesn't like a sign bit in the bytes, therefore
         // we have to extract that ourselves and do the two-s complement.
         let negative = !bytes.is_empty() && (bytes[bytes.len() - 1] & 0x80 != 0);
@@ -886,16 +1891,16 @@ impl<R: Read> Unpickler<R> {
         if negative {
             val -= BigInt::from(1) << (bytes.len() * 8);
         }
-        Value::Int(val)
+        val
     }
 
     // Modify the stack-top list.
     fn modify_list<F>(&mut self, f: F) -> Result<()>
     where
-        F: FnOnce(&mut Vec<Value>),
+        F: FnOnce(&mut Vec<Value<'de>>),
     {
         let pos = self.pos;
-        let top = self.top()?;
+        let top = self.top_container()?;
         if let Value::List(ref mut list) = *top {
             f(list);
             Ok(())
@@ -905,7 +1910,7 @@ impl<R: Read> Unpickler<R> {
     }
 
     // Push items from a (key, value, key, value) flattened list onto a (key, value) vec.
-    fn extend_dict(dict: &mut Vec<(Value, Value)>, items: Vec<Value>) {
+    fn extend_dict(dict: &mut Vec<(Value<'de>, Value<'de>)>, items: Vec<Value<'de>>) {
         let mut key = None;
         for value in items {
             match key.take() {
@@ -918,12 +1923,12 @@ impl<R: Read> Unpickler<R> {
     // Modify the stack-top dict.
     fn modify_dict<F>(&mut self, f: F) -> Result<()>
     where
-        F: FnOnce(&mut HashMap<Value, Value>),
+        F: FnOnce(&mut HashMapWrapper<Value<'de>, Value<'de>>),
     {
         let pos = self.pos;
-        let top = self.top()?;
+        let top = self.top_container()?;
         if let Value::Dict(ref mut dict) = *top {
-            f(&mut dict.0);
+            f(dict);
             Ok(())
         } else {
             Self::stack_error("dict", top, pos)
@@ -933,12 +1938,12 @@ impl<R: Read> Unpickler<R> {
     // Modify the stack-top set.
     fn modify_set<F>(&mut self, f: F) -> Result<()>
     where
-        F: FnOnce(&mut HashSet<Value>),
+        F: FnOnce(&mut HashSetWrapper<Value<'de>>),
     {
         let pos = self.pos;
-        let top = self.top()?;
+        let top = self.top_container()?;
         if let Value::Set(ref mut set) = *top {
-            f(&mut set.0);
+            f(set);
             Ok(())
         } else {
             Self::stack_error("set", top, pos)
@@ -946,8 +1951,8 @@ impl<R: Read> Unpickler<R> {
     }
 
     // Push the Value::Global referenced by modname and globname.
-    fn decode_global(&mut self, modname: Vec<u8>, globname: Vec<u8>) -> Result<Value> {
-        let value = match (&*modname, &*globname) {
+    fn decode_global(&mut self, modname: &[u8], globname: &[u8]) -> Result<Value<'de>> {
+        let value = match (modname, globname) {
             (b"_codecs", b"encode") => Value::Global(Global::Encode),
             (b"__builtin__", b"set") | (b"builtins", b"set") => Value::Global(Global::Set),
             (b"__builtin__", b"frozenset") | (b"builtins", b"frozenset") => {
@@ -958,47 +1963,59 @@ impl<R: Read> Unpickler<R> {
                 Value::Global(Global::Bytearray)
             }
             (b"__builtin__", b"int") | (b"builtins", b"int") => Value::Global(Global::Int),
+            (b"__builtin__", b"complex") | (b"builtins", b"complex") => {
+                Value::Global(Global::Complex)
+            }
             _ => Value::Global(Global::Other),
         };
         Ok(value)
     }
 
     // Handle the REDUCE opcode for the few Global objects we support.
-    fn reduce_global(&mut self, global: Value, mut argtuple: Vec<Value>) -> Result<()> {
+    fn reduce_global(&mut self, global: Value<'de>, mut argtuple: Vec<Value<'de>>) -> Result<()> {
         match global {
-            Value::Global(Global::Set) => match self.resolve(argtuple.pop()) {
-                Some(Value::List(items)) => {
-                    self.stack
-                        .push(Value::Set(HashSetWrapper(items.into_iter().collect())));
-                    Ok(())
+            Value::Global(Global::Set) => {
+                let arg = self.resolve(argtuple.pop());
+                match map_list(arg, |items| Value::Set(items.into_iter().collect())) {
+                    Some(set) => {
+                        self.stack.push(set);
+                        Ok(())
+                    }
+                    _ => self.error(ErrorCode::InvalidValue("set() arg".into())),
                 }
-                _ => self.error(ErrorCode::InvalidValue("set() arg".into())),
-            },
-            Value::Global(Global::Frozenset) => match self.resolve(argtuple.pop()) {
-                Some(Value::List(items)) => {
-                    self.stack.push(Value::FrozenSet(HashSetWrapper(
-                        items.into_iter().collect(),
-                    )));
-                    Ok(())
+            }
+            Value::Global(Global::Frozenset) => {
+                let arg = self.resolve(argtuple.pop());
+                match map_list(arg, |items| Value::FrozenSet(items.into_iter().collect())) {
+                    Some(set) => {
+                        self.stack.push(set);
+                        Ok(())
+                    }
+                    _ => self.error(ErrorCode::InvalidValue("frozenset() arg".into())),
                 }
-                _ => self.error(ErrorCode::InvalidValue("frozenset() arg".into())),
-            },
+            }
             Value::Global(Global::Bytearray) => {
                 // On Py2, the call is encoded as bytearray(u"foo", "latin-1").
                 argtuple.truncate(1);
                 match self.resolve(argtuple.pop()) {
-                    Some(Value::Bytes(bytes)) => {
-                        self.stack.push(Value::Bytes(bytes));
+                    Some(bytes @ (Value::Bytes(_) | Value::LazyBytes(_))) => {
+                        self.stack.push(bytes);
                         Ok(())
                     }
-                    Some(Value::String(string)) => {
-                        // The code points in the string are actually bytes values.
-                        // So we need to collect them individually.
-                        self.stack.push(Value::Bytes(
-                            string.chars().map(|ch| ch as u32 as u8).collect(),
-                        ));
+                    Some(Value::Truncated(truncated))
+                        if matches!(truncated.value, Value::Bytes(_)) =>
+                    {
+                        self.stack.push(Value::Truncated(truncated));
                         Ok(())
                     }
+                    // The code points in the string are actually bytes values.
+                    Some(string) => match latin1_bytes(string) {
+                        Some(bytes) => {
+                            self.stack.push(bytes);
+                            Ok(())
+                        }
+                        None => self.error(ErrorCode::InvalidValue("bytearray() arg".into())),
+                    },
                     _ => self.error(ErrorCode::InvalidValue("bytearray() arg".into())),
                 }
             }
@@ -1016,6 +2033,22 @@ impl<R: Read> Unpickler<R> {
                 }
                 _ => self.error(ErrorCode::InvalidValue("int() arg".into())),
             },
+            Value::Global(Global::Complex) => {
+                // complex(re=0, im=0), with ints or floats.
+                let parts = argtuple
+                    .into_iter()
+                    .map(|arg| self.resolve(Some(arg)).and_then(|arg| arg.as_f64()))
+                    .collect::<Option<Vec<_>>>();
+                let (re, im) = match parts.as_deref() {
+                    Some([]) => (0.0, 0.0),
+                    Some(&[re]) => (re, 0.0),
+                    Some(&[re, im]) => (re, im),
+                    _ => return self.error(ErrorCode::InvalidValue("complex() arg".into())),
+                };
+                self.stack
+                    .push(Value::Complex(F64Wrapper(re), F64Wrapper(im)));
+                Ok(())
+            }
             Value::Global(Global::Encode) => {
                 // Byte object encoded as _codecs.encode(x, 'latin1')
                 match self.resolve(argtuple.pop()) {
@@ -1023,13 +2056,11 @@ impl<R: Read> Unpickler<R> {
                     Some(Value::String(_)) => {}
                     _ => return self.error(ErrorCode::InvalidValue("encode() arg".into())),
                 }
-                match self.resolve(argtuple.pop()) {
-                    Some(Value::String(s)) => {
-                        // Now we have to convert the string to latin-1
-                        // encoded bytes.  It never contains codepoints
-                        // above 0xff.
-                        let bytes = s.chars().map(|ch| ch as u8).collect();
-                        self.stack.push(Value::Bytes(bytes));
+                // Now we have to convert the string to latin-1 encoded
+                // bytes.  It never contains codepoints above 0xff.
+                match self.resolve(argtuple.pop()).and_then(latin1_bytes) {
+                    Some(bytes) => {
+                        self.stack.push(bytes);
                         Ok(())
                     }
                     _ => self.error(ErrorCode::InvalidValue("encode() arg".into())),
@@ -1046,7 +2077,7 @@ impl<R: Read> Unpickler<R> {
         }
     }
 
-    fn convert_value(&mut self, value: Value) -> Result<Value> {
+    fn convert_value(&mut self, value: Value<'de>) -> Result<Value<'de>> {
         match value {
             Value::Int(v) => {
                 if let Some(i) = v.to_i64() {
@@ -1074,37 +2105,174 @@ impl<R: Read> Unpickler<R> {
                     v.0.into_iter()
                         .map(|v| self.convert_value(v))
                         .collect::<Result<_>>();
-                Ok(Value::Set(HashSetWrapper(new?)))
+                Ok(Value::Set(new?))
             }
             Value::FrozenSet(v) => {
                 let new =
                     v.0.into_iter()
                         .map(|v| self.convert_value(v))
                         .collect::<Result<_>>();
-                Ok(Value::FrozenSet(HashSetWrapper(new?)))
+                Ok(Value::FrozenSet(new?))
             }
             Value::Dict(v) => {
-                let mut map = HashMap::new();
+                let mut map = HashMapWrapper::new();
                 for (key, value) in v.0 {
                     let real_key = self.convert_value(key)?;
                     let real_value = self.convert_value(value)?;
                     map.insert(real_key, real_value);
                 }
-                Ok(Value::Dict(HashMapWrapper(map)))
+                Ok(Value::Dict(map))
             }
-            Value::MemoRef(memo_id) => {
-                self.resolve_recursive(memo_id, (), |slf, (), value| slf.convert_value(value))
+            Value::MemoRef(memo_id) => self.resolve_recursive(memo_id),
+            Value::Truncated(truncated) => {
+                let value = self.convert_value(truncated.value)?;
+                Ok(Value::Truncated(Box::new(Truncated {
+                    value,
+                    len: truncated.len,
+                })))
             }
             _ => Ok(value),
         }
     }
 
-    fn stack_error<T>(what: &'static str, value: &Value, pos: usize) -> Result<T> {
+    fn stack_error<T>(what: &'static str, value: &Value<'de>, pos: usize) -> Result<T> {
         let it = format!("{:?}", value);
-        Err(Error::Eval(ErrorCode::InvalidStackTop(what, it), pos))
+        Err(Error::eval(ErrorCode::InvalidStackTop(what, it), pos))
+    }
+
+    pub(crate) fn error<T>(&self, reason: ErrorCode) -> Result<T> {
+        Err(Error::eval(reason, self.pos))
+    }
+
+    fn set_last_global(&mut self, modname: &[u8], globname: &[u8]) {
+        let modname = String::from_utf8_lossy(modname).into_owned();
+        let globname = String::from_utf8_lossy(globname).into_owned();
+        self.last_global = Some((modname, globname));
+    }
+
+    // Adds the state of the machine to an error of `parse_value`.
+    fn with_context(&self, err: Error) -> Error {
+        let (code, pos) = match err {
+            Error::Eval {
+                code,
+                pos,
+                context: None,
+            } => (code, pos),
+            err => return err,
+        };
+        let info = self.op_code.and_then(OpcodeInfo::from_code);
+        let global = match self.op_code {
+            Some(GLOBAL | STACK_GLOBAL | INST | OBJ | REDUCE | NEWOBJ | NEWOBJ_EX | BUILD) => {
+                self.last_global.clone()
+            }
+            _ => None,
+        };
+        let top = self.stack.len().saturating_sub(CONTEXT_ENTRIES);
+        let context = EvalContext {
+            start: self.op_start,
+            code: self.op_code,
+            name: info.map(|info| info.name),
+            mark_depth: self.metastack.len(),
+            stack_len: self.stack.len(),
+            stack: self.stack[top..]
+                .iter()
+                .map(|v| self.short_repr(v))
+                .collect(),
+            memo_id: self.op_memo,
+            global,
+        };
+        Error::Eval {
+            code,
+            pos,
+            context: Some(Box::new(context)),
+        }
+    }
+
+    // The repr of a stack entry, looking through memo references, cut to
+    // `CONTEXT_REPR_LEN` characters.
+    fn short_repr(&self, value: &Value<'de>) -> String {
+        let value = match value {
+            Value::MemoRef(id) => self.memo.get(id).map_or(value, |(value, _)| value),
+            value => value,
+        };
+        let mut out = Capped(String::new(), CONTEXT_REPR_LEN);
+        if write!(out, "{}", value).is_err() {
+            out.0 += "...";
+        }
+        out.0
+    }
+}
+
+// A string that takes up to a number of characters, and fails to take more.
+struct Capped(String, usize);
+
+impl fmt::Write for Capped {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for ch in s.chars() {
+            if self.1 == 0 {
+                return Err(fmt::Error);
+            }
+            self.0.push(ch);
+            self.1 -= 1;
+        }
+        Ok(())
+    }
+}
+
+// The number of UTF-8 characters starting in `bytes`.
+fn count_chars(bytes: &[u8]) -> u64 {
+    bytes.iter().filter(|&&b| b & 0xc0 != 0x80).count() as u64
+}
+
+fn cut_bytes(bytes: Cow<[u8]>, end: usize) -> Cow<[u8]> {
+    match bytes {
+        Cow::Borrowed(slice) => Cow::Borrowed(&slice[..end]),
+        Cow::Owned(mut buf) => {
+            buf.truncate(end);
+            Cow::Owned(buf)
+        }
     }
+}
 
-    fn error<T>(&self, reason: ErrorCode) -> Result<T> {
-        Err(Error::Eval(reason, self.pos))
+fn cut_str(string: Cow<str>, end: usize) -> Cow<str> {
+    match string {
+        Cow::Borrowed(slice) => Cow::Borrowed(&slice[..end]),
+        Cow::Owned(mut buf) => {
+            buf.truncate(end);
+            Cow::Owned(buf)
+        }
+    }
+}
+
+// Build a value from the items of a list argument, keeping it truncated if
+// the list was.
+fn map_list<'de, F>(arg: Option<Value<'de>>, build: F) -> Option<Value<'de>>
+where
+    F: FnOnce(Vec<Value<'de>>) -> Value<'de>,
+{
+    match arg? {
+        Value::List(items) => Some(build(items)),
+        Value::Truncated(truncated) => {
+            let len = truncated.len;
+            let value = map_list(Some(truncated.value), build)?;
+            Some(Value::Truncated(Box::new(Truncated { value, len })))
+        }
+        _ => None,
+    }
+}
+
+// The bytes that a string of code points below 256 stands for, as in
+// `_codecs.encode(s, 'latin1')`.
+fn latin1_bytes(value: Value) -> Option<Value> {
+    match value {
+        Value::String(s) => Some(Value::Bytes(Cow::Owned(
+            s.chars().map(|ch| ch as u32 as u8).collect(),
+        ))),
+        Value::Truncated(truncated) => {
+            let len = truncated.len;
+            let value = latin1_bytes(truncated.value)?;
+            Some(Value::Truncated(Box::new(Truncated { value, len })))
+        }
+        _ => None,
     }
 }
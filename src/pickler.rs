@@ -0,0 +1,414 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::error::Result;
+use crate::lint::HIGHEST_PROTOCOL;
+use crate::ops::{encode_long, IntArg, Opcode};
+use crate::optimize::{Framer, FRAME_SIZE_TARGET};
+use crate::pickle::{
+    BINBYTES, BINBYTES8, BININT, BININT1, BININT2, BINSTRING, BINUNICODE, BINUNICODE8, BYTEARRAY8,
+    FLOAT, INT, LONG, LONG1, LONG4, SHORT_BINBYTES, SHORT_BINSTRING, SHORT_BINUNICODE, STRING,
+    UNICODE,
+};
+use crate::transcode::{bytes, global, int, unicode};
+use crate::{Error, ErrorCode, Path, PathElem, SourceMap, Value};
+
+// The number of items that `pickle` adds per APPENDS, SETITEMS or
+// ADDITEMS.
+const BATCH_SIZE: usize = 1000;
+
+pub struct PicklerOptions {
+    proto: u8,
+}
+
+impl PicklerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The protocol to write (default 4, as Python 3.8 to 3.13 do).
+    pub fn proto(mut self, proto: u8) -> Self {
+        self.proto = proto;
+        self
+    }
+}
+
+impl Default for PicklerOptions {
+    fn default() -> Self {
+        Self { proto: 4 }
+    }
+}
+
+/// Writes values as pickles, in the layout of Python's `pickle` module
+/// without its memo: a `Value` has no shared parts, so nothing is stored
+/// in the memo.  Dict and set items are written in their insertion order.
+pub struct Pickler<'s> {
+    options: PicklerOptions,
+    sources: Option<&'s SourceMap>,
+}
+
+impl<'s> Pickler<'s> {
+    pub fn new(options: PicklerOptions) -> Self {
+        Self {
+            options,
+            sources: None,
+        }
+    }
+
+    /// Write each value in the form that `sources` records for the value
+    /// at the same path, where the protocol and the value allow it, e.g. a
+    /// small int as `BININT` rather than `BININT1`, or a str as Python 2's
+    /// `STRING`.
+    pub fn sources(mut self, sources: &'s SourceMap) -> Self {
+        self.sources = Some(sources);
+        self
+    }
+
+    /// Pickles `value`.  Values that only stand for something in a pickle,
+    /// such as `Value::MemoRef`, `Value::Global` or `Value::Skipped`, can't
+    /// be pickled.
+    pub fn dump(&self, value: &Value) -> Result<Vec<u8>> {
+        let proto = self.options.proto;
+        if proto > HIGHEST_PROTOCOL {
+            let msg = format!("unknown protocol {}", proto);
+            return Err(Error::Syntax(ErrorCode::InvalidValue(msg)));
+        }
+        let mut ops = Vec::new();
+        self.write(value, &mut Path::new(), &mut ops)?;
+        ops.push(Opcode::Stop);
+
+        let mut out = Vec::new();
+        if proto >= 2 {
+            Opcode::Proto(proto).encode(&mut out)?;
+        }
+        let mut framer = Framer::new(proto >= 4);
+        for op in ops {
+            let mut bytes = Vec::new();
+            op.encode(&mut bytes)?;
+            framer.write(&mut out, &bytes, bytes.len() > FRAME_SIZE_TARGET);
+        }
+        framer.finish(&mut out);
+        Ok(out)
+    }
+
+    /// Pickles `value` with the given options.
+    pub fn value_to_vec(value: &Value, options: PicklerOptions) -> Result<Vec<u8>> {
+        Pickler::new(options).dump(value)
+    }
+
+    /// Appends the opcodes that push `value`, found at `path`, to `ops`.
+    pub(crate) fn write(
+        &self,
+        value: &Value,
+        path: &mut Path<'static>,
+        ops: &mut Vec<Opcode<'static>>,
+    ) -> Result<()> {
+        let proto = self.options.proto;
+        let form = self
+            .sources
+            .and_then(|sources| sources.get(path))
+            .map(|a| a.opcode);
+        match value {
+            Value::None => ops.push(Opcode::None),
+            Value::Bool(b) => match (proto, form, b) {
+                (2.., Some(INT), _) | (0..=1, _, _) => ops.push(Opcode::Int(IntArg::Bool(*b))),
+                (_, _, true) => ops.push(Opcode::NewTrue),
+                (_, _, false) => ops.push(Opcode::NewFalse),
+            },
+            Value::I64(i) => ops.push(self.int(&BigInt::from(*i), form)),
+            Value::Int(i) => ops.push(self.int(i, form)),
+            Value::F64(f) => match (proto, form) {
+                (0, _) | (_, Some(FLOAT)) => ops.push(Opcode::Float(f.0)),
+                _ => ops.push(Opcode::BinFloat(f.0)),
+            },
+            Value::Complex(re, im) => {
+                // As `complex.__reduce__` gives it: `complex(re, im)`.
+                match proto {
+                    0..=3 => ops.push(global("builtins", "complex", proto)),
+                    _ => ops.extend([
+                        Opcode::ShortBinUnicode("builtins".into()),
+                        Opcode::ShortBinUnicode("complex".into()),
+                        Opcode::StackGlobal,
+                    ]),
+                }
+                let parts = Value::Tuple(vec![Value::F64(re.clone()), Value::F64(im.clone())]);
+                self.write(&parts, &mut Path::new(), ops)?;
+                ops.push(Opcode::Reduce);
+            }
+            Value::Bytes(b) => match form {
+                Some(STRING | BINSTRING | SHORT_BINSTRING) => ops.push(self.string(b, form)),
+                _ => ops.extend(self.bytes(b, form)),
+            },
+            Value::String(s) => match form {
+                Some(STRING | BINSTRING | SHORT_BINSTRING) => {
+                    ops.push(self.string(s.as_bytes(), form))
+                }
+                _ => ops.push(self.unicode(s, form)),
+            },
+            Value::List(items) => {
+                match proto {
+                    0 => ops.extend([Opcode::Mark, Opcode::List]),
+                    _ => ops.push(Opcode::EmptyList),
+                }
+                let items = items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| (PathElem::Index(i), item));
+                self.batches(items, path, ops, Opcode::Append, Opcode::Appends)?;
+            }
+            Value::Tuple(items) => {
+                if items.is_empty() {
+                    match proto {
+                        0 => ops.extend([Opcode::Mark, Opcode::Tuple]),
+                        _ => ops.push(Opcode::EmptyTuple),
+                    }
+                    return Ok(());
+                }
+                let short = proto >= 2 && items.len() <= 3;
+                if !short {
+                    ops.push(Opcode::Mark);
+                }
+                for (i, item) in items.iter().enumerate() {
+                    self.write_at(item, path, PathElem::Index(i), ops)?;
+                }
+                ops.push(match (short, items.len()) {
+                    (true, 1) => Opcode::Tuple1,
+                    (true, 2) => Opcode::Tuple2,
+                    (true, _) => Opcode::Tuple3,
+                    (false, _) => Opcode::Tuple,
+                });
+            }
+            Value::Dict(dict) => {
+                match proto {
+                    0 => ops.extend([Opcode::Mark, Opcode::Dict]),
+                    _ => ops.push(Opcode::EmptyDict),
+                }
+                self.dict_batches(dict.0.iter(), path, ops)?;
+            }
+            Value::Set(set) if proto < 4 => {
+                ops.push(global("builtins", "set", proto));
+                self.set_args(set.0.iter(), ops)?;
+            }
+            Value::Set(set) => {
+                ops.push(Opcode::EmptySet);
+                let items = set.0.iter().collect::<Vec<_>>();
+                for batch in items.chunks(BATCH_SIZE) {
+                    ops.push(Opcode::Mark);
+                    for item in batch {
+                        self.write(item, &mut Path::new(), ops)?;
+                    }
+                    ops.push(Opcode::AddItems);
+                }
+            }
+            Value::FrozenSet(set) if proto < 4 => {
+                ops.push(global("builtins", "frozenset", proto));
+                self.set_args(set.0.iter(), ops)?;
+            }
+            Value::FrozenSet(set) => {
+                ops.push(Opcode::Mark);
+                for item in set.0.iter() {
+                    self.write(item, &mut Path::new(), ops)?;
+                }
+                ops.push(Opcode::FrozenSet);
+            }
+            Value::PersId(id) => ops.push(Opcode::PersId(id.as_bytes().to_vec().into())),
+            Value::BinPersId(id) if proto == 0 => match id.as_ref() {
+                Value::Bytes(id) => ops.push(Opcode::PersId(id.to_vec().into())),
+                Value::String(id) => ops.push(Opcode::PersId(id.as_bytes().to_vec().into())),
+                _ => return Err(unpicklable("a persistent id other than a string", proto)),
+            },
+            Value::BinPersId(id) => {
+                self.write(id, &mut Path::new(), ops)?;
+                ops.push(Opcode::BinPersId);
+            }
+            Value::MemoRef(_) => return Err(unpicklable("a memo reference", proto)),
+            Value::Global(_) => return Err(unpicklable("a global", proto)),
+            Value::LazyBytes(_) => return Err(unpicklable("a lazy bytes handle", proto)),
+            Value::Skipped(_) => return Err(unpicklable("a skipped value", proto)),
+            Value::Truncated(_) => return Err(unpicklable("a truncated value", proto)),
+        }
+        Ok(())
+    }
+
+    fn write_at(
+        &self,
+        value: &Value,
+        path: &mut Path<'static>,
+        elem: PathElem,
+        ops: &mut Vec<Opcode<'static>>,
+    ) -> Result<()> {
+        path.push(elem.into_owned());
+        let result = self.write(value, path, ops);
+        path.0.pop();
+        result
+    }
+
+    // Writes the items added to a list: one by one in protocol 0, else in
+    // batches, like `pickle`'s `_batch_appends`.
+    fn batches<'v, I>(
+        &self,
+        items: I,
+        path: &mut Path<'static>,
+        ops: &mut Vec<Opcode<'static>>,
+        one: Opcode<'static>,
+        many: Opcode<'static>,
+    ) -> Result<()>
+    where
+        I: Iterator<Item = (PathElem<'static>, &'v Value<'v>)>,
+    {
+        let items = items.collect::<Vec<_>>();
+        for batch in items.chunks(BATCH_SIZE) {
+            let single = self.options.proto == 0 || batch.len() == 1;
+            if !single {
+                ops.push(Opcode::Mark);
+            }
+            for (elem, item) in batch {
+                self.write_at(item, path, elem.clone(), ops)?;
+                if single {
+                    ops.push(one.clone());
+                }
+            }
+            if !single {
+                ops.push(many.clone());
+            }
+        }
+        Ok(())
+    }
+
+    // Writes the items added to a dict, like `pickle`'s `_batch_setitems`.
+    fn dict_batches<'v, I>(
+        &self,
+        items: I,
+        path: &mut Path<'static>,
+        ops: &mut Vec<Opcode<'static>>,
+    ) -> Result<()>
+    where
+        I: Iterator<Item = (&'v Value<'v>, &'v Value<'v>)>,
+    {
+        let items = items.collect::<Vec<_>>();
+        for batch in items.chunks(BATCH_SIZE) {
+            let single = self.options.proto == 0 || batch.len() == 1;
+            if !single {
+                ops.push(Opcode::Mark);
+            }
+            for (key, value) in batch {
+                self.write(key, &mut Path::new(), ops)?;
+                let elem = PathElem::Key((*key).clone().into_owned());
+                self.write_at(value, path, elem, ops)?;
+                if single {
+                    ops.push(Opcode::SetItem);
+                }
+            }
+            if !single {
+                ops.push(Opcode::SetItems);
+            }
+        }
+        Ok(())
+    }
+
+    // Writes the argument tuple of `set(list)` or `frozenset(list)`, and
+    // the call.
+    fn set_args<'v, I>(&self, items: I, ops: &mut Vec<Opcode<'static>>) -> Result<()>
+    where
+        I: Iterator<Item = &'v Value<'v>>,
+    {
+        let list = Value::List(items.cloned().collect());
+        if self.options.proto < 2 {
+            ops.push(Opcode::Mark);
+        }
+        self.write(&list, &mut Path::new(), ops)?;
+        ops.push(match self.options.proto {
+            0..=1 => Opcode::Tuple,
+            _ => Opcode::Tuple1,
+        });
+        ops.push(Opcode::Reduce);
+        Ok(())
+    }
+
+    fn int(&self, i: &BigInt, form: Option<u8>) -> Opcode<'static> {
+        let proto = self.options.proto;
+        let small = i.to_i64();
+        let op = match (form, small) {
+            (Some(INT), Some(small)) => Opcode::Int(IntArg::Int(small)),
+            (Some(BININT), Some(small)) if i32::try_from(small).is_ok() => {
+                Opcode::BinInt(small as i32)
+            }
+            (Some(BININT1), Some(small @ 0..=0xff)) => Opcode::BinInt1(small as u8),
+            (Some(BININT2), Some(small @ 0..=0xffff)) => Opcode::BinInt2(small as u16),
+            (Some(LONG), _) => Opcode::Long(i.clone()),
+            (Some(LONG1), _) if encode_long(i).len() < 256 => Opcode::Long1(i.clone()),
+            (Some(LONG4), _) => Opcode::Long4(i.clone()),
+            _ => return int(i, proto),
+        };
+        match op.proto() <= proto {
+            true => op,
+            false => int(i, proto),
+        }
+    }
+
+    fn string(&self, b: &[u8], form: Option<u8>) -> Opcode<'static> {
+        let b = b.to_vec().into();
+        match (self.options.proto, form) {
+            (0, _) | (_, Some(STRING)) => Opcode::String(b),
+            (_, Some(SHORT_BINSTRING)) if b.len() < 256 => Opcode::ShortBinString(b),
+            _ => Opcode::BinString(b),
+        }
+    }
+
+    fn unicode(&self, s: &str, form: Option<u8>) -> Opcode<'static> {
+        let proto = self.options.proto;
+        let owned = || s.to_string().into();
+        let op = match form {
+            Some(UNICODE) => Opcode::Unicode(owned()),
+            Some(BINUNICODE) if u32::try_from(s.len()).is_ok() => Opcode::BinUnicode(owned()),
+            Some(SHORT_BINUNICODE) if s.len() < 256 => Opcode::ShortBinUnicode(owned()),
+            Some(BINUNICODE8) => Opcode::BinUnicode8(owned()),
+            _ => return unicode(s, proto),
+        };
+        match op.proto() <= proto {
+            true => op,
+            false => unicode(s, proto),
+        }
+    }
+
+    fn bytes(&self, b: &[u8], form: Option<u8>) -> Vec<Opcode<'static>> {
+        let proto = self.options.proto;
+        let owned = || b.to_vec().into();
+        let op = match form {
+            Some(BINBYTES) if u32::try_from(b.len()).is_ok() => Opcode::BinBytes(owned()),
+            Some(SHORT_BINBYTES) if b.len() < 256 => Opcode::ShortBinBytes(owned()),
+            Some(BINBYTES8) => Opcode::BinBytes8(owned()),
+            Some(BYTEARRAY8) => Opcode::ByteArray8(owned()),
+            _ => return bytes(b, proto),
+        };
+        match op.proto() <= proto {
+            true => vec![op],
+            false => bytes(b, proto),
+        }
+    }
+}
+
+fn unpicklable(what: &str, proto: u8) -> Error {
+    let msg = format!("{} can't be pickled in protocol {}", what, proto);
+    Error::Syntax(ErrorCode::InvalidValue(msg))
+}
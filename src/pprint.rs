@@ -0,0 +1,377 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::cmp::Ordering;
+
+use crate::value::Truncated;
+use crate::Value;
+
+pub struct PrettyOptions {
+    width: usize,
+    indent: usize,
+    depth: Option<usize>,
+    sort_dicts: bool,
+    max_bytes: Option<usize>,
+}
+
+impl PrettyOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of characters a line should not exceed (default 80).
+    /// Lines can be longer when a value can't be split.
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// The number of blanks by which to indent each level (default 1).
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent.max(1);
+        self
+    }
+
+    /// The number of levels of containers to show; deeper ones are shown
+    /// as `[...]`, `(...)` or `{...}` (default all).
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Write dict keys and set members sorted, as `pprint` does (default),
+    /// rather than in insertion order.
+    pub fn sort_dicts(mut self, sort_dicts: bool) -> Self {
+        self.sort_dicts = sort_dicts;
+        self
+    }
+
+    /// Show only the first `max_bytes` bytes of longer bytes values,
+    /// followed by a count of what was left out.  The output then no longer
+    /// reads back as Python.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        Self {
+            width: 80,
+            indent: 1,
+            depth: None,
+            sort_dicts: true,
+            max_bytes: None,
+        }
+    }
+}
+
+/// Renders a value as Python's `pprint.pformat` does: as its repr if that
+/// fits in the width, else with the items of containers on lines of their
+/// own, and long strings and bytes split into literals that Python joins.
+pub fn pformat(value: &Value, options: PrettyOptions) -> String {
+    let mut out = String::new();
+    Printer { options }.format(value, &mut out, 0, 0, 0);
+    out
+}
+
+struct Printer {
+    options: PrettyOptions,
+}
+
+// The parts of a container's repr: the opening and closing delimiters, the
+// closing one for a single item, and the items, with the values of a dict.
+type Parts<'v, 'a> = (
+    &'static str,
+    &'static str,
+    &'static str,
+    Vec<(&'v Value<'a>, Option<&'v Value<'a>>)>,
+);
+
+impl Printer {
+    fn format(
+        &self,
+        value: &Value,
+        out: &mut String,
+        indent: usize,
+        allowance: usize,
+        level: usize,
+    ) {
+        let repr = self.repr(value, level);
+        let max_width = self.options.width.saturating_sub(indent + allowance);
+        if repr.chars().count() <= max_width {
+            out.push_str(&repr);
+            return;
+        }
+        match value {
+            Value::String(s) => self.format_str(s, out, indent, allowance, level + 1),
+            Value::Bytes(b) => self.format_bytes(b, out, indent, allowance, level + 1),
+            Value::Set(set) | Value::FrozenSet(set) if set.0.is_empty() => out.push_str(&repr),
+            container => match self.parts(container) {
+                Some(parts) => self.format_items(parts, out, indent, allowance, level + 1),
+                None => out.push_str(&repr),
+            },
+        }
+    }
+
+    // Writes the items of a container one per line, like `pprint`'s
+    // `_format_items` and `_format_dict_items`.
+    fn format_items(
+        &self,
+        (open, close, single, items): Parts,
+        out: &mut String,
+        indent: usize,
+        allowance: usize,
+        level: usize,
+    ) {
+        let close = if items.len() == 1 { single } else { close };
+        // The items of a frozenset line up with the braces.
+        let indent = indent + open.len() - 1 + self.options.indent;
+        let allowance = allowance + close.len();
+        out.push_str(open);
+        out.push_str(&" ".repeat(self.options.indent - 1));
+        let last = items.len().saturating_sub(1);
+        for (i, (item, value)) in items.into_iter().enumerate() {
+            let allowance = if i == last { allowance } else { 1 };
+            match value {
+                Some(value) => {
+                    let key = self.repr(item, level);
+                    out.push_str(&key);
+                    out.push_str(": ");
+                    let indent = indent + key.chars().count() + 2;
+                    self.format(value, out, indent, allowance, level);
+                }
+                None => self.format(item, out, indent, allowance, level),
+            }
+            if i < last {
+                out.push_str(",\n");
+                out.push_str(&" ".repeat(indent));
+            }
+        }
+        out.push_str(close);
+    }
+
+    // Splits a str into literals at line breaks and whitespace, like
+    // `pprint`'s `_pprint_str`.  A value at the top is put in parentheses.
+    fn format_str(&self, s: &str, out: &mut String, indent: usize, allowance: usize, level: usize) {
+        let (indent, allowance) = match level {
+            1 => (indent + 1, allowance + 1),
+            _ => (indent, allowance),
+        };
+        let repr = |s: &str| Value::String(s.into()).to_string();
+        let max_width = self.options.width.saturating_sub(indent);
+        let mut chunks = Vec::new();
+        let lines = s.split_inclusive('\n').collect::<Vec<_>>();
+        for (i, line) in lines.iter().enumerate() {
+            let last_line = i == lines.len() - 1;
+            let max_width1 = match last_line {
+                true => max_width.saturating_sub(allowance),
+                false => max_width,
+            };
+            if repr(line).chars().count() <= max_width1 {
+                chunks.push(repr(line));
+                continue;
+            }
+            let parts = words(line);
+            let mut current = String::new();
+            for (j, part) in parts.iter().enumerate() {
+                let candidate = current.clone() + part;
+                let max_width2 = match last_line && j == parts.len() - 1 {
+                    true => max_width.saturating_sub(allowance),
+                    false => max_width,
+                };
+                if repr(&candidate).chars().count() > max_width2 {
+                    if !current.is_empty() {
+                        chunks.push(repr(&current));
+                    }
+                    current = part.to_string();
+                } else {
+                    current = candidate;
+                }
+            }
+            if !current.is_empty() {
+                chunks.push(repr(&current));
+            }
+        }
+        self.write_chunks(chunks, out, indent, level);
+    }
+
+    // Splits bytes into literals of whole groups of 4 bytes, like
+    // `pprint`'s `_pprint_bytes`.
+    fn format_bytes(
+        &self,
+        b: &[u8],
+        out: &mut String,
+        indent: usize,
+        allowance: usize,
+        level: usize,
+    ) {
+        let (indent, allowance) = match level {
+            1 => (indent + 1, allowance + 1),
+            _ => (indent, allowance),
+        };
+        let repr = |b: &[u8]| Value::Bytes(b.into()).to_string();
+        let mut width = self.options.width.saturating_sub(indent);
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let last = b.len() / 4 * 4;
+        for i in (0..b.len()).step_by(4) {
+            if i == last {
+                width = width.saturating_sub(allowance);
+            }
+            let end = (i + 4).min(b.len());
+            if start < i && repr(&b[start..end]).chars().count() > width {
+                chunks.push(repr(&b[start..i]));
+                start = i;
+            }
+        }
+        chunks.push(repr(&b[start..]));
+        self.write_chunks(chunks, out, indent, level);
+    }
+
+    fn write_chunks(&self, chunks: Vec<String>, out: &mut String, indent: usize, level: usize) {
+        let parens = level == 1 && chunks.len() > 1;
+        if parens {
+            out.push('(');
+        }
+        for (i, chunk) in chunks.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+            }
+            out.push_str(chunk);
+        }
+        if parens {
+            out.push(')');
+        }
+    }
+
+    // Whether a container is too deep to show.
+    fn elided(&self, value: &Value, level: usize) -> bool {
+        let deep = self.options.depth.is_some_and(|depth| level >= depth);
+        deep && self.parts(value).is_some_and(|parts| !parts.3.is_empty())
+    }
+
+    // The repr of a value on one line, within the depth limit, with dict
+    // keys and set members in order, and long bytes cut short.
+    fn repr(&self, value: &Value, level: usize) -> String {
+        if let Value::Bytes(b) = value {
+            if let Some(max_bytes) = self.options.max_bytes.filter(|&n| b.len() > n) {
+                let truncated = Truncated {
+                    value: Value::Bytes(b[..max_bytes].into()),
+                    len: b.len() as u64,
+                };
+                return Value::Truncated(Box::new(truncated)).to_string();
+            }
+        }
+        let (open, close, single, items) = match self.parts(value) {
+            Some(parts) if !parts.3.is_empty() => parts,
+            _ => return value.to_string(),
+        };
+        let close = if items.len() == 1 { single } else { close };
+        if self.elided(value, level) {
+            return format!("{}...{}", open, close);
+        }
+        let mut out = open.to_string();
+        for (i, (item, value)) in items.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            out.push_str(&self.repr(item, level + 1));
+            if let Some(value) = value {
+                out.push_str(": ");
+                out.push_str(&self.repr(value, level + 1));
+            }
+        }
+        out.push_str(close);
+        out
+    }
+
+    fn parts<'v, 'a>(&self, value: &'v Value<'a>) -> Option<Parts<'v, 'a>> {
+        let mut parts: Parts = match value {
+            Value::List(items) => ("[", "]", "]", items.iter().map(|v| (v, None)).collect()),
+            Value::Tuple(items) => ("(", ")", ",)", items.iter().map(|v| (v, None)).collect()),
+            Value::Set(set) => ("{", "}", "}", set.0.iter().map(|v| (v, None)).collect()),
+            Value::FrozenSet(set) => (
+                "frozenset({",
+                "})",
+                "})",
+                set.0.iter().map(|v| (v, None)).collect(),
+            ),
+            Value::Dict(dict) => (
+                "{",
+                "}",
+                "}",
+                dict.0.iter().map(|(k, v)| (k, Some(v))).collect(),
+            ),
+            _ => return None,
+        };
+        let sort = match value {
+            Value::Set(_) | Value::FrozenSet(_) | Value::Dict(_) => self.options.sort_dicts,
+            _ => false,
+        };
+        if sort {
+            parts.3.sort_by(|(a, _), (b, _)| py_cmp(a, b));
+        }
+        Some(parts)
+    }
+}
+
+// Splits a line into runs of non-blanks, each with the blanks after it.
+fn words(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut in_blanks = false;
+    for (i, ch) in line.char_indices() {
+        if !ch.is_whitespace() && in_blanks {
+            words.push(&line[start..i]);
+            start = i;
+        }
+        in_blanks = ch.is_whitespace();
+    }
+    if start < line.len() {
+        words.push(&line[start..]);
+    }
+    words
+}
+
+// Orders values as Python's `<` does where it can: numbers by value, and
+// strs, bytes and tuples in lexicographic order.  Values that Python
+// can't compare are ordered by the names of their types, then their reprs.
+fn py_cmp(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bytes(a), Value::Bytes(b)) => a.cmp(b),
+        (Value::Tuple(a), Value::Tuple(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| py_cmp(a, b))
+            .find(|&order| order != Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        _ => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => match (a.as_f64(), b.as_f64()) {
+                (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+                _ => (a.type_name(), a.to_string()).cmp(&(b.type_name(), b.to_string())),
+            },
+        },
+    }
+}
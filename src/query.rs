@@ -0,0 +1,536 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use crate::error::Result;
+use crate::literal::{parse_literal_at, syntax_error};
+use crate::path::{Path, PathElem};
+use crate::{Error, PyKey, Value};
+
+// How deeply filters may nest.  Each level takes several frames to parse
+// and to evaluate, so this is kept well below the limit for literals.
+const MAX_DEPTH: usize = 64;
+
+/// A query that picks values out of a `Value`, in the style of JSONPath:
+///
+/// ```text
+/// optimizer.param_groups[*].lr
+/// $.state[0]['step']
+/// ..weight
+/// layers[?@.kind == 'conv' && @.size > 3][b'bias']
+/// ```
+///
+/// A query is a chain of steps from the value, which `$` may stand for.
+/// `.name` (or `name` at the start) is the str key `name` of a dict.
+/// `[key]` is a dict key written as a Python literal, such as `'a b'`,
+/// `b'k'` or `3`, where an int is also an index into a list or tuple,
+/// counted from the end if negative.  `.*` and `[*]` are all the items of a
+/// list, tuple or dict.  `..` before a name or bracket looks for it at any
+/// depth, the value itself included, so `..[?filter]` filters the items at
+/// every depth.  `[?filter]` keeps the items for which the filter holds:
+/// `@` is the item, followed by steps, and is compared to literals or other
+/// paths with `==`, `!=`, `<`, `<=`, `>` or `>=`, which hold if they hold for
+/// any of the values that the paths pick.  A path on its own holds if it
+/// picks anything.  Filters combine with `&&`, `||`, `!` and parentheses,
+/// and nest at most 64 deep.
+///
+/// Sets and persistent ids are not entered, as their contents have no path.
+#[derive(Clone, Debug)]
+pub struct Query(Vec<Step>);
+
+/// A value picked by a query, with its path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match<'v, 'a> {
+    pub path: Path<'a>,
+    pub value: &'v Value<'a>,
+}
+
+#[derive(Clone, Debug)]
+enum Step {
+    Child(Selector),
+    Descend(Selector),
+}
+
+#[derive(Clone, Debug)]
+enum Selector {
+    Key(Value<'static>),
+    Index(i64),
+    Wildcard,
+    Filter(Filter),
+}
+
+#[derive(Clone, Debug)]
+enum Filter {
+    Or(Box<Filter>, Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Exists(Vec<Step>),
+    Compare(Operand, Op, Operand),
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Path(Vec<Step>),
+    Literal(Value<'static>),
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Query {
+    /// Parses a query.  Errors give the line and column where they were
+    /// found, as those of `literal_eval` do.
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            src: source,
+            pos: 0,
+            depth: 0,
+        };
+        parser.skip_spaces();
+        parser.eat("$");
+        let mut steps = Vec::new();
+        if parser
+            .peek()
+            .is_some_and(|ch| ch == '*' || is_name_start(ch))
+        {
+            steps.push(Step::Child(parser.name()?));
+        }
+        steps.extend(parser.steps()?);
+        parser.skip_spaces();
+        if parser.pos < source.len() {
+            return Err(parser.unexpected());
+        }
+        Ok(Query(steps))
+    }
+
+    /// Returns the values that the query picks from `value`, in the order
+    /// they are found.
+    pub fn eval<'v, 'a>(&self, value: &'v Value<'a>) -> Vec<Match<'v, 'a>> {
+        eval(&self.0, value)
+    }
+
+    /// Whether the value at `path` has to be built to evaluate the query:
+    /// if it may be picked, lies on the way to something that may be, or
+    /// lies within something picked.  Below `..` and filters everything is
+    /// built, as what they pick is not known before it is built.  This is
+    /// what `UnpicklerOptions::select_query` selects.
+    pub fn selects(&self, path: &[PathElem]) -> bool {
+        let steps = &self.0;
+        // The steps the path may have got to, as in an NFA.
+        let mut states = vec![0];
+        for elem in path {
+            let mut next = vec![false; steps.len() + 1];
+            for &i in &states {
+                match steps.get(i) {
+                    // Inside a match, or where filters can't be told.
+                    None
+                    | Some(Step::Child(Selector::Filter(_)))
+                    | Some(Step::Descend(Selector::Filter(_))) => return true,
+                    Some(Step::Child(selector)) => next[i + 1] |= selector.accepts(elem),
+                    Some(Step::Descend(selector)) => {
+                        next[i] = true;
+                        next[i + 1] |= selector.accepts(elem);
+                    }
+                }
+            }
+            states = (0..next.len()).filter(|&i| next[i]).collect();
+            if states.is_empty() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Query::parse(s)
+    }
+}
+
+impl Selector {
+    // Whether a step along a path may be picked by the selector.
+    fn accepts(&self, elem: &PathElem) -> bool {
+        match (self, elem) {
+            (Selector::Wildcard | Selector::Filter(_), _) => true,
+            // Where a negative index lands is not known before the length.
+            (Selector::Index(i), PathElem::Index(j)) => *i < 0 || *i as usize == *j,
+            (Selector::Index(i), PathElem::Key(key)) => key.py_eq(&Value::I64(*i)),
+            (Selector::Key(key), PathElem::Key(other)) => key.py_eq(other),
+            (Selector::Key(_), PathElem::Index(_)) => false,
+        }
+    }
+
+    fn select<'v, 'a>(&self, from: &Match<'v, 'a>, out: &mut Vec<Match<'v, 'a>>) {
+        let key = match (self, from.value) {
+            (Selector::Wildcard, _) => return out.extend(children(from)),
+            (Selector::Filter(filter), _) => {
+                return out.extend(children(from).into_iter().filter(|m| filter.holds(m.value)))
+            }
+            (Selector::Index(i), Value::List(items) | Value::Tuple(items)) => {
+                let i = if *i < 0 { *i + items.len() as i64 } else { *i };
+                if let Some(value) = usize::try_from(i).ok().and_then(|i| items.get(i)) {
+                    out.push(Match {
+                        path: from.path.join(i as usize),
+                        value,
+                    });
+                }
+                return;
+            }
+            (Selector::Index(i), Value::Dict(_)) => &Value::I64(*i),
+            (Selector::Key(key), Value::Dict(_)) => key,
+            _ => return,
+        };
+        if let Value::Dict(dict) = from.value {
+            if let Some((key, value)) = dict.0.get_key_value(&PyKey(key)) {
+                out.push(Match {
+                    path: from.path.join(PathElem::Key(key.clone())),
+                    value,
+                });
+            }
+        }
+    }
+}
+
+fn eval<'v, 'a>(steps: &[Step], value: &'v Value<'a>) -> Vec<Match<'v, 'a>> {
+    let mut current = vec![Match {
+        path: Path::new(),
+        value,
+    }];
+    for step in steps {
+        let mut next = Vec::new();
+        for from in &current {
+            match step {
+                Step::Child(selector) => selector.select(from, &mut next),
+                Step::Descend(selector) => {
+                    for from in descendants(from) {
+                        selector.select(&from, &mut next);
+                    }
+                }
+            }
+        }
+        // `..` picks a value again from each match above it.
+        let mut seen = HashSet::new();
+        next.retain(|m| seen.insert(m.value as *const Value));
+        current = next;
+    }
+    current
+}
+
+fn children<'v, 'a>(from: &Match<'v, 'a>) -> Vec<Match<'v, 'a>> {
+    match from.value {
+        Value::List(items) | Value::Tuple(items) => items
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Match {
+                path: from.path.join(i),
+                value,
+            })
+            .collect(),
+        Value::Dict(dict) => dict
+            .0
+            .iter()
+            .map(|(key, value)| Match {
+                path: from.path.join(PathElem::Key(key.clone())),
+                value,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// The value and everything in it, depth-first, without recursing.
+fn descendants<'v, 'a>(from: &Match<'v, 'a>) -> Vec<Match<'v, 'a>> {
+    let mut out = Vec::new();
+    let mut stack = vec![from.clone()];
+    while let Some(m) = stack.pop() {
+        stack.extend(children(&m).into_iter().rev());
+        out.push(m);
+    }
+    out
+}
+
+impl Filter {
+    fn holds(&self, value: &Value) -> bool {
+        match self {
+            Filter::Or(a, b) => a.holds(value) || b.holds(value),
+            Filter::And(a, b) => a.holds(value) && b.holds(value),
+            Filter::Not(a) => !a.holds(value),
+            Filter::Exists(steps) => !eval(steps, value).is_empty(),
+            Filter::Compare(a, op, b) => {
+                let (a, b) = (a.values(value), b.values(value));
+                a.iter().any(|a| b.iter().any(|b| op.holds(a, b)))
+            }
+        }
+    }
+}
+
+impl Operand {
+    fn values<'v, 'a>(&'v self, value: &'v Value<'a>) -> Vec<&'v Value<'a>> {
+        match self {
+            Operand::Path(steps) => eval(steps, value).into_iter().map(|m| m.value).collect(),
+            Operand::Literal(literal) => vec![literal],
+        }
+    }
+}
+
+impl Op {
+    // Compares as Python does, where values of types Python can't order
+    // are neither less nor greater than each other.
+    fn holds(self, a: &Value, b: &Value) -> bool {
+        let order = match self {
+            Op::Eq => return a.py_eq(b),
+            Op::Ne => return !a.py_eq(b),
+            _ => match py_order(a, b) {
+                Some(order) => order,
+                None => return false,
+            },
+        };
+        match self {
+            Op::Lt => order.is_lt(),
+            Op::Le => order.is_le(),
+            Op::Gt => order.is_gt(),
+            _ => order.is_ge(),
+        }
+    }
+}
+
+fn py_order(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+        _ => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        },
+    }
+}
+
+fn is_name_start(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
+}
+
+struct Parser<'s> {
+    src: &'s str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn rest(&self) -> &'s str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error(&self, msg: impl Into<String>) -> Error {
+        syntax_error(self.src, self.pos, msg)
+    }
+
+    fn unexpected(&self) -> Error {
+        match self.peek() {
+            Some(ch) => self.error(format!("unexpected {:?}", ch)),
+            None => self.error("unexpected end of query"),
+        }
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+        Ok(())
+    }
+
+    fn expect(&mut self, token: &str) -> Result<()> {
+        self.skip_spaces();
+        match self.eat(token) {
+            true => Ok(()),
+            false => match self.peek() {
+                Some(ch) => Err(self.error(format!("expected {:?}, got {:?}", token, ch))),
+                None => Err(self.error(format!("expected {:?}", token))),
+            },
+        }
+    }
+
+    // The steps up to the first character that can't start one.
+    fn steps(&mut self) -> Result<Vec<Step>> {
+        let mut steps = Vec::new();
+        loop {
+            if self.eat("..") {
+                let selector = match self.peek() {
+                    Some('[') => self.bracket()?,
+                    _ => self.name()?,
+                };
+                steps.push(Step::Descend(selector));
+            } else if self.eat(".") {
+                steps.push(Step::Child(self.name()?));
+            } else if self.peek() == Some('[') {
+                steps.push(Step::Child(self.bracket()?));
+            } else {
+                return Ok(steps);
+            }
+        }
+    }
+
+    // A name or `*` after a dot.
+    fn name(&mut self) -> Result<Selector> {
+        if self.eat("*") {
+            return Ok(Selector::Wildcard);
+        }
+        let rest = self.rest();
+        if !rest.starts_with(is_name_start) {
+            return Err(match self.peek() {
+                Some(ch) => self.error(format!("expected a name or *, got {:?}", ch)),
+                None => self.error("expected a name or *"),
+            });
+        }
+        let len = rest
+            .find(|ch: char| !ch.is_alphanumeric() && ch != '_')
+            .unwrap_or(rest.len());
+        self.pos += len;
+        Ok(Selector::Key(Value::String(rest[..len].to_string().into())))
+    }
+
+    // The selector of a `[...]` step.
+    fn bracket(&mut self) -> Result<Selector> {
+        self.expect("[")?;
+        self.skip_spaces();
+        let selector = if self.eat("*") {
+            Selector::Wildcard
+        } else if self.eat("?") {
+            self.enter()?;
+            let filter = self.or()?;
+            self.depth -= 1;
+            Selector::Filter(filter)
+        } else {
+            match self.literal()? {
+                Value::I64(i) => Selector::Index(i),
+                key => Selector::Key(key),
+            }
+        };
+        self.expect("]")?;
+        Ok(selector)
+    }
+
+    fn literal(&mut self) -> Result<Value<'static>> {
+        let (value, end) = parse_literal_at(self.src, self.pos)?;
+        self.pos = end;
+        Ok(value)
+    }
+
+    fn or(&mut self) -> Result<Filter> {
+        let mut filter = self.and()?;
+        loop {
+            self.skip_spaces();
+            if !self.eat("||") {
+                return Ok(filter);
+            }
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+    }
+
+    fn and(&mut self) -> Result<Filter> {
+        let mut filter = self.unary()?;
+        loop {
+            self.skip_spaces();
+            if !self.eat("&&") {
+                return Ok(filter);
+            }
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Filter> {
+        self.skip_spaces();
+        if self.eat("!") {
+            self.enter()?;
+            let filter = self.unary()?;
+            self.depth -= 1;
+            return Ok(Filter::Not(Box::new(filter)));
+        }
+        if self.eat("(") {
+            self.enter()?;
+            let filter = self.or()?;
+            self.expect(")")?;
+            self.depth -= 1;
+            return Ok(filter);
+        }
+        let start = self.pos;
+        let a = self.operand()?;
+        self.skip_spaces();
+        let ops = [
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        match ops.into_iter().find(|(token, _)| self.eat(token)) {
+            Some((_, op)) => Ok(Filter::Compare(a, op, self.operand()?)),
+            None => match a {
+                Operand::Path(steps) => Ok(Filter::Exists(steps)),
+                Operand::Literal(_) => Err(syntax_error(
+                    self.src,
+                    start,
+                    "expected a path or a comparison",
+                )),
+            },
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        self.skip_spaces();
+        if self.eat("@") {
+            Ok(Operand::Path(self.steps()?))
+        } else {
+            Ok(Operand::Literal(self.literal()?))
+        }
+    }
+}
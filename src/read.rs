@@ -0,0 +1,215 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::borrow::Cow;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+
+/// A source of pickle bytes.
+///
+/// Sources backed by a byte slice hand out data borrowed for `'de`, so that
+/// decoded bytes and strings can point straight into the input buffer.
+/// Sources backed by a `std::io::Read` always hand out owned data.
+pub trait Source<'de> {
+    /// Reads a single byte, returning `None` at the end of the input.
+    fn read_byte(&mut self) -> io::Result<Option<u8>>;
+
+    /// Reads exactly `n` bytes, returning `None` if the input ends first.
+    fn read_bytes(&mut self, n: usize) -> io::Result<Option<Cow<'de, [u8]>>>;
+
+    /// Reads up to and including the next newline.  The newline is part of
+    /// the returned data, unless the input ended before one was found.
+    fn read_line(&mut self) -> io::Result<Cow<'de, [u8]>>;
+
+    /// Skips over `n` bytes, returning `false` if the input ends first.
+    fn skip(&mut self, n: u64) -> io::Result<bool>;
+
+    /// The offset in the underlying stream where the pickle starts, which
+    /// `Value::LazyBytes` offsets are relative to.
+    fn start(&self) -> u64 {
+        0
+    }
+}
+
+/// A `Source` reading from a `std::io::Read`.
+pub struct IoSource<R: Read> {
+    reader: BufReader<R>,
+}
+
+impl<R: Read> IoSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+        }
+    }
+}
+
+impl<'de, R: Read> Source<'de> for IoSource<R> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0];
+        match self.reader.read(&mut buf)? {
+            1 => Ok(Some(buf[0])),
+            _ => Ok(None),
+        }
+    }
+
+    fn read_bytes(&mut self, n: usize) -> io::Result<Option<Cow<'de, [u8]>>> {
+        let mut buf = Vec::new();
+        let m = self.reader.by_ref().take(n as u64).read_to_end(&mut buf)?;
+        if m == n {
+            Ok(Some(Cow::Owned(buf)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Cow<'de, [u8]>> {
+        let mut buf = Vec::with_capacity(16);
+        self.reader.read_until(b'\n', &mut buf)?;
+        Ok(Cow::Owned(buf))
+    }
+
+    fn skip(&mut self, n: u64) -> io::Result<bool> {
+        // Without Seek, the only way past the data is through it.
+        let m = io::copy(&mut self.reader.by_ref().take(n), &mut io::sink())?;
+        Ok(m == n)
+    }
+}
+
+/// A `Source` reading from a `std::io::Read + std::io::Seek`, which skips
+/// over data by seeking instead of reading it.
+pub struct SeekSource<R: Read + Seek> {
+    reader: BufReader<R>,
+    start: u64,
+    pos: u64,
+    end: u64,
+}
+
+impl<R: Read + Seek> SeekSource<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let pos = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(pos))?;
+        Ok(Self {
+            reader: BufReader::new(reader),
+            start: pos,
+            pos,
+            end,
+        })
+    }
+}
+
+impl<'de, R: Read + Seek> Source<'de> for SeekSource<R> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0];
+        match self.reader.read(&mut buf)? {
+            1 => {
+                self.pos += 1;
+                Ok(Some(buf[0]))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn read_bytes(&mut self, n: usize) -> io::Result<Option<Cow<'de, [u8]>>> {
+        let mut buf = Vec::new();
+        let m = self.reader.by_ref().take(n as u64).read_to_end(&mut buf)?;
+        self.pos += m as u64;
+        if m == n {
+            Ok(Some(Cow::Owned(buf)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Cow<'de, [u8]>> {
+        let mut buf = Vec::with_capacity(16);
+        self.pos += self.reader.read_until(b'\n', &mut buf)? as u64;
+        Ok(Cow::Owned(buf))
+    }
+
+    fn skip(&mut self, n: u64) -> io::Result<bool> {
+        if self.end.saturating_sub(self.pos) < n {
+            return Ok(false);
+        }
+        match i64::try_from(n) {
+            Ok(offset) => self.reader.seek_relative(offset)?,
+            Err(_) => {
+                self.reader.seek(SeekFrom::Start(self.pos + n))?;
+            }
+        }
+        self.pos += n;
+        Ok(true)
+    }
+
+    fn start(&self) -> u64 {
+        self.start
+    }
+}
+
+/// A `Source` reading from a byte slice, such as a memory-mapped file.
+pub struct SliceSource<'de> {
+    slice: &'de [u8],
+    index: usize,
+}
+
+impl<'de> SliceSource<'de> {
+    pub fn new(slice: &'de [u8]) -> Self {
+        Self { slice, index: 0 }
+    }
+}
+
+impl<'de> Source<'de> for SliceSource<'de> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.slice.get(self.index).copied();
+        if byte.is_some() {
+            self.index += 1;
+        }
+        Ok(byte)
+    }
+
+    fn read_bytes(&mut self, n: usize) -> io::Result<Option<Cow<'de, [u8]>>> {
+        if self.slice.len() - self.index < n {
+            return Ok(None);
+        }
+        let bytes = &self.slice[self.index..self.index + n];
+        self.index += n;
+        Ok(Some(Cow::Borrowed(bytes)))
+    }
+
+    fn read_line(&mut self) -> io::Result<Cow<'de, [u8]>> {
+        let rest = &self.slice[self.index..];
+        let len = match rest.iter().position(|&b| b == b'\n') {
+            Some(i) => i + 1,
+            None => rest.len(),
+        };
+        self.index += len;
+        Ok(Cow::Borrowed(&rest[..len]))
+    }
+
+    fn skip(&mut self, n: u64) -> io::Result<bool> {
+        match usize::try_from(n) {
+            Ok(n) if self.slice.len() - self.index >= n => {
+                self.index += n;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
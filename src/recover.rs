@@ -0,0 +1,83 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::fmt;
+
+use crate::{ErrorCode, Value};
+
+/// What `Unpickler::load_partial` did to make up a value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Recovery {
+    /// Decoding stopped at the opcode, for the reason given.
+    Stopped(ErrorCode),
+    /// The items above an open MARK were added to the list, dict or set
+    /// below it, as APPENDS, SETITEMS or ADDITEMS would.
+    AddedItems(usize),
+    /// The items above an open MARK were made into a list, as there was no
+    /// container below it.
+    MadeList(usize),
+    /// Values that were left on the stack above a list or dict were added
+    /// to it, as APPEND or SETITEM would.
+    AddedValues(usize),
+    /// The last item above an open MARK was a dict key without a value, and
+    /// was dropped.
+    DroppedKey,
+    /// Values that were left on the stack above the result, and were not
+    /// added to it, were dropped.
+    DroppedValues(usize),
+    /// Bytes after `STOP` were ignored.
+    TrailingBytes,
+}
+
+impl fmt::Display for Recovery {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recovery::Stopped(code) => write!(fmt, "decoding stopped: {}", code),
+            Recovery::AddedItems(n) => write!(fmt, "closed MARK, adding {} items", n),
+            Recovery::MadeList(n) => write!(fmt, "closed MARK as a list of {} items", n),
+            Recovery::AddedValues(n) => write!(fmt, "added {} values left on the stack", n),
+            Recovery::DroppedKey => write!(fmt, "dropped a dict key without a value"),
+            Recovery::DroppedValues(n) => write!(fmt, "dropped {} values left on the stack", n),
+            Recovery::TrailingBytes => write!(fmt, "ignored trailing bytes"),
+        }
+    }
+}
+
+/// A step of recovery, and the offset of the opcode that it concerns: the
+/// one where decoding stopped, or the open MARK.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub pos: usize,
+    pub recovery: Recovery,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {}", self.pos, self.recovery)
+    }
+}
+
+/// A value salvaged from a truncated or corrupt pickle, and how it was
+/// made up.  The diagnostics are empty if the pickle was whole.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Partial<'de> {
+    pub value: Value<'de>,
+    pub diagnostics: Vec<Diagnostic>,
+}
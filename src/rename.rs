@@ -0,0 +1,230 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::dis::{emulate, Slot};
+use crate::error::Result;
+use crate::ops::{ops_from_slice, Opcode};
+use crate::{Error, ErrorCode};
+
+/// A mapping of the globals that `rename_globals` replaces.
+#[derive(Clone, Debug, Default)]
+pub struct GlobalRenames {
+    globals: HashMap<(String, String), (String, String)>,
+    modules: Vec<(String, String)>,
+}
+
+impl GlobalRenames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the global `module.name` given as `(module, name)`.
+    pub fn global(mut self, from: (&str, &str), to: (&str, &str)) -> Self {
+        self.globals
+            .insert((from.0.into(), from.1.into()), (to.0.into(), to.1.into()));
+        self
+    }
+
+    /// Move all globals of the module `from`, and of its submodules, to
+    /// `to`.  Renames of single globals take precedence.
+    pub fn module(mut self, from: &str, to: &str) -> Self {
+        self.modules.push((from.into(), to.into()));
+        self
+    }
+
+    /// The new module and name of a global, if it is renamed.
+    pub fn rename(&self, module: &str, name: &str) -> Option<(String, String)> {
+        if let Some(renamed) = self.globals.get(&(module.into(), name.into())) {
+            return Some(renamed.clone());
+        }
+        self.modules.iter().find_map(|(from, to)| {
+            let rest = module.strip_prefix(from.as_str())?;
+            if rest.is_empty() || rest.starts_with('.') {
+                Some((format!("{}{}", to, rest), name.into()))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// Rewrites the globals that a pickle refers to by `GLOBAL`, `INST` and
+/// `STACK_GLOBAL` according to `renames`, without unpickling it.
+///
+/// The string operands of `STACK_GLOBAL` are rewritten where they are
+/// pushed.  Where an operand was fetched from the memo, the fetch is
+/// replaced by the new string, and where a renamed string is fetched again
+/// for another use, that fetch is replaced by the old string, so that
+/// every other value stays the same.  Length prefixes and frames are
+/// fixed up; all other opcodes are copied byte for byte.
+pub fn rename_globals(data: &[u8], renames: &GlobalRenames) -> Result<Vec<u8>> {
+    let mut ops = Vec::new();
+    let mut stack = Vec::new();
+    // The strings that opcodes push, and the opcodes that pushed the
+    // strings in the memo.
+    let mut strings = HashMap::new();
+    let mut memo = HashMap::new();
+    let mut stored = HashSet::new();
+    // The new strings to push, by opcode index.
+    let mut replaced = HashMap::new();
+    let mut fetches = Vec::new();
+
+    let mut iter = ops_from_slice(data);
+    while let Some(op) = iter.next() {
+        let (pos, op) = op?;
+        let index = ops.len();
+        let underflow = || Error::eval(ErrorCode::StackUnderflow, pos);
+        match &op {
+            Opcode::Unicode(s)
+            | Opcode::BinUnicode(s)
+            | Opcode::ShortBinUnicode(s)
+            | Opcode::BinUnicode8(s) => {
+                strings.insert(index, s.to_string());
+                stack.push(Slot::String(index));
+            }
+            Opcode::Put(_) | Opcode::BinPut(_) | Opcode::LongBinPut(_) | Opcode::Memoize => {
+                let id = match op {
+                    Opcode::Put(id) | Opcode::LongBinPut(id) => id,
+                    Opcode::BinPut(id) => id.into(),
+                    _ => stored.len() as u32,
+                };
+                stored.insert(id);
+                match stack.last() {
+                    Some(Slot::String(pusher)) => memo.insert(id, *pusher),
+                    _ => memo.remove(&id),
+                };
+            }
+            Opcode::Get(_) | Opcode::BinGet(_) | Opcode::LongBinGet(_) => {
+                let id = match op {
+                    Opcode::Get(id) | Opcode::LongBinGet(id) => id,
+                    Opcode::BinGet(id) => id.into(),
+                    _ => unreachable!(),
+                };
+                match memo.get(&id) {
+                    Some(&source) => {
+                        strings.insert(index, strings[&source].clone());
+                        fetches.push((index, source));
+                        stack.push(Slot::String(index));
+                    }
+                    None => stack.push(Slot::Item),
+                }
+            }
+            Opcode::StackGlobal => {
+                let name = stack.pop().ok_or_else(underflow)?;
+                let module = stack.pop().ok_or_else(underflow)?;
+                if let (Slot::String(module), Slot::String(name)) = (module, name) {
+                    if let Some((new_module, new_name)) =
+                        renames.rename(&strings[&module], &strings[&name])
+                    {
+                        for (pusher, new) in [(module, new_module), (name, new_name)] {
+                            if strings[&pusher] != new {
+                                replaced.insert(pusher, new);
+                            }
+                        }
+                    }
+                }
+                stack.push(Slot::Item);
+            }
+            op => {
+                let effect = emulate(&mut stack, op);
+                if effect.missing_mark || effect.missing_items {
+                    return Err(underflow());
+                }
+            }
+        }
+        ops.push((pos, iter.position(), op));
+    }
+
+    // A fetch of a string that is renamed where it is pushed, for a use
+    // other than a renamed global, gets the old string.
+    for (index, source) in fetches {
+        if replaced.contains_key(&source) && !replaced.contains_key(&index) {
+            replaced.insert(index, strings[&index].clone());
+        }
+    }
+
+    let mut out = Vec::with_capacity(data.len());
+    // The position of the length of the current frame, and its end in
+    // `data`.
+    let mut frame = None;
+    for (index, (pos, end, op)) in ops.into_iter().enumerate() {
+        if let Some((len_pos, frame_end)) = frame {
+            if pos >= frame_end {
+                close_frame(&mut out, len_pos);
+                frame = None;
+            }
+        }
+        match op {
+            Opcode::Frame(len) => {
+                if let Some((len_pos, _)) = frame {
+                    close_frame(&mut out, len_pos);
+                }
+                let len = usize::try_from(len).unwrap_or(usize::MAX);
+                frame = Some((out.len() + 1, end.saturating_add(len)));
+                out.extend_from_slice(&data[pos..end]);
+            }
+            Opcode::Global {
+                ref module,
+                ref name,
+            }
+            | Opcode::Inst {
+                ref module,
+                ref name,
+            } => match renames.rename(module, name) {
+                Some((module, name)) => {
+                    let (module, name) = (module.into(), name.into());
+                    let op = match op {
+                        Opcode::Global { .. } => Opcode::Global { module, name },
+                        _ => Opcode::Inst { module, name },
+                    };
+                    op.encode(&mut out)?;
+                }
+                None => out.extend_from_slice(&data[pos..end]),
+            },
+            _ => match replaced.remove(&index) {
+                Some(string) => string_op(string).encode(&mut out)?,
+                None => out.extend_from_slice(&data[pos..end]),
+            },
+        }
+    }
+    if let Some((len_pos, _)) = frame {
+        close_frame(&mut out, len_pos);
+    }
+    Ok(out)
+}
+
+fn close_frame(out: &mut [u8], len_pos: usize) {
+    let len = (out.len() - len_pos - 8) as u64;
+    out[len_pos..len_pos + 8].copy_from_slice(&len.to_le_bytes());
+}
+
+// The shortest opcode that pushes `string`, as `STACK_GLOBAL` needs
+// protocol 4.
+fn string_op(string: String) -> Opcode<'static> {
+    if string.len() < 256 {
+        Opcode::ShortBinUnicode(string.into())
+    } else if string.len() <= u32::MAX as usize {
+        Opcode::BinUnicode(string.into())
+    } else {
+        Opcode::BinUnicode8(string.into())
+    }
+}
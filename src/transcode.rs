@@ -0,0 +1,639 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::{HashMap, HashSet};
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::dis::stack_effect;
+use crate::error::Result;
+use crate::lint::HIGHEST_PROTOCOL;
+use crate::ops::{encode_long, ops_from_slice, IntArg, Opcode};
+use crate::optimize::{get, put, Framer, FRAME_SIZE_TARGET};
+use crate::{Error, ErrorCode};
+
+// An opcode to write, or a memo store or fetch by its id in the input.
+enum Edit {
+    Op(Opcode<'static>),
+    Store(u32),
+    Fetch(u32),
+}
+
+#[derive(Clone, PartialEq)]
+enum Kind {
+    Mark,
+    Item,
+    /// A str, as `STACK_GLOBAL` takes.
+    Str(String),
+    /// An empty dict that isn't memoized, pushed by the opcode at this
+    /// index.
+    EmptyDict(usize),
+    /// A set below protocol 4, written as `set(list)` with the list still
+    /// being filled.  Its memo stores wait until it is built.
+    PendingSet(Vec<u32>),
+}
+
+// An entry of the emulated stack, with the index of the first opcode that
+// went into it.
+#[derive(Clone)]
+struct Slot {
+    start: usize,
+    kind: Kind,
+}
+
+// The edits of each input opcode for the target protocol.
+struct Plan<'a> {
+    proto: u8,
+    ops: &'a [(usize, Opcode<'a>)],
+    // The opcodes that write each input opcode.
+    replace: Vec<Vec<Edit>>,
+    // The edits before each opcode: first those that finish items, in
+    // order, then those that open items, outermost first.
+    close: Vec<Vec<Edit>>,
+    open: Vec<Vec<Edit>>,
+    // The opcode that pops each MARK.
+    closers: HashMap<usize, usize>,
+    stack: Vec<Slot>,
+    memo: HashSet<u32>,
+    memo_strings: HashMap<u32, String>,
+    // The memo ids of sets that are being built.
+    pending: HashSet<u32>,
+}
+
+/// Transcodes a pickle of any protocol into an equivalent pickle of
+/// protocol `proto`, without unpickling it.
+///
+/// Opcodes are written in the forms that `pickle` uses at the target
+/// protocol, e.g. bytes as `_codecs.encode(str, 'latin1')` below protocol
+/// 3, sets as `set(list)` below protocol 4, and `STACK_GLOBAL` as `GLOBAL`;
+/// `builtins` and `copyreg` are renamed as Python 2 knows them below
+/// protocol 3, and the other way around.  Memo entries that are fetched
+/// are kept, so shared and recursive values stay shared, and protocol 4
+/// and later pickles are framed.
+///
+/// Content that the target protocol can't express, such as out-of-band
+/// buffers below protocol 5, `NEWOBJ` below protocol 2, or keyword
+/// arguments of `NEWOBJ_EX` below protocol 4, fails with an
+/// `Error::Eval` at the offending opcode.
+pub fn transcode(data: &[u8], proto: u8) -> Result<Vec<u8>> {
+    if proto > HIGHEST_PROTOCOL {
+        let msg = format!("unknown protocol {}", proto);
+        return Err(Error::Syntax(ErrorCode::InvalidValue(msg)));
+    }
+    let ops = ops_from_slice(data).collect::<Result<Vec<_>>>()?;
+
+    let mut plan = Plan {
+        proto,
+        ops: &ops,
+        replace: Vec::with_capacity(ops.len()),
+        close: std::iter::repeat_with(Vec::new).take(ops.len()).collect(),
+        open: std::iter::repeat_with(Vec::new).take(ops.len()).collect(),
+        closers: closers(&ops),
+        stack: Vec::new(),
+        memo: HashSet::new(),
+        memo_strings: HashMap::new(),
+        pending: HashSet::new(),
+    };
+    for (i, (pos, op)) in ops.iter().enumerate() {
+        plan.step(i, *pos, op)?;
+    }
+
+    let fetched = ops
+        .iter()
+        .filter_map(|(_, op)| match op {
+            Opcode::Get(id) | Opcode::LongBinGet(id) => Some(*id),
+            Opcode::BinGet(id) => Some((*id).into()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let mut out = Vec::new();
+    if proto >= 2 {
+        Opcode::Proto(proto).encode(&mut out)?;
+    }
+    let mut framer = Framer::new(proto >= 4);
+    let mut ids = HashMap::new();
+    let mut edits = plan.close.into_iter().zip(plan.open).zip(plan.replace);
+    for (pos, _) in &ops {
+        let ((close, open), replace) = edits.next().unwrap_or_default();
+        for edit in close.into_iter().chain(open).chain(replace) {
+            let op = match edit {
+                Edit::Op(op) => op,
+                Edit::Store(id) if fetched.contains(&id) => {
+                    let new_id = ids.len() as u32;
+                    ids.insert(id, new_id);
+                    put(new_id, proto)
+                }
+                Edit::Store(_) => continue,
+                Edit::Fetch(id) => match ids.get(&id) {
+                    Some(&new_id) => get(new_id, proto),
+                    None => return Err(Error::eval(ErrorCode::MissingMemo(id), *pos)),
+                },
+            };
+            if op.proto() > proto {
+                return Err(inexpressible(op.name(), proto, *pos));
+            }
+            let mut bytes = Vec::new();
+            op.encode(&mut bytes)?;
+            framer.write(&mut out, &bytes, bytes.len() > FRAME_SIZE_TARGET);
+        }
+    }
+    framer.finish(&mut out);
+    Ok(out)
+}
+
+fn inexpressible(what: &str, proto: u8, pos: usize) -> Error {
+    let msg = format!("{} can't be expressed in protocol {}", what, proto);
+    Error::eval(ErrorCode::InvalidValue(msg), pos)
+}
+
+// The opcode that pops each MARK, by index.
+fn closers(ops: &[(usize, Opcode)]) -> HashMap<usize, usize> {
+    let mut closers = HashMap::new();
+    // The index of each MARK on the stack, or `None` for other items.
+    let mut stack = Vec::new();
+    for (i, (_, op)) in ops.iter().enumerate() {
+        if *op == Opcode::Mark {
+            stack.push(Some(i));
+            continue;
+        }
+        let (uses_mark, mut pops, pushes) = stack_effect(op);
+        if uses_mark || (*op == Opcode::Pop && matches!(stack.last(), Some(Some(_)))) {
+            while let Some(slot) = stack.pop() {
+                if let Some(mark) = slot {
+                    closers.insert(mark, i);
+                    break;
+                }
+            }
+            if !uses_mark {
+                pops = 0;
+            }
+        }
+        stack.truncate(stack.len().saturating_sub(pops));
+        stack.extend(std::iter::repeat_n(None, pushes));
+    }
+    closers
+}
+
+impl<'a> Plan<'a> {
+    fn underflow(&self, pos: usize) -> Error {
+        Error::eval(ErrorCode::StackUnderflow, pos)
+    }
+
+    fn pop(&mut self, n: usize, pos: usize) -> Result<Vec<Slot>> {
+        let len = self.stack.len();
+        if len < n
+            || self.stack[len - n..]
+                .iter()
+                .any(|slot| slot.kind == Kind::Mark)
+        {
+            return Err(self.underflow(pos));
+        }
+        Ok(self.stack.split_off(len - n))
+    }
+
+    // Pops the items down to the topmost MARK, and the MARK.
+    fn pop_mark(&mut self, pos: usize) -> Result<(Slot, Vec<Slot>)> {
+        match self.stack.iter().rposition(|slot| slot.kind == Kind::Mark) {
+            Some(mark) => {
+                let items = self.stack.split_off(mark + 1);
+                let mark = self.stack.pop().ok_or_else(|| self.underflow(pos))?;
+                Ok((mark, items))
+            }
+            None => Err(self.underflow(pos)),
+        }
+    }
+
+    // Builds a pending set before opcode `i`, and does its memo stores.
+    fn finish_set(&mut self, i: usize, slot: &mut Slot) {
+        if let Kind::PendingSet(ids) = &mut slot.kind {
+            let ids = std::mem::take(ids);
+            slot.kind = Kind::Item;
+            let tuple = if self.proto >= 2 {
+                Opcode::Tuple1
+            } else {
+                Opcode::Tuple
+            };
+            self.close[i].push(Edit::Op(tuple));
+            self.close[i].push(Edit::Op(Opcode::Reduce));
+            for id in ids {
+                self.pending.remove(&id);
+                self.close[i].push(Edit::Store(id));
+            }
+        }
+    }
+
+    fn step(&mut self, i: usize, pos: usize, op: &Opcode) -> Result<()> {
+        let proto = self.proto;
+        let edits = match op {
+            Opcode::Proto(_) | Opcode::Frame(_) => Vec::new(),
+            Opcode::Put(_) | Opcode::BinPut(_) | Opcode::LongBinPut(_) | Opcode::Memoize => {
+                let id = match op {
+                    Opcode::Put(id) | Opcode::LongBinPut(id) => *id,
+                    Opcode::BinPut(id) => (*id).into(),
+                    _ => self.memo.len() as u32,
+                };
+                self.memo.insert(id);
+                self.memo_strings.remove(&id);
+                let top = match self.stack.last_mut() {
+                    Some(slot) if slot.kind != Kind::Mark => slot,
+                    _ => return Err(self.underflow(pos)),
+                };
+                match &mut top.kind {
+                    Kind::PendingSet(ids) => {
+                        ids.push(id);
+                        self.pending.insert(id);
+                        Vec::new()
+                    }
+                    Kind::Str(s) => {
+                        self.memo_strings.insert(id, s.clone());
+                        vec![Edit::Store(id)]
+                    }
+                    kind => {
+                        *kind = Kind::Item;
+                        vec![Edit::Store(id)]
+                    }
+                }
+            }
+            Opcode::Get(_) | Opcode::BinGet(_) | Opcode::LongBinGet(_) => {
+                let id = match op {
+                    Opcode::Get(id) | Opcode::LongBinGet(id) => *id,
+                    Opcode::BinGet(id) => (*id).into(),
+                    _ => unreachable!(),
+                };
+                let kind = match self.memo_strings.get(&id) {
+                    Some(s) => Kind::Str(s.clone()),
+                    None => Kind::Item,
+                };
+                self.push(i, Slot { start: i, kind });
+                if self.pending.contains(&id) {
+                    return Err(inexpressible("a set in its own items", proto, pos));
+                }
+                vec![Edit::Fetch(id)]
+            }
+            Opcode::AddItems if proto < 4 => {
+                let (mark, items) = self.pop_mark(pos)?;
+                if proto == 0 {
+                    self.replace[mark.start].clear();
+                }
+                let target = self.stack.len().checked_sub(1);
+                match target.map(|t| &self.stack[t].kind) {
+                    Some(Kind::PendingSet(_)) => {}
+                    _ => {
+                        return Err(inexpressible(
+                            "ADDITEMS to a set built elsewhere",
+                            proto,
+                            pos,
+                        ))
+                    }
+                }
+                // Extend the list of the set.
+                self.extend(items, 1, Opcode::Append, Opcode::Appends)
+            }
+            _ => self.generic(i, pos, op)?,
+        };
+        self.replace.push(edits);
+        Ok(())
+    }
+
+    // Pushes a slot, building a set below it first.
+    fn push(&mut self, i: usize, slot: Slot) {
+        let keep_set = slot.kind == Kind::Mark
+            && matches!(
+                self.closers.get(&i).map(|&closer| &self.ops[closer].1),
+                Some(Opcode::AddItems)
+            );
+        if let Some(mut top) = self.stack.pop() {
+            if !keep_set {
+                self.finish_set(i, &mut top);
+            }
+            self.stack.push(top);
+        }
+        self.stack.push(slot);
+    }
+
+    // The opcodes that add items one by one in protocol 0, where `APPENDS`
+    // and `SETITEMS` are missing: after each group of `per` items, or
+    // else all at once.
+    fn extend(
+        &mut self,
+        items: Vec<Slot>,
+        per: usize,
+        one: Opcode<'static>,
+        all: Opcode<'static>,
+    ) -> Vec<Edit> {
+        if self.proto >= 1 {
+            return vec![Edit::Op(all)];
+        }
+        for group in items.chunks(per).skip(1) {
+            self.close[group[0].start].push(Edit::Op(one.clone()));
+        }
+        match items.is_empty() {
+            true => Vec::new(),
+            false => vec![Edit::Op(one)],
+        }
+    }
+
+    fn generic(&mut self, i: usize, pos: usize, op: &Opcode) -> Result<Vec<Edit>> {
+        let proto = self.proto;
+        let (uses_mark, mut pops, pushes) = stack_effect(op);
+        let pops_mark = uses_mark
+            || (*op == Opcode::Pop
+                && matches!(self.stack.last(), Some(slot) if slot.kind == Kind::Mark));
+        let mut mark = None;
+        let mut items = Vec::new();
+        if pops_mark {
+            let (slot, popped) = self.pop_mark(pos)?;
+            mark = Some(slot);
+            items = popped;
+            if !uses_mark {
+                pops = 0;
+            }
+        }
+        let mut below = self.pop(pops, pos)?;
+        for slot in items.iter_mut().chain(below.iter_mut()) {
+            self.finish_set(i, slot);
+        }
+        let start = mark
+            .iter()
+            .chain(&items)
+            .chain(&below)
+            .map(|slot| slot.start)
+            .min()
+            .unwrap_or(i);
+        let mark_index = mark.as_ref().map(|slot| slot.start);
+
+        let mut kind = Kind::Item;
+        let edits = match op {
+            Opcode::Mark => {
+                self.push(
+                    i,
+                    Slot {
+                        start: i,
+                        kind: Kind::Mark,
+                    },
+                );
+                return Ok(vec![Edit::Op(Opcode::Mark)]);
+            }
+            Opcode::Dup => {
+                let slot = below.pop().ok_or_else(|| self.underflow(pos))?;
+                let kind = slot.kind.clone();
+                self.stack.push(slot);
+                self.push(i, Slot { start: i, kind });
+                return Ok(vec![Edit::Op(Opcode::Dup)]);
+            }
+            Opcode::Appends | Opcode::SetItems | Opcode::PopMark if proto == 0 => {
+                if let Some(mark) = mark_index {
+                    self.replace[mark].clear();
+                }
+                match op {
+                    Opcode::Appends => self.extend(items, 1, Opcode::Append, Opcode::Appends),
+                    Opcode::SetItems => self.extend(items, 2, Opcode::SetItem, Opcode::SetItems),
+                    _ => std::iter::repeat_with(|| Edit::Op(Opcode::Pop))
+                        .take(items.len())
+                        .collect(),
+                }
+            }
+            Opcode::FrozenSet if proto < 4 => {
+                if let Some(mark) = mark_index {
+                    let mut edits = vec![Edit::Op(global("builtins", "frozenset", proto))];
+                    if proto < 2 {
+                        edits.push(Edit::Op(Opcode::Mark));
+                    }
+                    edits.push(Edit::Op(Opcode::Mark));
+                    self.replace[mark] = edits;
+                }
+                let tuple = if proto >= 2 {
+                    Opcode::Tuple1
+                } else {
+                    Opcode::Tuple
+                };
+                vec![
+                    Edit::Op(Opcode::List),
+                    Edit::Op(tuple),
+                    Edit::Op(Opcode::Reduce),
+                ]
+            }
+            Opcode::Tuple1 | Opcode::Tuple2 | Opcode::Tuple3 if proto < 2 => {
+                self.open[start].insert(0, Edit::Op(Opcode::Mark));
+                vec![Edit::Op(Opcode::Tuple)]
+            }
+            Opcode::EmptySet if proto < 4 => {
+                kind = Kind::PendingSet(Vec::new());
+                let mut edits = vec![global("builtins", "set", proto)];
+                if proto < 2 {
+                    edits.push(Opcode::Mark);
+                }
+                edits.extend(convert(&Opcode::EmptyList, proto));
+                edits.into_iter().map(Edit::Op).collect()
+            }
+            Opcode::StackGlobal if proto < 4 => match (&below[0].kind, &below[1].kind) {
+                (Kind::Str(module), Kind::Str(name)) => vec![
+                    Edit::Op(Opcode::Pop),
+                    Edit::Op(Opcode::Pop),
+                    Edit::Op(global(module, name, proto)),
+                ],
+                _ => {
+                    return Err(inexpressible(
+                        "STACK_GLOBAL of values other than strings",
+                        proto,
+                        pos,
+                    ))
+                }
+            },
+            Opcode::NewObjEx if proto < 4 => match below[2].kind {
+                Kind::EmptyDict(producer) => {
+                    self.replace[producer].clear();
+                    vec![Edit::Op(Opcode::NewObj)]
+                }
+                _ => {
+                    return Err(inexpressible(
+                        "NEWOBJ_EX with keyword arguments",
+                        proto,
+                        pos,
+                    ))
+                }
+            },
+            op => {
+                kind = match op {
+                    Opcode::EmptyDict => Kind::EmptyDict(i),
+                    Opcode::Unicode(s)
+                    | Opcode::BinUnicode(s)
+                    | Opcode::ShortBinUnicode(s)
+                    | Opcode::BinUnicode8(s) => Kind::Str(s.to_string()),
+                    _ => Kind::Item,
+                };
+                convert(op, proto).into_iter().map(Edit::Op).collect()
+            }
+        };
+        for _ in 0..pushes {
+            self.push(
+                i,
+                Slot {
+                    start,
+                    kind: kind.clone(),
+                },
+            );
+        }
+        Ok(edits)
+    }
+}
+
+// The module of a global as `pickle` writes it in `proto`, which maps
+// Python 3 names to Python 2 ones below protocol 3.
+pub(crate) fn global(module: &str, name: &str, proto: u8) -> Opcode<'static> {
+    const RENAMES: [(&str, &str); 2] = [("builtins", "__builtin__"), ("copyreg", "copy_reg")];
+    let module = RENAMES
+        .iter()
+        .find_map(|&(py3, py2)| match proto {
+            0..=2 if module == py3 => Some(py2),
+            3.. if module == py2 => Some(py3),
+            _ => None,
+        })
+        .unwrap_or(module);
+    Opcode::Global {
+        module: module.to_string().into(),
+        name: name.to_string().into(),
+    }
+}
+
+// The opcodes that push the same value as `op` in `proto`.
+fn convert(op: &Opcode, proto: u8) -> Vec<Opcode<'static>> {
+    match op {
+        Opcode::NewTrue | Opcode::Int(IntArg::Bool(true)) if proto >= 2 => vec![Opcode::NewTrue],
+        Opcode::NewFalse | Opcode::Int(IntArg::Bool(false)) if proto >= 2 => {
+            vec![Opcode::NewFalse]
+        }
+        Opcode::NewTrue => vec![Opcode::Int(IntArg::Bool(true))],
+        Opcode::NewFalse => vec![Opcode::Int(IntArg::Bool(false))],
+        Opcode::Int(IntArg::Int(i)) => vec![int(&BigInt::from(*i), proto)],
+        Opcode::BinInt(i) => vec![int(&BigInt::from(*i), proto)],
+        Opcode::BinInt1(i) => vec![int(&BigInt::from(*i), proto)],
+        Opcode::BinInt2(i) => vec![int(&BigInt::from(*i), proto)],
+        Opcode::Long(i) | Opcode::Long1(i) | Opcode::Long4(i) => vec![int(i, proto)],
+        Opcode::Float(f) | Opcode::BinFloat(f) => match proto {
+            0 => vec![Opcode::Float(*f)],
+            _ => vec![Opcode::BinFloat(*f)],
+        },
+        Opcode::String(b) | Opcode::BinString(b) | Opcode::ShortBinString(b) => {
+            let b = b.to_vec().into();
+            match proto {
+                0 => vec![Opcode::String(b)],
+                _ if b.len() < 256 => vec![Opcode::ShortBinString(b)],
+                _ => vec![Opcode::BinString(b)],
+            }
+        }
+        Opcode::Unicode(s)
+        | Opcode::BinUnicode(s)
+        | Opcode::ShortBinUnicode(s)
+        | Opcode::BinUnicode8(s) => vec![unicode(s, proto)],
+        Opcode::BinBytes(b) | Opcode::ShortBinBytes(b) | Opcode::BinBytes8(b) => bytes(b, proto),
+        Opcode::ByteArray8(b) if proto < 5 => {
+            let mut ops = vec![global("builtins", "bytearray", proto)];
+            if proto >= 3 {
+                ops.extend(bytes(b, proto));
+                ops.push(Opcode::Tuple1);
+            } else {
+                // bytearray(str, 'latin-1'), as bytearray.__reduce_ex__.
+                if proto < 2 {
+                    ops.push(Opcode::Mark);
+                }
+                ops.push(unicode(&latin1(b), proto));
+                ops.push(unicode("latin-1", proto));
+                ops.push(if proto >= 2 {
+                    Opcode::Tuple2
+                } else {
+                    Opcode::Tuple
+                });
+            }
+            ops.push(Opcode::Reduce);
+            ops
+        }
+        Opcode::EmptyList if proto == 0 => vec![Opcode::Mark, Opcode::List],
+        Opcode::EmptyDict if proto == 0 => vec![Opcode::Mark, Opcode::Dict],
+        Opcode::EmptyTuple if proto == 0 => vec![Opcode::Mark, Opcode::Tuple],
+        Opcode::Global { module, name } => vec![global(module, name, proto)],
+        Opcode::Inst { module, name } => match global(module, name, proto) {
+            Opcode::Global { module, name } => vec![Opcode::Inst { module, name }],
+            op => vec![op],
+        },
+        op => vec![op.clone().into_owned()],
+    }
+}
+
+// An integer as `pickle` writes it in `proto`.
+pub(crate) fn int(i: &BigInt, proto: u8) -> Opcode<'static> {
+    match (proto, i.to_i64()) {
+        (0, Some(i)) if i32::try_from(i).is_ok() => Opcode::Int(IntArg::Int(i)),
+        (0, _) => Opcode::Long(i.clone()),
+        (_, Some(i @ 0..=0xff)) => Opcode::BinInt1(i as u8),
+        (_, Some(i @ 0x100..=0xffff)) => Opcode::BinInt2(i as u16),
+        (_, Some(i)) if i32::try_from(i).is_ok() => Opcode::BinInt(i as i32),
+        (1, _) => Opcode::Long(i.clone()),
+        _ if encode_long(i).len() < 256 => Opcode::Long1(i.clone()),
+        _ => Opcode::Long4(i.clone()),
+    }
+}
+
+pub(crate) fn unicode(s: &str, proto: u8) -> Opcode<'static> {
+    let s = s.to_string().into();
+    match proto {
+        0 => Opcode::Unicode(s),
+        4.. if s.len() < 256 => Opcode::ShortBinUnicode(s),
+        4.. if s.len() > u32::MAX as usize => Opcode::BinUnicode8(s),
+        _ => Opcode::BinUnicode(s),
+    }
+}
+
+// Bytes as `pickle` writes them in `proto`, which is a call of
+// `_codecs.encode` below protocol 3.
+pub(crate) fn bytes(b: &[u8], proto: u8) -> Vec<Opcode<'static>> {
+    let owned = || b.to_vec().into();
+    match proto {
+        4.. if b.len() > u32::MAX as usize => vec![Opcode::BinBytes8(owned())],
+        3.. if b.len() < 256 => vec![Opcode::ShortBinBytes(owned())],
+        3.. => vec![Opcode::BinBytes(owned())],
+        _ if b.is_empty() => {
+            let mut ops = vec![global("builtins", "bytes", proto)];
+            ops.extend(convert(&Opcode::EmptyTuple, proto));
+            ops.push(Opcode::Reduce);
+            ops
+        }
+        _ => {
+            let mut ops = vec![global("_codecs", "encode", proto)];
+            if proto < 2 {
+                ops.push(Opcode::Mark);
+            }
+            ops.push(unicode(&latin1(b), proto));
+            ops.push(unicode("latin1", proto));
+            ops.push(if proto >= 2 {
+                Opcode::Tuple2
+            } else {
+                Opcode::Tuple
+            });
+            ops.push(Opcode::Reduce);
+            ops
+        }
+    }
+}
+
+fn latin1(b: &[u8]) -> String {
+    b.iter().map(|&b| b as char).collect()
+}
@@ -18,10 +18,18 @@
 // specific language governing permissions and limitations
 // under the License.
 
-use std::hash::Hash;
+use std::borrow::Cow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem;
+use std::ops::Index;
 
 use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive};
 
+use crate::path::PathElem;
+use crate::pprint::{pformat, PrettyOptions};
 use crate::{F64Wrapper, HashMapWrapper, HashSetWrapper};
 
 pub type MemoId = u32;
@@ -34,11 +42,71 @@ pub enum Global {
     List,      // builtins/__builtin__.list
     Int,       // builtins/__builtin__.int
     Encode,    // _codecs.encode
+    Complex,   // builtins/__builtin__.complex
     Other,     // anything else (may be a classobj that is later discarded)
 }
 
-#[derive(Clone, Debug, PartialEq, Hash)]
-pub enum Value {
+/// A handle to a bytes payload that was left in the input instead of being
+/// read.  The offset is counted from the start of the stream for pickles
+/// read through a `SeekSource`, and from the first byte of the pickle
+/// otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LazyBytes {
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl LazyBytes {
+    /// Reads the payload from the stream the pickle was decoded from.
+    pub fn fetch<R: Read + Seek>(&self, reader: &mut R) -> io::Result<Vec<u8>> {
+        reader.seek(SeekFrom::Start(self.offset))?;
+        let mut buf = Vec::new();
+        let n = reader.take(self.len).read_to_end(&mut buf)?;
+        if (n as u64) < self.len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(buf)
+    }
+
+    /// Returns the payload within `data`, e.g. a memory map of the pickle.
+    pub fn slice<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        let start = usize::try_from(self.offset).ok()?;
+        let end = start.checked_add(usize::try_from(self.len).ok()?)?;
+        data.get(start..end)
+    }
+}
+
+/// A placeholder for a value that was not materialized, because it lies
+/// outside the selected paths.  `start..end` is the byte span of the
+/// opcodes that built it (as far as they were seen), and `len` the number
+/// of items of a container or the payload size of a string or bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Skipped {
+    pub start: u64,
+    pub end: u64,
+    pub len: u64,
+}
+
+/// A value that was cut short in preview mode: the first items of a
+/// container, or the start of a string or bytes.  `len` is the true number
+/// of items, characters of a string, or bytes of a bytes value.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Truncated<'a> {
+    pub value: Value<'a>,
+    pub len: u64,
+}
+
+/// A decoded Python value.
+///
+/// Bytes and strings are held as `Cow`s, so that values decoded from a slice
+/// can borrow their payloads from the input buffer.  Values decoded from a
+/// `std::io::Read` are always owned, i.e. `Value<'static>`.
+///
+/// `==` compares values structurally, so `Value::I64(1)` is not equal to
+/// `Value::Bool(true)`; `py_eq` compares them as Python does.  The hash
+/// agrees with both, so a value can be looked up either way.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value<'a> {
     MemoRef(MemoId),
     Global(Global),
     None,
@@ -46,21 +114,550 @@ pub enum Value {
     Int(BigInt),
     I64(i64),
     F64(F64Wrapper),
-    Bytes(Vec<u8>),
-    String(String),
-    List(Vec<Value>),
-    Tuple(Vec<Value>),
-    Set(HashSetWrapper<Value>),
-    FrozenSet(HashSetWrapper<Value>),
-    Dict(HashMapWrapper<Value, Value>),
+    /// A complex number, with its real and imaginary parts.
+    Complex(F64Wrapper, F64Wrapper),
+    Bytes(Cow<'a, [u8]>),
+    LazyBytes(LazyBytes),
+    String(Cow<'a, str>),
+    List(Vec<Value<'a>>),
+    Tuple(Vec<Value<'a>>),
+    Set(HashSetWrapper<Value<'a>>),
+    FrozenSet(HashSetWrapper<Value<'a>>),
+    Dict(HashMapWrapper<Value<'a>, Value<'a>>),
     PersId(String),
-    BinPersId(Box<Value>),
+    BinPersId(Box<Value<'a>>),
+    Skipped(Skipped),
+    Truncated(Box<Truncated<'a>>),
+}
+
+impl<'a> std::cmp::Eq for Value<'a> {}
+
+impl<'a> Hash for Value<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Values that Python takes as equal, such as `1`, `1.0` and `True`,
+        // or a set and a frozenset, have to hash the same.
+        if let Some(number) = self.number() {
+            number.hash(state);
+            return;
+        }
+        match self {
+            Value::Set(set) | Value::FrozenSet(set) => {
+                mem::discriminant(&Value::Set(HashSetWrapper::new())).hash(state);
+                set.hash(state);
+                return;
+            }
+            _ => mem::discriminant(self).hash(state),
+        }
+        match self {
+            Value::MemoRef(id) => id.hash(state),
+            Value::Global(g) => g.hash(state),
+            Value::Bytes(b) => b.hash(state),
+            Value::LazyBytes(l) => l.hash(state),
+            Value::String(s) => s.hash(state),
+            Value::List(items) | Value::Tuple(items) => items.hash(state),
+            Value::Dict(dict) => dict.hash(state),
+            Value::PersId(s) => s.hash(state),
+            Value::BinPersId(v) => v.hash(state),
+            Value::Skipped(s) => s.hash(state),
+            Value::Truncated(t) => t.hash(state),
+            _ => {}
+        }
+    }
+}
+
+fn float_number(f: f64) -> Number {
+    if f.is_nan() {
+        return Number::Nan;
+    }
+    if f.is_finite() && f.fract() == 0.0 {
+        if let Some(i) = BigInt::from_f64(f) {
+            return match i.to_i64() {
+                Some(i) => Number::Int(i),
+                None => Number::Big(i),
+            };
+        }
+    }
+    Number::Float(f.to_bits())
+}
+
+// A number in a form that is the same for all the values Python takes as
+// equal to it.
+#[derive(PartialEq, Eq, Hash)]
+enum Number {
+    Int(i64),
+    Big(BigInt),
+    Float(u64),
+    Nan,
+    Complex(Box<Number>, Box<Number>),
+}
+
+impl Number {
+    fn is_nan(&self) -> bool {
+        match self {
+            Number::Nan => true,
+            Number::Complex(re, im) => re.is_nan() || im.is_nan(),
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Value<'a> {
+    // The number that a bool, int, float or complex stands for.
+    fn number(&self) -> Option<Number> {
+        Some(match self {
+            Value::Bool(b) => Number::Int(*b as i64),
+            Value::I64(i) => Number::Int(*i),
+            Value::Int(i) => match i.to_i64() {
+                Some(i) => Number::Int(i),
+                None => Number::Big(i.clone()),
+            },
+            Value::F64(f) => float_number(f.0),
+            Value::Complex(re, im) if im.0 == 0.0 => float_number(re.0),
+            Value::Complex(re, im) => {
+                Number::Complex(Box::new(float_number(re.0)), Box::new(float_number(im.0)))
+            }
+            _ => return None,
+        })
+    }
+
+    /// Compares the values as Python's `==` does: bools, ints and floats
+    /// by their numeric value, sets with frozensets, and dicts and sets by
+    /// their contents with keys and members matched the same way.  As in
+    /// Python, NaN is equal to nothing, not even itself.  (A NaN key that a
+    /// pickle fetches from the memo more than once is the same object in
+    /// Python, and so the same key; loading merges such keys by their memo
+    /// id.)
+    pub fn py_eq(&self, other: &Value) -> bool {
+        if let (Some(a), Some(b)) = (self.number(), other.number()) {
+            return a == b && !a.is_nan();
+        }
+        match (self, other) {
+            (Value::List(a), Value::List(b)) | (Value::Tuple(a), Value::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.py_eq(b))
+            }
+            (Value::Set(a) | Value::FrozenSet(a), Value::Set(b) | Value::FrozenSet(b)) => {
+                a.0.len() == b.0.len() && a.0.iter().all(|item| b.contains(item))
+            }
+            (Value::Dict(a), Value::Dict(b)) => {
+                a.0.len() == b.0.len()
+                    && a.0
+                        .iter()
+                        .all(|(key, value)| b.get(key).is_some_and(|other| value.py_eq(other)))
+            }
+            (Value::BinPersId(a), Value::BinPersId(b)) => a.py_eq(b),
+            (Value::Truncated(a), Value::Truncated(b)) => a.len == b.len && a.value.py_eq(&b.value),
+            (a, b) => a == b,
+        }
+    }
+
+    /// Converts the value into one that owns all of its data, copying any
+    /// bytes or strings still borrowed from the input.
+    pub fn into_owned(self) -> Value<'static> {
+        match self {
+            Value::MemoRef(id) => Value::MemoRef(id),
+            Value::Global(g) => Value::Global(g),
+            Value::None => Value::None,
+            Value::Bool(b) => Value::Bool(b),
+            Value::Int(i) => Value::Int(i),
+            Value::I64(i) => Value::I64(i),
+            Value::F64(f) => Value::F64(f),
+            Value::Complex(re, im) => Value::Complex(re, im),
+            Value::Bytes(b) => Value::Bytes(Cow::Owned(b.into_owned())),
+            Value::LazyBytes(l) => Value::LazyBytes(l),
+            Value::String(s) => Value::String(Cow::Owned(s.into_owned())),
+            Value::List(v) => Value::List(v.into_iter().map(Value::into_owned).collect()),
+            Value::Tuple(v) => Value::Tuple(v.into_iter().map(Value::into_owned).collect()),
+            Value::Set(v) => Value::Set(HashSetWrapper(
+                v.0.into_iter().map(Value::into_owned).collect(),
+            )),
+            Value::FrozenSet(v) => Value::FrozenSet(HashSetWrapper(
+                v.0.into_iter().map(Value::into_owned).collect(),
+            )),
+            Value::Dict(v) => Value::Dict(HashMapWrapper(
+                v.0.into_iter()
+                    .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                    .collect(),
+            )),
+            Value::PersId(s) => Value::PersId(s),
+            Value::BinPersId(v) => Value::BinPersId(Box::new(v.into_owned())),
+            Value::Skipped(s) => Value::Skipped(s),
+            Value::Truncated(t) => Value::Truncated(Box::new(Truncated {
+                value: t.value.into_owned(),
+                len: t.len,
+            })),
+        }
+    }
+}
+
+// What indexing yields for a missing key or index.
+static NONE: Value<'static> = Value::None;
+
+impl<'a> Value<'a> {
+    pub fn is_none(&self) -> bool {
+        matches!(self, Value::None)
+    }
+
+    /// The name of the Python type of the value, e.g. `"dict"`, as in
+    /// Python's error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::MemoRef(_) => "memo reference",
+            Value::Global(_) => "global",
+            Value::None => "NoneType",
+            Value::Bool(_) => "bool",
+            Value::Int(_) | Value::I64(_) => "int",
+            Value::F64(_) => "float",
+            Value::Complex(_, _) => "complex",
+            Value::Bytes(_) | Value::LazyBytes(_) => "bytes",
+            Value::String(_) => "str",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Set(_) => "set",
+            Value::FrozenSet(_) => "frozenset",
+            Value::Dict(_) => "dict",
+            Value::PersId(_) | Value::BinPersId(_) => "persistent id",
+            Value::Skipped(_) => "skipped value",
+            Value::Truncated(_) => "truncated value",
+        }
+    }
+
+    /// The string, if the value is a str.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The number, if the value is a bool, or an int that fits in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Bool(b) => Some(*b as i64),
+            Value::I64(i) => Some(*i),
+            Value::Int(i) => i.to_i64(),
+            _ => None,
+        }
+    }
+
+    /// The number, if the value is a float, or an int or bool converted to
+    /// a float as Python's `float()` would.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::F64(f) => Some(f.0),
+            Value::Int(i) => i.to_f64(),
+            other => other.as_i64().map(|i| i as f64),
+        }
+    }
+
+    /// The payload, if the value is a bytes value that was read.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// The items, if the value is a list or tuple.
+    pub fn as_list(&self) -> Option<&[Value<'a>]> {
+        match self {
+            Value::List(items) | Value::Tuple(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// The dict, if the value is one.
+    pub fn as_dict(&self) -> Option<&HashMapWrapper<Value<'a>, Value<'a>>> {
+        match self {
+            Value::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a dict key, e.g. `value.get("name")`, or an item
+    /// of a list or tuple, e.g. `value.get(0)`.  Keys are matched as in
+    /// Python, see `Value::py_eq`, and an index looks up the int key of a
+    /// dict, so `value.get(0)` finds the key `0`.
+    pub fn get<'k, E: Into<PathElem<'k>>>(&self, elem: E) -> Option<&Value<'a>> {
+        match (self, elem.into()) {
+            (Value::Dict(dict), PathElem::Key(key)) => dict.get(&key),
+            (Value::Dict(dict), PathElem::Index(i)) => dict.get(&Value::I64(i.try_into().ok()?)),
+            (Value::List(items) | Value::Tuple(items), PathElem::Index(i)) => items.get(i),
+            _ => None,
+        }
+    }
+}
+
+/// Looks up a dict key like `Value::get`, giving `Value::None` if the value
+/// is not a dict or has no such key, so that lookups can be chained:
+/// `value["config"]["name"]`.
+impl<'a> Index<&str> for Value<'a> {
+    type Output = Value<'a>;
+
+    fn index(&self, key: &str) -> &Value<'a> {
+        self.get(key).unwrap_or(&NONE)
+    }
+}
+
+/// Looks up an item of a list or tuple, or an int key of a dict, like
+/// `Value::get`, giving `Value::None` if there is no such item.
+impl<'a> Index<usize> for Value<'a> {
+    type Output = Value<'a>;
+
+    fn index(&self, index: usize) -> &Value<'a> {
+        self.get(index).unwrap_or(&NONE)
+    }
+}
+
+/// Renders the value the way Python's `repr` does, as far as the value
+/// allows.  Truncated values end in a count of what was left out, e.g.
+/// `[0, 1, 2, ... 9,997 more items]`.  The alternate form, `{:#}`, renders
+/// it as `pformat` does with the default options.
+impl<'a> fmt::Display for Value<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if fmt.alternate() {
+            return fmt.write_str(&pformat(self, PrettyOptions::new()));
+        }
+        match self {
+            Value::MemoRef(id) => write!(fmt, "<memo {}>", id),
+            Value::Global(g) => fmt.write_str(global_name(g)),
+            Value::None => fmt.write_str("None"),
+            Value::Bool(true) => fmt.write_str("True"),
+            Value::Bool(false) => fmt.write_str("False"),
+            Value::Int(i) => write!(fmt, "{}", i),
+            Value::I64(i) => write!(fmt, "{}", i),
+            Value::F64(f) => write_float(fmt, f.0),
+            Value::Complex(re, im) => write_complex(fmt, re.0, im.0),
+            Value::Bytes(b) => write_bytes(fmt, b),
+            Value::LazyBytes(l) => {
+                write!(fmt, "<{} bytes at {}>", thousands(l.len), l.offset)
+            }
+            Value::String(s) => write_str(fmt, s),
+            Value::PersId(s) => {
+                fmt.write_str("persistent_id(")?;
+                write_str(fmt, s)?;
+                fmt.write_str(")")
+            }
+            Value::BinPersId(v) => write!(fmt, "persistent_id({})", v),
+            Value::Skipped(s) => write!(fmt, "<skipped {}..{}>", s.start, s.end),
+            Value::Truncated(t) => write_truncated(fmt, &t.value, t.len),
+            container => write_container(fmt, container, 0),
+        }
+    }
+}
+
+// Write a list, tuple, set or dict, followed by a count of `more` items
+// that were left out.
+fn write_container(fmt: &mut fmt::Formatter, value: &Value, more: u64) -> fmt::Result {
+    let (open, close, items, single): (_, _, Vec<_>, _) = match value {
+        Value::List(items) => ("[", "]", items.iter().map(|v| (v, None)).collect(), ""),
+        Value::Tuple(items) => ("(", ")", items.iter().map(|v| (v, None)).collect(), ","),
+        Value::Set(set) if set.0.is_empty() && more == 0 => return fmt.write_str("set()"),
+        Value::Set(set) => ("{", "}", set.0.iter().map(|v| (v, None)).collect(), ""),
+        Value::FrozenSet(set) if set.0.is_empty() && more == 0 => {
+            return fmt.write_str("frozenset()");
+        }
+        Value::FrozenSet(set) => (
+            "frozenset({",
+            "})",
+            set.0.iter().map(|v| (v, None)).collect(),
+            "",
+        ),
+        Value::Dict(dict) => (
+            "{",
+            "}",
+            dict.0.iter().map(|(k, v)| (k, Some(v))).collect(),
+            "",
+        ),
+        other => return write!(fmt, "{}", other),
+    };
+    fmt.write_str(open)?;
+    for (i, (item, value)) in items.iter().enumerate() {
+        if i > 0 {
+            fmt.write_str(", ")?;
+        }
+        write!(fmt, "{}", item)?;
+        if let Some(value) = value {
+            write!(fmt, ": {}", value)?;
+        }
+    }
+    if more > 0 {
+        if !items.is_empty() {
+            fmt.write_str(", ")?;
+        }
+        write!(fmt, "... {} more items", thousands(more))?;
+    } else if items.len() == 1 {
+        fmt.write_str(single)?;
+    }
+    fmt.write_str(close)
+}
+
+fn write_truncated(fmt: &mut fmt::Formatter, value: &Value, len: u64) -> fmt::Result {
+    match value {
+        Value::String(s) => {
+            write_str(fmt, s)?;
+            let more = len.saturating_sub(s.chars().count() as u64);
+            write!(fmt, "... {} more chars", thousands(more))
+        }
+        Value::Bytes(b) => {
+            write_bytes(fmt, b)?;
+            let more = len.saturating_sub(b.len() as u64);
+            write!(fmt, "... {} more bytes", thousands(more))
+        }
+        Value::List(items) | Value::Tuple(items) => {
+            write_container(fmt, value, len.saturating_sub(items.len() as u64))
+        }
+        Value::Set(set) | Value::FrozenSet(set) => {
+            write_container(fmt, value, len.saturating_sub(set.0.len() as u64))
+        }
+        Value::Dict(dict) => write_container(fmt, value, len.saturating_sub(dict.0.len() as u64)),
+        other => write!(fmt, "{}", other),
+    }
+}
+
+fn global_name(global: &Global) -> &'static str {
+    match global {
+        Global::Set => "set",
+        Global::Frozenset => "frozenset",
+        Global::Bytearray => "bytearray",
+        Global::List => "list",
+        Global::Int => "int",
+        Global::Encode => "_codecs.encode",
+        Global::Complex => "complex",
+        Global::Other => "<global>",
+    }
+}
+
+// NaN and the infinities, which Python's `repr` gives as names, are written
+// as calls that read back.
+fn write_float(fmt: &mut fmt::Formatter, f: f64) -> fmt::Result {
+    match float_repr(f).as_str() {
+        "nan" => fmt.write_str("float('nan')"),
+        "inf" => fmt.write_str("float('inf')"),
+        "-inf" => fmt.write_str("-float('inf')"),
+        repr => fmt.write_str(repr),
+    }
+}
+
+// Python writes complex numbers like `(1.5-2j)`, or `2j` without a real
+// part, and leaves out the `.0` of whole parts.  Parts that are NaN or
+// infinite are written as a call that reads back.
+fn write_complex(fmt: &mut fmt::Formatter, re: f64, im: f64) -> fmt::Result {
+    if !re.is_finite() || !im.is_finite() {
+        fmt.write_str("complex(")?;
+        write_float(fmt, re)?;
+        fmt.write_str(", ")?;
+        write_float(fmt, im)?;
+        return fmt.write_str(")");
+    }
+    let part = |f: f64| {
+        let repr = float_repr(f);
+        match repr.strip_suffix(".0") {
+            Some(whole) => whole.to_string(),
+            None => repr,
+        }
+    };
+    if re == 0.0 && re.is_sign_positive() {
+        return write!(fmt, "{}j", part(im));
+    }
+    let sign = if im.is_sign_negative() { "" } else { "+" };
+    write!(fmt, "({}{}{}j)", part(re), sign, part(im))
+}
+
+// Python's `repr` of a float: the shortest form that reads back the same,
+// with an exponent outside of 1e-4..1e16.
+pub(crate) fn float_repr(f: f64) -> String {
+    if f.is_nan() {
+        return "nan".into();
+    }
+    if f.is_infinite() {
+        return if f > 0.0 { "inf" } else { "-inf" }.into();
+    }
+    let abs = f.abs();
+    if abs != 0.0 && !(1e-4..1e16).contains(&abs) {
+        let repr = format!("{:e}", f);
+        let (mantissa, exp) = repr.split_once('e').unwrap_or((&repr, "0"));
+        let (sign, digits) = match exp.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("+", exp),
+        };
+        return format!("{}e{}{:0>2}", mantissa, sign, digits);
+    }
+    let repr = f.to_string();
+    if repr.contains('.') {
+        repr
+    } else {
+        format!("{}.0", repr)
+    }
+}
+
+// Python quotes with single quotes, unless only double quotes avoid
+// escaping.
+fn quote_for(has_single: bool, has_double: bool) -> char {
+    if has_single && !has_double {
+        '"'
+    } else {
+        '\''
+    }
+}
+
+fn write_str(fmt: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    let quote = quote_for(s.contains('\''), s.contains('"'));
+    write!(fmt, "{}", quote)?;
+    for ch in s.chars() {
+        match ch {
+            '\\' => fmt.write_str("\\\\")?,
+            '\n' => fmt.write_str("\\n")?,
+            '\r' => fmt.write_str("\\r")?,
+            '\t' => fmt.write_str("\\t")?,
+            ch if ch == quote => write!(fmt, "\\{}", ch)?,
+            ch if !printable(ch) => match ch as u32 {
+                code @ 0..=0xff => write!(fmt, "\\x{:02x}", code)?,
+                code @ 0x100..=0xffff => write!(fmt, "\\u{:04x}", code)?,
+                code => write!(fmt, "\\U{:08x}", code)?,
+            },
+            ch => write!(fmt, "{}", ch)?,
+        }
+    }
+    write!(fmt, "{}", quote)
+}
+
+// Whether Python's `repr` shows the character as is: not a control or
+// format character, a private-use one, or a separator other than the space.
+// Unassigned code points, which Python escapes too, are shown as is.
+fn printable(ch: char) -> bool {
+    !matches!(ch as u32,
+        0x00..=0x1f | 0x7f..=0xa0 | 0xad | 0x600..=0x605 | 0x61c | 0x6dd | 0x70f
+        | 0x180e | 0x1680 | 0x2000..=0x200f | 0x2028..=0x202f | 0x205f..=0x206f
+        | 0x3000 | 0xe000..=0xf8ff | 0xfeff | 0xfff9..=0xfffb | 0xe0001
+        | 0xe0020..=0xe007f | 0xf0000..)
 }
 
-impl std::cmp::Eq for Value {}
+fn write_bytes(fmt: &mut fmt::Formatter, b: &[u8]) -> fmt::Result {
+    let quote = quote_for(b.contains(&b'\''), b.contains(&b'"')) as u8;
+    write!(fmt, "b{}", quote as char)?;
+    for &byte in b {
+        match byte {
+            b'\\' => fmt.write_str("\\\\")?,
+            b'\n' => fmt.write_str("\\n")?,
+            b'\r' => fmt.write_str("\\r")?,
+            b'\t' => fmt.write_str("\\t")?,
+            byte if byte == quote => write!(fmt, "\\{}", byte as char)?,
+            0x20..=0x7e => write!(fmt, "{}", byte as char)?,
+            byte => write!(fmt, "\\x{:02x}", byte)?,
+        }
+    }
+    write!(fmt, "{}", quote as char)
+}
 
-impl From<i128> for Value {
-    fn from(i: i128) -> Self {
-        Value::Int(BigInt::from(i))
+// Format a count with thousands separators, e.g. `9,980`.
+fn thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(ch);
     }
+    out
 }
@@ -0,0 +1,299 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::vec;
+
+use crate::path::{Path, PathElem};
+use crate::{HashMapWrapper, HashSetWrapper, Value};
+
+/// A read-only pass over a value and everything in it.  Each callback gets
+/// the path to the value: the items of lists, tuples, sets and frozensets
+/// are reached by their positions, and those of dicts by their keys.  The
+/// id of a `BinPersId` has the path of the `BinPersId`.
+pub trait Visit<'a> {
+    /// Called on each value before its items.  Returns whether to visit
+    /// the items.
+    fn visit_value(&mut self, _value: &Value<'a>, _path: &Path<'a>) -> bool {
+        true
+    }
+
+    /// Called on each container after its items.
+    fn leave_value(&mut self, _value: &Value<'a>, _path: &Path<'a>) {}
+
+    /// Called on each dict key, with the path of the dict, before the
+    /// value at the key.
+    fn visit_key(&mut self, _key: &Value<'a>, _path: &Path<'a>) {}
+}
+
+/// A pass that changes values in place, like `Visit`.  Dict keys and the
+/// members of sets can't change without rehashing, so the keys are only
+/// shown to `visit_key`, and sets are not entered; use `Fold` for those.
+pub trait VisitMut<'a> {
+    /// Called on each value before its items, which it may change or
+    /// replace.  Returns whether to visit the items of what it leaves.
+    fn visit_value_mut(&mut self, _value: &mut Value<'a>, _path: &Path<'a>) -> bool {
+        true
+    }
+
+    /// Called on each dict key, with the path of the dict, before the
+    /// value at the key.
+    fn visit_key(&mut self, _key: &Value<'a>, _path: &Path<'a>) {}
+}
+
+/// A pass that rebuilds a value bottom-up, with paths as for `Visit`.
+/// Dict keys and set members that become equal are merged as Python does.
+pub trait Fold<'a> {
+    /// Transforms a value whose items are already folded.
+    fn fold_value(&mut self, value: Value<'a>, _path: &Path<'a>) -> Value<'a> {
+        value
+    }
+
+    /// Transforms a dict key, with the path of the dict, before the value
+    /// at the key is folded.  The value keeps the path of the old key.
+    fn fold_key(&mut self, key: Value<'a>, _path: &Path<'a>) -> Value<'a> {
+        key
+    }
+}
+
+// The walks below keep their own stacks rather than recursing, so that
+// values nested as deeply as the unpickler builds them can't overflow the
+// call stack.  Each step truncates the path to the length it had at the
+// container, then adds the step to the item, if any.
+
+enum Step<'v, 'a> {
+    Enter(&'v Value<'a>),
+    Leave(&'v Value<'a>),
+    Key(&'v Value<'a>),
+}
+
+/// Walks `value` depth-first, calling `visitor` on everything in it.
+pub fn visit<'a, V: Visit<'a> + ?Sized>(value: &Value<'a>, visitor: &mut V) {
+    let mut path = Path::new();
+    let mut stack = vec![(0, None, Step::Enter(value))];
+    while let Some((depth, elem, step)) = stack.pop() {
+        path.0.truncate(depth);
+        path.0.extend(elem);
+        let value = match step {
+            Step::Key(key) => {
+                visitor.visit_key(key, &path);
+                continue;
+            }
+            Step::Leave(value) => {
+                visitor.leave_value(value, &path);
+                continue;
+            }
+            Step::Enter(value) => value,
+        };
+        if !visitor.visit_value(value, &path) {
+            continue;
+        }
+        let depth = path.len();
+        let start = stack.len();
+        match value {
+            Value::List(items) | Value::Tuple(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    stack.push((depth, Some(PathElem::Index(i)), Step::Enter(item)));
+                }
+            }
+            Value::Set(set) | Value::FrozenSet(set) => {
+                for (i, item) in set.0.iter().enumerate() {
+                    stack.push((depth, Some(PathElem::Index(i)), Step::Enter(item)));
+                }
+            }
+            Value::Dict(dict) => {
+                for (key, item) in &dict.0 {
+                    stack.push((depth, None, Step::Key(key)));
+                    stack.push((depth, Some(PathElem::Key(key.clone())), Step::Enter(item)));
+                }
+            }
+            Value::BinPersId(id) => stack.push((depth, None, Step::Enter(id))),
+            _ => continue,
+        }
+        // The items were pushed in order, so they are popped in reverse.
+        stack[start..].reverse();
+        stack.insert(start, (depth, None, Step::Leave(value)));
+    }
+}
+
+/// Walks `value` depth-first, letting `visitor` change everything in it.
+pub fn visit_mut<'a, V: VisitMut<'a> + ?Sized>(value: &mut Value<'a>, visitor: &mut V) {
+    let mut path = Path::new();
+    let mut stack = vec![(0, None, Ok(value))];
+    while let Some((depth, elem, step)) = stack.pop() {
+        path.0.truncate(depth);
+        path.0.extend(elem);
+        let value = match step {
+            Ok(value) => value,
+            Err(key) => {
+                visitor.visit_key(key, &path);
+                continue;
+            }
+        };
+        if !visitor.visit_value_mut(value, &path) {
+            continue;
+        }
+        let depth = path.len();
+        let start = stack.len();
+        match value {
+            Value::List(items) | Value::Tuple(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    stack.push((depth, Some(PathElem::Index(i)), Ok(item)));
+                }
+            }
+            Value::Dict(dict) => {
+                for (key, item) in dict.0.iter_mut() {
+                    stack.push((depth, None, Err(key)));
+                    stack.push((depth, Some(PathElem::Key(key.clone())), Ok(item)));
+                }
+            }
+            Value::BinPersId(id) => stack.push((depth, None, Ok(id))),
+            _ => continue,
+        }
+        stack[start..].reverse();
+    }
+}
+
+// A container being folded: the items still to fold, and those done.
+enum Frame<'a> {
+    Seq {
+        kind: fn(Vec<Value<'a>>) -> Value<'a>,
+        items: vec::IntoIter<Value<'a>>,
+        done: Vec<Value<'a>>,
+    },
+    Dict {
+        items: indexmap::map::IntoIter<Value<'a>, Value<'a>>,
+        key: Option<Value<'a>>,
+        done: HashMapWrapper<Value<'a>, Value<'a>>,
+    },
+    PersId {
+        id: Option<Value<'a>>,
+        done: Option<Value<'a>>,
+    },
+}
+
+impl<'a> Frame<'a> {
+    // Starts on a container, or gives back a value that has no items.
+    fn open(value: Value<'a>) -> Result<Self, Value<'a>> {
+        let seq = |kind, items: Vec<Value<'a>>| Frame::Seq {
+            kind,
+            done: Vec::with_capacity(items.len()),
+            items: items.into_iter(),
+        };
+        Ok(match value {
+            Value::List(items) => seq(Value::List, items),
+            Value::Tuple(items) => seq(Value::Tuple, items),
+            Value::Set(set) => seq(set_of, set.0.into_iter().collect()),
+            Value::FrozenSet(set) => seq(frozenset_of, set.0.into_iter().collect()),
+            Value::Dict(dict) => Frame::Dict {
+                items: dict.0.into_iter(),
+                key: None,
+                done: HashMapWrapper::new(),
+            },
+            Value::BinPersId(id) => Frame::PersId {
+                id: Some(*id),
+                done: None,
+            },
+            leaf => return Err(leaf),
+        })
+    }
+
+    // The next item to fold, with the step to it.
+    fn next<F: Fold<'a> + ?Sized>(
+        &mut self,
+        folder: &mut F,
+        path: &Path<'a>,
+    ) -> Option<(Option<PathElem<'a>>, Value<'a>)> {
+        match self {
+            Frame::Seq { items, done, .. } => {
+                let item = items.next()?;
+                Some((Some(PathElem::Index(done.len())), item))
+            }
+            Frame::Dict { items, key, .. } => {
+                let (old_key, item) = items.next()?;
+                *key = Some(folder.fold_key(old_key.clone(), path));
+                Some((Some(PathElem::Key(old_key)), item))
+            }
+            Frame::PersId { id, .. } => Some((None, id.take()?)),
+        }
+    }
+
+    fn put(&mut self, value: Value<'a>) {
+        match self {
+            Frame::Seq { done, .. } => done.push(value),
+            Frame::Dict { key, done, .. } => {
+                if let Some(key) = key.take() {
+                    done.insert(key, value);
+                }
+            }
+            Frame::PersId { done, .. } => *done = Some(value),
+        }
+    }
+
+    fn close(self) -> Value<'a> {
+        match self {
+            Frame::Seq { kind, done, .. } => kind(done),
+            Frame::Dict { done, .. } => Value::Dict(done),
+            Frame::PersId { done, .. } => Value::BinPersId(Box::new(done.unwrap_or(Value::None))),
+        }
+    }
+}
+
+fn set_of(items: Vec<Value>) -> Value {
+    Value::Set(items.into_iter().collect::<HashSetWrapper<_>>())
+}
+
+fn frozenset_of(items: Vec<Value>) -> Value {
+    Value::FrozenSet(items.into_iter().collect::<HashSetWrapper<_>>())
+}
+
+/// Rebuilds `value` bottom-up, passing everything in it through `folder`.
+pub fn fold<'a, F: Fold<'a> + ?Sized>(value: Value<'a>, folder: &mut F) -> Value<'a> {
+    let mut path = Path::new();
+    // The open containers, with the lengths of their paths.
+    let mut stack: Vec<(usize, Frame<'a>)> = Vec::new();
+    let mut next = value;
+    loop {
+        let mut folded = match Frame::open(next) {
+            Ok(frame) => {
+                stack.push((path.len(), frame));
+                None
+            }
+            Err(leaf) => Some(folder.fold_value(leaf, &path)),
+        };
+        // Hand folded values up until a container has an item left.
+        loop {
+            let Some((depth, frame)) = stack.last_mut() else {
+                return folded.unwrap_or(Value::None);
+            };
+            path.0.truncate(*depth);
+            if let Some(value) = folded.take() {
+                frame.put(value);
+            }
+            if let Some((elem, item)) = frame.next(folder, &path) {
+                path.0.extend(elem);
+                next = item;
+                break;
+            }
+            if let Some((_, frame)) = stack.pop() {
+                folded = Some(folder.fold_value(frame.close(), &path));
+            }
+        }
+    }
+}
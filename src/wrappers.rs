@@ -19,29 +19,63 @@
 // under the License.
 
 use std::cmp::{Eq, PartialEq};
-use std::hash::Hash;
-use std::{collections::HashMap, collections::HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use indexmap::{Equivalent, IndexMap, IndexSet};
 
 use crate::Value;
 
-#[derive(Clone, Debug, PartialEq)]
+/// A float that can be hashed.  It compares as in Python: `0.0` equals
+/// `-0.0`, and a NaN equals nothing, not even itself, so that distinct NaN
+/// keys stay distinct in a dict.
+#[derive(Clone, Debug)]
 pub struct F64Wrapper(pub f64);
 
+impl PartialEq for F64Wrapper {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
 impl std::cmp::Eq for F64Wrapper {}
 
 impl std::hash::Hash for F64Wrapper {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        let bits = self.0.to_bits();
+        let bits = match self.0 {
+            f if f.is_nan() => f64::NAN.to_bits(),
+            0.0 => 0, // and -0.0
+            f => f.to_bits(),
+        };
         bits.hash(state);
     }
 }
 
+/// A value looked up in a dict or set by Python's equality, `Value::py_eq`,
+/// rather than by `==`: `PyKey(&Value::Bool(true))` finds the key `1`.
+#[derive(Debug)]
+pub struct PyKey<'v, 'a>(pub &'v Value<'a>);
+
+impl<'v, 'a> Hash for PyKey<'v, 'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<'v, 'a, 'b> Equivalent<Value<'b>> for PyKey<'v, 'a> {
+    fn equivalent(&self, key: &Value<'b>) -> bool {
+        self.0.py_eq(key)
+    }
+}
+
+/// A dict, which keeps its keys in insertion order like Python's.  Equality
+/// and hashing don't depend on the order.
 #[derive(Clone, Debug)]
-pub struct HashMapWrapper<K: Eq + Hash, V: Eq + Hash>(pub HashMap<K, V>);
+pub struct HashMapWrapper<K: Eq + Hash, V: Eq + Hash>(pub IndexMap<K, V>);
 
 impl<K: Eq + Hash, V: Eq + Hash> HashMapWrapper<K, V> {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self(IndexMap::new())
     }
 }
 
@@ -67,52 +101,128 @@ where
     K: std::hash::Hash,
 {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        for (k, v) in self.0.iter() {
-            k.hash(state);
-            v.hash(state);
+        // Insertion order differs between equal maps, so the entry hashes
+        // have to be combined in an order-independent way.
+        let combined = self.0.iter().map(hash_one).fold(0u64, u64::wrapping_add);
+        self.0.len().hash(state);
+        combined.hash(state);
+    }
+}
+
+impl<'a> HashMapWrapper<Value<'a>, Value<'a>> {
+    /// Sets `key` to `value` as Python's `d[key] = value` does: if the dict
+    /// holds a key equal to `key` by `Value::py_eq`, such as `1` for
+    /// `True`, its value is replaced and returned, and the key and its
+    /// place are kept.
+    pub fn insert(&mut self, key: Value<'a>, value: Value<'a>) -> Option<Value<'a>> {
+        match self.0.get_index_of(&PyKey(&key)) {
+            Some(index) => Some(std::mem::replace(&mut self.0[index], value)),
+            None => self.0.insert(key, value),
+        }
+    }
+
+    /// Returns the value of the key equal to `key` by `Value::py_eq`.
+    pub fn get(&self, key: &Value) -> Option<&Value<'a>> {
+        self.0.get(&PyKey(key))
+    }
+}
+
+impl<'a> FromIterator<(Value<'a>, Value<'a>)> for HashMapWrapper<Value<'a>, Value<'a>> {
+    fn from_iter<I: IntoIterator<Item = (Value<'a>, Value<'a>)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<'a> Extend<(Value<'a>, Value<'a>)> for HashMapWrapper<Value<'a>, Value<'a>> {
+    fn extend<I: IntoIterator<Item = (Value<'a>, Value<'a>)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
         }
     }
 }
 
-impl From<Vec<(Value, Value)>> for HashMapWrapper<Value, Value> {
-    fn from(hm: Vec<(Value, Value)>) -> Self {
-        Self(hm.into_iter().collect())
+impl<'a> From<Vec<(Value<'a>, Value<'a>)>> for HashMapWrapper<Value<'a>, Value<'a>> {
+    fn from(hm: Vec<(Value<'a>, Value<'a>)>) -> Self {
+        hm.into_iter().collect()
     }
 }
 
+/// A set, which keeps its items in insertion order, so that it is shown and
+/// pickled the same way each time.  Equality and hashing don't depend on the
+/// order.
 #[derive(Clone, Debug)]
-pub struct HashSetWrapper<T: Eq + Hash>(pub HashSet<T>);
+pub struct HashSetWrapper<T: Eq + Hash>(pub IndexSet<T>);
 
-impl HashSetWrapper<Value> {
+impl<'a> HashSetWrapper<Value<'a>> {
     pub fn new() -> Self {
-        Self(HashSet::new())
+        Self(IndexSet::new())
     }
 }
 
-impl Default for HashSetWrapper<Value> {
+impl<'a> Default for HashSetWrapper<Value<'a>> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl std::cmp::PartialEq for HashSetWrapper<Value> {
+impl<'a> std::cmp::PartialEq for HashSetWrapper<Value<'a>> {
     fn eq(&self, other: &Self) -> bool {
         self.0.len() == other.0.len() && self.0.iter().all(|v| other.0.get(v) == Some(v))
     }
 }
 
-impl std::cmp::Eq for HashSetWrapper<Value> {}
+impl<'a> std::cmp::Eq for HashSetWrapper<Value<'a>> {}
 
-impl std::hash::Hash for HashSetWrapper<Value> {
+impl<'a> std::hash::Hash for HashSetWrapper<Value<'a>> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        for v in self.0.iter() {
-            v.hash(state);
+        let combined = self.0.iter().map(hash_one).fold(0u64, u64::wrapping_add);
+        self.0.len().hash(state);
+        combined.hash(state);
+    }
+}
+
+impl<'a> HashSetWrapper<Value<'a>> {
+    /// Adds `item` as Python's `s.add(item)` does: nothing is added if the
+    /// set holds a member equal to `item` by `Value::py_eq`.  Returns
+    /// whether `item` was added.
+    pub fn insert(&mut self, item: Value<'a>) -> bool {
+        !self.contains(&item) && self.0.insert(item)
+    }
+
+    /// Whether the set holds a member equal to `item` by `Value::py_eq`.
+    pub fn contains(&self, item: &Value) -> bool {
+        self.0.contains(&PyKey(item))
+    }
+}
+
+impl<'a> FromIterator<Value<'a>> for HashSetWrapper<Value<'a>> {
+    fn from_iter<I: IntoIterator<Item = Value<'a>>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<'a> Extend<Value<'a>> for HashSetWrapper<Value<'a>> {
+    fn extend<I: IntoIterator<Item = Value<'a>>>(&mut self, iter: I) {
+        for item in iter {
+            self.insert(item);
         }
     }
 }
 
-impl From<Vec<Value>> for HashSetWrapper<Value> {
-    fn from(hm: Vec<Value>) -> Self {
-        Self(hm.into_iter().collect())
+impl<'a> From<Vec<Value<'a>>> for HashSetWrapper<Value<'a>> {
+    fn from(hm: Vec<Value<'a>>) -> Self {
+        hm.into_iter().collect()
     }
 }
+
+// Hash a single item with a fixed hasher, so the result can be combined
+// with the hashes of other items.
+fn hash_one<T: Hash>(item: T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    item.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,102 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use pickle_rs::{assemble, Error, ErrorCode, Unpickler, UnpicklerOptions};
+
+#[test]
+fn reassembles_disassembly() {
+    for name in [
+        "tests_py2_proto2",
+        "tests_py3_proto1",
+        "tests_py3_proto3",
+        "tests_py3_proto5",
+        "test_recursive_proto4",
+    ] {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        let listing = std::fs::read_to_string(format!("tests/data/{}.dis", name)).unwrap();
+        assert_eq!(assemble(&listing).unwrap(), data, "{}", name);
+    }
+
+    // Text arguments are not necessarily written the same way, e.g. `F1`
+    // and `F1.0`, but they decode to the same values.
+    for name in ["tests_py2_proto0", "tests_py3_proto0"] {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        let listing = std::fs::read_to_string(format!("tests/data/{}.dis", name)).unwrap();
+        let assembled = assemble(&listing).unwrap();
+        let decode = |data: &[u8]| {
+            let options = UnpicklerOptions::new().decode_strings(false);
+            Unpickler::value_from_slice(data, options)
+                .unwrap()
+                .into_owned()
+        };
+        assert_eq!(decode(&assembled), decode(&data), "{}", name);
+    }
+}
+
+#[test]
+fn computes_lengths_and_frames() {
+    let listing = "
+        # {'a': [1, b'xyz']}
+        PROTO      4
+        FRAME
+        EMPTY_DICT
+        MEMOIZE    (as 0)
+        SHORT_BINUNICODE 'a'
+        MARK
+            BININT1    1
+            SHORT_BINBYTES b'x\\x79z'
+            LIST       (MARK at 8)
+        SETITEM
+        STOP
+    ";
+    let data = assemble(listing).unwrap();
+    assert_eq!(
+        data,
+        b"\x80\x04\x95\x10\x00\x00\x00\x00\x00\x00\x00}\x94\x8c\x01a(K\x01C\x03xyzls."
+    );
+    let value = Unpickler::value_from_slice(&data, UnpicklerOptions::new()).unwrap();
+    assert_eq!(value.to_string(), "{'a': [1, b'xyz']}");
+}
+
+#[test]
+fn checks_protocol() {
+    let err = assemble("PROTO 2\nEMPTY_SET\nSTOP").unwrap_err();
+    match err {
+        Error::Syntax(ErrorCode::InvalidValue(msg)) => {
+            assert_eq!(
+                msg,
+                "line 2: EMPTY_SET needs protocol 4, but the pickle is protocol 2"
+            )
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+    assert!(assemble("NEWTRUE\nSTOP").is_err());
+    assert!(assemble("BININT1 256").is_err());
+    assert!(assemble(
+        "PROTO 4\nSHORT_BINUNICODE '{}'"
+            .replace("{}", &"x".repeat(256))
+            .as_str()
+    )
+    .is_err());
+    assert_eq!(
+        assemble("PROTO 2\nNEWTRUE\nSTOP").unwrap(),
+        b"\x80\x02\x88."
+    );
+}
@@ -0,0 +1,114 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::{BTreeMap, HashSet};
+
+use num_bigint::BigInt;
+use pickle_rs::{
+    literal_eval, py, DictBuilder, FrozenSetBuilder, ListBuilder, SetBuilder, TupleBuilder, Value,
+};
+
+fn literal(source: &str) -> Value<'static> {
+    literal_eval(source).unwrap()
+}
+
+#[test]
+fn builds_with_python_syntax() {
+    let x = 7;
+    let name = String::from("adam");
+    let value = py!({
+        "a": [1, 2.5, None],
+        b"k": (True, frozenset({3})),
+        "neg": -x,
+        name.as_str(): x + 1,
+        "t": (x,),
+        "u": (x),
+        "e": ((), [], {}, set(), frozenset()),
+        "s": {1, 2, 1 + 1},
+        (1, "k"): { 1: { "deep": [[]] } },
+    });
+    assert_eq!(
+        value,
+        literal(
+            "{'a': [1, 2.5, None], b'k': (True, frozenset({3})), 'neg': -7, 'adam': 8, \
+             't': (7,), 'u': 7, 'e': ((), [], {}, set(), frozenset()), 's': {1, 2}, \
+             (1, 'k'): {1: {'deep': [[]]}}}"
+        )
+    );
+    assert_eq!(py!([1, 2,]), literal("[1, 2]"));
+    assert_eq!(py!({"a": 1,}), literal("{'a': 1}"));
+    assert_eq!(py!(Some("x")), literal("'x'"));
+    assert_eq!(py!(None::<i64>), Value::None);
+}
+
+#[test]
+fn builds_long_literal_lists() {
+    let value = py!([
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47,
+        48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70,
+        71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93,
+        94, 95, 96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111, 112,
+        113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127, 128, 129, 130,
+        131, 132, 133, 134, 135, 136, 137, 138, 139, 140, 141, 142, 143, 144, 145, 146, 147, 148,
+        149, 150, 151, 152, 153, 154, 155, 156, 157, 158, 159, 160, 161, 162, 163, 164, 165, 166,
+        167, 168, 169, 170, 171, 172, 173, 174, 175, 176, 177, 178, 179, 180, 181, 182, 183, 184,
+        185, 186, 187, 188, 189, 190, 191, 192, 193, 194, 195, 196, 197, 198, 199, 200, 201, 202,
+        203, 204, 205, 206, 207, 208, 209, 210, 211, 212, 213, 214, 215, 216, 217, 218, 219, 220,
+        221, 222, 223, 224, 225, 226, 227, 228, 229, 230, 231, 232, 233, 234, 235, 236, 237, 238,
+        239, 240, 241, 242, 243, 244, 245, 246, 247, 248, 249, 250, 251, 252, 253, 254, 255, 256,
+        257, 258, 259, 260, 261, 262, 263, 264, 265, 266, 267, 268, 269, 270, 271, 272, 273, 274,
+        275, 276, 277, 278, 279, 280, 281, 282, 283, 284, 285, 286, 287, 288, 289, 290, 291, 292,
+        293, 294, 295, 296, 297, 298, 299, -300, 2.5, "s", b"b",
+    ]);
+    let mut expected: Vec<Value> = (0..300).map(Value::from).collect();
+    expected.extend([py!(-300), py!(2.5), py!("s"), py!(b"b")]);
+    assert_eq!(value, Value::List(expected));
+    assert_eq!(py!([1, -2,]), literal("[1, -2]"));
+}
+
+#[test]
+fn builds_with_builders() {
+    let value = DictBuilder::new()
+        .item("list", ListBuilder::new().item(1).items(["a", "b"]))
+        .item("tuple", TupleBuilder::new().item(b"x").item(None::<bool>))
+        .item("set", SetBuilder::new().items([1.0, 2.0]).item(1))
+        .item("frozen", FrozenSetBuilder::new())
+        .items([("list", 0)])
+        .build();
+    assert_eq!(
+        value,
+        literal("{'list': 0, 'tuple': (b'x', None), 'set': {1.0, 2.0}, 'frozen': frozenset()}")
+    );
+}
+
+#[test]
+fn converts_rust_values() {
+    assert_eq!(Value::from(u64::MAX), Value::Int(BigInt::from(u64::MAX)));
+    assert_eq!(Value::from(-5i128), Value::I64(-5));
+    assert_eq!(Value::from(BigInt::from(5)), Value::I64(5));
+    assert_eq!(Value::from(1.5f32), literal("1.5"));
+    assert_eq!(Value::from(vec![1u8, 2]), literal("[1, 2]"));
+    assert_eq!(Value::from(&b"ab"[..]), literal("b'ab'"));
+    assert_eq!(Value::from((1, "a", (true,))), literal("(1, 'a', (True,))"));
+    assert_eq!(Value::from(HashSet::from([3])), literal("{3}"));
+    let map = BTreeMap::from([("b", vec![Some(1), None]), ("a", vec![])]);
+    assert_eq!(Value::from(map), literal("{'a': [], 'b': [1, None]}"));
+}
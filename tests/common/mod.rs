@@ -0,0 +1,63 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+// Helpers shared by the integration tests, each of which uses only some.
+#![allow(dead_code)]
+
+use pickle_rs::{Error, ErrorCode, Result, Unpickler, UnpicklerOptions, Value};
+
+pub fn decode(data: &[u8]) -> Value<'static> {
+    Unpickler::value_from_slice(data, UnpicklerOptions::new())
+        .unwrap()
+        .into_owned()
+}
+
+// The message of an `InvalidValue` syntax error.
+pub fn invalid<T: std::fmt::Debug>(result: Result<T>) -> String {
+    match result {
+        Err(Error::Syntax(ErrorCode::InvalidValue(msg))) => msg,
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+// The message and position of an `InvalidValue` evaluation error.
+pub fn invalid_at<T: std::fmt::Debug>(result: Result<T>) -> (String, usize) {
+    match result {
+        Err(Error::Eval {
+            code: ErrorCode::InvalidValue(msg),
+            pos,
+            ..
+        }) => (msg, pos),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+// The names of the pickles of the test classes that Python 2 and 3 wrote,
+// in each protocol they support.
+pub fn python_fixtures() -> Vec<String> {
+    let mut names = Vec::new();
+    for proto in 0..3 {
+        names.push(format!("tests_py2_proto{}", proto));
+    }
+    for proto in 0..6 {
+        names.push(format!("tests_py3_proto{}", proto));
+    }
+    names
+}
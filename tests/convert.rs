@@ -0,0 +1,128 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::collections::HashMap;
+
+use num_bigint::BigInt;
+use pickle_rs::{py, Error, ErrorCode, Path, PathElem, Unpickler, UnpicklerOptions, Value};
+
+// pickle.dumps({'name': 'adam', 'lr': 0.5, 'steps': [1, 2, 3],
+//               'betas': (0.9, 0.99), 'eps': None, 'flag': True}, 2)
+const CONFIG: &[u8] = b"\x80\x02}q\x00(X\x04\x00\x00\x00nameq\x01X\x04\x00\x00\x00adamq\x02X\x02\x00\x00\x00lrq\x03G?\xe0\x00\x00\x00\x00\x00\x00X\x05\x00\x00\x00stepsq\x04]q\x05(K\x01K\x02K\x03eX\x05\x00\x00\x00betasq\x06G?\xec\xcc\xcc\xcc\xcc\xcc\xcdG?\xef\xae\x14z\xe1G\xae\x86q\x07X\x03\x00\x00\x00epsq\x08NX\x04\x00\x00\x00flagq\t\x88u.";
+
+fn config() -> Value<'static> {
+    Unpickler::value_from_slice(CONFIG, UnpicklerOptions::new())
+        .unwrap()
+        .into_owned()
+}
+
+#[test]
+fn accessors() {
+    let config = config();
+    assert_eq!(config["name"].as_str(), Some("adam"));
+    assert_eq!(config["lr"].as_f64(), Some(0.5));
+    assert_eq!(config["steps"][2].as_i64(), Some(3));
+    assert_eq!(config["steps"][2].as_f64(), Some(3.0));
+    assert_eq!(config["flag"].as_i64(), Some(1));
+    assert_eq!(
+        config
+            .get("betas")
+            .and_then(|b| b.as_list())
+            .map(<[_]>::len),
+        Some(2)
+    );
+    assert_eq!(config.as_dict().map(|d| d.0.len()), Some(6));
+    assert!(config["eps"].is_none());
+
+    // Missing keys and items, and lookups on the wrong type, give None.
+    assert!(config["missing"]["deeper"][7].is_none());
+    assert!(config["steps"]["name"].is_none());
+    assert_eq!(config.get(0), None);
+    let by_int = py!({0: "a", 1: [5, 6]});
+    assert_eq!(by_int.get(0), Some(&py!("a")));
+    assert_eq!(by_int[1][1].as_i64(), Some(6));
+    assert_eq!(config["name"].as_i64(), None);
+    assert_eq!(Value::Bytes(b"ab"[..].into()).as_bytes(), Some(&b"ab"[..]));
+
+    let big = Value::Int(BigInt::from(1) << 70);
+    assert_eq!(big.as_i64(), None);
+    assert_eq!(big.as_f64(), Some(2f64.powi(70)));
+}
+
+#[test]
+fn converts_to_std_types() {
+    let config = config();
+    let name = String::try_from(config["name"].clone()).unwrap();
+    assert_eq!(name, "adam");
+    let steps = Vec::<i64>::try_from(config["steps"].clone()).unwrap();
+    assert_eq!(steps, vec![1, 2, 3]);
+    let betas = <(f64, f64)>::try_from(config["betas"].clone()).unwrap();
+    assert_eq!(betas, (0.9, 0.99));
+    let eps = Option::<f64>::try_from(config["eps"].clone()).unwrap();
+    assert_eq!(eps, None);
+    let lr = Option::<f64>::try_from(config["lr"].clone()).unwrap();
+    assert_eq!(lr, Some(0.5));
+    assert!(bool::try_from(config["flag"].clone()).unwrap());
+
+    let Value::Dict(mut dict) = config else {
+        unreachable!()
+    };
+    dict.0.retain(|key, _| key.as_str() == Some("steps"));
+    let steps = HashMap::<String, Vec<i64>>::try_from(Value::Dict(dict)).unwrap();
+    assert_eq!(steps["steps"], vec![1, 2, 3]);
+}
+
+#[test]
+fn conversion_errors_have_paths() {
+    // Dict keys are converted in order, and "name" comes first.
+    let err = HashMap::<String, Vec<i64>>::try_from(config()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid type at [\"name\"], expected list, got str"
+    );
+
+    let value = Value::List(vec![Value::Tuple(vec![Value::I64(1), Value::None])]);
+    let err = Vec::<(i64, String)>::try_from(value).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid type at [0][1], expected str, got NoneType"
+    );
+    let Error::Syntax(ErrorCode::InvalidType(path, _, _)) = err else {
+        unreachable!()
+    };
+    assert_eq!(path, Path(vec![PathElem::Index(0), PathElem::Index(1)]));
+
+    let path = Path(vec![
+        PathElem::Key(Value::Tuple(vec![Value::I64(1), Value::I64(2)])),
+        PathElem::Key(Value::from(0.5)),
+    ]);
+    assert_eq!(path.to_string(), "[(1, 2)][0.5]");
+
+    let err = i64::try_from(Value::Int(BigInt::from(1) << 70)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid type, expected int in the range of i64, got int"
+    );
+    let err = <(i64, i64)>::try_from(Value::Tuple(vec![Value::I64(1)])).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid type, expected tuple of 2 items, got tuple"
+    );
+}
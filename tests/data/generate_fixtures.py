@@ -0,0 +1,124 @@
+#!/usr/bin/env python3
+#
+# Regenerates the fixtures of the selection, preview, disassembler and
+# optimizer tests.  The tests_py* and test_recursive* pickles are older and
+# are not written here, but their .dis and .opt listings are.
+#
+# Run from this directory with Python 3.11: later versions may frame or
+# memoize differently.
+#
+#   python3 generate_fixtures.py [--check]
+#
+# With --check, nothing is written and the files that would change are
+# listed instead.
+
+import io
+import pickle
+import pickletools
+import sys
+
+
+def select_object():
+    shared = [1, 2, 3]
+    return {
+        "optimizer": {"lr": 0.1, "betas": (0.9, 0.99)},
+        "model": {"w": b"x" * 300, "b": [1.0, 1.0, 1.0], "t": (1, "a", [2])},
+        "epoch": 5,
+        "s": shared,
+        "t": shared,
+    }
+
+
+def select_nested_object():
+    return [
+        {1, 2},
+        {3, 4},
+        frozenset({5, "a"}),
+        frozenset({6}),
+        b"ab",
+        b"cd",
+        [(1, 2), (3, (4, 5))],
+        {"k": {8, 9}, "n": [frozenset({b"x"}), (b"y", {10}, ((11, {12}),))]},
+    ]
+
+
+def preview_object():
+    return {
+        "xs": list(range(1000)),
+        "s": "é" * 300 + "x" * 50,
+        "b": bytes(5000),
+        "d": {i: i for i in range(60)},
+        "t": tuple(range(30)),
+        "fs": frozenset(range(30)),
+        "set": set(range(30)),
+    }
+
+
+# The pickles written here, by name and object.
+PICKLES = [
+    ("test_select", select_object),
+    ("test_select_nested", select_nested_object),
+    ("test_preview", preview_object),
+]
+
+# The pickles whose optimized forms the optimizer tests compare with.
+OPTIMIZED = (
+    [f"tests_py2_proto{p}" for p in range(3)]
+    + [f"tests_py3_proto{p}" for p in range(6)]
+    + [f"test_recursive_proto{p}" for p in range(6)]
+    + ["test_preview_proto5"]
+)
+
+# The pickles whose listings the disassembler tests compare with.
+LISTED = [
+    "tests_py2_proto0",
+    "tests_py2_proto2",
+    "tests_py3_proto0",
+    "tests_py3_proto1",
+    "tests_py3_proto3",
+    "tests_py3_proto5",
+    "test_recursive_proto4",
+]
+
+
+def main():
+    check = "--check" in sys.argv[1:]
+    files = {}
+    for name, make in PICKLES:
+        for proto in range(6):
+            files[f"{name}_proto{proto}.pickle"] = pickle.dumps(make(), proto)
+
+    def read(name):
+        path = f"{name}.pickle"
+        if path in files:
+            return files[path]
+        with open(path, "rb") as f:
+            return f.read()
+
+    for name in OPTIMIZED:
+        files[f"{name}.opt"] = pickletools.optimize(read(name))
+    for name in LISTED:
+        out = io.StringIO()
+        pickletools.dis(read(name), out)
+        files[f"{name}.dis"] = out.getvalue().encode()
+
+    changed = []
+    for path, data in files.items():
+        try:
+            with open(path, "rb") as f:
+                same = f.read() == data
+        except FileNotFoundError:
+            same = False
+        if same:
+            continue
+        changed.append(path)
+        if not check:
+            with open(path, "wb") as f:
+                f.write(data)
+    for path in changed:
+        print(("differs: " if check else "wrote: ") + path)
+    return 1 if check and changed else 0
+
+
+if __name__ == "__main__":
+    sys.exit(main())
@@ -0,0 +1,164 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use pickle_rs::{assemble, decompile, Error, ErrorCode};
+
+#[test]
+fn decompiles_reduce() {
+    // pickle.dumps(OrderedDict(a=[1, 2]), 2)
+    let data = b"\x80\x02ccollections\nOrderedDict\nq\x00)Rq\x01X\x01\x00\x00\x00aq\x02]q\x03(K\x01K\x02es.";
+    assert_eq!(
+        decompile(&data[..]).unwrap(),
+        "from collections import OrderedDict
+_0 = OrderedDict()
+_0['a'] = [1, 2]
+result = _0
+"
+    );
+}
+
+#[test]
+fn decompiles_fixtures() {
+    let data = std::fs::read("tests/data/tests_py3_proto0.pickle").unwrap();
+    assert_eq!(
+        decompile(&data[..]).unwrap(),
+        r"from _codecs import encode
+from copyreg import _reconstructor
+from __main__ import Class
+_0 = encode('bytes', 'latin1')
+_1 = _reconstructor(Class, object, None)
+_1.__dict__.update({'attr': 5})
+result = {None: None, False: (False, True), 10: 100000, 100000000000000000000: 100000000000000000000, 1.0: 1.0, _0: _0, 'string': 'string', (1, 2): (1, 2, 3), frozenset([0, 42]): frozenset([0, 42]), (): [[1, 2, 3], set([0, 42]), {}, bytearray(encode('\x00Uªÿ', 'latin1'))], 7: _1}
+"
+    );
+
+    let data = std::fs::read("tests/data/test_recursive_proto4.pickle").unwrap();
+    assert_eq!(
+        decompile(&data[..]).unwrap(),
+        "_0 = []\n_0.append(([_0],))\nresult = _0\n"
+    );
+}
+
+#[test]
+fn decompiles_objects() {
+    let data = assemble(
+        "PROTO 4
+        MARK
+            SHORT_BINUNICODE 'app.models'
+            MEMOIZE
+            SHORT_BINUNICODE 'Point'
+            STACK_GLOBAL
+            EMPTY_TUPLE
+            NEWOBJ
+            NONE
+            EMPTY_DICT
+            BINUNICODE 'x'
+            BININT1    1
+            SETITEM
+            TUPLE2
+            BUILD
+            BINGET     0
+            SHORT_BINUNICODE 'Frame'
+            STACK_GLOBAL
+            MARK
+                FLOAT      inf
+            TUPLE
+            EMPTY_DICT
+            NEWOBJ_EX
+            BINUNICODE 'state'
+            BUILD
+            GLOBAL     'other.models Point'
+            EMPTY_TUPLE
+            REDUCE
+            POP
+            GLOBAL     'app.models Point'
+        LIST
+        STOP",
+    )
+    .unwrap();
+    assert_eq!(
+        decompile(&data[..]).unwrap(),
+        "from app.models import Point
+from app.models import Frame
+from other.models import Point as Point_1
+_0 = Point.__new__(Point)
+setattr(_0, 'x', 1)
+_1 = Frame.__new__(Frame, float('inf'), **{})
+_1.__setstate__('state')
+Point_1()
+result = [_0, _1, Point]
+"
+    );
+}
+
+#[test]
+fn keeps_builtins_apart() {
+    let data = assemble(
+        "PROTO 2
+        MARK
+            GLOBAL     'foo eval'
+            BINUNICODE 'x'
+            TUPLE1
+            REDUCE
+            GLOBAL     'builtins eval'
+            BINUNICODE 'y'
+            TUPLE1
+            REDUCE
+            GLOBAL     '__builtin__ len'
+        LIST
+        STOP",
+    )
+    .unwrap();
+    assert_eq!(
+        decompile(&data[..]).unwrap(),
+        "from foo import eval
+from builtins import eval as eval_1
+result = [eval('x'), eval_1('y'), len]
+"
+    );
+
+    let data = assemble(
+        "PROTO 2
+        GLOBAL     'builtins eval'
+        GLOBAL     'foo eval'
+        TUPLE2
+        STOP",
+    )
+    .unwrap();
+    assert_eq!(
+        decompile(&data[..]).unwrap(),
+        "from foo import eval as eval_1
+result = (eval, eval_1)
+"
+    );
+}
+
+#[test]
+fn reports_missing_memo() {
+    let data = assemble("EMPTY_LIST\nBINGET 3\nAPPEND\nSTOP").unwrap();
+    match decompile(&data[..]) {
+        Err(Error::Eval {
+            code: ErrorCode::MissingMemo(3),
+            pos: 1,
+            ..
+        }) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
@@ -0,0 +1,90 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use pickle_rs::{dis, DisOptions, Error, ErrorCode, Unpickler, UnpicklerOptions};
+
+fn disassemble(data: &[u8], options: DisOptions) -> (String, pickle_rs::Result<()>) {
+    let mut out = Vec::new();
+    let result = dis(data, &mut out, options);
+    (String::from_utf8(out).unwrap(), result)
+}
+
+#[test]
+fn matches_pickletools() {
+    for name in [
+        "tests_py2_proto0",
+        "tests_py2_proto2",
+        "tests_py3_proto0",
+        "tests_py3_proto1",
+        "tests_py3_proto3",
+        "tests_py3_proto5",
+        "test_recursive_proto4",
+    ] {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        let expected = std::fs::read_to_string(format!("tests/data/{}.dis", name)).unwrap();
+        let (listing, result) = disassemble(&data, DisOptions::new());
+        result.unwrap();
+        assert_eq!(listing, expected, "{}", name);
+    }
+}
+
+#[test]
+fn stack_depth_and_error_offset() {
+    // [1, 2], then an APPEND onto an int, which leaves the list behind
+    let data = b"\x80\x02]q\x00(K\x01K\x02eK\x03K\x04a.";
+    let offset = match Unpickler::value_from_slice(data, UnpicklerOptions::new()) {
+        Err(Error::Eval {
+            code: ErrorCode::InvalidStackTop(..),
+            pos,
+            ..
+        }) => pos,
+        other => panic!("expected invalid stack top, got {:?}", other),
+    };
+    let options = DisOptions::new().stack_depth(true).error_offset(offset);
+    let (listing, result) = disassemble(data, options);
+    result.unwrap_err();
+    let expected = "    0: \\x80 PROTO      2  [depth 0]
+    2: ]    EMPTY_LIST  [depth 1]
+    3: q    BINPUT     0  [depth 1]
+    5: (    MARK  [depth 2]
+    6: K        BININT1    1  [depth 3]
+    8: K        BININT1    2  [depth 4]
+   10: e        APPENDS    (MARK at 5)  [depth 1]
+   11: K    BININT1    3  [depth 2]
+   13: K    BININT1    4  [depth 3]
+   15: a    APPEND  [depth 2]  <== error
+   16: .    STOP  [depth 1]
+";
+    assert_eq!(listing, expected);
+}
+
+#[test]
+fn reports_missing_mark() {
+    let (listing, result) = disassemble(b"K\x01e.", DisOptions::new());
+    assert!(listing.ends_with("    2: e    APPENDS    no MARK exists on stack\n"));
+    assert!(matches!(
+        result,
+        Err(Error::Eval {
+            code: ErrorCode::StackUnderflow,
+            pos: 2,
+            ..
+        })
+    ));
+}
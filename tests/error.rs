@@ -0,0 +1,150 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::io::{self, Read};
+
+use pickle_rs::{Error, ErrorCode, EvalContext, Unpickler, UnpicklerOptions, Value};
+
+fn load(data: &[u8]) -> pickle_rs::Result<Value<'_>> {
+    Unpickler::value_from_slice(data, UnpicklerOptions::new())
+}
+
+#[test]
+fn displays_errors() {
+    let err = load(b"\x80\x02]q\x00(K\x01h\x05e.").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "missing memo with id 5 at offset 10 in BINGET"
+    );
+    let err = Error::Syntax(ErrorCode::Recursive);
+    assert_eq!(err.to_string(), "recursive structure found");
+
+    // The errors work as `std::error::Error`s.
+    fn boxed() -> Result<Value<'static>, Box<dyn std::error::Error>> {
+        Ok(load(b"K\x01").map(Value::into_owned)?)
+    }
+    let err = boxed().unwrap_err();
+    assert_eq!(err.to_string(), "EOF while parsing at offset 2");
+    assert!(err.source().is_none());
+}
+
+#[test]
+fn keeps_io_errors_as_source() {
+    struct Failing;
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk on fire"))
+        }
+    }
+    let err = Unpickler::value_from_reader(Failing, UnpicklerOptions::new()).unwrap_err();
+    assert_eq!(err.to_string(), "I/O error: disk on fire");
+    let source = std::error::Error::source(&err).unwrap();
+    assert_eq!(source.to_string(), "disk on fire");
+}
+
+#[test]
+fn records_the_state_of_the_unpickler() {
+    let err = load(b"\x80\x02]q\x00(K\x01h\x05e.").unwrap_err();
+    let expected = EvalContext {
+        start: 8,
+        code: Some(b'h'),
+        name: Some("BINGET"),
+        mark_depth: 1,
+        stack_len: 2,
+        stack: vec!["1".into(), "<memo 5>".into()],
+        memo_id: Some(5),
+        global: None,
+    };
+    assert_eq!(err.context(), Some(&expected));
+
+    let err = load(b"\x80\x02cos\nsystem\nK\x01R.").unwrap_err();
+    let context = err.context().unwrap();
+    assert_eq!(context.name, Some("REDUCE"));
+    assert_eq!(context.global, Some(("os".into(), "system".into())));
+
+    // The memo value stands in for a reference, and long reprs are cut.
+    let err = load(b"\x80\x02X\x64\x00\x00\x00aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaq\x00e.").unwrap_err();
+    let context = err.context().unwrap();
+    assert_eq!(context.stack, [format!("'{}...", "a".repeat(59))]);
+
+    // An unknown opcode has a byte but no name, and the end of the input
+    // has neither.
+    let err = load(b"K\x01\xff.").unwrap_err();
+    let context = err.context().unwrap();
+    assert_eq!(
+        (context.start, context.code, context.name),
+        (2, Some(0xff), None)
+    );
+    let err = load(b"(K\x01").unwrap_err();
+    let context = err.context().unwrap();
+    assert_eq!(
+        (context.start, context.code, context.mark_depth),
+        (3, None, 1)
+    );
+
+    // Errors of other tools have no context.
+    let err = pickle_rs::optimize(b"h\x01.").unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Eval {
+            code: ErrorCode::MissingMemo(1),
+            context: None,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn renders_reports() {
+    let data = b"\x80\x02]q\x00(K\x01h\x05e.";
+    let report = load(data).unwrap_err().report(data);
+    let expected = "\
+error: missing memo with id 5 at offset 10 in BINGET
+  opcode: BINGET (0x68) at offset 8
+  mark depth: 1
+  stack: 2 entries above the mark, topmost last: 1, <memo 5>
+  memo id: 5
+
+  00000000  80 02 5d 71 00 28 4b 01  68 05 65 2e              |..]q.(K.h.e.|
+                                     ^^ ^^
+";
+    assert_eq!(report, expected);
+
+    let mut data = b"\x80\x02]q\x00(".to_vec();
+    for i in 0..20 {
+        data.extend([b'K', i]);
+    }
+    data.extend(b"e0e.");
+    let report = load(&data).unwrap_err().report(&data);
+    let expected = "\
+error: pickle stack underflow at offset 49 in APPENDS
+  opcode: APPENDS (0x65) at offset 48
+  mark depth: 0
+  stack: 0 entries
+
+  00000020  4b 0d 4b 0e 4b 0f 4b 10  4b 11 4b 12 4b 13 65 30  |K.K.K.K.K.K.K.e0|
+  00000030  65 2e                                             |e.|
+            ^^
+";
+    assert_eq!(report, expected);
+
+    let report = Error::Syntax(ErrorCode::Recursive).report(&data);
+    assert_eq!(report, "error: recursive structure found\n");
+}
@@ -0,0 +1,191 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::fs::File;
+
+use pickle_rs::{
+    ContainerId, Error, ErrorCode, EventHandler, Result, Unpickler, UnpicklerOptions, Value,
+};
+
+#[derive(Default)]
+struct Recorder(Vec<String>);
+
+impl<'de> EventHandler<'de> for Recorder {
+    fn start_list(&mut self, id: ContainerId) -> Result<()> {
+        self.0.push(format!("start_list {}", id));
+        Ok(())
+    }
+
+    fn end_list(&mut self, id: ContainerId) -> Result<()> {
+        self.0.push(format!("end_list {}", id));
+        Ok(())
+    }
+
+    fn start_dict(&mut self, id: ContainerId) -> Result<()> {
+        self.0.push(format!("start_dict {}", id));
+        Ok(())
+    }
+
+    fn end_dict(&mut self, id: ContainerId) -> Result<()> {
+        self.0.push(format!("end_dict {}", id));
+        Ok(())
+    }
+
+    fn item(&mut self, id: ContainerId) -> Result<()> {
+        self.0.push(format!("item {}", id));
+        Ok(())
+    }
+
+    fn dict_key(&mut self, id: ContainerId) -> Result<()> {
+        self.0.push(format!("dict_key {}", id));
+        Ok(())
+    }
+
+    fn scalar(&mut self, value: Value<'de>) -> Result<()> {
+        self.0.push(format!("scalar {:?}", value));
+        Ok(())
+    }
+
+    fn reference(&mut self, id: ContainerId) -> Result<()> {
+        self.0.push(format!("reference {}", id));
+        Ok(())
+    }
+
+    fn result(&mut self) -> Result<()> {
+        self.0.push("result".into());
+        Ok(())
+    }
+}
+
+#[test]
+fn list_of_records() {
+    // [{'a': 1}, {'a': 2}, <first dict again>], with the first dict memoized
+    let data =
+        b"\x80\x02]q\x00(}q\x01X\x01\x00\x00\x00aK\x01s}q\x02X\x01\x00\x00\x00aK\x02sh\x01e.";
+    let mut recorder = Recorder::default();
+    Unpickler::from_slice(data, UnpicklerOptions::default())
+        .stream_events(&mut recorder)
+        .unwrap();
+    assert_eq!(
+        recorder.0,
+        vec![
+            "start_list 0",
+            "start_dict 1",
+            "dict_key 1",
+            "scalar String(\"a\")",
+            "scalar I64(1)",
+            "start_dict 2",
+            "dict_key 2",
+            "scalar String(\"a\")",
+            "scalar I64(2)",
+            "end_dict 1",
+            "item 0",
+            "reference 1",
+            "end_dict 2",
+            "item 0",
+            "reference 2",
+            "item 0",
+            "reference 1",
+            "end_list 0",
+            "result",
+            "reference 0",
+        ]
+    );
+}
+
+#[test]
+fn stream_all() {
+    for &(major, proto) in &[(2, 0), (2, 2), (3, 0), (3, 3), (3, 4), (3, 5)] {
+        let filename = format!("tests/data/tests_py{}_proto{}.pickle", major, proto);
+        let file = File::open(filename).unwrap();
+        let mut recorder = Recorder::default();
+        Unpickler::new(file, UnpicklerOptions::default())
+            .stream_events(&mut recorder)
+            .unwrap();
+        let results = recorder.0.iter().filter(|e| *e == "result").count();
+        assert_eq!(results, 1, "py {}, proto {}", major, proto);
+        assert_eq!(
+            recorder.0[0], "start_dict 0",
+            "py {}, proto {}",
+            major, proto
+        );
+    }
+}
+
+struct Failing;
+
+impl<'de> EventHandler<'de> for Failing {
+    fn scalar(&mut self, _value: Value<'de>) -> Result<()> {
+        Err(Error::Syntax(ErrorCode::Structure("no scalars".into())))
+    }
+}
+
+#[test]
+fn handler_errors_pass_through() {
+    // [1]
+    let data = b"\x80\x02]q\x00K\x01a.";
+    let err = Unpickler::from_slice(data, UnpicklerOptions::default())
+        .stream_events(&mut Failing)
+        .unwrap_err();
+    match err {
+        Error::Syntax(ErrorCode::Structure(msg)) => assert_eq!(msg, "no scalars"),
+        other => panic!("expected the handler's error, got {:?}", other),
+    }
+}
+
+#[test]
+fn adding_to_ended_container() {
+    // A list appended to another, then fetched from the memo and appended to.
+    let data = b"\x80\x02]q\x00]q\x01ah\x01K\x01a0.";
+    let mut recorder = Recorder::default();
+    let err = Unpickler::from_slice(data, UnpicklerOptions::default())
+        .stream_events(&mut recorder)
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::Eval {
+                code: ErrorCode::Recursive,
+                pos: 14,
+                ..
+            }
+        ),
+        "{:?}",
+        err
+    );
+
+    // A list appended to itself.
+    let data = b"\x80\x02]q\x00h\x00a.";
+    let mut recorder = Recorder::default();
+    let err = Unpickler::from_slice(data, UnpicklerOptions::default())
+        .stream_events(&mut recorder)
+        .unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::Eval {
+                code: ErrorCode::Recursive,
+                ..
+            }
+        ),
+        "{:?}",
+        err
+    );
+}
@@ -0,0 +1,200 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+mod common;
+
+use common::python_fixtures;
+use pickle_rs::{assemble, lint, ErrorCode, Finding, Lint};
+
+#[test]
+fn accepts_python_pickles() {
+    let mut names = python_fixtures();
+    for proto in 0..6 {
+        names.push(format!("test_recursive_proto{}", proto));
+        names.push(format!("test_select_proto{}", proto));
+        names.push(format!("test_preview_proto{}", proto));
+    }
+    for name in names {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        assert_eq!(lint(&data), vec![], "{}", name);
+        let optimized = std::fs::read(format!("tests/data/{}.opt", name));
+        if let Ok(optimized) = optimized {
+            assert_eq!(lint(&optimized), vec![], "{}.opt", name);
+        }
+    }
+}
+
+#[test]
+fn reports_all_findings() {
+    let mut data = assemble(
+        "PROTO 4
+        FRAME 5
+        EMPTY_LIST
+        BINPUT 2
+        MARK
+        BININT 5
+        LONG1 70000
+        BININT2 0
+        APPENDS
+        MEMOIZE
+        MARK
+        BINGET 7
+        EMPTY_DICT
+        STOP",
+    )
+    .unwrap();
+    data[1] = 2;
+    let findings = lint(&data)
+        .into_iter()
+        .map(|Finding { pos, lint }| (pos, lint))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        findings,
+        vec![
+            (
+                2,
+                Lint::NewerOpcode {
+                    name: "FRAME",
+                    proto: 4,
+                    declared: 2
+                }
+            ),
+            (
+                2,
+                Lint::FrameLength {
+                    declared: 5,
+                    actual: 9
+                }
+            ),
+            (
+                15,
+                Lint::NonCanonicalInt {
+                    name: "BININT",
+                    canonical: "BININT1"
+                }
+            ),
+            (
+                20,
+                Lint::NonCanonicalInt {
+                    name: "LONG1",
+                    canonical: "BININT"
+                }
+            ),
+            (
+                25,
+                Lint::NonCanonicalInt {
+                    name: "BININT2",
+                    canonical: "BININT1"
+                }
+            ),
+            (
+                29,
+                Lint::NewerOpcode {
+                    name: "MEMOIZE",
+                    proto: 4,
+                    declared: 2
+                }
+            ),
+            (29, Lint::MemoOutOfOrder { id: 1, expected: 3 }),
+            (31, Lint::MissingMemo(7)),
+            (34, Lint::UnclosedMarks(1)),
+            (34, Lint::StackNotEmpty(2)),
+        ]
+    );
+    assert_eq!(
+        Finding {
+            pos: 20,
+            lint: findings[3].1.clone()
+        }
+        .to_string(),
+        "20: integer written with LONG1, expected BININT"
+    );
+}
+
+#[test]
+fn reports_unreadable_pickles() {
+    let data = assemble("PROTO 3\nNONE\nSTOP\nNONE").unwrap();
+    assert_eq!(
+        lint(&data),
+        vec![Finding {
+            pos: 4,
+            lint: Lint::TrailingBytes
+        }]
+    );
+    let findings = lint(b"\x80\x03K\x01\xff.");
+    assert_eq!(
+        findings,
+        vec![Finding {
+            pos: 4,
+            lint: Lint::Invalid(ErrorCode::Unsupported('\u{ff}'))
+        }]
+    );
+    let findings = lint(b"\x80\x04\x95\xff\xff\xff\xff\xff\xff\xff\xffN.");
+    assert_eq!(
+        findings,
+        vec![Finding {
+            pos: 2,
+            lint: Lint::FrameLength {
+                declared: u64::MAX,
+                actual: 2
+            }
+        }]
+    );
+    let findings = lint(b"(I01\nI007\nL1L\nl\x80\x02.");
+    assert_eq!(
+        findings
+            .iter()
+            .map(|finding| finding.to_string())
+            .collect::<Vec<_>>(),
+        [
+            "5: INT argument not written in the shortest form",
+            "10: integer written with LONG, expected INT",
+            "15: PROTO not at the start of the pickle",
+        ]
+    );
+}
+
+#[test]
+fn reports_small_longs() {
+    // Below protocol 2, `pickle` writes LONG only for what doesn't fit in
+    // 32 bits.
+    let findings = lint(b"(L5L\nL4294967296L\nl.");
+    assert_eq!(
+        findings,
+        vec![Finding {
+            pos: 1,
+            lint: Lint::NonCanonicalInt {
+                name: "LONG",
+                canonical: "INT"
+            }
+        }]
+    );
+    let data = assemble("PROTO 2\nLONG 300\nSTOP").unwrap();
+    assert_eq!(
+        lint(&data),
+        vec![Finding {
+            pos: 2,
+            lint: Lint::NonCanonicalInt {
+                name: "LONG",
+                canonical: "BININT2"
+            }
+        }]
+    );
+}
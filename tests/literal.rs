@@ -0,0 +1,242 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use num_bigint::BigInt;
+use pickle_rs::{
+    literal_eval, Error, ErrorCode, F64Wrapper, Pickler, PicklerOptions, Unpickler,
+    UnpicklerOptions, Value,
+};
+
+fn load(data: &[u8]) -> Value<'static> {
+    Unpickler::value_from_slice(data, UnpicklerOptions::new())
+        .unwrap()
+        .into_owned()
+}
+
+fn error(source: &str) -> String {
+    match literal_eval(source) {
+        Err(Error::Syntax(ErrorCode::InvalidValue(msg))) => msg,
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+fn complex(re: f64, im: f64) -> Value<'static> {
+    Value::Complex(F64Wrapper(re), F64Wrapper(im))
+}
+
+#[test]
+fn parses_what_python_pickles() {
+    let source =
+        "{'a': [1, 2.5, None], b'k': (True, frozenset({3})), 'big': -1180591620717411303424, \
+                  'c': (1-2j), 's': {1, 'x'}, 't': ((), (1,), [])}";
+    let value = literal_eval(source).unwrap();
+    // pickle.dumps of the same value with protocols 0, 2 and 4.
+    let pickles: [&[u8]; 3] = [
+        b"(dp0\nVa\np1\n(lp2\nI1\naF2.5\naNasc_codecs\nencode\np3\n(Vk\np4\nVlatin1\np5\ntp6\nRp7\n(I01\nc__builtin__\nfrozenset\np8\n((lp9\nI3\natp10\nRp11\ntp12\nsVbig\np13\nL-1180591620717411303424L\nsVc\np14\nc__builtin__\ncomplex\np15\n(F1.0\nF-2.0\ntp16\nRp17\nsVs\np18\nc__builtin__\nset\np19\n((lp20\nI1\naVx\np21\natp22\nRp23\nsVt\np24\n((t(I1\ntp25\n(lp26\ntp27\ns.",
+        b"\x80\x02}q\x00(X\x01\x00\x00\x00aq\x01]q\x02(K\x01G@\x04\x00\x00\x00\x00\x00\x00Nec_codecs\nencode\nq\x03X\x01\x00\x00\x00kq\x04X\x06\x00\x00\x00latin1q\x05\x86q\x06Rq\x07\x88c__builtin__\nfrozenset\nq\x08]q\tK\x03a\x85q\nRq\x0b\x86q\x0cX\x03\x00\x00\x00bigq\r\x8a\t\x00\x00\x00\x00\x00\x00\x00\x00\xc0X\x01\x00\x00\x00cq\x0ec__builtin__\ncomplex\nq\x0fG?\xf0\x00\x00\x00\x00\x00\x00G\xc0\x00\x00\x00\x00\x00\x00\x00\x86q\x10Rq\x11X\x01\x00\x00\x00sq\x12c__builtin__\nset\nq\x13]q\x14(K\x01X\x01\x00\x00\x00xq\x15e\x85q\x16Rq\x17X\x01\x00\x00\x00tq\x18)K\x01\x85q\x19]q\x1a\x87q\x1bu.",
+        b"\x80\x04\x95\x82\x00\x00\x00\x00\x00\x00\x00}\x94(\x8c\x01a\x94]\x94(K\x01G@\x04\x00\x00\x00\x00\x00\x00NeC\x01k\x94\x88(K\x03\x91\x94\x86\x94\x8c\x03big\x94\x8a\t\x00\x00\x00\x00\x00\x00\x00\x00\xc0\x8c\x01c\x94\x8c\x08builtins\x94\x8c\x07complex\x94\x93\x94G?\xf0\x00\x00\x00\x00\x00\x00G\xc0\x00\x00\x00\x00\x00\x00\x00\x86\x94R\x94\x8c\x01s\x94\x8f\x94(K\x01\x8c\x01x\x94\x90\x8c\x01t\x94)K\x01\x85\x94]\x94\x87\x94u.",
+    ];
+    for data in pickles {
+        assert_eq!(load(data), value);
+    }
+    assert_eq!(value.to_string(), source);
+    for proto in 0..6 {
+        let data = Pickler::value_to_vec(&value, PicklerOptions::new().proto(proto)).unwrap();
+        assert_eq!(load(&data), value);
+    }
+}
+
+#[test]
+fn parses_numbers() {
+    let cases = [
+        ("0", Value::I64(0)),
+        ("-00", Value::I64(0)),
+        ("1_000", Value::I64(1000)),
+        ("0x_ff", Value::I64(255)),
+        ("0o17", Value::I64(15)),
+        ("-0B101", Value::I64(-5)),
+        ("9223372036854775807", Value::I64(i64::MAX)),
+        ("-9223372036854775808", Value::I64(i64::MIN)),
+        ("9223372036854775808", Value::Int(BigInt::from(1u64 << 63))),
+        (
+            "0xffffffffffffffffff",
+            Value::Int(BigInt::from(u128::MAX >> 56)),
+        ),
+        ("1.5", Value::F64(F64Wrapper(1.5))),
+        ("-.5e-3", Value::F64(F64Wrapper(-0.0005))),
+        ("1.", Value::F64(F64Wrapper(1.0))),
+        ("1E+2_0", Value::F64(F64Wrapper(1e20))),
+        ("1e400", Value::F64(F64Wrapper(f64::INFINITY))),
+        ("float('-inf')", Value::F64(F64Wrapper(f64::NEG_INFINITY))),
+        ("float(' 1_0.5 ')", Value::F64(F64Wrapper(10.5))),
+        ("float(2)", Value::F64(F64Wrapper(2.0))),
+        ("2j", complex(0.0, 2.0)),
+        ("1_0.5J", complex(0.0, 10.5)),
+        ("-1 + 2j", complex(-1.0, 2.0)),
+        ("-(5)", Value::I64(-5)),
+        ("+ (2.5)", Value::F64(F64Wrapper(2.5))),
+        ("(1.5-2e3j)", complex(1.5, -2000.0)),
+        ("complex(2)", complex(2.0, 0.0)),
+    ];
+    for (source, value) in cases {
+        assert_eq!(literal_eval(source).unwrap(), value, "{}", source);
+    }
+    // NaN is not equal to itself.
+    let nan = literal_eval("float('nan')").unwrap();
+    assert!(nan.as_f64().unwrap().is_nan());
+    match literal_eval("complex(1, float('nan'))").unwrap() {
+        Value::Complex(re, im) => assert!(re.0 == 1.0 && im.0.is_nan()),
+        other => panic!("expected complex, got {:?}", other),
+    }
+}
+
+#[test]
+fn parses_strings() {
+    let cases = [
+        (r#"'it\'s' "q""#, "it'sq"),
+        (r"'\x41\101é\U0001F600\n\t\q'", "AAé😀\n\t\\q"),
+        (r"r'\n\'' R'\\'", "\\n\\'\\\\"),
+        ("\"\"\"a\n'b'\"\"\"", "a\n'b'"),
+        ("u'é' 'x'", "éx"),
+        ("'a\\\nb'", "ab"),
+        ("('first '\n 'second')", "first second"),
+    ];
+    for (source, s) in cases {
+        assert_eq!(
+            literal_eval(source).unwrap(),
+            Value::String(s.into()),
+            "{}",
+            source
+        );
+    }
+    let cases: [(&str, &[u8]); 4] = [
+        (r"b'\x00\xff\377\u00e9'", b"\x00\xff\xff\\u00e9"),
+        (r"rb'\x' BR'\''", b"\\x\\'"),
+        ("Br'a' b\"b\"", b"ab"),
+        (r#"b"""\"""""#, b"\""),
+    ];
+    for (source, b) in cases {
+        let value = literal_eval(source).unwrap();
+        assert_eq!(value, Value::Bytes(b.into()), "{}", source);
+    }
+}
+
+#[test]
+fn parses_containers() {
+    let value = literal_eval(
+        "
+        # A comment.
+        {
+            'list': [1, 2,],  # A trailing comma.
+            'tuple': (1,),
+            'set': set(),
+            'frozen': frozenset([1, 1, 2]),
+            'empty': ({}, [], ()),
+            'same': {1: 'a', 1.0: 'b', True: 'c'},
+        }",
+    )
+    .unwrap();
+    assert_eq!(
+        value.to_string(),
+        "{'list': [1, 2], 'tuple': (1,), 'set': set(), 'frozen': frozenset({1, 2}), \
+         'empty': ({}, [], ()), 'same': {1: 'c'}}"
+    );
+    assert_eq!(literal_eval("1, 'a',").unwrap().to_string(), "(1, 'a')");
+    assert_eq!(
+        literal_eval("{1, True, 1.0, 2}").unwrap().to_string(),
+        "{1, 2}"
+    );
+    assert_eq!("(None,)".parse::<Value>().unwrap().to_string(), "(None,)");
+}
+
+#[test]
+fn reports_errors_with_positions() {
+    assert_eq!(error("[1, 2"), "line 1, column 6: expected ']'");
+    assert_eq!(
+        error("{'a': 1,\n 'b' 2}"),
+        "line 2, column 6: expected ':', got '2'"
+    );
+    assert_eq!(error("[1] 2"), "line 1, column 5: unexpected '2'");
+    assert_eq!(error(""), "line 1, column 1: unexpected end of input");
+    assert_eq!(error("[x]"), "line 1, column 2: unknown name x");
+    assert_eq!(
+        error("'é\nx'"),
+        "line 1, column 1: unterminated string literal"
+    );
+    assert_eq!(
+        error("b'é'"),
+        "line 1, column 3: bytes can only contain ASCII literal characters"
+    );
+    assert_eq!(
+        error("'a' b'b'"),
+        "line 1, column 5: cannot mix bytes and nonbytes literals"
+    );
+    assert_eq!(error(r"'\x4'"), r"line 1, column 2: truncated \xXX escape");
+    assert_eq!(
+        error(r"b'\777'"),
+        "line 1, column 3: octal escape out of range"
+    );
+    assert_eq!(
+        error(r"'\ud800'"),
+        "line 1, column 1: surrogates are not supported"
+    );
+    assert_eq!(
+        error("007"),
+        "line 1, column 1: leading zeros in decimal integer literals are not permitted: 007"
+    );
+    assert_eq!(
+        error("1__0"),
+        "line 1, column 1: invalid decimal literal 1__0"
+    );
+    assert_eq!(
+        error("0x"),
+        "line 1, column 1: invalid hexadecimal literal 0x"
+    );
+    assert_eq!(
+        error("1 + 2"),
+        "line 1, column 5: expected an imaginary number"
+    );
+    assert_eq!(error("-'a'"), "line 1, column 2: expected a number");
+    assert_eq!(error("--5"), "line 1, column 2: expected a number");
+    assert_eq!(error("-(1+2j)"), "line 1, column 4: expected ')', got '+'");
+    assert_eq!(error("-(-5)"), "line 1, column 3: expected a number");
+    assert_eq!(error("[...]"), "line 1, column 2: unexpected '.'");
+    assert_eq!(
+        error("'\\N{DASH}'"),
+        "line 1, column 2: \\N{...} escapes are not supported"
+    );
+    assert_eq!(
+        error("{[1]: 2}"),
+        "line 1, column 2: unhashable type: 'list'"
+    );
+    assert_eq!(
+        error("{(1, {2})}"),
+        "line 1, column 2: unhashable type: 'set'"
+    );
+    assert_eq!(
+        error("float('x')"),
+        "line 1, column 7: could not convert string to float: \"x\""
+    );
+    assert_eq!(error("set(1)"), "line 1, column 5: set() needs an iterable");
+    assert_eq!(
+        error(&"[".repeat(300)),
+        "line 1, column 201: too deeply nested"
+    );
+}
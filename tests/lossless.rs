@@ -0,0 +1,342 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+mod common;
+
+use common::{decode, invalid, python_fixtures};
+use pickle_rs::{assemble, Document, F64Wrapper, PathElem, SourceMap, Value};
+
+fn lookup<'v>(value: &'v Value<'static>, path: &[PathElem<'static>]) -> &'v Value<'static> {
+    match (value, path.first()) {
+        (value, None) => value,
+        (Value::List(items) | Value::Tuple(items), Some(PathElem::Index(i))) => {
+            lookup(&items[*i], &path[1..])
+        }
+        (Value::Dict(dict), Some(PathElem::Key(key))) => lookup(&dict.0[key], &path[1..]),
+        (value, Some(elem)) => panic!("no {} in {:?}", elem, value),
+    }
+}
+
+fn fixtures() -> Vec<String> {
+    let mut names = python_fixtures();
+    for proto in 0..6 {
+        names.push(format!("test_select_proto{}", proto));
+    }
+    names
+}
+
+// A different value of the same type, for the scalars.
+fn edit(value: &Value<'static>) -> Option<Value<'static>> {
+    let edited = match value {
+        Value::None => Value::I64(0),
+        Value::Bool(b) => Value::Bool(!b),
+        Value::I64(i) => Value::I64(i.wrapping_add(1)),
+        Value::Int(i) => Value::Int(i + 1),
+        Value::F64(f) => Value::F64(F64Wrapper(f.0 + 0.5)),
+        Value::String(s) => Value::String(format!("{}!", s).into()),
+        Value::Bytes(b) => Value::Bytes([b.as_ref(), b"!"].concat().into()),
+        _ => return None,
+    };
+    Some(edited)
+}
+
+fn lookup_mut<'v>(
+    value: &'v mut Value<'static>,
+    path: &[PathElem<'static>],
+) -> &'v mut Value<'static> {
+    match (value, path.first()) {
+        (value, None) => value,
+        (Value::List(items) | Value::Tuple(items), Some(PathElem::Index(i))) => {
+            lookup_mut(&mut items[*i], &path[1..])
+        }
+        (Value::Dict(dict), Some(PathElem::Key(key))) => {
+            lookup_mut(dict.0.get_mut(key).unwrap(), &path[1..])
+        }
+        (value, Some(elem)) => panic!("no {} in {:?}", elem, value),
+    }
+}
+
+// The bytes of a pickle, without the length of a frame that starts after
+// PROTO.
+fn unframed(data: &[u8]) -> Vec<u8> {
+    let mut data = data.to_vec();
+    if data.get(2) == Some(&0x95) {
+        data[3..11].fill(0);
+    }
+    data
+}
+
+#[test]
+fn edits_python_pickles_in_place() {
+    let (mut edits, mut shared) = (0, 0);
+    for name in fixtures() {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        let doc = Document::from_slice(&data).unwrap();
+        assert_eq!(doc.value(), &decode(&data), "{}", name);
+        for (path, annotation) in doc.sources().iter() {
+            let context = format!("{}{}", name, path);
+            let old = lookup(doc.value(), path);
+            let mut edited = Document::from_slice(&data).unwrap();
+            // Replacing a value by itself changes nothing.
+            edited.replace(path, old).unwrap();
+            assert_eq!(edited.as_bytes(), &data[..], "{}", context);
+
+            let new = match edit(old) {
+                Some(new) => new,
+                None => continue,
+            };
+            let mut expected = doc.value().clone();
+            *lookup_mut(&mut expected, path) = new.clone();
+            // Values shared through the memo are left alone.
+            if let Err(e) = edited.replace(path, &new) {
+                let msg = invalid::<()>(Err(e));
+                assert!(msg.ends_with("is shared through the memo"), "{}", context);
+                shared += 1;
+                continue;
+            }
+            edits += 1;
+            assert_eq!(edited.value(), &expected, "{}", context);
+            assert_eq!(decode(edited.as_bytes()), expected, "{}", context);
+            // Only the opcodes of the value change.
+            let (start, end) = (annotation.start, annotation.end);
+            let bytes = edited.as_bytes();
+            assert_eq!(
+                unframed(&bytes[..start]),
+                unframed(&data[..start]),
+                "{}",
+                context
+            );
+            assert_eq!(
+                bytes[bytes.len() - (data.len() - end)..],
+                data[end..],
+                "{}",
+                context
+            );
+        }
+    }
+    assert_eq!((edits, shared), (210, 36));
+}
+
+#[test]
+fn keeps_memo_ids_and_frames() {
+    let data = assemble(
+        "PROTO 4
+        FRAME
+        EMPTY_DICT
+        MEMOIZE
+        MARK
+        SHORT_BINUNICODE 'a'
+        MEMOIZE
+        BININT 1
+        SHORT_BINUNICODE 'b'
+        MEMOIZE
+        EMPTY_LIST
+        MEMOIZE
+        MARK
+        BINUNICODE 'x'
+        MEMOIZE
+        BININT2 2
+        APPENDS
+        SHORT_BINUNICODE 'c'
+        MEMOIZE
+        BINGET 1
+        SETITEMS
+        STOP",
+    )
+    .unwrap();
+    let mut doc = Document::from_slice(&data).unwrap();
+    let sources = doc.sources();
+    let annotation = sources.get(&["b".into()]).unwrap();
+    assert_eq!((annotation.start, annotation.end), (27, 41));
+    assert_eq!(annotation.memo, [3]);
+    let annotation = sources.get(&["c".into()]).unwrap();
+    assert_eq!((annotation.opcode, annotation.fetched), (b'h', Some(1)));
+
+    doc.replace(&["a".into()], &Value::I64(100000)).unwrap();
+    doc.replace(&["b".into(), 0.into()], &Value::String("yz".into()))
+        .unwrap();
+    doc.replace(&["c".into()], &Value::String("d".into()))
+        .unwrap();
+    let expected = assemble(
+        "PROTO 4
+        FRAME
+        EMPTY_DICT
+        MEMOIZE
+        MARK
+        SHORT_BINUNICODE 'a'
+        MEMOIZE
+        BININT 100000
+        SHORT_BINUNICODE 'b'
+        MEMOIZE
+        EMPTY_LIST
+        MEMOIZE
+        MARK
+        BINUNICODE 'yz'
+        MEMOIZE
+        BININT2 2
+        APPENDS
+        SHORT_BINUNICODE 'c'
+        MEMOIZE
+        SHORT_BINUNICODE 'd'
+        SETITEMS
+        STOP",
+    )
+    .unwrap();
+    assert_eq!(doc.as_bytes(), &expected[..]);
+}
+
+#[test]
+fn fails_on_shared_values() {
+    let data = assemble(
+        "PROTO 2
+        EMPTY_LIST
+        MARK
+        EMPTY_LIST
+        BINPUT 0
+        BININT1 1
+        APPEND
+        BINGET 0
+        APPENDS
+        STOP",
+    )
+    .unwrap();
+    let mut doc = Document::from_slice(&data).unwrap();
+    let msg = invalid(doc.replace(&[0.into()], &Value::None));
+    assert_eq!(msg, "the value at [0] is shared through the memo");
+    let msg = invalid(doc.replace(&[1.into(), 0.into()], &Value::None));
+    assert_eq!(msg, "the value at [1][0] is shared through the memo");
+    let msg = invalid(doc.replace(&[2.into()], &Value::None));
+    assert_eq!(msg, "no value at [2]");
+    // The place where the value is fetched can be replaced on its own.
+    doc.replace(&[1.into()], &Value::None).unwrap();
+    assert_eq!(
+        doc.value(),
+        &Value::List(vec![Value::List(vec![Value::I64(1)]), Value::None])
+    );
+
+    let mut doc = Document::from_slice(&data).unwrap();
+    let msg = invalid(doc.replace(&[], &Value::MemoRef(0)));
+    assert_eq!(msg, "a memo reference can't be pickled in protocol 2");
+}
+
+#[test]
+fn maps_offsets_to_paths() {
+    let data = assemble(
+        "PROTO 2
+        EMPTY_DICT
+        BINPUT 0
+        MARK
+        BINUNICODE 'a'
+        EMPTY_LIST
+        BINPUT 1
+        MARK
+        BININT1 1
+        BININT1 2
+        TUPLE2
+        NONE
+        APPENDS
+        BINUNICODE 'b'
+        BINGET 1
+        SETITEMS
+        STOP",
+    )
+    .unwrap();
+    let sources = SourceMap::from_slice(&data).unwrap();
+    assert_eq!(sources.span(&[]), Some(2..32));
+    assert_eq!(sources.span(&["a".into()]), Some(12..23));
+    assert_eq!(sources.span(&["a".into(), 0.into()]), Some(16..21));
+    assert_eq!(
+        sources.span(&["a".into(), 0.into(), 1.into()]),
+        Some(18..20)
+    );
+    assert_eq!(sources.span(&["b".into()]), Some(29..31));
+    // The items below a value fetched from the memo span where they were
+    // built.
+    assert_eq!(sources.span(&["b".into(), 1.into()]), Some(21..22));
+    assert_eq!(sources.span(&["c".into()]), None);
+
+    let path_at = |offset| sources.path_at(offset).map(|path| path.to_string());
+    let expected = [
+        (0, None),
+        (2, Some("")),
+        (6, Some("")),
+        (12, Some("[\"a\"]")),
+        (13, Some("[\"a\"]")),
+        (16, Some("[\"a\"][0][0]")),
+        (18, Some("[\"a\"][0][1]")),
+        (20, Some("[\"a\"][0]")),
+        (21, Some("[\"a\"][1]")),
+        (22, Some("[\"a\"]")),
+        (23, Some("")),
+        (29, Some("[\"b\"]")),
+        (31, Some("")),
+        (32, None),
+    ];
+    for (offset, path) in expected {
+        assert_eq!(path_at(offset).as_deref(), path, "{}", offset);
+    }
+}
+
+#[test]
+fn merges_keys_python_takes_as_equal() {
+    // {1: 'a', True: 'b'} is {1: 'b'}, with the value built at 13.
+    let data = b"\x80\x02}(K\x01X\x01\x00\x00\x00a\x88X\x01\x00\x00\x00bu.";
+    let one = PathElem::Key(Value::I64(1));
+    let sources = SourceMap::from_slice(data).unwrap();
+    assert_eq!(sources.span(std::slice::from_ref(&one)), Some(13..19));
+    assert_eq!(sources.span(&[PathElem::Key(Value::Bool(true))]), None);
+    assert_eq!(sources.iter().count(), 2);
+    assert_eq!(
+        sources.path_at(7).map(|path| path.to_string()).as_deref(),
+        Some("")
+    );
+
+    let mut doc = Document::from_slice(data).unwrap();
+    doc.replace(&[one], &Value::String("c".into())).unwrap();
+    assert_eq!(doc.value().to_string(), "{1: 'c'}");
+    assert_eq!(&doc.as_bytes()[..13], &data[..13]);
+}
+
+#[test]
+fn maps_python_pickles_both_ways() {
+    for name in fixtures() {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        let sources = SourceMap::from_slice(&data).unwrap();
+        for offset in 0..data.len() + 1 {
+            let path = match sources.path_at(offset) {
+                Some(path) => path,
+                None => continue,
+            };
+            // The value and the ones that hold it span the offset.
+            for len in 0..=path.len() {
+                let span = sources.span(&path[..len]).unwrap();
+                assert!(span.contains(&offset), "{} {} {}", name, offset, path);
+            }
+        }
+        // Everything but PROTO, FRAME and STOP belongs to the root.
+        let root = sources.span(&[]).unwrap();
+        let start = match (data[0], data[2]) {
+            (0x80, 0x95) => 11,
+            (0x80, _) => 2,
+            _ => 0,
+        };
+        assert_eq!(root, start..data.len() - 1, "{}", name);
+    }
+}
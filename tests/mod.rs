@@ -19,13 +19,16 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use indexmap::{IndexMap, IndexSet};
 use num_bigint::BigInt;
-use std::collections::{HashMap, HashSet};
-use std::{fs::File, io::BufReader};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
 
 use pickle_rs::{
-    Error, ErrorCode, F64Wrapper, HashMapWrapper, HashSetWrapper, Unpickler, UnpicklerOptions,
-    Value,
+    py, Error, ErrorCode, F64Wrapper, HashMapWrapper, HashSetWrapper, LazyBytes, Path, PathElem,
+    PreviewLimits, Truncated, Unpickler, UnpicklerOptions, Value,
 };
 
 macro_rules! pyobj {
@@ -35,14 +38,14 @@ macro_rules! pyobj {
     (i=$i:expr)  => { Value::I64($i) };
     (ii=$i:expr) => { Value::Int($i.clone()) };
     (f=$f:expr)  => { Value::F64(F64Wrapper($f)) };
-    (bb=$b:expr) => { Value::Bytes($b.to_vec()) };
+    (bb=$b:expr) => { Value::Bytes($b.to_vec().into()) };
     (s=$s:expr)  => { Value::String($s.into()) };
     (t=($($m:ident=$v:tt),*))  => { Value::Tuple(vec![$(pyobj!($m=$v)),*]) };
     (l=[$($m:ident=$v:tt),*])  => { Value::List(vec![$(pyobj!($m=$v)),*]) };
-    (ss=($($m:ident=$v:tt),*)) => { Value::Set(HashSetWrapper(HashSet::from_iter(vec![$(pyobj!($m=$v)),*]))) };
-    (fs=($($m:ident=$v:tt),*)) => { Value::FrozenSet(HashSetWrapper(HashSet::from_iter(vec![$(pyobj!($m=$v)),*]))) };
+    (ss=($($m:ident=$v:tt),*)) => { Value::Set(HashSetWrapper(IndexSet::from_iter(vec![$(pyobj!($m=$v)),*]))) };
+    (fs=($($m:ident=$v:tt),*)) => { Value::FrozenSet(HashSetWrapper(IndexSet::from_iter(vec![$(pyobj!($m=$v)),*]))) };
     (d={$($km:ident=$kv:tt => $vm:ident=$vv:tt),*}) => {
-        Value::Dict(HashMapWrapper(HashMap::from_iter(vec![$((pyobj!($km=$kv),
+        Value::Dict(HashMapWrapper(IndexMap::from_iter(vec![$((pyobj!($km=$kv),
                                                 pyobj!($vm=$vv))),*]))) };
 }
 
@@ -59,7 +62,7 @@ const TEST_CASES: &[(u32, u32)] = &[
     (3, 5),
 ];
 
-fn get_test_object(pyver: u32) -> Value {
+fn get_test_object(pyver: u32) -> Value<'static> {
     // Reproduces the test_object from test/data/generate.py.
     let longish = BigInt::from(10000000000u64) * BigInt::from(10000000000u64);
     let mut obj = pyobj!(d={
@@ -103,7 +106,9 @@ fn unpickle_all() {
         let file = File::open(filename).unwrap();
 
         let comparison = get_test_object(major);
-        let unpickled = Unpickler::value_from_reader(file, UnpicklerOptions::default()).unwrap();
+        // Python 2 strings in the test object are expected as bytes.
+        let options = UnpicklerOptions::new().decode_strings(false);
+        let unpickled = Unpickler::value_from_reader(file, options).unwrap();
 
         assert_eq!(unpickled, comparison, "py {}, proto {}", major, proto);
     }
@@ -122,3 +127,301 @@ fn recursive() {
         }
     }
 }
+
+#[test]
+fn unpickle_from_slice_borrows() {
+    for &(major, proto) in TEST_CASES {
+        let filename = format!("tests/data/tests_py{}_proto{}.pickle", major, proto);
+        let data = std::fs::read(filename).unwrap();
+
+        let options = UnpicklerOptions::new().decode_strings(false);
+        let unpickled = Unpickler::value_from_slice(&data, options).unwrap();
+        assert_eq!(
+            unpickled,
+            get_test_object(major),
+            "py {}, proto {}",
+            major,
+            proto
+        );
+        assert_eq!(unpickled.into_owned(), get_test_object(major));
+    }
+
+    // SHORT_BINUNICODE 'abc', STOP
+    let data = b"\x8c\x03abc.";
+    match Unpickler::value_from_slice(data, UnpicklerOptions::default()).unwrap() {
+        Value::String(Cow::Borrowed(s)) => assert_eq!(s, "abc"),
+        other => panic!("expected borrowed string, got {:?}", other),
+    }
+}
+
+#[test]
+fn lazy_bytes() {
+    // PROTO 4, EMPTY_LIST, MARK, BINBYTES8 <1000 bytes>, SHORT_BINBYTES 'abc', APPENDS, STOP
+    let payload: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+    let mut data = b"\x80\x04](\x8e".to_vec();
+    data.extend_from_slice(&1000u64.to_le_bytes());
+    data.extend_from_slice(&payload);
+    data.extend_from_slice(b"C\x03abce.");
+
+    let options = UnpicklerOptions::new().lazy_bytes(256);
+    let mut cursor = std::io::Cursor::new(&data);
+    let unpickled = Unpickler::value_from_seekable(&mut cursor, options).unwrap();
+    let lazy = LazyBytes {
+        offset: 13,
+        len: 1000,
+    };
+    assert_eq!(
+        unpickled,
+        Value::List(vec![Value::LazyBytes(lazy), pyobj!(bb = b"abc")])
+    );
+    assert_eq!(lazy.fetch(&mut cursor).unwrap(), payload);
+    assert_eq!(lazy.slice(&data), Some(&payload[..]));
+
+    let options = UnpicklerOptions::new().lazy_bytes(256);
+    let unpickled = Unpickler::value_from_reader(&data[..], options).unwrap();
+    assert_eq!(
+        unpickled,
+        Value::List(vec![Value::LazyBytes(lazy), py!(b"abc")])
+    );
+
+    // The same pickle after a header in the stream.
+    let mut stream = b"header".to_vec();
+    stream.extend_from_slice(&data);
+    let mut cursor = std::io::Cursor::new(&stream);
+    cursor.set_position(6);
+    let options = UnpicklerOptions::new().lazy_bytes(256);
+    let unpickled = Unpickler::value_from_seekable(&mut cursor, options).unwrap();
+    let lazy = LazyBytes {
+        offset: 19,
+        len: 1000,
+    };
+    assert_eq!(
+        unpickled,
+        Value::List(vec![Value::LazyBytes(lazy), py!(b"abc")])
+    );
+    assert_eq!(lazy.fetch(&mut cursor).unwrap(), payload);
+}
+
+#[test]
+fn select_path() {
+    for proto in &[0, 1, 2, 3, 4, 5] {
+        let filename = format!("tests/data/test_select_proto{}.pickle", proto);
+        let data = std::fs::read(filename).unwrap();
+
+        let path = Path(vec!["optimizer".into(), "lr".into()]);
+        let options = UnpicklerOptions::new().select_path(path);
+        let dict = match Unpickler::value_from_slice(&data, options).unwrap() {
+            Value::Dict(dict) => dict.0,
+            other => panic!("expected dict, got {:?}", other),
+        };
+        match &dict[&pyobj!(s = "optimizer")] {
+            Value::Dict(optimizer) => {
+                assert_eq!(optimizer.0[&pyobj!(s = "lr")], pyobj!(f = 0.1))
+            }
+            other => panic!("expected dict, got {:?}", other),
+        }
+        match &dict[&pyobj!(s = "model")] {
+            Value::Skipped(model) => {
+                assert_eq!(model.len, 3);
+                assert!(model.end - model.start > 300);
+            }
+            other => panic!("expected placeholder, got {:?}", other),
+        }
+        assert!(matches!(dict[&pyobj!(s = "epoch")], Value::Skipped(_)));
+        // "t" is the same list as "s", through the memo.
+        assert!(matches!(dict[&pyobj!(s = "s")], Value::Skipped(_)));
+        assert_eq!(dict[&pyobj!(s = "t")], dict[&pyobj!(s = "s")]);
+
+        let path = Path(vec!["model".into(), "t".into(), 2.into()]);
+        let options = UnpicklerOptions::new().select_path(path);
+        let value = Unpickler::value_from_slice(&data, options).unwrap();
+        let model = match value {
+            Value::Dict(dict) => dict.0[&pyobj!(s = "model")].clone(),
+            other => panic!("expected dict, got {:?}", other),
+        };
+        match model {
+            Value::Dict(model) => {
+                assert_eq!(
+                    model.0[&pyobj!(s = "t")],
+                    pyobj!(t = (i = 1, s = "a", l = [i = 2]))
+                );
+                assert!(matches!(model.0[&pyobj!(s = "w")], Value::Skipped(_)));
+            }
+            other => panic!("expected dict, got {:?}", other),
+        }
+    }
+
+    for &(major, proto) in TEST_CASES {
+        let filename = format!("tests/data/tests_py{}_proto{}.pickle", major, proto);
+        let data = std::fs::read(filename).unwrap();
+        let options = UnpicklerOptions::new()
+            .decode_strings(false)
+            .select(|_: &[PathElem<'_>]| true);
+        let unpickled = Unpickler::value_from_slice(&data, options).unwrap();
+        assert_eq!(
+            unpickled,
+            get_test_object(major),
+            "py {}, proto {}",
+            major,
+            proto
+        );
+    }
+}
+
+#[test]
+fn select_path_shared_and_nested() {
+    // Sets, frozensets and bytes that share their global through the memo
+    // (protocols 0 to 3), and values nested in tuples.
+    let paths: &[&[PathElem<'static>]] = &[
+        &[0.into()],
+        &[1.into()],
+        &[2.into()],
+        &[3.into()],
+        &[4.into()],
+        &[5.into()],
+        &[6.into(), 1.into(), 1.into()],
+        &[6.into(), 1.into(), 1.into(), 0.into()],
+        &[7.into(), "k".into()],
+        &[7.into(), "n".into(), 0.into()],
+        &[7.into(), "n".into(), 1.into(), 1.into()],
+        &[7.into(), "n".into(), 1.into(), 2.into(), 0.into(), 1.into()],
+    ];
+    for proto in &[0, 1, 2, 3, 4, 5] {
+        let filename = format!("tests/data/test_select_nested_proto{}.pickle", proto);
+        let data = std::fs::read(filename).unwrap();
+        let full = Unpickler::value_from_slice(&data, UnpicklerOptions::new()).unwrap();
+
+        for path in paths {
+            let options = UnpicklerOptions::new().select_path(Path(path.to_vec()));
+            let value = Unpickler::value_from_slice(&data, options).unwrap();
+            let expected = path.iter().try_fold(&full, |v, elem| v.get(elem.clone()));
+            let found = path.iter().try_fold(&value, |v, elem| v.get(elem.clone()));
+            assert_eq!(found, expected, "proto {}, path {:?}", proto, path);
+        }
+    }
+}
+
+#[test]
+fn preview() {
+    for proto in &[0, 1, 2, 3, 4, 5] {
+        let filename = format!("tests/data/test_preview_proto{}.pickle", proto);
+        let data = std::fs::read(filename).unwrap();
+        let options = UnpicklerOptions::new().preview(PreviewLimits::default());
+        let dict = match Unpickler::value_from_slice(&data, options).unwrap() {
+            Value::Dict(dict) => dict.0,
+            other => panic!("expected dict, got {:?}", other),
+        };
+        let truncated = |key: &str| match &dict[&pyobj!(s = key)] {
+            Value::Truncated(truncated) => (truncated.value.clone(), truncated.len),
+            other => panic!("expected truncated {}, got {:?}", key, other),
+        };
+
+        let xs = (0..20).map(Value::I64).collect::<Vec<_>>();
+        assert_eq!(truncated("xs"), (Value::List(xs.clone()), 1000));
+        assert_eq!(truncated("t"), (Value::Tuple(xs), 30));
+        match truncated("fs") {
+            (Value::FrozenSet(set), 30) => assert_eq!(set.0.len(), 20),
+            other => panic!("expected frozenset, got {:?}", other),
+        }
+        match truncated("set") {
+            (Value::Set(set), 30) => assert_eq!(set.0.len(), 20),
+            other => panic!("expected set, got {:?}", other),
+        }
+        match truncated("d") {
+            (Value::Dict(d), 60) => assert_eq!(d.0.len(), 50),
+            other => panic!("expected dict, got {:?}", other),
+        }
+        assert_eq!(truncated("s"), (pyobj!(s = "é".repeat(200)), 350));
+        assert_eq!(truncated("b"), (pyobj!(bb = [0; 200]), 5000));
+    }
+
+    let list = Value::Truncated(Box::new(Truncated {
+        value: Value::List(vec![Value::I64(1), Value::I64(2)]),
+        len: 9982,
+    }));
+    assert_eq!(list.to_string(), "[1, 2, ... 9,980 more items]");
+    let string = Value::Truncated(Box::new(Truncated {
+        value: pyobj!(s = "it's"),
+        len: 1004,
+    }));
+    assert_eq!(string.to_string(), "\"it's\"... 1,000 more chars");
+}
+
+#[test]
+fn dict_order() {
+    // pickle.dumps({'z': 1, 'a': 2, 'm': 3}), in protocols 0 and 2.
+    let proto0 = b"(dp0\nVz\np1\nI1\nsVa\np2\nI2\nsVm\np3\nI3\ns.";
+    let proto2 = b"\x80\x02}q\x00(X\x01\x00\x00\x00zq\x01K\x01X\x01\x00\x00\x00aq\x02K\x02X\x01\x00\x00\x00mq\x03K\x03u.";
+    for data in [&proto0[..], &proto2[..]] {
+        let value = Unpickler::value_from_slice(data, UnpicklerOptions::new()).unwrap();
+        assert_eq!(value.to_string(), "{'z': 1, 'a': 2, 'm': 3}");
+    }
+
+    // Setting a key again keeps its place, as in Python.
+    let data = b"}(X\x01\x00\x00\x00zK\x01X\x01\x00\x00\x00aK\x02X\x01\x00\x00\x00zK\x03u.";
+    let value = Unpickler::value_from_slice(data, UnpicklerOptions::new()).unwrap();
+    assert_eq!(value.to_string(), "{'z': 3, 'a': 2}");
+
+    // Equal dicts and sets hash the same whatever their order.
+    let hash = |value: &Value| {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    };
+    let za = pyobj!(d={s="z" => i=1, s="a" => i=2});
+    let az = pyobj!(d={s="a" => i=2, s="z" => i=1});
+    assert_eq!(za, az);
+    assert_ne!(za.to_string(), az.to_string());
+    assert_eq!(hash(&za), hash(&az));
+    let set = pyobj!(ss = (i = 1, s = "two", n = None));
+    let reversed = pyobj!(ss = (n = None, s = "two", i = 1));
+    assert_eq!(set, reversed);
+    assert_eq!(hash(&set), hash(&reversed));
+}
+
+#[test]
+fn python_keys() {
+    fn load(data: &[u8]) -> Value<'_> {
+        Unpickler::value_from_slice(data, UnpicklerOptions::new()).unwrap()
+    }
+
+    // {1: 'a', True: 'b'} is {1: 'b'} in Python: the first key is kept.
+    let dict = load(b"\x80\x02}(K\x01X\x01\x00\x00\x00a\x88X\x01\x00\x00\x00bu.");
+    assert_eq!(dict, py!({1: "b"}));
+
+    // {0.0, -0.0, 1, True, 1.0, (1,), (True,)}, by ADDITEMS.
+    let set = load(
+        b"\x80\x04\x8f(G\x00\x00\x00\x00\x00\x00\x00\x00G\x80\x00\x00\x00\x00\x00\x00\x00\
+          K\x01\x88G?\xf0\x00\x00\x00\x00\x00\x00K\x01\x85\x88\x85\x90.",
+    );
+    assert_eq!(set, py!({0.0, 1, (1,)}));
+
+    // 2**70 as an int and a float, by SETITEM in protocol 0.
+    let dict = load(b"(dL1180591620717411303424L\nI1\nsF1.180591620717411303424e21\nI2\ns.");
+    assert_eq!(dict.to_string(), "{1180591620717411303424: 2}");
+
+    // Python's equality, and lookups by it.
+    assert!(py!(True).py_eq(&py!(1.0)));
+    assert!(Value::Int(BigInt::from(7)).py_eq(&py!(7)));
+    assert!(py!({ 1 }).py_eq(&py!(frozenset({ True }))));
+    assert!(!py!([1]).py_eq(&py!((1,))));
+    assert!(!py!("1").py_eq(&py!(1)));
+    assert_ne!(py!(True), py!(1));
+    let Value::Dict(dict) = py!({(1, 2.5): "x"}) else {
+        unreachable!()
+    };
+    assert_eq!(dict.get(&py!((True, 2.5))), Some(&py!("x")));
+    assert_eq!(dict.get(&py!((1, 2.0))), None);
+
+    // Two NaNs are two keys, but a NaN fetched from the memo twice is the
+    // same object in Python, and so one key.
+    let dict = load(
+        b"\x80\x02}(G\x7f\xf8\x00\x00\x00\x00\x00\x00K\x01G\x7f\xf8\x00\x00\x00\x00\x00\x00K\x02u.",
+    );
+    assert_eq!(dict.as_dict().unwrap().0.len(), 2);
+    let dict = load(b"\x80\x02}(G\x7f\xf8\x00\x00\x00\x00\x00\x00q\x00K\x01h\x00K\x02u.");
+    let dict = &dict.as_dict().unwrap().0;
+    assert_eq!(dict.len(), 1);
+    assert_eq!(dict[0], py!(2));
+    assert!(!py!(f64::NAN).py_eq(&py!(f64::NAN)));
+}
@@ -0,0 +1,107 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use std::borrow::Cow;
+
+use num_bigint::BigInt;
+use pickle_rs::{ops, ops_from_slice, IntArg, Opcode, OpcodeInfo};
+
+#[test]
+fn genops() {
+    // {'a': [1, 'x', b'yz', True, None, 1.5, -300, 2**70]}, protocol 4
+    let data = b"\x80\x04\x952\x00\x00\x00\x00\x00\x00\x00}\x94\x8c\x01a\x94]\x94(K\x01\x8c\x01x\x94C\x02yz\x94\x88NG?\xf8\x00\x00\x00\x00\x00\x00J\xd4\xfe\xff\xff\x8a\t\x00\x00\x00\x00\x00\x00\x00\x00@es.";
+    let ops = ops_from_slice(data).collect::<Result<Vec<_>, _>>().unwrap();
+    let expected = vec![
+        (0, Opcode::Proto(4)),
+        (2, Opcode::Frame(50)),
+        (11, Opcode::EmptyDict),
+        (12, Opcode::Memoize),
+        (13, Opcode::ShortBinUnicode("a".into())),
+        (16, Opcode::Memoize),
+        (17, Opcode::EmptyList),
+        (18, Opcode::Memoize),
+        (19, Opcode::Mark),
+        (20, Opcode::BinInt1(1)),
+        (22, Opcode::ShortBinUnicode("x".into())),
+        (25, Opcode::Memoize),
+        (26, Opcode::ShortBinBytes(Cow::Borrowed(b"yz"))),
+        (30, Opcode::Memoize),
+        (31, Opcode::NewTrue),
+        (32, Opcode::None),
+        (33, Opcode::BinFloat(1.5)),
+        (42, Opcode::BinInt(-300)),
+        (47, Opcode::Long1(BigInt::from(1u128 << 70))),
+        (58, Opcode::Appends),
+        (59, Opcode::SetItem),
+        (60, Opcode::Stop),
+    ];
+    assert_eq!(ops, expected);
+    // Arguments borrow from the slice.
+    assert!(matches!(
+        ops[4].1,
+        Opcode::ShortBinUnicode(Cow::Borrowed(_))
+    ));
+    assert_eq!(ops[4].1.name(), "SHORT_BINUNICODE");
+    assert_eq!(ops[4].1.proto(), 4);
+}
+
+#[test]
+fn genops_protocol_0() {
+    // (True, 'é', u'é', 12L, C.x), protocol 0
+    let data = b"(I01\nS'\\xc3\\xa9'\np0\nV\\u00e9\np1\nL12L\ncmod\nC\np2\n(tR.";
+    let ops = ops(&data[..]).map(|op| op.unwrap().1).collect::<Vec<_>>();
+    assert_eq!(ops[1], Opcode::Int(IntArg::Bool(true)));
+    assert_eq!(ops[2], Opcode::String(Cow::Borrowed(&b"\xc3\xa9"[..])));
+    assert_eq!(ops[3], Opcode::Put(0));
+    assert_eq!(ops[4], Opcode::Unicode("é".into()));
+    assert_eq!(ops[6], Opcode::Long(BigInt::from(12)));
+    assert_eq!(
+        ops[7],
+        Opcode::Global {
+            module: "mod".into(),
+            name: "C".into()
+        }
+    );
+    assert_eq!(ops.last(), Some(&Opcode::Stop));
+}
+
+#[test]
+fn genops_all_fixtures() {
+    for name in ["tests_py2_proto2", "tests_py3_proto0", "tests_py3_proto5"] {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        let ops = ops_from_slice(&data)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let (offset, last) = ops.last().unwrap();
+        assert_eq!((*offset, last), (data.len() - 1, &Opcode::Stop));
+        for (offset, op) in ops {
+            assert_eq!(data[offset], op.code());
+            assert_eq!(OpcodeInfo::from_code(op.code()).unwrap().name, op.name());
+        }
+    }
+}
+
+#[test]
+fn genops_stops_at_error() {
+    let mut ops = ops_from_slice(b"K\x01\xff.");
+    assert_eq!(ops.next().unwrap().unwrap(), (0, Opcode::BinInt1(1)));
+    assert!(ops.next().unwrap().is_err());
+    assert!(ops.next().is_none());
+}
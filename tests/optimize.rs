@@ -0,0 +1,130 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+mod common;
+
+use common::{decode, python_fixtures};
+use pickle_rs::{assemble, ops_from_slice, optimize, Error, ErrorCode, Opcode};
+
+#[test]
+fn matches_pickletools() {
+    let mut names = python_fixtures();
+    names.push("test_preview_proto5".to_string());
+    for proto in 0..6 {
+        names.push(format!("test_recursive_proto{}", proto));
+    }
+    for name in names {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        let expected = std::fs::read(format!("tests/data/{}.opt", name)).unwrap();
+        let optimized = optimize(&data).unwrap();
+        assert_eq!(optimized, expected, "{}", name);
+        // Recursive values can't be unpickled into a `Value`.
+        if !name.starts_with("test_recursive") {
+            assert_eq!(decode(&optimized), decode(&data), "{}", name);
+        }
+    }
+}
+
+#[test]
+fn frames_around_large_opcodes() {
+    let listing = format!(
+        "PROTO 4
+        FRAME
+        EMPTY_LIST
+        MEMOIZE
+        MARK
+        SHORT_BINBYTES b'ab'
+        MEMOIZE
+        BINBYTES b'{}'
+        MEMOIZE
+        BINGET 1
+        APPENDS
+        STOP",
+        "x".repeat(70000)
+    );
+    let data = assemble(&listing).unwrap();
+    let optimized = optimize(&data).unwrap();
+    let ops = ops_from_slice(&optimized)
+        .map(|op| op.unwrap())
+        .map(|(pos, op)| match op {
+            Opcode::BinBytes(_) => (pos, "BINBYTES".into()),
+            op => (pos, format!("{:?}", op)),
+        })
+        .collect::<Vec<_>>();
+    let expected = [
+        (0, "Proto(4)"),
+        (2, "Frame(7)"),
+        (11, "EmptyList"),
+        (12, "Mark"),
+        (13, "ShortBinBytes([97, 98])"),
+        (17, "Memoize"),
+        (18, "BINBYTES"),
+        (70023, "Frame(4)"),
+        (70032, "BinGet(0)"),
+        (70034, "Appends"),
+        (70035, "Stop"),
+    ];
+    assert_eq!(
+        ops,
+        expected
+            .iter()
+            .map(|&(pos, op)| (pos, op.to_string()))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(decode(&optimized), decode(&data));
+}
+
+#[test]
+fn reports_missing_memo() {
+    let data = assemble("PROTO 2\nEMPTY_LIST\nBINPUT 0\nPOP\nBINGET 1\nSTOP").unwrap();
+    match optimize(&data) {
+        Err(Error::Eval {
+            code: ErrorCode::MissingMemo(1),
+            pos: 6,
+            ..
+        }) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn memoizes_after_distinct_ids() {
+    // `MEMOIZE` stores at the number of distinct ids, so the re-`PUT` of
+    // id 0 leaves the tuple at id 1.
+    let data = assemble(
+        "PROTO 4
+        EMPTY_LIST
+        BINPUT 0
+        POP
+        EMPTY_DICT
+        BINPUT 0
+        POP
+        EMPTY_TUPLE
+        MEMOIZE
+        POP
+        BINGET 0
+        BINGET 1
+        TUPLE2
+        STOP",
+    )
+    .unwrap();
+    let expected = b"\x80\x04\x95\x0f\x00\x00\x00\x00\x00\x00\x00]\x940}\x940)\x940h\x01h\x02\x86.";
+    assert_eq!(optimize(&data).unwrap(), expected);
+}
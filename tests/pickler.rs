@@ -0,0 +1,223 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+mod common;
+
+use common::{decode, invalid, python_fixtures};
+use indexmap::{IndexMap, IndexSet};
+use num_bigint::BigInt;
+use pickle_rs::{
+    assemble, F64Wrapper, HashMapWrapper, HashSetWrapper, Pickler, PicklerOptions, SourceMap, Value,
+};
+
+fn dump(value: &Value, proto: u8) -> pickle_rs::Result<Vec<u8>> {
+    Pickler::value_to_vec(value, PicklerOptions::new().proto(proto))
+}
+
+#[test]
+fn round_trips_python_pickles() {
+    let mut names = python_fixtures();
+    for proto in 0..6 {
+        names.push(format!("test_preview_proto{}", proto));
+        names.push(format!("test_select_proto{}", proto));
+    }
+    for name in names {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        let value = decode(&data);
+        for proto in 0..6 {
+            let dumped = dump(&value, proto).unwrap();
+            assert_eq!(decode(&dumped), value, "{} to protocol {}", name, proto);
+        }
+    }
+}
+
+#[test]
+fn matches_python_layout() {
+    // The output of `pickletools.optimize(pickle.dumps(value, proto))`,
+    // with the dict in the order of its keys.
+    let value = Value::Dict(HashMapWrapper(IndexMap::from([
+        (
+            Value::String("a".into()),
+            Value::List(vec![
+                Value::I64(1),
+                Value::I64(300),
+                Value::I64(-5),
+                Value::I64(1 << 40),
+                Value::Int(BigInt::from(10).pow(30)),
+            ]),
+        ),
+        (
+            Value::String("b".into()),
+            Value::Tuple(vec![
+                Value::F64(F64Wrapper(0.5)),
+                Value::Bool(true),
+                Value::None,
+            ]),
+        ),
+        (
+            Value::String("c".into()),
+            Value::Bytes(b"\x00\xffab".to_vec().into()),
+        ),
+        (
+            Value::String("d".into()),
+            Value::Set(HashSetWrapper(IndexSet::from([Value::I64(7)]))),
+        ),
+        (
+            Value::String("e".into()),
+            Value::FrozenSet(HashSetWrapper(IndexSet::from([Value::String("x".into())]))),
+        ),
+    ])));
+    let expected = assemble(
+        "PROTO 2
+        EMPTY_DICT
+        MARK
+        BINUNICODE 'a'
+        EMPTY_LIST
+        MARK
+        BININT1 1
+        BININT2 300
+        BININT -5
+        LONG1 1099511627776
+        LONG1 1000000000000000000000000000000
+        APPENDS
+        BINUNICODE 'b'
+        BINFLOAT 0.5
+        NEWTRUE
+        NONE
+        TUPLE3
+        BINUNICODE 'c'
+        GLOBAL '_codecs encode'
+        BINUNICODE '\\x00ÿab'
+        BINUNICODE 'latin1'
+        TUPLE2
+        REDUCE
+        BINUNICODE 'd'
+        GLOBAL '__builtin__ set'
+        EMPTY_LIST
+        BININT1 7
+        APPEND
+        TUPLE1
+        REDUCE
+        BINUNICODE 'e'
+        GLOBAL '__builtin__ frozenset'
+        EMPTY_LIST
+        BINUNICODE 'x'
+        APPEND
+        TUPLE1
+        REDUCE
+        SETITEMS
+        STOP",
+    )
+    .unwrap();
+    assert_eq!(dump(&value, 2).unwrap(), expected);
+    let expected = assemble(
+        "PROTO 4
+        FRAME
+        EMPTY_DICT
+        MARK
+        SHORT_BINUNICODE 'a'
+        EMPTY_LIST
+        MARK
+        BININT1 1
+        BININT2 300
+        BININT -5
+        LONG1 1099511627776
+        LONG1 1000000000000000000000000000000
+        APPENDS
+        SHORT_BINUNICODE 'b'
+        BINFLOAT 0.5
+        NEWTRUE
+        NONE
+        TUPLE3
+        SHORT_BINUNICODE 'c'
+        SHORT_BINBYTES b'\\x00\\xffab'
+        SHORT_BINUNICODE 'd'
+        EMPTY_SET
+        MARK
+        BININT1 7
+        ADDITEMS
+        SHORT_BINUNICODE 'e'
+        MARK
+        SHORT_BINUNICODE 'x'
+        FROZENSET
+        SETITEMS
+        STOP",
+    )
+    .unwrap();
+    assert_eq!(dump(&value, 4).unwrap(), expected);
+}
+
+#[test]
+fn writes_source_forms() {
+    let data = assemble(
+        "PROTO 2
+        EMPTY_DICT
+        MARK
+        BINUNICODE 'z'
+        BININT 1
+        BINUNICODE 'a'
+        MARK
+        STRING 'py2'
+        UNICODE 'text'
+        INT 01
+        FLOAT 2.5
+        LONG 7
+        TUPLE
+        SETITEMS
+        STOP",
+    )
+    .unwrap();
+    let sources = SourceMap::from_slice(&data).unwrap();
+    assert_eq!(sources.proto(), 2);
+    let pickler = Pickler::new(PicklerOptions::new().proto(2)).sources(&sources);
+    let value = decode(&data);
+    assert_eq!(pickler.dump(&value).unwrap(), data);
+
+    // Forms that the protocol doesn't have are replaced.
+    let pickler = Pickler::new(PicklerOptions::new().proto(0)).sources(&sources);
+    let expected = assemble(
+        "MARK
+        DICT
+        UNICODE 'z'
+        INT 1
+        SETITEM
+        UNICODE 'a'
+        MARK
+        STRING 'py2'
+        UNICODE 'text'
+        INT 01
+        FLOAT 2.5
+        LONG 7
+        TUPLE
+        SETITEM
+        STOP",
+    )
+    .unwrap();
+    assert_eq!(pickler.dump(&value).unwrap(), expected);
+}
+
+#[test]
+fn fails_on_unpicklable_values() {
+    let data = assemble("PROTO 2\nGLOBAL 'os system'\nSTOP").unwrap();
+    let msg = invalid(dump(&decode(&data), 2));
+    assert_eq!(msg, "a global can't be pickled in protocol 2");
+    let msg = invalid(dump(&Value::None, 6));
+    assert_eq!(msg, "unknown protocol 6");
+}
@@ -0,0 +1,109 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use pickle_rs::{pformat, PrettyOptions, Unpickler, UnpicklerOptions, Value};
+
+fn load(data: &[u8]) -> Value<'_> {
+    Unpickler::value_from_slice(data, UnpicklerOptions::new()).unwrap()
+}
+
+#[test]
+fn displays_python_literals() {
+    // {'a': [1], b'x': (1,), 'f': frozenset({2}), 's': set(), 'n': nan}
+    let value = load(b"\x80\x04\x95(\x00\x00\x00\x00\x00\x00\x00}(\x8c\x01a]K\x01aC\x01xK\x01\x85\x8c\x01f(K\x02\x91\x8c\x01s\x8f\x8c\x01nG\x7f\xf8\x00\x00\x00\x00\x00\x00u.");
+    assert_eq!(
+        value.to_string(),
+        "{'a': [1], b'x': (1,), 'f': frozenset({2}), 's': set(), 'n': float('nan')}"
+    );
+
+    let escaped = "it's \"q\"\t\u{7f}\u{85}\u{a0}\u{200b}\u{2028}é€\u{1f600}";
+    assert_eq!(
+        Value::String(escaped.into()).to_string(),
+        r#"'it\'s "q"\t\x7f\x85\xa0\u200b\u2028é€😀'"#
+    );
+    assert_eq!(Value::String("it's".into()).to_string(), r#""it's""#);
+    let bytes = Value::Bytes(b"'\"\\\x00\xff"[..].into());
+    assert_eq!(bytes.to_string(), r#"b'\'"\\\x00\xff'"#);
+    assert_eq!(
+        Value::PersId("a'b".into()).to_string(),
+        r#"persistent_id("a'b")"#
+    );
+}
+
+#[test]
+fn pretty_prints_like_pprint() {
+    // {'name': 'adam', 'steps': [1, 2, 3], 'nested': {'b': (1, 2), 'a': [{}]},
+    //  'text': 'the quick brown fox jumps over the lazy dog'}
+    let value = load(b"\x80\x02}(X\x04\x00\x00\x00nameX\x04\x00\x00\x00adamX\x05\x00\x00\x00steps](K\x01K\x02K\x03eX\x06\x00\x00\x00nested}(X\x01\x00\x00\x00bK\x01K\x02\x86X\x01\x00\x00\x00a]}auX\x04\x00\x00\x00textX+\x00\x00\x00the quick brown fox jumps over the lazy dogu.");
+
+    // Outputs of pprint.pformat.
+    assert_eq!(
+        pformat(&value, PrettyOptions::new().width(40)),
+        "\
+{'name': 'adam',
+ 'nested': {'a': [{}], 'b': (1, 2)},
+ 'steps': [1, 2, 3],
+ 'text': 'the quick brown fox jumps '
+         'over the lazy dog'}"
+    );
+    assert_eq!(
+        pformat(&value, PrettyOptions::new().width(30).sort_dicts(false)),
+        "\
+{'name': 'adam',
+ 'steps': [1, 2, 3],
+ 'nested': {'b': (1, 2),
+            'a': [{}]},
+ 'text': 'the quick brown '
+         'fox jumps over the '
+         'lazy dog'}"
+    );
+    assert_eq!(
+        pformat(&value, PrettyOptions::new().width(30).indent(4).depth(1)),
+        "\
+{   'name': 'adam',
+    'nested': {...},
+    'steps': [...],
+    'text': 'the quick brown '
+            'fox jumps over '
+            'the lazy dog'}"
+    );
+    assert_eq!(
+        pformat(&value["text"], PrettyOptions::new().width(20)),
+        "\
+('the quick brown '
+ 'fox jumps over '
+ 'the lazy dog')"
+    );
+    assert_eq!(format!("{:#}", value["steps"]), "[1, 2, 3]");
+
+    // Long bytes are split in groups of 4, or cut short.
+    let blob = Value::Bytes((0..12).collect::<Vec<u8>>().into());
+    assert_eq!(
+        pformat(&blob, PrettyOptions::new().width(30)),
+        "\
+(b'\\x00\\x01\\x02\\x03'
+ b'\\x04\\x05\\x06\\x07'
+ b'\\x08\\t\\n\\x0b')"
+    );
+    assert_eq!(
+        pformat(&blob, PrettyOptions::new().max_bytes(2)),
+        "b'\\x00\\x01'... 10 more bytes"
+    );
+}
@@ -0,0 +1,254 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use pickle_rs::{literal_eval, py, Error, ErrorCode, Query, Unpickler, UnpicklerOptions, Value};
+
+fn checkpoint() -> Value<'static> {
+    literal_eval(
+        "{'optimizer': {'param_groups': [{'lr': 0.1, 'params': [0, 1], 'name': 'base'},
+                                         {'lr': 0.01, 'params': [2], 'name': 'head'}],
+                        'state': {0: {'step': 3, 'buf': b'xx'}}},
+          'model': {'w': b'ww', b'k': [(1, 2), 'x'], 'lr': 0.5},
+          'epoch': 7}",
+    )
+    .unwrap()
+}
+
+// The paths and values that a query picks.
+fn run(query: &str, value: &Value) -> Vec<(String, String)> {
+    let query: Query = query.parse().unwrap();
+    query
+        .eval(value)
+        .into_iter()
+        .map(|m| (m.path.to_string(), m.value.to_string()))
+        .collect()
+}
+
+fn error(query: &str) -> String {
+    match Query::parse(query) {
+        Err(Error::Syntax(ErrorCode::InvalidValue(msg))) => msg,
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn picks_values_with_paths() {
+    let value = checkpoint();
+    let pairs = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(path, value)| (path.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        run("optimizer.param_groups[*].lr", &value),
+        pairs(&[
+            (r#"["optimizer"]["param_groups"][0]["lr"]"#, "0.1"),
+            (r#"["optimizer"]["param_groups"][1]["lr"]"#, "0.01"),
+        ])
+    );
+    assert_eq!(
+        run("$.optimizer.state[0]['step']", &value),
+        pairs(&[(r#"["optimizer"]["state"][0]["step"]"#, "3")])
+    );
+    assert_eq!(
+        run("model[b'k'][-1]", &value),
+        pairs(&[(r#"["model"][b"k"][1]"#, "'x'")])
+    );
+    assert_eq!(
+        run("..lr", &value),
+        pairs(&[
+            (r#"["optimizer"]["param_groups"][0]["lr"]"#, "0.1"),
+            (r#"["optimizer"]["param_groups"][1]["lr"]"#, "0.01"),
+            (r#"["model"]["lr"]"#, "0.5"),
+        ])
+    );
+    assert_eq!(
+        run("..[0]", &value),
+        pairs(&[
+            (
+                r#"["optimizer"]["param_groups"][0]"#,
+                "{'lr': 0.1, 'params': [0, 1], 'name': 'base'}"
+            ),
+            (r#"["optimizer"]["param_groups"][0]["params"][0]"#, "0"),
+            (r#"["optimizer"]["param_groups"][1]["params"][0]"#, "2"),
+            (r#"["optimizer"]["state"][0]"#, "{'step': 3, 'buf': b'xx'}"),
+            (r#"["model"][b"k"][0]"#, "(1, 2)"),
+            (r#"["model"][b"k"][0][0]"#, "1"),
+        ])
+    );
+    assert_eq!(run("", &value), pairs(&[("", &value.to_string())]));
+    assert_eq!(run("model.*", &value).len(), 3);
+    assert!(run("epoch.x", &value).is_empty());
+    assert!(run("optimizer.param_groups[2]", &value).is_empty());
+}
+
+#[test]
+fn filters_items() {
+    let value = checkpoint();
+    let names = |query: &str| {
+        let query = Query::parse(query).unwrap();
+        query
+            .eval(&value)
+            .into_iter()
+            .map(|m| m.value.clone())
+            .collect::<Vec<_>>()
+    };
+    let groups = "optimizer.param_groups";
+    assert_eq!(
+        names(&format!("{}[?@.lr > 0.05].name", groups)),
+        [py!("base")]
+    );
+    assert_eq!(
+        names(&format!("{}[?@.lr <= 1e-2].name", groups)),
+        [py!("head")]
+    );
+    assert_eq!(
+        names(&format!("{}[?@.params[*] == 1].name", groups)),
+        [py!("base")]
+    );
+    assert_eq!(
+        names(&format!("{}[?@.name != 'base' || !(@.lr)].name", groups)),
+        [py!("head")]
+    );
+    assert_eq!(
+        names(&format!("{}[?@.lr < 1 && @.name >= 'b'].name", groups)),
+        [py!("base"), py!("head")]
+    );
+    assert_eq!(names("model[?@[1] == 'x'][0]"), [py!((1, 2))]);
+    assert_eq!(
+        names("optimizer.state[?@.buf]"),
+        [literal_eval("{'step': 3, 'buf': b'xx'}").unwrap()]
+    );
+    // Values of types that Python can't order don't compare.
+    assert!(names(&format!("{}[?@.name < 1]", groups)).is_empty());
+    assert_eq!(names("optimizer.state[?@.step == 3.0].step"), [py!(3)]);
+
+    // `..` filters the items at every depth.
+    assert_eq!(
+        run("..[?@ == 2 || @.step]", &value),
+        [
+            (
+                r#"["optimizer"]["param_groups"][1]["params"][0]"#.to_string(),
+                "2".to_string()
+            ),
+            (
+                r#"["optimizer"]["state"][0]"#.to_string(),
+                "{'step': 3, 'buf': b'xx'}".to_string()
+            ),
+            (r#"["model"][b"k"][0][1]"#.to_string(), "2".to_string()),
+        ]
+    );
+    assert_eq!(names("optimizer..[?@.lr < 0.05].name"), [py!("head")]);
+}
+
+#[test]
+fn selects_while_decoding() {
+    for proto in 0..6 {
+        let filename = format!("tests/data/test_select_proto{}.pickle", proto);
+        let data = std::fs::read(filename).unwrap();
+        let query = Query::parse("model.t[2][*]").unwrap();
+        let options = UnpicklerOptions::new().select_query(&query);
+        let value = Unpickler::value_from_slice(&data, options).unwrap();
+        let matches = query.eval(&value);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].path.to_string(), r#"["model"]["t"][2][0]"#);
+        assert_eq!(matches[0].value, &py!(2));
+        assert!(matches!(value["optimizer"], Value::Skipped(_)));
+        assert!(matches!(value["model"]["w"], Value::Skipped(_)));
+
+        // What lies below `..` can't be told apart before it is built.
+        let query = Query::parse("model..b").unwrap();
+        let options = UnpicklerOptions::new().select_query(&query);
+        let value = Unpickler::value_from_slice(&data, options).unwrap();
+        assert_eq!(query.eval(&value)[0].value, &py!([1.0, 1.0, 1.0]));
+        assert!(matches!(value["model"]["w"], Value::Bytes(_)));
+        assert!(matches!(value["optimizer"], Value::Skipped(_)));
+    }
+}
+
+#[test]
+fn selects_what_a_full_decode_picks() {
+    let queries = [
+        "",
+        "model.t[2][*]",
+        "model..b",
+        "..[-1]",
+        "..[?@ == 1]",
+        "optimizer[?@ > 0.5]",
+        "model[?@[0] == 1][*]",
+        "$..t..*",
+        "*.lr",
+        "s[?@ > 1]",
+    ];
+    for proto in 0..6 {
+        let filename = format!("tests/data/test_select_proto{}.pickle", proto);
+        let data = std::fs::read(filename).unwrap();
+        let full = Unpickler::value_from_slice(&data, UnpicklerOptions::default()).unwrap();
+        for query in queries {
+            let query = Query::parse(query).unwrap();
+            let options = UnpicklerOptions::new().select_query(&query);
+            let value = Unpickler::value_from_slice(&data, options).unwrap();
+            assert_eq!(query.eval(&value), query.eval(&full), "{:?}", query);
+        }
+    }
+}
+
+#[test]
+fn reports_errors_with_positions() {
+    assert_eq!(
+        error("a.[0]"),
+        "line 1, column 3: expected a name or *, got '['"
+    );
+    assert_eq!(error("a.b c"), "line 1, column 5: unexpected 'c'");
+    assert_eq!(error("a[0"), "line 1, column 4: expected \"]\"");
+    assert_eq!(error("a['x' 'y'"), "line 1, column 10: expected \"]\"");
+    assert_eq!(error("a[]"), "line 1, column 3: unexpected ']'");
+    assert_eq!(
+        error("a[b'\\xZ']"),
+        "line 1, column 5: truncated \\xXX escape"
+    );
+    assert_eq!(
+        error("a[?1]"),
+        "line 1, column 4: expected a path or a comparison"
+    );
+    assert_eq!(
+        error("a[?(@.x]"),
+        "line 1, column 8: expected \")\", got ']'"
+    );
+    assert_eq!(error("a[?@.x == ]"), "line 1, column 11: unexpected ']'");
+    assert_eq!(error("1"), "line 1, column 1: unexpected '1'");
+
+    let deep =
+        |open: &str, close: &str| format!("a[?{}@{}]", open.repeat(200_000), close.repeat(200_000));
+    assert_eq!(
+        error(&deep("!", "")),
+        "line 1, column 68: too deeply nested"
+    );
+    assert_eq!(
+        error(&deep("(", ")")),
+        "line 1, column 68: too deeply nested"
+    );
+    assert_eq!(
+        error(&deep("@[?", "]")),
+        "line 1, column 196: too deeply nested"
+    );
+    assert!(Query::parse(&format!("a[?{}@]", "!".repeat(63))).is_ok());
+}
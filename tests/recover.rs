@@ -0,0 +1,130 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use indexmap::IndexMap;
+
+use pickle_rs::{
+    Diagnostic, ErrorCode, HashMapWrapper, Partial, Recovery, Unpickler, UnpicklerOptions, Value,
+};
+
+fn load_partial(data: &[u8]) -> pickle_rs::Result<Partial<'_>> {
+    Unpickler::from_slice(data, UnpicklerOptions::new()).load_partial()
+}
+
+fn dict(items: Vec<(&str, Value<'static>)>) -> Value<'static> {
+    let items = items
+        .into_iter()
+        .map(|(k, v)| (Value::String(k.to_owned().into()), v));
+    Value::Dict(HashMapWrapper(items.collect::<IndexMap<_, _>>()))
+}
+
+fn ints(items: &[i64]) -> Value<'static> {
+    Value::List(items.iter().map(|&i| Value::I64(i)).collect())
+}
+
+fn recoveries(partial: &Partial) -> Vec<(usize, Recovery)> {
+    let diagnostics = partial.diagnostics.iter();
+    diagnostics.map(|d| (d.pos, d.recovery.clone())).collect()
+}
+
+#[test]
+fn salvages_truncated_pickles() {
+    // {'a': [1, 2, 3], 'c': [[4, 5], [6]]}, cut short in the middle.
+    let data = b"\x80\x02}(X\x01\x00\x00\x00a](K\x01K\x02K\x03eX\x01\x00\x00\x00c](](K\x04K\x05e]K\x06aeu.";
+    let partial = load_partial(&data[..31]).unwrap();
+    let c = Value::List(vec![ints(&[4])]);
+    assert_eq!(partial.value, dict(vec![("a", ints(&[1, 2, 3])), ("c", c)]));
+    assert_eq!(
+        recoveries(&partial),
+        vec![
+            (31, Recovery::Stopped(ErrorCode::EOFWhileParsing)),
+            (28, Recovery::AddedItems(1)),
+            (26, Recovery::AddedItems(1)),
+            (3, Recovery::AddedItems(2)),
+        ]
+    );
+
+    // A dict key without its value is dropped.
+    let partial = load_partial(&data[..25]).unwrap();
+    assert_eq!(partial.value.to_string(), "{'a': [1, 2, 3]}");
+    assert!(recoveries(&partial).contains(&(3, Recovery::DroppedKey)));
+
+    // A whole pickle needs no recovery.
+    let partial = load_partial(data).unwrap();
+    let c = Value::List(vec![ints(&[4, 5]), ints(&[6])]);
+    assert_eq!(partial.value, dict(vec![("a", ints(&[1, 2, 3])), ("c", c)]));
+    assert!(partial.diagnostics.is_empty());
+}
+
+#[test]
+fn salvages_protocol_0_pickles() {
+    // [1, 2] and {'a': 1}, with the last APPEND or SETITEM unread.
+    let partial = load_partial(b"(lI1\naI2\n").unwrap();
+    assert_eq!(partial.value, ints(&[1, 2]));
+    assert_eq!(
+        recoveries(&partial),
+        vec![
+            (9, Recovery::Stopped(ErrorCode::EOFWhileParsing)),
+            (9, Recovery::AddedValues(1)),
+        ]
+    );
+    let partial = load_partial(b"(dVa\nI1\n").unwrap();
+    assert_eq!(partial.value, dict(vec![("a", Value::I64(1))]));
+
+    // Only one value can be waiting; the others are dropped.
+    let partial = load_partial(b"(lI1\nI2\n").unwrap();
+    assert_eq!(partial.value, ints(&[]));
+    assert!(recoveries(&partial).contains(&(8, Recovery::DroppedValues(2))));
+
+    // A line cut short is not taken as a whole one.
+    let partial = load_partial(b"(lI1\naI2").unwrap();
+    assert_eq!(partial.value, ints(&[1]));
+
+    // The items of a MARK with no container below become a list.
+    let partial = load_partial(b"(I1\nI2\n").unwrap();
+    assert_eq!(partial.value, ints(&[1, 2]));
+    assert_eq!(partial.diagnostics[1].recovery, Recovery::MadeList(2));
+}
+
+#[test]
+fn stops_at_invalid_opcodes() {
+    let partial = load_partial(b"\x80\x02](K\x01\xffK\x02e.").unwrap();
+    assert_eq!(partial.value, Value::List(vec![Value::I64(1)]));
+    let Recovery::Stopped(ErrorCode::Unsupported(_)) = partial.diagnostics[0].recovery else {
+        panic!("{:?}", partial.diagnostics);
+    };
+    assert_eq!(partial.diagnostics[0].pos, 6);
+
+    // Bytes after STOP are noted, not fatal.
+    let partial = load_partial(b"K\x01.junk").unwrap();
+    assert_eq!(partial.value, Value::I64(1));
+    let diagnostic = Diagnostic {
+        pos: 3,
+        recovery: Recovery::TrailingBytes,
+    };
+    assert_eq!(partial.diagnostics, vec![diagnostic]);
+    assert_eq!(
+        partial.diagnostics[0].to_string(),
+        "3: ignored trailing bytes"
+    );
+
+    // Nothing to salvage.
+    assert!(load_partial(b"\x80\x02").is_err());
+}
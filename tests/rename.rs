@@ -0,0 +1,168 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use pickle_rs::{assemble, dis, rename_globals, DisOptions, GlobalRenames};
+
+fn listing(data: &[u8]) -> String {
+    let mut out = Vec::new();
+    dis(data, &mut out, DisOptions::new()).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn renames_stack_globals() {
+    // pickle.dumps([OrderedDict(), OrderedDict, Counter, 'collections'], 4)
+    let data = b"\x80\x04\x958\x00\x00\x00\x00\x00\x00\x00]\x94(\x8c\x0bcollections\x94\
+        \x8c\x0bOrderedDict\x94\x93\x94)R\x94h\x03h\x01\x8c\x07Counter\x94\x93\x94h\x01e.";
+    let renames =
+        GlobalRenames::new().global(("collections", "OrderedDict"), ("mylib.dicts", "ODict"));
+    let renamed = rename_globals(data, &renames).unwrap();
+
+    // The module name is still fetched from the memo for `Counter` and the
+    // string item, so those fetches now push the old name.
+    let expected = r"    0: \x80 PROTO      4
+    2: \x95 FRAME      72
+   11: ]    EMPTY_LIST
+   12: \x94 MEMOIZE    (as 0)
+   13: (    MARK
+   14: \x8c     SHORT_BINUNICODE 'mylib.dicts'
+   27: \x94     MEMOIZE    (as 1)
+   28: \x8c     SHORT_BINUNICODE 'ODict'
+   35: \x94     MEMOIZE    (as 2)
+   36: \x93     STACK_GLOBAL
+   37: \x94     MEMOIZE    (as 3)
+   38: )        EMPTY_TUPLE
+   39: R        REDUCE
+   40: \x94     MEMOIZE    (as 4)
+   41: h        BINGET     3
+   43: \x8c     SHORT_BINUNICODE 'collections'
+   56: \x8c     SHORT_BINUNICODE 'Counter'
+   65: \x94     MEMOIZE    (as 5)
+   66: \x93     STACK_GLOBAL
+   67: \x94     MEMOIZE    (as 6)
+   68: \x8c     SHORT_BINUNICODE 'collections'
+   81: e        APPENDS    (MARK at 13)
+   82: .    STOP
+highest protocol among opcodes = 4
+";
+    assert_eq!(listing(&renamed), expected);
+}
+
+#[test]
+fn renames_globals_and_modules() {
+    let data = assemble(
+        "MARK
+        GLOBAL 'collections OrderedDict'
+        MARK
+        INST 'myproj.models.user User'
+        GLOBAL 'myproj.modelsx Other'
+        LIST
+        STOP",
+    )
+    .unwrap();
+    let renames = GlobalRenames::new()
+        .module("myproj.models", "myproj.accounts")
+        .global(("collections", "OrderedDict"), ("builtins", "dict"));
+    assert_eq!(
+        renames.rename("myproj.models", "User"),
+        Some(("myproj.accounts".into(), "User".into()))
+    );
+    assert_eq!(renames.rename("collections", "Counter"), None);
+
+    let renamed = rename_globals(&data, &renames).unwrap();
+    assert_eq!(
+        renamed,
+        b"(cbuiltins\ndict\n(imyproj.accounts.user\nUser\ncmyproj.modelsx\nOther\nl."
+    );
+}
+
+#[test]
+fn leaves_other_pickles_unchanged() {
+    let renames = GlobalRenames::new().module("myproj", "other");
+    for name in [
+        "tests_py2_proto0",
+        "tests_py2_proto2",
+        "tests_py3_proto0",
+        "tests_py3_proto3",
+        "tests_py3_proto5",
+        "test_recursive_proto4",
+        "test_select_proto4",
+    ] {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        assert_eq!(rename_globals(&data, &renames).unwrap(), data, "{}", name);
+    }
+}
+
+#[test]
+fn rewrites_oversized_frames() {
+    // A FRAME claiming u64::MAX bytes gets the length of what follows.
+    let data = b"\x80\x04\x95\xff\xff\xff\xff\xff\xff\xff\xffcfoo\nbar\n.";
+    let renames = GlobalRenames::new().module("foo", "baz");
+    assert_eq!(
+        rename_globals(data, &renames).unwrap(),
+        b"\x80\x04\x95\x0a\x00\x00\x00\x00\x00\x00\x00cbaz\nbar\n."
+    );
+}
+
+#[test]
+fn follows_memoize_after_a_re_put() {
+    // The re-`PUT` of id 0 leaves the module name at id 1, and its fetch
+    // is replaced by the new name.
+
+    let data = assemble(
+        "PROTO 4
+        EMPTY_LIST
+        BINPUT 0
+        POP
+        EMPTY_DICT
+        BINPUT 0
+        POP
+        SHORT_BINUNICODE 'collections'
+        MEMOIZE
+        POP
+        BINGET 1
+        SHORT_BINUNICODE 'OrderedDict'
+        STACK_GLOBAL
+        STOP",
+    )
+    .unwrap();
+    let renames = GlobalRenames::new().module("collections", "mylib");
+    let renamed = rename_globals(&data, &renames).unwrap();
+    assert_eq!(
+        renamed,
+        assemble(
+            "PROTO 4
+            EMPTY_LIST
+            BINPUT 0
+            POP
+            EMPTY_DICT
+            BINPUT 0
+            POP
+            SHORT_BINUNICODE 'collections'
+            MEMOIZE
+            POP
+            SHORT_BINUNICODE 'mylib'
+            SHORT_BINUNICODE 'OrderedDict'
+            STACK_GLOBAL
+            STOP",
+        )
+        .unwrap()
+    );
+}
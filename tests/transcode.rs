@@ -0,0 +1,207 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+mod common;
+
+use common::{decode, invalid, invalid_at, python_fixtures};
+use pickle_rs::{assemble, lint, ops_from_slice, transcode};
+
+#[test]
+fn transcodes_python_pickles() {
+    let mut names = python_fixtures();
+    for proto in 0..6 {
+        names.push(format!("test_recursive_proto{}", proto));
+        names.push(format!("test_preview_proto{}", proto));
+    }
+    for name in names {
+        let data = std::fs::read(format!("tests/data/{}.pickle", name)).unwrap();
+        for proto in 0..6 {
+            let context = format!("{} to protocol {}", name, proto);
+            // The test classes are built with NEWOBJ from protocol 2.
+            if name.starts_with("tests_") && !name.ends_with(['0', '1']) && proto < 2 {
+                let (msg, _) = invalid_at(transcode(&data, proto));
+                assert_eq!(
+                    msg,
+                    format!("NEWOBJ can't be expressed in protocol {}", proto)
+                );
+                continue;
+            }
+            let transcoded = transcode(&data, proto).unwrap();
+            assert!(lint(&transcoded).is_empty(), "{}", context);
+            for op in ops_from_slice(&transcoded) {
+                assert!(op.unwrap().1.proto() <= proto, "{}", context);
+            }
+            // Recursive values can't be unpickled into a `Value`.
+            if !name.starts_with("test_recursive") {
+                assert_eq!(decode(&transcoded), decode(&data), "{}", context);
+            }
+        }
+    }
+}
+
+#[test]
+fn converts_opcodes() {
+    let data = assemble(
+        "PROTO 4
+        FRAME
+        EMPTY_LIST
+        MEMOIZE
+        MARK
+        SHORT_BINBYTES b'ab'
+        MEMOIZE
+        EMPTY_SET
+        MARK
+        BININT1 1
+        ADDITEMS
+        BINGET 1
+        APPENDS
+        STOP",
+    )
+    .unwrap();
+    let expected = assemble(
+        "PROTO 2
+        EMPTY_LIST
+        MARK
+        GLOBAL '_codecs encode'
+        BINUNICODE 'ab'
+        BINUNICODE 'latin1'
+        TUPLE2
+        REDUCE
+        BINPUT 0
+        GLOBAL '__builtin__ set'
+        EMPTY_LIST
+        MARK
+        BININT1 1
+        APPENDS
+        TUPLE1
+        REDUCE
+        BINGET 0
+        APPENDS
+        STOP",
+    )
+    .unwrap();
+    let transcoded = transcode(&data, 2).unwrap();
+    assert_eq!(transcoded, expected);
+    assert_eq!(decode(&transcoded), decode(&data));
+
+    let expected = assemble(
+        "MARK
+        LIST
+        GLOBAL '_codecs encode'
+        MARK
+        UNICODE 'ab'
+        UNICODE 'latin1'
+        TUPLE
+        REDUCE
+        PUT 0
+        APPEND
+        GLOBAL '__builtin__ set'
+        MARK
+        MARK
+        LIST
+        INT 1
+        APPEND
+        TUPLE
+        REDUCE
+        APPEND
+        GET 0
+        APPEND
+        STOP",
+    )
+    .unwrap();
+    assert_eq!(transcode(&data, 0).unwrap(), expected);
+
+    // The memo is kept only where it is fetched, and the pickle is framed.
+    let expected = assemble(
+        "PROTO 4
+        FRAME
+        EMPTY_LIST
+        MARK
+        SHORT_BINBYTES b'ab'
+        MEMOIZE
+        EMPTY_SET
+        MARK
+        BININT1 1
+        ADDITEMS
+        BINGET 0
+        APPENDS
+        STOP",
+    )
+    .unwrap();
+    assert_eq!(transcode(&data, 4).unwrap(), expected);
+}
+
+#[test]
+fn fails_on_inexpressible_content() {
+    let data = assemble("PROTO 5\nNEXT_BUFFER\nSTOP").unwrap();
+    let (msg, pos) = invalid_at(transcode(&data, 4));
+    assert_eq!(msg, "NEXT_BUFFER can't be expressed in protocol 4");
+    assert_eq!(pos, 2);
+
+    let data = assemble(
+        "PROTO 4
+        GLOBAL 'collections OrderedDict'
+        EMPTY_TUPLE
+        EMPTY_DICT
+        SHORT_BINUNICODE 'a'
+        NONE
+        SETITEM
+        NEWOBJ_EX
+        STOP",
+    )
+    .unwrap();
+    let (msg, pos) = invalid_at(transcode(&data, 3));
+    assert_eq!(
+        msg,
+        "NEWOBJ_EX with keyword arguments can't be expressed in protocol 3"
+    );
+    assert_eq!(pos, 34);
+    let data = assemble(
+        "PROTO 4
+        GLOBAL 'collections OrderedDict'
+        EMPTY_TUPLE
+        EMPTY_DICT
+        NEWOBJ_EX
+        STOP",
+    )
+    .unwrap();
+    assert_eq!(decode(&transcode(&data, 2).unwrap()), decode(&data));
+
+    // A set can only be fetched once it is built below protocol 4.
+    let data = assemble(
+        "PROTO 4
+        EMPTY_SET
+        MEMOIZE
+        MARK
+        EMPTY_TUPLE
+        BINGET 0
+        TUPLE2
+        ADDITEMS
+        STOP",
+    )
+    .unwrap();
+    let (msg, _) = invalid_at(transcode(&data, 3));
+    assert_eq!(
+        msg,
+        "a set in its own items can't be expressed in protocol 3"
+    );
+
+    assert_eq!(invalid(transcode(&data, 6)), "unknown protocol 6");
+}
@@ -0,0 +1,199 @@
+//
+// Copyright (C) 2023 SpinorML.
+//
+// Licensed to the Apache Software Foundation (ASF) under one
+// or more contributor license agreements.  See the NOTICE file
+// distributed with this work for additional information
+// regarding copyright ownership.  The ASF licenses this file
+// to you under the Apache License, Version 2.0 (the
+// "License"); you may not use this file except in compliance
+// with the License.  You may obtain a copy of the License at
+
+//   http://www.apache.org/licenses/LICENSE-2.0
+
+// Unless required by applicable law or agreed to in writing,
+// software distributed under the License is distributed on an
+// "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.  See the License for the
+// specific language governing permissions and limitations
+// under the License.
+
+use pickle_rs::{fold, literal_eval, py, visit, visit_mut, Fold, Path, Value, Visit, VisitMut};
+
+fn literal(source: &str) -> Value<'static> {
+    literal_eval(source).unwrap()
+}
+
+// Records the order of the callbacks.
+#[derive(Default)]
+struct Trace(Vec<String>);
+
+impl<'a> Visit<'a> for Trace {
+    fn visit_value(&mut self, value: &Value<'a>, path: &Path<'a>) -> bool {
+        self.0.push(format!("visit {} {}", path, value));
+        !matches!(value, Value::Tuple(_))
+    }
+
+    fn leave_value(&mut self, value: &Value<'a>, path: &Path<'a>) {
+        self.0.push(format!("leave {} {}", path, value.type_name()));
+    }
+
+    fn visit_key(&mut self, key: &Value<'a>, path: &Path<'a>) {
+        self.0.push(format!("key {} {}", path, key));
+    }
+}
+
+#[test]
+fn visits_in_order_with_paths() {
+    let value = literal("{'a': [1, {2}], b'b': (3,), 'c': {'d': None}}");
+    let mut trace = Trace::default();
+    visit(&value, &mut trace);
+    assert_eq!(
+        trace.0,
+        [
+            "visit  {'a': [1, {2}], b'b': (3,), 'c': {'d': None}}",
+            "key  'a'",
+            "visit [\"a\"] [1, {2}]",
+            "visit [\"a\"][0] 1",
+            "visit [\"a\"][1] {2}",
+            "visit [\"a\"][1][0] 2",
+            "leave [\"a\"][1] set",
+            "leave [\"a\"] list",
+            "key  b'b'",
+            "visit [b\"b\"] (3,)",
+            "key  'c'",
+            "visit [\"c\"] {'d': None}",
+            "key [\"c\"] 'd'",
+            "visit [\"c\"][\"d\"] None",
+            "leave [\"c\"] dict",
+            "leave  dict",
+        ]
+    );
+}
+
+// Replaces bytes longer than a limit by their length, and notes the keys.
+struct StripBlobs(usize, Vec<String>);
+
+impl<'a> VisitMut<'a> for StripBlobs {
+    fn visit_value_mut(&mut self, value: &mut Value<'a>, _path: &Path<'a>) -> bool {
+        if let Value::Bytes(b) = value {
+            if b.len() > self.0 {
+                *value = Value::from(b.len());
+            }
+        }
+        true
+    }
+
+    fn visit_key(&mut self, key: &Value<'a>, path: &Path<'a>) {
+        self.1.push(format!("{}{}", path, key));
+    }
+}
+
+#[test]
+fn changes_values_in_place() {
+    let mut value = literal("{'w': b'xxxxxxxx', 'l': [b'x', (b'yyyyy',)], 's': {b'zzzzzz'}}");
+    let mut strip = StripBlobs(4, Vec::new());
+    visit_mut(&mut value, &mut strip);
+    assert_eq!(
+        value,
+        literal("{'w': 8, 'l': [b'x', (5,)], 's': {b'zzzzzz'}}")
+    );
+    assert_eq!(strip.1, ["'w'", "'l'", "'s'"]);
+}
+
+// Turns bytes keys into strs, and counts the values folded.
+#[derive(Default)]
+struct DecodeKeys(Vec<String>);
+
+impl<'a> Fold<'a> for DecodeKeys {
+    fn fold_value(&mut self, value: Value<'a>, path: &Path<'a>) -> Value<'a> {
+        self.0.push(path.to_string());
+        match value {
+            Value::I64(i) => Value::I64(i * 10),
+            value => value,
+        }
+    }
+
+    fn fold_key(&mut self, key: Value<'a>, _path: &Path<'a>) -> Value<'a> {
+        match key {
+            Value::Bytes(b) => Value::String(String::from_utf8_lossy(&b).into_owned().into()),
+            key => key,
+        }
+    }
+}
+
+#[test]
+fn folds_bottom_up() {
+    let value = literal("{b'a': {b'b': [1, 2]}, 'a': 3, 's': frozenset({1, 10})}");
+    let mut decode = DecodeKeys::default();
+    let value = fold(value, &mut decode);
+    // The str key 'a' lands on the decoded b'a', as in Python.
+    assert_eq!(value, literal("{'a': 30, 's': frozenset({10, 100})}"));
+    assert_eq!(
+        decode.0,
+        [
+            "[b\"a\"][b\"b\"][0]",
+            "[b\"a\"][b\"b\"][1]",
+            "[b\"a\"][b\"b\"]",
+            "[b\"a\"]",
+            "[\"a\"]",
+            "[\"s\"][0]",
+            "[\"s\"][1]",
+            "[\"s\"]",
+            "",
+        ]
+    );
+    assert_eq!(fold(py!(1), &mut decode), py!(10));
+    assert_eq!(
+        fold(Value::BinPersId(Box::new(py!((1, 2)))), &mut decode),
+        Value::BinPersId(Box::new(py!((10, 20))))
+    );
+}
+
+// Counts the values, and the deepest path.
+#[derive(Default)]
+struct Depth(usize, usize);
+
+impl<'a> Visit<'a> for Depth {
+    fn visit_value(&mut self, _value: &Value<'a>, path: &Path<'a>) -> bool {
+        self.0 += 1;
+        self.1 = self.1.max(path.len());
+        true
+    }
+}
+
+impl<'a> VisitMut<'a> for Depth {
+    fn visit_value_mut(&mut self, value: &mut Value<'a>, path: &Path<'a>) -> bool {
+        self.visit_value(value, path)
+    }
+}
+
+impl<'a> Fold<'a> for Depth {}
+
+#[test]
+fn walks_deep_values_without_recursing() {
+    const DEPTH: usize = 200_000;
+    let mut value = Value::None;
+    for i in 0..DEPTH {
+        value = match i % 3 {
+            0 => Value::List(vec![value]),
+            1 => py!({"k": value}),
+            _ => Value::BinPersId(Box::new(value)),
+        };
+    }
+    let mut depth = Depth::default();
+    visit(&value, &mut depth);
+    visit_mut(&mut value, &mut depth);
+    assert_eq!((depth.0, depth.1), (2 * (DEPTH + 1), DEPTH - DEPTH / 3));
+    let mut value = fold(value, &mut Depth::default());
+    // Take the value apart from the top, as dropping it would recurse.
+    for _ in 0..DEPTH {
+        value = match value {
+            Value::List(mut items) => items.pop().unwrap(),
+            Value::Dict(mut dict) => dict.0.pop().unwrap().1,
+            Value::BinPersId(id) => *id,
+            other => panic!("unexpected {:?}", other),
+        };
+    }
+    assert_eq!(value, Value::None);
+}